@@ -3,35 +3,71 @@
 //! Provisions a server with Tengu `PaaS` installed.
 //! - Default: connects to user@host via SSH and provisions
 //! - `--hetzner`: creates a Hetzner VPS first, then provisions via SSH
+//! - `aws`: launches an EC2 instance provisioned by cloud-init
 
+mod output;
 mod providers;
+mod secrets;
+mod theme;
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
-use std::{env, fs};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use std::{env, fs, thread};
 
 use anyhow::{Context, Result, bail};
-use clap::{Parser, Subcommand};
-use comfy_table::{Cell, Color, Table, presets::UTF8_FULL_CONDENSED};
-use console::{Emoji, style};
+use clap::{Parser, Subcommand, ValueEnum};
+use comfy_table::{Cell, Color, Table};
 use dialoguer::{Input, Password};
 use serde::{Deserialize, Serialize};
-use tengu_provision::{BashRenderer, Manifest, Renderer, TenguConfig, TlsMode};
-
-use providers::{Hetzner, SshProvider, TunnelConfig, hetzner::ServerParams};
-
-static ROCKET: Emoji<'_, '_> = Emoji("🚀 ", "");
-static SPARKLE: Emoji<'_, '_> = Emoji("✨ ", "");
-static FOLDER: Emoji<'_, '_> = Emoji("📁 ", "");
-static CHECK: Emoji<'_, '_> = Emoji("✅ ", "✓ ");
+use tengu_provision::{
+    BashRenderer, CloudInitRenderer, DEFAULT_TENGU_CADDY_REPO, DEFAULT_TENGU_REPO,
+    DockerfileRenderer, FileSpec, JsonRenderer, LocalExecutor, Manifest, Phase, PlanRenderer,
+    Renderer, StepResult, TenguConfig, TlsMode, UserSpec, steps::Owner,
+};
+use zeroize::Zeroizing;
+
+use output::say;
+use providers::{
+    CheckStatus, Ec2, Hetzner, ProvisionReport, Proxmox, ServerStatus, SshProvider, StepStatus,
+    TunnelConfig,
+    ec2::InstanceParams,
+    hetzner::{ServerParams, VolumeSpec},
+    proxmox::ContainerParams,
+    ssh::CORE_SERVICES,
+};
+use theme::{ARROW, CROSS, DASH, Glyph, TICK, style};
+
+static ROCKET: Glyph = Glyph("🚀 ", "");
+static SPARKLE: Glyph = Glyph("✨ ", "");
+static FOLDER: Glyph = Glyph("📁 ", "");
+static CHECK: Glyph = Glyph("✅ ", "v ");
 
 const DEFAULT_RELEASE: &str = "v0.1.0-22879bf";
+const DEFAULT_TIMEZONE: &str = "UTC";
+const DEFAULT_LOCALE: &str = "en_US.UTF-8";
 const SSH_KEY_NAME: &str = "tengu-init";
+/// Default instance type for `tengu-init aws`
+const DEFAULT_EC2_INSTANCE_TYPE: &str = "t3.large";
+/// Default `pct create` template for `tengu-init proxmox`
+const DEFAULT_PROXMOX_TEMPLATE: &str = "local:vztmpl/ubuntu-24.04-standard_24.04-2_amd64.tar.zst";
+/// Mount point for a Hetzner data volume
+const POSTGRES_DATA_PATH: &str = "/var/lib/postgresql";
+/// Maximum number of hosts provisioned at once
+const MAX_PARALLEL_HOSTS: usize = 4;
 
 /// Configuration file structure
 /// Path: ~/.config/tengu/init.toml (XDG-style, same as main tengu config)
 #[derive(Debug, Default, Serialize, Deserialize)]
 struct Config {
+    /// Raw commands appended after all steps (cloud-init `runcmd`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    extra_runcmd: Vec<String>,
+    /// Raw commands appended after all steps (cloud-init `bootcmd`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    extra_bootcmd: Vec<String>,
     #[serde(default)]
     server: ServerConfig,
     #[serde(default)]
@@ -46,6 +82,32 @@ struct Config {
     ssh: SshConfig,
     #[serde(default)]
     notifications: NotificationsConfig,
+    #[serde(default)]
+    cloud_init: CloudInitConfig,
+    #[serde(default)]
+    ollama: OllamaConfig,
+    /// Extra files to write (`[[files]]` tables)
+    #[serde(default)]
+    files: Vec<FileSpec>,
+    /// Additional login users (`[[users]]` tables)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    users: Vec<UserSpec>,
+}
+
+/// Ollama models to pre-pull so the first request doesn't download them
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct OllamaConfig {
+    #[serde(default)]
+    models: Vec<String>,
+}
+
+/// First-boot behavior for cloud-init output (apt options both default to true)
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CloudInitConfig {
+    package_update: Option<bool>,
+    package_upgrade: Option<bool>,
+    /// Completion line logged by cloud-init (default: `"Tengu PaaS server ready!"`)
+    final_message: Option<String>,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -66,6 +128,71 @@ struct ServerConfig {
     release: Option<String>,
     /// Admin username for Tengu (default: tengu)
     admin_user: Option<String>,
+    /// Admin user's groups (default: docker and sudo)
+    admin_groups: Option<Vec<String>>,
+    /// GitHub `owner/repo` (or mirror URL) for the tengu .deb
+    tengu_repo: Option<String>,
+    /// GitHub `owner/repo` (or mirror URL) for the tengu-caddy .deb
+    tengu_caddy_repo: Option<String>,
+    /// Internal mirror for apt repositories and .deb downloads (air-gapped installs)
+    mirror: Option<String>,
+    /// Size in GB of a volume for Postgres data (Hetzner only)
+    volume_size: Option<u32>,
+    /// System timezone (default: UTC)
+    timezone: Option<String>,
+    /// System locale (default: en_US.UTF-8)
+    locale: Option<String>,
+}
+
+impl ServerConfig {
+    /// Source of the tengu .deb (default: [`DEFAULT_TENGU_REPO`])
+    fn tengu_repo(&self) -> &str {
+        self.tengu_repo.as_deref().unwrap_or(DEFAULT_TENGU_REPO)
+    }
+
+    /// Source of the tengu-caddy .deb (default: [`DEFAULT_TENGU_CADDY_REPO`])
+    fn tengu_caddy_repo(&self) -> &str {
+        self.tengu_caddy_repo
+            .as_deref()
+            .unwrap_or(DEFAULT_TENGU_CADDY_REPO)
+    }
+}
+
+impl Config {
+    /// Resolve `env:`, `file:` and `op://` references in secret fields
+    fn resolve_secrets(&mut self) -> Result<()> {
+        secrets::resolve_in_place(&mut self.cloudflare.api_key).context("cloudflare.api_key")?;
+        secrets::resolve_in_place(&mut self.cloudflare.api_token)
+            .context("cloudflare.api_token")?;
+        secrets::resolve_in_place(&mut self.resend.api_key).context("resend.api_key")
+    }
+
+    /// Reject malformed `[[files]]` owners before they reach `chown` on the server
+    fn validate_files(&self) -> Result<()> {
+        for file in &self.files {
+            if let Some(owner) = &file.owner {
+                owner
+                    .parse::<Owner>()
+                    .with_context(|| format!("[[files]] entry for {}", file.path))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Reject `[[users]]` entries that are unnamed or declared twice
+    fn validate_users(&self) -> Result<()> {
+        let mut seen: Vec<&str> = self.server.admin_user.iter().map(String::as_str).collect();
+        for user in &self.users {
+            if user.name.is_empty() {
+                bail!("[[users]] entry without a name");
+            }
+            if seen.contains(&user.name.as_str()) {
+                bail!("[[users]] entry for {} duplicates another user", user.name);
+            }
+            seen.push(&user.name);
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -107,9 +234,11 @@ struct Args {
     #[command(subcommand)]
     command: Option<Commands>,
 
-    /// SSH destination (user@host), required unless --hetzner
-    #[arg()]
-    host: Option<String>,
+    /// SSH destinations (user@host), required unless --hetzner
+    ///
+    /// Several hosts are provisioned concurrently.
+    #[arg(conflicts_with = "hetzner")]
+    hosts: Vec<String>,
 
     /// Create Hetzner VPS first (uses hcloud CLI)
     #[arg(long)]
@@ -119,6 +248,22 @@ struct Args {
     #[arg(short, long, default_value = "22")]
     port: u16,
 
+    /// SSH private key to use (disables agent keys)
+    #[arg(short, long)]
+    identity: Option<PathBuf>,
+
+    /// Seconds to wait for each SSH connection attempt (raise on slow networks)
+    #[arg(long, default_value_t = 10, value_name = "SECS")]
+    ssh_connect_timeout: u64,
+
+    /// Sudo password on the target (prompted for if omitted and sudo needs one)
+    #[arg(long, conflicts_with = "no_sudo_password")]
+    sudo_password: Option<String>,
+
+    /// Target has passwordless sudo, don't prompt for a password
+    #[arg(long)]
+    no_sudo_password: bool,
+
     /// Skip confirmation prompt
     #[arg(long, short = 'y')]
     yes: bool,
@@ -171,6 +316,14 @@ struct Args {
     #[arg(long, short = 'u')]
     user: Option<String>,
 
+    /// System timezone (default: UTC)
+    #[arg(long)]
+    timezone: Option<String>,
+
+    /// System locale (default: en_US.UTF-8)
+    #[arg(long)]
+    locale: Option<String>,
+
     /// Config file path
     #[arg(short, long)]
     config: Option<PathBuf>,
@@ -187,6 +340,14 @@ struct Args {
     #[arg(long)]
     ufw: bool,
 
+    /// Install unattended-upgrades for automatic security patches
+    #[arg(long)]
+    auto_upgrades: bool,
+
+    /// Add a swap file sized to the server's RAM (up to 4G; none above 16G)
+    #[arg(long)]
+    auto_swap: bool,
+
     /// Path to local tengu .deb package (skips download)
     #[arg(long)]
     deb_path: Option<PathBuf>,
@@ -199,6 +360,51 @@ struct Args {
     #[arg(long)]
     dry_run: bool,
 
+    /// Skip the steps an interrupted run of the same manifest already finished
+    #[arg(long)]
+    resume: bool,
+
+    /// Write a JSON provisioning report (an array when provisioning several hosts)
+    #[arg(long, value_name = "PATH")]
+    report: Option<PathBuf>,
+
+    /// Only run these phases (comma-separated, e.g. postgres,services)
+    #[arg(long, global = true, value_delimiter = ',', conflicts_with = "skip")]
+    only: Vec<Phase>,
+
+    /// Skip these phases (comma-separated, e.g. docker)
+    #[arg(long, global = true, value_delimiter = ',')]
+    skip: Vec<Phase>,
+
+    /// Only run steps with any of these tags (comma-separated: database, ai, security)
+    #[arg(long = "tag", global = true, value_delimiter = ',')]
+    tags: Vec<String>,
+
+    /// Skip steps with any of these tags (comma-separated)
+    #[arg(long = "exclude-tag", global = true, value_delimiter = ',')]
+    exclude_tags: Vec<String>,
+
+    /// Only install packages: one `apt-get update`, then a single `apt-get install`
+    #[arg(long, global = true)]
+    packages_only: bool,
+
+    /// Don't refresh apt package lists on first boot (cloud-init output)
+    #[arg(long, global = true)]
+    no_package_update: bool,
+
+    /// Don't upgrade installed packages on first boot (cloud-init output)
+    #[arg(long, global = true)]
+    no_package_upgrade: bool,
+
+    /// Print results as JSON objects (one per line) instead of tables;
+    /// progress goes to stderr
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Plain ASCII output without colors or emoji (also with `NO_COLOR` or `TERM=dumb`)
+    #[arg(long, global = true)]
+    plain: bool,
+
     /// Force recreation (Hetzner only)
     #[arg(short, long)]
     force: bool,
@@ -219,12 +425,172 @@ struct Args {
     /// Ubuntu image (Hetzner only)
     #[arg(long)]
     image: Option<String>,
+
+    /// Attach a volume of this many GB for Postgres data (Hetzner only)
+    #[arg(long, value_name = "GB")]
+    volume_size: Option<u32>,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Show generated provisioning script
-    Show,
+    Show {
+        /// Output format
+        #[arg(value_enum, default_value = "bash")]
+        format: OutputFormat,
+
+        /// Mask API keys so the output is safe to share
+        #[arg(long)]
+        redact: bool,
+    },
+    /// Write rendered provisioning output to a file
+    Export(ExportArgs),
+    /// Show which steps would apply on a server, without changing it
+    Diff {
+        /// SSH destination (user@host)
+        host: String,
+    },
+    /// Report cloud-init, service, disk/memory and tengu version status of a server
+    ///
+    /// Exits non-zero if a core service (docker, postgresql, caddy, tengu) is down.
+    Status {
+        /// SSH destination (user@host); defaults to root@<server IP> with --hetzner
+        #[arg(required_unless_present = "hetzner")]
+        host: Option<String>,
+
+        /// Look up the Hetzner server (name from config, default: tengu)
+        #[arg(long)]
+        hetzner: bool,
+    },
+    /// Check that every step comes after the steps it depends on
+    Validate,
+    /// Provision this machine directly, without SSH
+    ApplyLocal {
+        /// Keep going after a failed step
+        #[arg(long)]
+        continue_on_error: bool,
+    },
+    /// Create a Proxmox LXC container and provision it (run on the Proxmox host)
+    Proxmox(ProxmoxArgs),
+    /// Launch an AWS EC2 instance provisioned by cloud-init (uses aws CLI)
+    Aws(AwsArgs),
+    /// Manage the config file
+    #[command(subcommand)]
+    Config(ConfigCommand),
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+    /// Interactively create the config file
+    Init {
+        /// Overwrite an existing config file
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct ExportArgs {
+    /// Output format
+    #[arg(long, value_enum, default_value = "bash")]
+    format: OutputFormat,
+
+    /// File to write
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+#[derive(clap::Args, Debug)]
+struct ProxmoxArgs {
+    /// Container ID
+    #[arg(long)]
+    vmid: u32,
+
+    /// Container template volume
+    #[arg(long, default_value = DEFAULT_PROXMOX_TEMPLATE)]
+    template: String,
+
+    /// Container hostname (default: server name from config, or tengu)
+    #[arg(long)]
+    hostname: Option<String>,
+
+    /// Storage for the root disk
+    #[arg(long, default_value = "local-lvm")]
+    storage: String,
+
+    /// Root disk size in GB
+    #[arg(long, default_value_t = 32)]
+    disk: u32,
+
+    /// Memory in MB
+    #[arg(long, default_value_t = 4096)]
+    memory: u32,
+
+    /// CPU cores
+    #[arg(long, default_value_t = 2)]
+    cores: u32,
+
+    /// Network bridge (the container uses DHCP)
+    #[arg(long, default_value = "vmbr0")]
+    bridge: String,
+
+    /// Destroy and recreate an existing container without asking
+    #[arg(short, long)]
+    force: bool,
+
+    /// Print the pct commands without running them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+#[derive(clap::Args, Debug)]
+struct AwsArgs {
+    /// Instance Name tag (default: server name from config, or tengu)
+    #[arg(short, long)]
+    name: Option<String>,
+
+    /// Instance type
+    #[arg(short = 't', long, default_value = DEFAULT_EC2_INSTANCE_TYPE)]
+    instance_type: String,
+
+    /// AWS region (default: the aws CLI's configured region)
+    #[arg(short, long)]
+    region: Option<String>,
+
+    /// Ubuntu AMI ID
+    #[arg(long)]
+    ami: String,
+
+    /// EC2 key pair for the default AMI user
+    #[arg(long)]
+    key_name: Option<String>,
+
+    /// Security group ID (must allow SSH, and HTTP/HTTPS without Cloudflare)
+    #[arg(long)]
+    security_group: Option<String>,
+
+    /// Terminate and relaunch an existing instance without asking
+    #[arg(short, long)]
+    force: bool,
+
+    /// Print the aws commands without running them
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Rendered output format for `show` and `export`
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    /// Idempotent bash script
+    Bash,
+    /// cloud-init `#cloud-config` YAML
+    CloudInit,
+    /// JSON manifest for tooling
+    Json,
+    /// Dockerfile for baking an image
+    Dockerfile,
+    /// Readable per-phase plan of checks and commands (not executable)
+    Plan,
 }
 
 /// Resolved provisioning configuration (all credentials present)
@@ -237,6 +603,8 @@ struct ResolvedConfig {
     notify_email: String,
     ssh_key: String,
     release: String,
+    timezone: String,
+    locale: String,
     /// Cloudflare credentials for DNS management (available in ALL modes if configured)
     cf_email: Option<String>,
     cf_api_key: Option<String>,
@@ -250,6 +618,7 @@ struct HetznerParams {
     server_type: String,
     location: String,
     image: String,
+    volume: Option<VolumeSpec>,
 }
 
 /// Config path - uses same XDG-style path as main tengu config
@@ -269,8 +638,12 @@ fn load_config(path: Option<&PathBuf>) -> Result<Config> {
     if path.exists() {
         let content = fs::read_to_string(&path)
             .with_context(|| format!("Failed to read config: {}", path.display()))?;
-        toml::from_str(&content)
-            .with_context(|| format!("Failed to parse config: {}", path.display()))
+        let mut config: Config = toml::from_str(&content)
+            .with_context(|| format!("Failed to parse config: {}", path.display()))?;
+        config.resolve_secrets()?;
+        config.validate_files()?;
+        config.validate_users()?;
+        Ok(config)
     } else {
         Ok(Config::default())
     }
@@ -294,6 +667,69 @@ fn detect_ssh_key() -> Option<String> {
     None
 }
 
+/// Validate an email address (loosely - just enough to catch typos)
+#[allow(clippy::ptr_arg)] // dialoguer validators take &String
+fn validate_email(input: &String) -> Result<(), &'static str> {
+    match input.split_once('@') {
+        Some((user, domain)) if !user.is_empty() && domain.contains('.') => Ok(()),
+        _ => Err("Please enter a valid email address"),
+    }
+}
+
+/// Key types accepted by [`validate_ssh_public_key`]
+const SSH_KEY_TYPES: &[&str] = &[
+    "ssh-ed25519",
+    "ssh-rsa",
+    "ecdsa-sha2-nistp256",
+    "ecdsa-sha2-nistp384",
+    "ecdsa-sha2-nistp521",
+    "sk-ssh-ed25519@openssh.com",
+    "sk-ecdsa-sha2-nistp256@openssh.com",
+];
+
+/// Validate an OpenSSH public key (`type base64-blob [comment]`)
+///
+/// The type must be one sshd accepts, and the blob must decode and name the
+/// same key type as the prefix. A bad key would lock us out of the server.
+fn validate_ssh_public_key(key: &str) -> Result<()> {
+    use base64::Engine;
+
+    let invalid = |reason: &str| anyhow::anyhow!("Invalid SSH public key `{key}`: {reason}");
+    let mut parts = key.split_whitespace();
+    let (Some(kind), Some(blob)) = (parts.next(), parts.next()) else {
+        return Err(invalid(
+            "expected an OpenSSH public key (e.g. ssh-ed25519 AAAA... user@host)",
+        ));
+    };
+    if !SSH_KEY_TYPES.contains(&kind) {
+        return Err(invalid(&format!(
+            "unknown key type {kind} (expected one of {})",
+            SSH_KEY_TYPES.join(", ")
+        )));
+    }
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(blob)
+        .map_err(|_| invalid("not valid base64"))?;
+    // Blob starts with the key type as a length-prefixed string
+    let embedded = bytes
+        .get(..4)
+        .and_then(|len| {
+            let len = u32::from_be_bytes(len.try_into().ok()?) as usize;
+            bytes.get(4..4 + len)
+        })
+        .ok_or_else(|| invalid("truncated"))?;
+    if embedded != kind.as_bytes() {
+        return Err(invalid("key type does not match its contents"));
+    }
+    Ok(())
+}
+
+/// [`validate_ssh_public_key`] as a dialoguer validator
+#[allow(clippy::ptr_arg)] // dialoguer validators take &String
+fn validate_ssh_key(input: &String) -> Result<(), String> {
+    validate_ssh_public_key(input).map_err(|e| e.to_string())
+}
+
 /// Check if cloudflared cert.pem exists
 fn cloudflared_cert_exists() -> bool {
     let home = env::var("HOME").unwrap_or_default();
@@ -305,11 +741,11 @@ fn cloudflared_cert_exists() -> bool {
 
 /// Run `cloudflared tunnel login` interactively
 fn run_cloudflared_login() -> Result<()> {
-    println!(
+    say!(
         "\n{} Cloudflare tunnel authentication required.",
         style("*").cyan()
     );
-    println!("  A browser window will open for authentication...\n");
+    say!("  A browser window will open for authentication...\n");
 
     let status = Command::new("cloudflared")
         .args(["tunnel", "login"])
@@ -320,7 +756,7 @@ fn run_cloudflared_login() -> Result<()> {
         bail!("cloudflared tunnel login failed");
     }
 
-    println!("  {} Cloudflare tunnel authenticated\n", style("v").green());
+    say!("  {} Cloudflare tunnel authenticated\n", style("v").green());
     Ok(())
 }
 
@@ -340,9 +776,11 @@ fn resolve_config(args: &Args, config: &Config) -> Result<ResolvedConfig> {
             || env::var("CF_EMAIL").is_ok()
             || config.cloudflare.email.is_some();
         if !has_cf && !args.yes {
-            println!(
+            say!(
                 "\n{}",
-                style("--- Tengu Init \u{2014} TLS Mode ---").cyan().bold()
+                style(format!("--- Tengu Init {DASH} TLS Mode ---"))
+                    .cyan()
+                    .bold()
             );
             let selection = dialoguer::Select::new()
                 .with_prompt("TLS mode")
@@ -365,13 +803,13 @@ fn resolve_config(args: &Args, config: &Config) -> Result<ResolvedConfig> {
         && config.cloudflare.email.is_none();
 
     if needs_interactive {
-        println!(
+        say!(
             "\n{}",
-            style("--- Tengu Init \u{2014} Credential Setup ---")
+            style(format!("--- Tengu Init {DASH} Credential Setup ---"))
                 .cyan()
                 .bold()
         );
-        println!();
+        say!();
     }
 
     // TLS mode — resolve credentials based on mode
@@ -399,13 +837,7 @@ fn resolve_config(args: &Args, config: &Config) -> Result<ResolvedConfig> {
                 || {
                     Input::<String>::new()
                         .with_prompt("Cloudflare email")
-                        .validate_with(|input: &String| {
-                            if input.contains('@') && input.contains('.') {
-                                Ok(())
-                            } else {
-                                Err("Please enter a valid email address")
-                            }
-                        })
+                        .validate_with(validate_email)
                         .interact_text()
                         .context("Failed to read Cloudflare email")
                 },
@@ -495,7 +927,9 @@ fn resolve_config(args: &Args, config: &Config) -> Result<ResolvedConfig> {
         .or_else(|| config.ssh.public_key.clone())
         .map_or_else(
             || {
-                let prompt = Input::<String>::new().with_prompt("SSH public key");
+                let prompt = Input::<String>::new()
+                    .with_prompt("SSH public key")
+                    .validate_with(validate_ssh_key);
                 let prompt = if let Some(ref key) = detected_key {
                     prompt.default(key.clone())
                 } else {
@@ -507,6 +941,7 @@ fn resolve_config(args: &Args, config: &Config) -> Result<ResolvedConfig> {
             },
             Ok,
         )?;
+    validate_ssh_public_key(&ssh_key)?;
 
     // Notification email (default: CF email in CF mode, or prompt in direct)
     let default_email = match &tls_mode {
@@ -557,6 +992,17 @@ fn resolve_config(args: &Args, config: &Config) -> Result<ResolvedConfig> {
             Ok,
         )?;
 
+    let timezone = args
+        .timezone
+        .clone()
+        .or_else(|| config.server.timezone.clone())
+        .unwrap_or_else(|| DEFAULT_TIMEZONE.to_string());
+    let locale = args
+        .locale
+        .clone()
+        .or_else(|| config.server.locale.clone())
+        .unwrap_or_else(|| DEFAULT_LOCALE.to_string());
+
     // Admin username
     let admin_user = args
         .user
@@ -608,6 +1054,8 @@ fn resolve_config(args: &Args, config: &Config) -> Result<ResolvedConfig> {
         notify_email,
         ssh_key,
         release,
+        timezone,
+        locale,
         cf_email,
         cf_api_key,
         cf_api_token,
@@ -640,70 +1088,103 @@ fn resolve_hetzner_params(args: &Args, config: &Config) -> HetznerParams {
             .clone()
             .or_else(|| config.server.image.clone())
             .unwrap_or_else(|| "ubuntu-24.04".to_string()),
+        volume: args
+            .volume_size
+            .or(config.server.volume_size)
+            .map(|size_gb| VolumeSpec {
+                size_gb,
+                mount_path: POSTGRES_DATA_PATH.to_string(),
+            }),
     }
 }
 
 #[allow(clippy::too_many_lines)]
 fn main() -> Result<()> {
     let args = Args::parse();
+    output::set_json(args.json);
+    theme::set_plain(theme::plain_requested(args.plain));
 
     // Show config path and exit
     if args.show_config {
         let path = args.config.clone().unwrap_or_else(config_path);
-        println!("{} Config: {}", FOLDER, path.display());
+        say!("{} Config: {}", FOLDER, path.display());
         if path.exists() {
-            println!("  {CHECK} exists");
+            say!("  {CHECK} exists");
         } else {
-            println!("  {} not found (will use defaults)", style("!").yellow());
+            say!("  {} not found (will use defaults)", style("!").yellow());
         }
         return Ok(());
     }
 
-    // Route show subcommand
-    if let Some(Commands::Show) = &args.command {
-        let file_config = load_config(args.config.as_ref())?;
-        return run_show(&file_config);
+    // Route show/export subcommands
+    match &args.command {
+        Some(Commands::Show { format, redact }) => {
+            let file_config = load_config(args.config.as_ref())?;
+            return run_show(&file_config, &args, *format, *redact);
+        }
+        Some(Commands::Export(export)) => {
+            let file_config = load_config(args.config.as_ref())?;
+            return run_export(&file_config, &args, export);
+        }
+        Some(Commands::Diff { host }) => {
+            let file_config = load_config(args.config.as_ref())?;
+            return run_diff(&file_config, &args, host);
+        }
+        Some(Commands::Status { host, hetzner }) => {
+            let file_config = load_config(args.config.as_ref())?;
+            return run_status(&file_config, &args, host.as_deref(), *hetzner);
+        }
+        Some(Commands::Validate) => {
+            let file_config = load_config(args.config.as_ref())?;
+            return run_validate(&file_config, &args);
+        }
+        Some(Commands::ApplyLocal { continue_on_error }) => {
+            let file_config = load_config(args.config.as_ref())?;
+            return run_apply_local(&file_config, &args, *continue_on_error);
+        }
+        Some(Commands::Proxmox(proxmox)) => {
+            let file_config = load_config(args.config.as_ref())?;
+            return run_proxmox(&file_config, &args, proxmox);
+        }
+        Some(Commands::Aws(aws)) => {
+            let file_config = load_config(args.config.as_ref())?;
+            return run_aws(&file_config, &args, aws);
+        }
+        Some(Commands::Config(ConfigCommand::Init { force })) => {
+            let path = args.config.clone().unwrap_or_else(config_path);
+            return run_config_init(&path, *force);
+        }
+        None => {}
     }
 
     // Validate: need either host or --hetzner
-    if args.host.is_none() && !args.hetzner {
+    if args.hosts.is_empty() && !args.hetzner {
         bail!(
             "Missing SSH destination. Usage:\n  \
              tengu-init user@host          Provision existing server\n  \
+             tengu-init host1 host2 ...    Provision several servers concurrently\n  \
              tengu-init --hetzner          Create Hetzner VPS and provision"
         );
     }
 
     // Handle --remove: uninstall everything from the target server
     if args.remove {
-        let host = args.host.as_ref().ok_or_else(|| {
+        if args.hosts.len() > 1 {
+            bail!("--remove takes a single host");
+        }
+        let host = args.hosts.first().ok_or_else(|| {
             anyhow::anyhow!("--remove requires a host argument: tengu-init user@host --remove")
         })?;
 
-        println!();
-        println!(
-            "{}",
-            style("╔═══════════════════════════════════════╗")
-                .red()
-                .bold()
-        );
-        println!(
-            "{}",
-            style("║          TENGU REMOVAL                ║")
-                .red()
-                .bold()
-        );
-        println!(
-            "{}",
-            style("╚═══════════════════════════════════════╝")
-                .red()
-                .bold()
-        );
-        println!(
+        say!();
+        for line in theme::banner("TENGU REMOVAL") {
+            say!("{}", style(line).red().bold());
+        }
+        say!(
             "\nThis will remove Tengu and all installed dependencies from {}",
             style(host).cyan()
         );
-        println!("Including: tengu, caddy, ollama, postgresql, docker, fail2ban\n");
+        say!("Including: tengu, caddy, ollama, postgresql, docker, fail2ban\n");
 
         if !args.force {
             let confirm = dialoguer::Confirm::new()
@@ -712,7 +1193,7 @@ fn main() -> Result<()> {
                 .interact()?;
 
             if !confirm {
-                println!("Aborted.");
+                say!("Aborted.");
                 return Ok(());
             }
         }
@@ -722,7 +1203,10 @@ fn main() -> Result<()> {
             return Ok(());
         }
 
-        let provider = SshProvider::new(host, args.port);
+        let mut provider = SshProvider::new(host, args.port);
+        provider.identity.clone_from(&args.identity);
+        provider.connect_timeout = Duration::from_secs(args.ssh_connect_timeout);
+        provider.sudo_password = resolve_sudo_password(&args, &provider)?;
         provider.remove()?;
 
         return Ok(());
@@ -735,26 +1219,11 @@ fn main() -> Result<()> {
     let resolved = resolve_config(&args, &file_config)?;
 
     // Build TenguConfig for provisioning
-    let tengu_config = TenguConfig::builder()
-        .user(&resolved.admin_user)
-        .domain_platform(&resolved.domain_platform)
-        .domain_apps(&resolved.domain_apps)
-        .tls_mode(resolved.tls_mode.clone())
-        .resend_api_key(&resolved.resend_api_key)
-        .notify_email(&resolved.notify_email)
-        .ssh_keys(if resolved.ssh_key.is_empty() {
-            vec![]
-        } else {
-            vec![resolved.ssh_key.clone()]
-        })
-        .release(&resolved.release)
-        .enable_ufw(args.ufw)
-        .deb_path(args.deb_path.as_ref().map(|p| p.display().to_string()))
-        .build();
+    let mut tengu_config = provision_tengu_config(&resolved, &args, &file_config);
 
     // Script-only mode (only for direct SSH)
     if args.script_only && !args.hetzner {
-        let script = SshProvider::generate_script(&tengu_config)?;
+        let script = SshProvider::generate_script(&build_manifest(&tengu_config, &args)?)?;
         println!("{script}");
         return Ok(());
     }
@@ -765,6 +1234,7 @@ fn main() -> Result<()> {
     // Determine the host - either from args or create via Hetzner
     // server_ip is Some(ip) when we created the server (for DNS update)
     let (host, server_ip) = if args.hetzner {
+        Hetzner::ensure_cli_available()?;
         let hetzner_params = resolve_hetzner_params(&args, &file_config);
         print_hetzner_config_table(&resolved, &hetzner_params)?;
 
@@ -778,19 +1248,37 @@ fn main() -> Result<()> {
                 .interact()?;
 
             if !confirm {
-                println!("Aborted.");
+                say!("Aborted.");
                 return Ok(());
             }
         }
 
         if args.dry_run {
-            println!("\n{} Dry run - not creating server", style("i").cyan());
+            say!("\n{} Dry run - not creating server", style("i").cyan());
+            let params = ServerParams {
+                name: &hetzner_params.name,
+                server_type: &hetzner_params.server_type,
+                image: &hetzner_params.image,
+                location: &hetzner_params.location,
+                ssh_key_name: SSH_KEY_NAME,
+                volume: hetzner_params.volume.as_ref(),
+            };
+            say!("\nWould run:");
+            say!(
+                "  {}  {}",
+                providers::format_command(&Hetzner::delete_server_command(&hetzner_params.name)),
+                style("# only if the server already exists").dim()
+            );
+            say!(
+                "  {}",
+                providers::format_command(&Hetzner::create_server_command(&params))
+            );
             return Ok(());
         }
 
         // Check if server exists
         if Hetzner::server_exists(&hetzner_params.name)? {
-            println!(
+            say!(
                 "\n{} Server '{}' already exists",
                 style("!").yellow(),
                 hetzner_params.name
@@ -803,7 +1291,7 @@ fn main() -> Result<()> {
                     .interact()?;
 
                 if !confirm {
-                    println!("Aborted.");
+                    say!("Aborted.");
                     return Ok(());
                 }
             }
@@ -816,7 +1304,7 @@ fn main() -> Result<()> {
         if Hetzner::ssh_key_exists(SSH_KEY_NAME)? {
             Hetzner::delete_ssh_key(SSH_KEY_NAME)?;
         }
-        println!("{} Creating SSH key in Hetzner...", style("*").cyan());
+        say!("{} Creating SSH key in Hetzner...", style("*").cyan());
         match Hetzner::create_ssh_key(SSH_KEY_NAME, &resolved.ssh_key) {
             Ok(()) => {
                 ssh_key_name = SSH_KEY_NAME.to_string();
@@ -827,7 +1315,7 @@ fn main() -> Result<()> {
                     // Key content exists under another name — find it by fingerprint
                     ssh_key_name = Hetzner::find_key_name_by_content(&resolved.ssh_key)?
                         .unwrap_or_else(|| SSH_KEY_NAME.to_string());
-                    println!(
+                    say!(
                         "  {} SSH key exists as '{}', reusing",
                         style("*").dim(),
                         ssh_key_name
@@ -839,28 +1327,36 @@ fn main() -> Result<()> {
         }
 
         // Create server (plain Ubuntu with SSH key)
-        println!("\n{ROCKET} Creating server...");
+        say!("\n{ROCKET} Creating server...");
         let params = ServerParams {
             name: &hetzner_params.name,
             server_type: &hetzner_params.server_type,
             image: &hetzner_params.image,
             location: &hetzner_params.location,
             ssh_key_name: &ssh_key_name,
+            volume: hetzner_params.volume.as_ref(),
         };
         let ip = Hetzner::create_server(&params)?;
 
-        println!("  {} IP: {}", style("->").dim(), style(&ip).cyan());
+        say!("  {} IP: {}", style("->").dim(), style(&ip).cyan());
+
+        Hetzner::wait_until_running(&hetzner_params.name)?;
+
+        if let Some(volume) = &hetzner_params.volume {
+            let device = Hetzner::volume_device(&Hetzner::volume_name(&hetzner_params.name))?;
+            tengu_config.data_volume = Some((device, volume.mount_path.clone()));
+        }
 
         // Remove old host key
-        Hetzner::clear_host_key(&ip);
+        providers::clear_host_key(&ip);
 
         // Host is root@ip (Hetzner default)
         (format!("root@{ip}"), Some(ip))
     } else {
-        print_provision_config_table(&resolved);
+        print_provision_config_table(&resolved)?;
 
         if !args.yes && !args.dry_run {
-            let host_display = args.host.as_deref().unwrap_or("unknown");
+            let host_display = args.hosts.join(", ");
             let confirm = dialoguer::Confirm::new()
                 .with_prompt(format!(
                     "Provision server {host_display}? This will install Tengu PaaS and all dependencies"
@@ -869,28 +1365,42 @@ fn main() -> Result<()> {
                 .interact()?;
 
             if !confirm {
-                println!("Aborted.");
+                say!("Aborted.");
                 return Ok(());
             }
         }
 
         if args.dry_run {
-            println!("\n{} Dry run - not provisioning", style("i").cyan());
+            say!("\n{} Dry run - not provisioning", style("i").cyan());
             return Ok(());
         }
 
-        (args.host.clone().unwrap(), None)
+        if args.hosts.len() > 1 {
+            return provision_hosts(&args, &tengu_config);
+        }
+
+        (args.hosts[0].clone(), None)
     };
 
-    println!(
+    say!(
         "\n{} Provisioning {} via SSH\n",
         style("*").cyan(),
         style(&host).cyan()
     );
 
     // Create provider and provision
-    let provider = SshProvider::new(&host, args.port);
-    provider.provision(&tengu_config)?;
+    let mut provider = SshProvider::new(&host, args.port);
+    provider.identity.clone_from(&args.identity);
+    provider.connect_timeout = Duration::from_secs(args.ssh_connect_timeout);
+    // Pin the host key of a server created above instead of trusting known_hosts
+    provider.new_server = server_ip.is_some();
+    provider.sudo_password = resolve_sudo_password(&args, &provider)?;
+    provider.resume = args.resume;
+    let mut report = provider.provision(&tengu_config, &build_manifest(&tengu_config, &args)?)?;
+    report.ip.clone_from(&server_ip);
+    if let Some(path) = &args.report {
+        write_report(path, &report)?;
+    }
 
     // Post-provision: mode-dependent setup
     let has_cf_creds = resolved.cf_api_token.is_some()
@@ -927,30 +1437,30 @@ fn main() -> Result<()> {
                 if let Some(ref ip) = server_ip {
                     setup_dns_records(&resolved, ip)?;
                 } else {
-                    println!(
-                        "\n{} No server IP available — DNS records must be created manually.",
+                    say!(
+                        "\n{} No server IP available {DASH} DNS records must be created manually.",
                         style("!").yellow()
                     );
                 }
             } else {
                 // No CF credentials — print manual DNS reminder
                 let ip_hint = server_ip.as_deref().unwrap_or("<your-server-ip>");
-                println!(
+                say!(
                     "\n{} {}",
                     style("!").yellow().bold(),
                     style("DNS Configuration Required").yellow().bold()
                 );
-                println!("  Point these A records to {}:", style(ip_hint).cyan());
-                println!("    api.{}", resolved.domain_platform);
-                println!("    docs.{}", resolved.domain_platform);
-                println!(
+                say!("  Point these A records to {}:", style(ip_hint).cyan());
+                say!("    api.{}", resolved.domain_platform);
+                say!("    docs.{}", resolved.domain_platform);
+                say!(
                     "    *.{}  (apps + git deploy via SSH)",
                     resolved.domain_apps
                 );
-                println!(
+                say!(
                     "\n  Caddy will automatically obtain Let's Encrypt certificates once DNS resolves."
                 );
-                println!(
+                say!(
                     "  {} Add [cloudflare] credentials to init.toml for automatic DNS setup.\n",
                     style("tip:").dim()
                 );
@@ -958,11 +1468,13 @@ fn main() -> Result<()> {
         }
     }
 
+    print_report_summary(&report);
+
     // Print success
     if server_ip.is_some() {
-        print_success(&resolved);
+        print_success(&resolved, &report)?;
     } else {
-        print_provision_success(&tengu_config);
+        print_provision_success(&tengu_config, None, Some(&report))?;
     }
 
     Ok(())
@@ -1007,7 +1519,7 @@ fn create_dns_record(config: &ResolvedConfig, zone: &str, name: &str, content: &
         bail!("flarectl dns create-or-update failed: {stderr}");
     }
 
-    println!("  {} {}.{} -> {}", style("v").green(), name, zone, content);
+    say!("  {} {}.{} -> {}", style("v").green(), name, zone, content);
     Ok(())
 }
 
@@ -1018,8 +1530,8 @@ fn create_dns_record(config: &ResolvedConfig, zone: &str, name: &str, content: &
 /// - `docs.<domain-platform>` → VM IP
 /// - `*.<domain-apps>` → VM IP
 fn setup_dns_records(config: &ResolvedConfig, ip: &str) -> Result<()> {
-    println!(
-        "\n{} Setting up DNS records (→ {})...",
+    say!(
+        "\n{} Setting up DNS records ({ARROW} {})...",
         style("*").cyan(),
         style(ip).cyan()
     );
@@ -1031,7 +1543,7 @@ fn setup_dns_records(config: &ResolvedConfig, ip: &str) -> Result<()> {
     // Apps wildcard — zone is the apps domain
     create_dns_record(config, &config.domain_apps, "*", ip)?;
 
-    println!("  {} DNS records configured", style("✓").green().bold());
+    say!("  {} DNS records configured", style(TICK).green().bold());
     Ok(())
 }
 
@@ -1047,7 +1559,7 @@ fn update_wildcard_dns_tunnel(
     domain: &str,
     tunnel_name: &str,
 ) -> Result<()> {
-    println!(
+    say!(
         "\n{} Updating *.{} DNS to tunnel '{}'...",
         style("*").cyan(),
         domain,
@@ -1177,7 +1689,7 @@ fn update_wildcard_dns_tunnel(
         bail!("Failed to create wildcard DNS CNAME for tunnel");
     }
 
-    println!(
+    say!(
         "  {} *.{} -> {} (proxied)",
         style("v").green(),
         domain,
@@ -1206,10 +1718,261 @@ fn extract_json_field(json: &str, field: &str) -> Option<String> {
     Some(remaining[..value_end].to_string())
 }
 
-/// Run show command - displays the generated provisioning script
-fn run_show(config: &Config) -> Result<()> {
-    // Create a default TenguConfig from file config
-    let tengu_config = TenguConfig::builder()
+/// Resolve the sudo password: --sudo-password, else an interactive prompt
+///
+/// Root needs no sudo password, and `--no-sudo-password` or `-y` skips the
+/// prompt (non-interactive runs assume passwordless sudo). The prompt is only
+/// shown when `sudo -n true` fails on the target; an empty answer means
+/// passwordless sudo.
+fn resolve_sudo_password(args: &Args, provider: &SshProvider) -> Result<Option<Zeroizing<String>>> {
+    if let Some(password) = &args.sudo_password {
+        return Ok(Some(Zeroizing::new(password.clone())));
+    }
+    if args.no_sudo_password || args.yes || provider.user == "root" {
+        return Ok(None);
+    }
+    if provider.has_passwordless_sudo() {
+        return Ok(None);
+    }
+
+    let password = Zeroizing::new(
+        Password::new()
+            .with_prompt(format!(
+                "Sudo password for {}@{} (empty for passwordless sudo)",
+                provider.user, provider.host
+            ))
+            .allow_empty_password(true)
+            .interact()?,
+    );
+    Ok((!password.is_empty()).then_some(password))
+}
+
+/// Provision several hosts concurrently and print a per-host summary
+///
+/// Each host gets its own labeled output; a failure on one host doesn't stop
+/// the others. Tunnel and DNS setup are per-domain and are left to a
+/// single-host run.
+fn provision_hosts(args: &Args, config: &TenguConfig) -> Result<()> {
+    let manifest = build_manifest(config, args)?;
+
+    let mut providers = Vec::new();
+    for host in &args.hosts {
+        let mut provider = SshProvider::new(host, args.port);
+        provider.identity.clone_from(&args.identity);
+        provider.connect_timeout = Duration::from_secs(args.ssh_connect_timeout);
+        provider.sudo_password = resolve_sudo_password(args, &provider)?;
+        provider.resume = args.resume;
+        provider.label = Some(provider.host.clone());
+        providers.push(provider);
+    }
+
+    say!(
+        "\n{} Provisioning {} hosts via SSH ({} at a time)\n",
+        style("*").cyan(),
+        providers.len(),
+        MAX_PARALLEL_HOSTS.min(providers.len())
+    );
+
+    // Bounded pool: workers pull the next host index until all are done
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<Result<ProvisionReport>>>> =
+        Mutex::new(providers.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..MAX_PARALLEL_HOSTS.min(providers.len()) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(provider) = providers.get(index) else {
+                        break;
+                    };
+                    let result = provider.provision(config, &manifest);
+                    results.lock().expect("results lock poisoned")[index] = Some(result);
+                }
+            });
+        }
+    });
+    let results = results.into_inner().expect("results lock poisoned");
+
+    let mut table = theme::table();
+    table.set_header(vec!["Host", "Result"]);
+    let mut failed = 0;
+    let mut reports = Vec::new();
+    let mut json_hosts = Vec::new();
+    for (host, result) in args.hosts.iter().zip(results) {
+        let cell = match result {
+            Some(Ok(report)) => {
+                let cell = Cell::new(format!(
+                    "provisioned ({} applied, {} skipped, {:.0}s)",
+                    report.count(StepStatus::Applied),
+                    report.count(StepStatus::Skipped),
+                    report.duration.as_secs_f64()
+                ))
+                .fg(Color::Green);
+                json_hosts.push(serde_json::json!({
+                    "host": host,
+                    "status": "provisioned",
+                    "report": report,
+                }));
+                reports.push(report);
+                cell
+            }
+            Some(Err(e)) => {
+                failed += 1;
+                json_hosts.push(serde_json::json!({
+                    "host": host,
+                    "status": "failed",
+                    "error": format!("{e:#}"),
+                }));
+                Cell::new(format!("failed: {e}")).fg(Color::Red)
+            }
+            None => {
+                failed += 1;
+                json_hosts.push(serde_json::json!({"host": host, "status": "not-run"}));
+                Cell::new("not run").fg(Color::Red)
+            }
+        };
+        table.add_row(vec![Cell::new(host), cell]);
+    }
+    if output::json() {
+        output::emit(&serde_json::json!({"type": "hosts", "hosts": json_hosts}))?;
+    } else {
+        say!("\n{table}");
+    }
+
+    if let Some(path) = &args.report {
+        write_report(path, &reports)?;
+    }
+
+    if failed > 0 {
+        bail!("{failed} of {} hosts failed to provision", providers.len());
+    }
+    Ok(())
+}
+
+/// Write a provisioning report (or several) as pretty JSON
+fn write_report(path: &Path, report: &impl Serialize) -> Result<()> {
+    let json = serde_json::to_string_pretty(report).context("Failed to serialize report")?;
+    fs::write(path, json + "\n")
+        .with_context(|| format!("Failed to write report: {}", path.display()))?;
+    say!("{} Report written to {}", style("*").cyan(), path.display());
+    Ok(())
+}
+
+/// Print step counts and timings from a provisioning report
+///
+/// Skipped with `--json`, where the success object carries the report.
+fn print_report_summary(report: &ProvisionReport) {
+    if output::json() {
+        return;
+    }
+    let mut table = theme::table();
+    table.set_header(vec!["Provisioning", ""]);
+    table.add_row(vec![
+        Cell::new("Applied"),
+        Cell::new(report.count(StepStatus::Applied)).fg(Color::Green),
+    ]);
+    table.add_row(vec![
+        Cell::new("Skipped"),
+        Cell::new(report.count(StepStatus::Skipped)).fg(Color::Yellow),
+    ]);
+    let warned = report.count(StepStatus::Warned);
+    if warned > 0 {
+        table.add_row(vec![
+            Cell::new("Failed (allowed)"),
+            Cell::new(warned).fg(Color::Yellow),
+        ]);
+    }
+    let failed = report.count(StepStatus::Failed);
+    if failed > 0 {
+        table.add_row(vec![Cell::new("Failed"), Cell::new(failed).fg(Color::Red)]);
+    }
+    if let Some(slowest) = report.steps.iter().max_by_key(|s| s.duration) {
+        table.add_row(vec![
+            Cell::new("Slowest step"),
+            Cell::new(format!(
+                "{} ({:.1}s)",
+                slowest.description,
+                slowest.duration.as_secs_f64()
+            )),
+        ]);
+    }
+    let attempts = if report.attempts > 1 {
+        format!(" ({} attempts)", report.attempts)
+    } else {
+        String::new()
+    };
+    table.add_row(vec![
+        Cell::new("Total time"),
+        Cell::new(format!("{:.0}s{attempts}", report.duration.as_secs_f64())),
+    ]);
+    say!("\n{table}");
+}
+
+/// Build the `TenguConfig` for provisioning from resolved values, CLI flags
+/// and the config file
+fn provision_tengu_config(
+    resolved: &ResolvedConfig,
+    args: &Args,
+    file_config: &Config,
+) -> TenguConfig {
+    TenguConfig::builder()
+        .user(&resolved.admin_user)
+        .domain_platform(&resolved.domain_platform)
+        .domain_apps(&resolved.domain_apps)
+        .tls_mode(resolved.tls_mode.clone())
+        .resend_api_key(&resolved.resend_api_key)
+        .notify_email(&resolved.notify_email)
+        .ssh_keys(vec![resolved.ssh_key.clone()])
+        .release(&resolved.release)
+        .timezone(&resolved.timezone)
+        .locale(&resolved.locale)
+        .enable_ufw(args.ufw)
+        .auto_upgrades(args.auto_upgrades)
+        .auto_swap(args.auto_swap)
+        .deb_path(args.deb_path.as_ref().map(|p| p.display().to_string()))
+        .files(file_config.files.clone())
+        .user_groups(file_config.server.admin_groups.clone())
+        .tengu_repo(file_config.server.tengu_repo())
+        .tengu_caddy_repo(file_config.server.tengu_caddy_repo())
+        .mirror(file_config.server.mirror.clone())
+        .users(file_config.users.clone())
+        .ollama_models(file_config.ollama.models.clone())
+        .extra_runcmd(file_config.extra_runcmd.clone())
+        .extra_bootcmd(file_config.extra_bootcmd.clone())
+        .build()
+}
+
+/// Build the Tengu manifest, restricted to the `--only`/`--skip` phases and
+/// `--tag`/`--exclude-tag` tags
+///
+/// With `--packages-only` the remaining package installs are batched into one step.
+fn build_manifest(config: &TenguConfig, args: &Args) -> Result<Manifest> {
+    let manifest = Manifest::tengu(config)?
+        .filter_phases(&args.only, &args.skip)
+        .filter_tags(&args.tags, &args.exclude_tags);
+    Ok(if args.packages_only {
+        manifest.packages_only()
+    } else {
+        manifest.coalesce_apt_updates()
+    })
+}
+
+/// [`build_manifest`] for an unprivileged LXC container
+///
+/// The Storage phase loop-mounts an XFS image for Docker, which an
+/// unprivileged container can't do; Docker uses the container's rootfs.
+fn lxc_manifest(config: &TenguConfig, args: &Args) -> Result<Manifest> {
+    Ok(build_manifest(config, args)?.filter_phases(&[], &[Phase::Storage]))
+}
+
+/// Build a `TenguConfig` from the config file alone, with placeholders for secrets
+///
+/// Fails if the configured SSH public key is malformed.
+fn show_tengu_config(config: &Config) -> Result<TenguConfig> {
+    if let Some(key) = &config.ssh.public_key {
+        validate_ssh_public_key(key)?;
+    }
+    Ok(TenguConfig::builder()
         .user(
             config
                 .server
@@ -1268,14 +2031,7 @@ fn run_show(config: &Config) -> Result<()> {
                 .clone()
                 .unwrap_or_else(|| "admin@example.com".to_string()),
         )
-        .ssh_keys(
-            config
-                .ssh
-                .public_key
-                .clone()
-                .map(|k| vec![k])
-                .unwrap_or_default(),
-        )
+        .ssh_keys(config.ssh.public_key.clone())
         .release(
             config
                 .server
@@ -1283,44 +2039,850 @@ fn run_show(config: &Config) -> Result<()> {
                 .clone()
                 .unwrap_or_else(|| DEFAULT_RELEASE.to_string()),
         )
+        .timezone(
+            config
+                .server
+                .timezone
+                .clone()
+                .unwrap_or_else(|| DEFAULT_TIMEZONE.to_string()),
+        )
+        .locale(
+            config
+                .server
+                .locale
+                .clone()
+                .unwrap_or_else(|| DEFAULT_LOCALE.to_string()),
+        )
         .enable_ufw(false)
-        .build();
+        .files(config.files.clone())
+        .user_groups(config.server.admin_groups.clone())
+        .tengu_repo(config.server.tengu_repo())
+        .tengu_caddy_repo(config.server.tengu_caddy_repo())
+        .mirror(config.server.mirror.clone())
+        .users(config.users.clone())
+        .ollama_models(config.ollama.models.clone())
+        .extra_runcmd(config.extra_runcmd.clone())
+        .extra_bootcmd(config.extra_bootcmd.clone())
+        .build())
+}
+
+/// Render the manifest in the requested format
+fn render_output(
+    format: OutputFormat,
+    config: &TenguConfig,
+    manifest: &Manifest,
+    redact: bool,
+    cloud_init: &CloudInitOptions,
+) -> Result<String> {
+    match format {
+        OutputFormat::Bash => BashRenderer::new()
+            .verbose(true)
+            .color(true)
+            .redact(redact)
+            .render(manifest)
+            .context("Failed to render bash script"),
+        OutputFormat::CloudInit => {
+            let mut renderer = CloudInitRenderer::new()
+                .with_config(config)
+                .redact(redact)
+                .package_update(cloud_init.update)
+                .package_upgrade(cloud_init.upgrade);
+            if let Some(message) = &cloud_init.final_message {
+                renderer = renderer.final_message(message);
+            }
+            renderer
+                .render(manifest)
+                .context("Failed to render cloud-init config")
+        }
+        OutputFormat::Json => JsonRenderer::new()
+            .redact(redact)
+            .render(manifest)
+            .context("Failed to render JSON manifest"),
+        OutputFormat::Dockerfile => DockerfileRenderer::new()
+            .redact(redact)
+            .render(manifest)
+            .context("Failed to render Dockerfile"),
+        OutputFormat::Plan => PlanRenderer::new()
+            .color(console::colors_enabled())
+            .redact(redact)
+            .render(manifest)
+            .context("Failed to render plan"),
+    }
+}
 
-    let manifest = Manifest::tengu(&tengu_config);
-    let renderer = BashRenderer::new().verbose(true).color(true);
-    let script = renderer
-        .render(&manifest)
-        .map_err(|e| anyhow::anyhow!("Failed to render bash script: {e:?}"))?;
-    println!("{script}");
+/// First-boot behavior for cloud-init output
+#[derive(Debug, Clone)]
+struct CloudInitOptions {
+    update: bool,
+    upgrade: bool,
+    final_message: Option<String>,
+}
 
-    Ok(())
+impl CloudInitOptions {
+    /// CLI `--no-package-*` flags > config file > enabled
+    fn resolve(args: &Args, config: &Config) -> Self {
+        let resolve =
+            |disabled: bool, configured: Option<bool>| !disabled && configured.unwrap_or(true);
+        Self {
+            update: resolve(args.no_package_update, config.cloud_init.package_update),
+            upgrade: resolve(args.no_package_upgrade, config.cloud_init.package_upgrade),
+            final_message: config.cloud_init.final_message.clone(),
+        }
+    }
 }
 
-/// Print success for SSH provisioning
-fn print_provision_success(config: &TenguConfig) {
-    println!();
+/// Run show command - displays the generated provisioning script
+fn run_show(config: &Config, args: &Args, format: OutputFormat, redact: bool) -> Result<()> {
+    let tengu_config = show_tengu_config(config)?;
+    let manifest = build_manifest(&tengu_config, args)?;
     println!(
         "{}",
-        style("+=======================================+")
-            .green()
-            .bold()
+        render_output(
+            format,
+            &tengu_config,
+            &manifest,
+            redact,
+            &CloudInitOptions::resolve(args, config)
+        )?
     );
-    println!(
-        "{}",
-        style("|            SERVER READY!              |")
-            .green()
+
+    Ok(())
+}
+
+/// Run validate command - reports steps ordered before their dependencies
+fn run_validate(config: &Config, args: &Args) -> Result<()> {
+    let tengu_config = show_tengu_config(config)?;
+    let manifest = build_manifest(&tengu_config, args)?;
+    let issues = manifest.validate_ordering();
+
+    if issues.is_empty() {
+        say!("{CHECK}{} steps, no ordering issues", manifest.step_count());
+        return Ok(());
+    }
+
+    for issue in &issues {
+        say!("{} {issue}", style(CROSS).red());
+    }
+    bail!("{} ordering issue(s) found", issues.len());
+}
+
+/// Run export command - writes rendered output to a file
+fn run_export(config: &Config, args: &Args, export: &ExportArgs) -> Result<()> {
+    let tengu_config = show_tengu_config(config)?;
+    let manifest = build_manifest(&tengu_config, args)?;
+    let output = render_output(
+        export.format,
+        &tengu_config,
+        &manifest,
+        false,
+        &CloudInitOptions::resolve(args, config),
+    )?;
+
+    fs::write(&export.output, output)
+        .with_context(|| format!("Failed to write {}", export.output.display()))?;
+
+    #[cfg(unix)]
+    if export.format == OutputFormat::Bash {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&export.output, fs::Permissions::from_mode(0o755))
+            .with_context(|| format!("Failed to chmod {}", export.output.display()))?;
+    }
+
+    let path = fs::canonicalize(&export.output)?;
+    say!("{CHECK} Wrote {}", path.display());
+
+    Ok(())
+}
+
+/// Run config init - prompts for settings and writes the config file
+#[allow(clippy::too_many_lines)]
+fn run_config_init(path: &Path, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        bail!(
+            "Config already exists: {}\n  Use --force to overwrite it.",
+            path.display()
+        );
+    }
+
+    say!(
+        "\n{}",
+        style(format!("--- Tengu Init {DASH} Configuration ---"))
+            .cyan()
             .bold()
     );
-    println!(
-        "{}",
-        style("+=======================================+")
-            .green()
-            .bold()
+
+    let cf_email: String = Input::new()
+        .with_prompt("Cloudflare email")
+        .validate_with(validate_email)
+        .interact_text()
+        .context("Failed to read Cloudflare email")?;
+    let cf_api_key = Password::new()
+        .with_prompt("Cloudflare API key")
+        .interact()
+        .context("Failed to read Cloudflare API key")?;
+    let resend_api_key = Password::new()
+        .with_prompt("Resend API key")
+        .interact()
+        .context("Failed to read Resend API key")?;
+
+    let domain_platform: String = Input::new()
+        .with_prompt("Platform domain")
+        .default("tengu.to".into())
+        .interact_text()
+        .context("Failed to read platform domain")?;
+    let domain_apps: String = Input::new()
+        .with_prompt("Apps domain")
+        .default("tengu.host".into())
+        .interact_text()
+        .context("Failed to read apps domain")?;
+
+    let prompt = Input::<String>::new()
+        .with_prompt("SSH public key")
+        .validate_with(validate_ssh_key);
+    let prompt = match detect_ssh_key() {
+        Some(key) => prompt.default(key),
+        None => prompt,
+    };
+    let ssh_key = prompt
+        .interact_text()
+        .context("Failed to read SSH public key")?;
+
+    let notify_email: String = Input::new()
+        .with_prompt("Notification email")
+        .default(cf_email.clone())
+        .validate_with(validate_email)
+        .interact_text()
+        .context("Failed to read notification email")?;
+
+    say!("\n{}", style("Server defaults").bold());
+    let admin_user: String = Input::new()
+        .with_prompt("Admin username")
+        .default("tengu".into())
+        .interact_text()
+        .context("Failed to read admin username")?;
+    let release: String = Input::new()
+        .with_prompt("Tengu release")
+        .default(DEFAULT_RELEASE.into())
+        .interact_text()
+        .context("Failed to read release tag")?;
+    let server_type: String = Input::new()
+        .with_prompt("Hetzner server type")
+        .default("cax41".into())
+        .interact_text()
+        .context("Failed to read server type")?;
+    let location: String = Input::new()
+        .with_prompt("Hetzner location")
+        .default("hel1".into())
+        .interact_text()
+        .context("Failed to read location")?;
+
+    let config = Config {
+        server: ServerConfig {
+            name: Some("tengu".into()),
+            server_type: Some(server_type),
+            location: Some(location),
+            image: Some("ubuntu-24.04".into()),
+            release: Some(release),
+            admin_user: Some(admin_user),
+            admin_groups: None,
+            tengu_repo: None,
+            tengu_caddy_repo: None,
+            mirror: None,
+            volume_size: None,
+            timezone: None,
+            locale: None,
+        },
+        mode: ModeConfig {
+            tls: Some("cloudflare".into()),
+            acme_email: None,
+        },
+        domains: DomainsConfig {
+            platform: Some(domain_platform),
+            apps: Some(domain_apps),
+        },
+        cloudflare: CloudflareConfig {
+            api_key: Some(cf_api_key),
+            email: Some(cf_email),
+            api_token: None,
+        },
+        resend: ResendConfig {
+            api_key: Some(resend_api_key),
+        },
+        ssh: SshConfig {
+            public_key: Some(ssh_key),
+        },
+        notifications: NotificationsConfig {
+            email: Some(notify_email),
+        },
+        cloud_init: CloudInitConfig::default(),
+        ollama: OllamaConfig::default(),
+        files: vec![],
+        users: vec![],
+        extra_runcmd: vec![],
+        extra_bootcmd: vec![],
+    };
+    let content = format!(
+        "# Tengu Init configuration\n# Generated by `tengu-init config init`\n\n{}",
+        toml::to_string_pretty(&config).context("Failed to serialize config")?
+    );
+
+    write_private_file(path, &content)?;
+    say!("\n{CHECK} Wrote {}", path.display());
+
+    Ok(())
+}
+
+/// Write a file readable only by the owner (it holds API keys)
+fn write_private_file(path: &Path, content: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create {}", parent.display()))?;
+    }
+
+    // Create with 0600 rather than chmod after the fact
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    #[cfg(unix)]
+    {
+        // mode() only applies to newly created files
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .with_context(|| format!("Failed to chmod {}", path.display()))?;
+    }
+    std::io::Write::write_all(&mut file, content.as_bytes())
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+
+    Ok(())
+}
+
+/// Run diff command - runs each step's check on a server and reports the result
+fn run_diff(config: &Config, args: &Args, host: &str) -> Result<()> {
+    let tengu_config = show_tengu_config(config)?;
+    let manifest = build_manifest(&tengu_config, args)?;
+
+    let mut provider = SshProvider::new(host, args.port);
+    provider.identity.clone_from(&args.identity);
+    provider.connect_timeout = Duration::from_secs(args.ssh_connect_timeout);
+    provider.sudo_password = resolve_sudo_password(args, &provider)?;
+    let mut statuses = provider.check_steps(&manifest)?;
+    // Secrets are placeholders here, so a step writing one can't match the
+    // server's copy; its check result says nothing
+    for ((_, step), status) in manifest.steps.iter().zip(statuses.iter_mut()) {
+        if *status != CheckStatus::AlwaysRuns
+            && step
+                .to_bash()
+                .iter()
+                .any(|cmd| manifest.secrets.iter().any(|secret| cmd.contains(secret)))
+        {
+            *status = CheckStatus::Unknown;
+        }
+    }
+    let pending = statuses
+        .iter()
+        .filter(|s| **s == CheckStatus::WouldApply)
+        .count();
+
+    if output::json() {
+        let steps: Vec<_> = manifest
+            .steps
+            .iter()
+            .zip(&statuses)
+            .enumerate()
+            .map(|(i, ((_, step), status))| {
+                serde_json::json!({
+                    "step": i + 1,
+                    "description": step.description(),
+                    "status": status,
+                })
+            })
+            .collect();
+        return output::emit(&serde_json::json!({
+            "type": "diff",
+            "host": host,
+            "steps": steps,
+            "would_apply": pending,
+        }));
+    }
+
+    let mut table = theme::table();
+    table.set_header(vec!["#", "Step", "Status"]);
+    for (i, ((_, step), status)) in manifest.steps.iter().zip(&statuses).enumerate() {
+        let status = match status {
+            CheckStatus::Satisfied => Cell::new("satisfied").fg(Color::Green),
+            CheckStatus::WouldApply => Cell::new("would-apply").fg(Color::Yellow),
+            CheckStatus::AlwaysRuns => Cell::new("always runs").fg(Color::Cyan),
+            CheckStatus::Unknown => Cell::new("unknown (secret)").fg(Color::DarkGrey),
+        };
+        table.add_row(vec![
+            Cell::new(i + 1),
+            Cell::new(step.description()),
+            status,
+        ]);
+    }
+    say!("{table}");
+
+    say!(
+        "\n{} {pending} of {} steps would apply",
+        style("*").cyan(),
+        statuses.len()
+    );
+
+    Ok(())
+}
+
+/// Run apply-local command - provisions this machine directly, without SSH
+fn run_apply_local(file_config: &Config, args: &Args, continue_on_error: bool) -> Result<()> {
+    let resolved = resolve_config(args, file_config)?;
+    let tengu_config = provision_tengu_config(&resolved, args, file_config);
+    let manifest = build_manifest(&tengu_config, args)?;
+
+    // The manifest installs a local .deb from where the SSH provider uploads it
+    if let Some(deb_path) = &args.deb_path {
+        fs::copy(deb_path, "/root/tengu-local.deb")
+            .with_context(|| format!("Failed to copy {}", deb_path.display()))?;
+    }
+
+    let total = manifest.step_count();
+    let results = LocalExecutor::new()
+        .continue_on_error(continue_on_error)
+        .run_with(&manifest, |i, desc, result| {
+            let n = format!("[{}/{total}]", i + 1);
+            match result {
+                StepResult::Applied => say!("{} {} {desc}", style("v").green(), style(n).dim()),
+                StepResult::Skipped => {
+                    say!(
+                        "{} {} {desc} {}",
+                        style("-").dim(),
+                        style(n).dim(),
+                        style("(satisfied)").dim()
+                    );
+                }
+                StepResult::Failed(err) => {
+                    say!("{} {} {desc}", style("x").red(), style(n).dim());
+                    for line in err.lines() {
+                        say!("    {}", style(line).dim());
+                    }
+                }
+            }
+        });
+
+    if output::json() {
+        let steps: Vec<_> = results
+            .iter()
+            .enumerate()
+            .map(|(i, (desc, result))| {
+                let (status, error) = match result {
+                    StepResult::Applied => ("applied", None),
+                    StepResult::Skipped => ("skipped", None),
+                    StepResult::Failed(err) => ("failed", Some(err)),
+                };
+                serde_json::json!({
+                    "step": i + 1,
+                    "description": desc,
+                    "status": status,
+                    "error": error,
+                })
+            })
+            .collect();
+        output::emit(&serde_json::json!({"type": "apply-local", "steps": steps}))?;
+    }
+
+    let failed = results
+        .iter()
+        .filter(|(_, r)| matches!(r, StepResult::Failed(_)))
+        .count();
+    if failed > 0 {
+        bail!("{failed} step(s) failed");
+    }
+
+    say!("\n{CHECK}{} steps complete", results.len());
+    Ok(())
+}
+
+/// Run status command - reports server health, failing if a core service is down
+fn run_status(config: &Config, args: &Args, host: Option<&str>, hetzner: bool) -> Result<()> {
+    let hetzner_line = if hetzner {
+        Hetzner::ensure_cli_available()?;
+        let name = resolve_hetzner_params(args, config).name;
+        let line = Hetzner::server_status_line(&name)?;
+        Some((name, line))
+    } else {
+        None
+    };
+    let host = match (host, &hetzner_line) {
+        (Some(host), _) => host.to_string(),
+        (None, Some((name, _))) => format!("root@{}", Hetzner::server_ip(name)?),
+        (None, None) => bail!("Missing SSH destination: tengu-init status user@host"),
+    };
+
+    let mut provider = SshProvider::new(&host, args.port);
+    provider.identity.clone_from(&args.identity);
+    provider.connect_timeout = Duration::from_secs(args.ssh_connect_timeout);
+    let status: ServerStatus = provider.status()?;
+    let down = status.down_services();
+
+    if output::json() {
+        let services: Vec<_> = status
+            .services
+            .iter()
+            .map(|(name, state)| {
+                serde_json::json!({
+                    "name": name,
+                    "state": state,
+                    "core": CORE_SERVICES.contains(&name.as_str()),
+                })
+            })
+            .collect();
+        output::emit(&serde_json::json!({
+            "type": "status",
+            "host": host,
+            "hetzner": hetzner_line.as_ref().map(|(_, line)| line),
+            "cloud_init": status.cloud_init,
+            "services": services,
+            "disk": status.disk,
+            "memory": status.memory,
+            "tengu_version": status.tengu_version,
+            "down": down,
+        }))?;
+    } else {
+        print_status_table(
+            hetzner_line.as_ref().map(|(_, line)| line.as_str()),
+            &status,
+        );
+    }
+
+    if !down.is_empty() {
+        bail!("core service(s) down on {host}: {}", down.join(", "));
+    }
+    Ok(())
+}
+
+/// Print the human-readable `status` table
+fn print_status_table(hetzner_line: Option<&str>, status: &ServerStatus) {
+    if let Some(line) = hetzner_line {
+        say!("{} {line}", style("Hetzner").bold());
+    }
+
+    let mut table = theme::table();
+    table.set_header(vec!["Check", "Status"]);
+    let cloud_init = match status.cloud_init.as_str() {
+        "" => Cell::new("not installed").fg(Color::DarkGrey),
+        "done" => Cell::new("done").fg(Color::Green),
+        "error" => Cell::new("error").fg(Color::Red),
+        other => Cell::new(other).fg(Color::Yellow),
+    };
+    table.add_row(vec![Cell::new("cloud-init"), cloud_init]);
+    for (service, state) in &status.services {
+        let color = if state == "active" {
+            Color::Green
+        } else if CORE_SERVICES.contains(&service.as_str()) {
+            Color::Red
+        } else {
+            Color::DarkGrey
+        };
+        table.add_row(vec![Cell::new(service), Cell::new(state).fg(color)]);
+    }
+    table.add_row(vec![Cell::new("disk (/)"), Cell::new(&status.disk)]);
+    table.add_row(vec![Cell::new("memory"), Cell::new(&status.memory)]);
+    table.add_row(vec![
+        Cell::new("tengu version"),
+        status
+            .tengu_version
+            .as_deref()
+            .map_or_else(|| Cell::new("not installed").fg(Color::Red), Cell::new),
+    ]);
+    say!("{table}");
+}
+
+/// Create a Proxmox container and run the provisioning script in it via `pct exec`
+#[allow(clippy::too_many_lines)]
+fn run_proxmox(file_config: &Config, args: &Args, proxmox: &ProxmoxArgs) -> Result<()> {
+    let resolved = resolve_config(args, file_config)?;
+    let tengu_config = provision_tengu_config(&resolved, args, file_config);
+
+    let hostname = proxmox
+        .hostname
+        .clone()
+        .or_else(|| file_config.server.name.clone())
+        .unwrap_or_else(|| "tengu".to_string());
+    let params = ContainerParams {
+        vmid: proxmox.vmid,
+        hostname: &hostname,
+        template: &proxmox.template,
+        storage: &proxmox.storage,
+        disk_gb: proxmox.disk,
+        memory_mb: proxmox.memory,
+        cores: proxmox.cores,
+        bridge: &proxmox.bridge,
+    };
+
+    print_banner();
+    print_provision_config_table(&resolved)?;
+
+    if proxmox.dry_run {
+        say!("\n{} Dry run - not creating container", style("i").cyan());
+        say!("\nWould run:");
+        say!(
+            "  {}  {}",
+            providers::format_command(&Proxmox::delete_container_command(proxmox.vmid)),
+            style("# only if the container already exists").dim()
+        );
+        say!(
+            "  {}",
+            providers::format_command(&Proxmox::create_container_command(
+                &params,
+                "<ssh-key-file>"
+            ))
+        );
+        return Ok(());
+    }
+
+    if !args.yes {
+        let confirm = dialoguer::Confirm::new()
+            .with_prompt(format!(
+                "Create container {} ({hostname})? This will install Tengu PaaS and all dependencies",
+                proxmox.vmid
+            ))
+            .default(false)
+            .interact()?;
+
+        if !confirm {
+            say!("Aborted.");
+            return Ok(());
+        }
+    }
+
+    if Proxmox::container_exists(proxmox.vmid)? {
+        say!(
+            "\n{} Container {} already exists",
+            style("!").yellow(),
+            proxmox.vmid
+        );
+
+        if !proxmox.force {
+            let confirm = dialoguer::Confirm::new()
+                .with_prompt("Delete and recreate?")
+                .default(false)
+                .interact()?;
+
+            if !confirm {
+                say!("Aborted.");
+                return Ok(());
+            }
+        }
+
+        Proxmox::delete_container(proxmox.vmid)?;
+    }
+
+    say!("\n{ROCKET} Creating container...");
+    Proxmox::create_container(&params, &resolved.ssh_key)?;
+    let ip = Proxmox::wait_for_ip(proxmox.vmid)?;
+    say!("  {} IP: {}", style("->").dim(), style(&ip).cyan());
+
+    if let Some(deb_path) = &tengu_config.deb_path {
+        Proxmox::push_file(proxmox.vmid, deb_path, "/root/tengu-local.deb")?;
+    }
+
+    say!(
+        "\n{} Provisioning container {} via pct exec\n",
+        style("*").cyan(),
+        proxmox.vmid
+    );
+    let script = SshProvider::generate_script(&lxc_manifest(&tengu_config, args)?)?;
+    Proxmox::run_script(proxmox.vmid, &script)?;
+
+    match &resolved.tls_mode {
+        TlsMode::Cloudflare { .. } => {
+            // The container has the operator's key for root, so the tunnel
+            // is set up over SSH like any other server
+            let tunnel_config = TunnelConfig {
+                domain_platform: resolved.domain_platform.clone(),
+                domain_apps: resolved.domain_apps.clone(),
+                tunnel_name: "tengu".to_string(),
+            };
+            providers::clear_host_key(&ip);
+            let mut provider = SshProvider::new(&format!("root@{ip}"), 22);
+            provider.new_server = true;
+            provider.identity.clone_from(&args.identity);
+            provider.connect_timeout = Duration::from_secs(args.ssh_connect_timeout);
+            provider.setup_tunnel(&tunnel_config)?;
+
+            if let (Some(key), Some(email)) = (&resolved.cf_api_key, &resolved.cf_email) {
+                update_wildcard_dns_tunnel(
+                    email,
+                    key,
+                    &resolved.domain_apps,
+                    &tunnel_config.tunnel_name,
+                )?;
+            }
+        }
+        TlsMode::Direct { .. } => {
+            say!(
+                "\n{} Point api.{}, docs.{} and *.{} at {} (or forward ports 80/443 to it)",
+                style("!").yellow(),
+                resolved.domain_platform,
+                resolved.domain_platform,
+                resolved.domain_apps,
+                style(&ip).cyan()
+            );
+        }
+    }
+
+    print_provision_success(&tengu_config, Some(&ip), None)
+}
+
+/// Launch an EC2 instance whose cloud-init user data provisions it on first boot
+#[allow(clippy::too_many_lines)]
+fn run_aws(file_config: &Config, args: &Args, aws: &AwsArgs) -> Result<()> {
+    let resolved = resolve_config(args, file_config)?;
+    let tengu_config = provision_tengu_config(&resolved, args, file_config);
+    let user_data = render_output(
+        OutputFormat::CloudInit,
+        &tengu_config,
+        &build_manifest(&tengu_config, args)?,
+        false,
+        &CloudInitOptions::resolve(args, file_config),
+    )?;
+
+    let name = aws
+        .name
+        .clone()
+        .or_else(|| file_config.server.name.clone())
+        .unwrap_or_else(|| "tengu".to_string());
+    let params = InstanceParams {
+        name: &name,
+        instance_type: &aws.instance_type,
+        ami: &aws.ami,
+        key_name: aws.key_name.as_deref(),
+        security_group: aws.security_group.as_deref(),
+    };
+    let ec2 = Ec2::new(aws.region.clone());
+
+    print_banner();
+    print_ec2_config_table(&resolved, &ec2, &params)?;
+
+    if aws.dry_run {
+        say!("\n{} Dry run - not launching instance", style("i").cyan());
+        say!("\nWould run:");
+        say!(
+            "  {}  {}",
+            providers::format_command(&ec2.delete_server_command("<instance-id>")),
+            style("# only if an instance with this name exists").dim()
+        );
+        say!(
+            "  {}",
+            providers::format_command(&ec2.create_server_command(&params, "<user-data-file>"))
+        );
+        return Ok(());
+    }
+
+    if !args.yes {
+        let confirm = dialoguer::Confirm::new()
+            .with_prompt(format!(
+                "Launch EC2 instance {name}? This will install Tengu PaaS and all dependencies"
+            ))
+            .default(false)
+            .interact()?;
+
+        if !confirm {
+            say!("Aborted.");
+            return Ok(());
+        }
+    }
+
+    if ec2.server_exists(&name)? {
+        say!("\n{} Instance '{name}' already exists", style("!").yellow());
+
+        if !aws.force {
+            let confirm = dialoguer::Confirm::new()
+                .with_prompt("Terminate and relaunch?")
+                .default(false)
+                .interact()?;
+
+            if !confirm {
+                say!("Aborted.");
+                return Ok(());
+            }
+        }
+
+        ec2.delete_server(&name)?;
+    }
+
+    say!("\n{ROCKET} Launching instance...");
+    let instance_id = ec2.create_server(&params, &user_data)?;
+    ec2.wait_until_running(&instance_id)?;
+    let ip = ec2.server_ip(&instance_id)?;
+    say!("  {} IP: {}", style(ARROW).dim(), style(&ip).cyan());
+    providers::clear_host_key(&ip);
+
+    if output::json() {
+        return output::emit(&serde_json::json!({
+            "type": "launched",
+            "instance_id": instance_id,
+            "ip": ip,
+        }));
+    }
+
+    say!(
+        "\n{} cloud-init is provisioning the instance; follow it with:",
+        style("*").cyan()
     );
-    println!();
+    say!("  tengu-init status {}@{ip}", resolved.admin_user);
+    match &resolved.tls_mode {
+        TlsMode::Cloudflare { .. } => {
+            // cloud-init can't run the interactive tunnel login
+            say!(
+                "\n{} Once it is done, run `tengu-init {}@{ip}` to set up the Cloudflare tunnel",
+                style("!").yellow(),
+                resolved.admin_user
+            );
+        }
+        TlsMode::Direct { .. } => {
+            say!(
+                "\n{} Point api.{}, docs.{} and *.{} at {}",
+                style("!").yellow(),
+                resolved.domain_platform,
+                resolved.domain_platform,
+                resolved.domain_apps,
+                style(&ip).cyan()
+            );
+        }
+    }
+    Ok(())
+}
 
-    let mut table = Table::new();
-    table.load_preset(UTF8_FULL_CONDENSED);
+/// Print success for SSH provisioning
+fn print_provision_success(
+    config: &TenguConfig,
+    ip: Option<&str>,
+    report: Option<&ProvisionReport>,
+) -> Result<()> {
+    if output::json() {
+        return output::emit(&serde_json::json!({
+            "type": "success",
+            "ip": ip,
+            "api": format!("https://api.{}", config.domain_platform),
+            "docs": format!("https://docs.{}", config.domain_platform),
+            "apps": format!("https://<app>.{}", config.domain_apps),
+            "report": report,
+        }));
+    }
+
+    say!();
+    for line in theme::banner("SERVER READY!") {
+        say!("{}", style(line).green().bold());
+    }
+    say!();
+
+    let mut table = theme::table();
 
     table.add_row(vec![
         Cell::new("API").fg(Color::Cyan),
@@ -1335,32 +2897,46 @@ fn print_provision_success(config: &TenguConfig) {
         Cell::new(format!("https://<app>.{}", config.domain_apps)),
     ]);
 
-    println!("{table}");
-    println!();
+    say!("{table}");
+    say!();
 
-    println!("{SPARKLE} Deployment complete!");
+    say!("{SPARKLE} Deployment complete!");
+    Ok(())
 }
 
 fn print_banner() {
-    println!();
-    println!(
-        "{}",
-        style("╔═══════════════════════════════════════╗")
-            .cyan()
-            .bold()
-    );
-    println!(
-        "{}",
-        style("║          TENGU PROVISIONING           ║")
-            .cyan()
-            .bold()
-    );
-    println!(
-        "{}",
-        style("╚═══════════════════════════════════════╝")
-            .cyan()
-            .bold()
-    );
+    if output::json() {
+        return;
+    }
+    say!();
+    for line in theme::banner("TENGU PROVISIONING") {
+        say!("{}", style(line).cyan().bold());
+    }
+}
+
+/// `--json` counterpart of the config tables (API key truncated the same way)
+fn config_json(cfg: &ResolvedConfig) -> serde_json::Value {
+    let mut config = serde_json::json!({
+        "type": "config",
+        "admin_user": cfg.admin_user,
+        "resend_api_key": format!("{}...", &cfg.resend_api_key[..12.min(cfg.resend_api_key.len())]),
+        "domain_platform": cfg.domain_platform,
+        "domain_apps": cfg.domain_apps,
+        "release": cfg.release,
+        "timezone": cfg.timezone,
+        "locale": cfg.locale,
+    });
+    match &cfg.tls_mode {
+        TlsMode::Cloudflare { email, .. } => {
+            config["tls_mode"] = "cloudflare".into();
+            config["cf_email"] = email.as_str().into();
+        }
+        TlsMode::Direct { acme_email } => {
+            config["tls_mode"] = "direct".into();
+            config["acme_email"] = acme_email.as_str().into();
+        }
+    }
+    config
 }
 
 /// Add TLS mode rows to a config display table
@@ -1381,10 +2957,25 @@ fn add_tls_mode_rows(table: &mut Table, tls_mode: &TlsMode) {
 fn print_hetzner_config_table(cfg: &ResolvedConfig, hetzner: &HetznerParams) -> Result<()> {
     let type_info = Hetzner::server_type_info(&hetzner.server_type)?;
 
-    println!("\n{} Configuration\n", style("v").blue().bold());
+    if output::json() {
+        let mut config = config_json(cfg);
+        config["server"] = serde_json::json!({
+            "name": hetzner.name,
+            "type": hetzner.server_type,
+            "type_info": type_info,
+            "location": hetzner.location,
+            "image": hetzner.image,
+            "volume": hetzner.volume.as_ref().map(|v| serde_json::json!({
+                "size_gb": v.size_gb,
+                "mount_path": v.mount_path,
+            })),
+        });
+        return output::emit(&config);
+    }
+
+    say!("\n{} Configuration\n", style("v").blue().bold());
 
-    let mut table = Table::new();
-    table.load_preset(UTF8_FULL_CONDENSED);
+    let mut table = theme::table();
     table.set_header(vec![
         Cell::new("Setting").fg(Color::Cyan),
         Cell::new("Value").fg(Color::Cyan),
@@ -1397,6 +2988,73 @@ fn print_hetzner_config_table(cfg: &ResolvedConfig, hetzner: &HetznerParams) ->
     ]);
     table.add_row(vec!["Location", &hetzner.location]);
     table.add_row(vec!["Image", &hetzner.image]);
+    if let Some(volume) = &hetzner.volume {
+        table.add_row(vec![
+            "Volume",
+            &format!("{} GB at {}", volume.size_gb, volume.mount_path),
+        ]);
+    }
+    table.add_row(vec!["Admin User", &cfg.admin_user]);
+    add_tls_mode_rows(&mut table, &cfg.tls_mode);
+    table.add_row(vec![
+        "Resend",
+        &format!(
+            "{}...",
+            &cfg.resend_api_key[..12.min(cfg.resend_api_key.len())]
+        ),
+    ]);
+    table.add_row(vec![
+        "Domains",
+        &format!("{}, {}", cfg.domain_platform, cfg.domain_apps),
+    ]);
+    table.add_row(vec!["Release", &cfg.release]);
+    table.add_row(vec!["Timezone", &cfg.timezone]);
+    table.add_row(vec!["Locale", &cfg.locale]);
+
+    say!("{table}");
+    Ok(())
+}
+
+/// Print config table for EC2 flow (includes instance type info)
+fn print_ec2_config_table(cfg: &ResolvedConfig, ec2: &Ec2, params: &InstanceParams) -> Result<()> {
+    let type_info = ec2.instance_type_info(params.instance_type)?;
+    let region = ec2.region.as_deref().unwrap_or("(aws CLI default)");
+
+    if output::json() {
+        let mut config = config_json(cfg);
+        config["server"] = serde_json::json!({
+            "name": params.name,
+            "type": params.instance_type,
+            "type_info": type_info,
+            "region": ec2.region,
+            "ami": params.ami,
+            "key_name": params.key_name,
+            "security_group": params.security_group,
+        });
+        return output::emit(&config);
+    }
+
+    say!("\n{} Configuration\n", style("v").blue().bold());
+
+    let mut table = theme::table();
+    table.set_header(vec![
+        Cell::new("Setting").fg(Color::Cyan),
+        Cell::new("Value").fg(Color::Cyan),
+    ]);
+
+    table.add_row(vec!["Name", params.name]);
+    table.add_row(vec![
+        "Type",
+        &format!("{} ({})", params.instance_type, type_info),
+    ]);
+    table.add_row(vec!["Region", region]);
+    table.add_row(vec!["AMI", params.ami]);
+    if let Some(key_name) = params.key_name {
+        table.add_row(vec!["Key Pair", key_name]);
+    }
+    if let Some(group) = params.security_group {
+        table.add_row(vec!["Security Group", group]);
+    }
     table.add_row(vec!["Admin User", &cfg.admin_user]);
     add_tls_mode_rows(&mut table, &cfg.tls_mode);
     table.add_row(vec![
@@ -1411,17 +3069,22 @@ fn print_hetzner_config_table(cfg: &ResolvedConfig, hetzner: &HetznerParams) ->
         &format!("{}, {}", cfg.domain_platform, cfg.domain_apps),
     ]);
     table.add_row(vec!["Release", &cfg.release]);
+    table.add_row(vec!["Timezone", &cfg.timezone]);
+    table.add_row(vec!["Locale", &cfg.locale]);
 
-    println!("{table}");
+    say!("{table}");
     Ok(())
 }
 
 /// Print config table for baremetal/SSH flow
-fn print_provision_config_table(cfg: &ResolvedConfig) {
-    println!("\n{} Configuration\n", style("v").blue().bold());
+fn print_provision_config_table(cfg: &ResolvedConfig) -> Result<()> {
+    if output::json() {
+        return output::emit(&config_json(cfg));
+    }
+
+    say!("\n{} Configuration\n", style("v").blue().bold());
 
-    let mut table = Table::new();
-    table.load_preset(UTF8_FULL_CONDENSED);
+    let mut table = theme::table();
     table.set_header(vec![
         Cell::new("Setting").fg(Color::Cyan),
         Cell::new("Value").fg(Color::Cyan),
@@ -1441,34 +3104,33 @@ fn print_provision_config_table(cfg: &ResolvedConfig) {
         &format!("{}, {}", cfg.domain_platform, cfg.domain_apps),
     ]);
     table.add_row(vec!["Release", &cfg.release]);
+    table.add_row(vec!["Timezone", &cfg.timezone]);
+    table.add_row(vec!["Locale", &cfg.locale]);
 
-    println!("{table}");
+    say!("{table}");
+    Ok(())
 }
 
-fn print_success(cfg: &ResolvedConfig) {
-    println!();
-    println!(
-        "{}",
-        style("╔═══════════════════════════════════════╗")
-            .green()
-            .bold()
-    );
-    println!(
-        "{}",
-        style("║            SERVER READY!              ║")
-            .green()
-            .bold()
-    );
-    println!(
-        "{}",
-        style("╚═══════════════════════════════════════╝")
-            .green()
-            .bold()
-    );
-    println!();
+fn print_success(cfg: &ResolvedConfig, report: &ProvisionReport) -> Result<()> {
+    if output::json() {
+        return output::emit(&serde_json::json!({
+            "type": "success",
+            "ip": report.ip,
+            "ssh": format!("ssh {}@ssh.{}", cfg.admin_user, cfg.domain_platform),
+            "api": format!("https://api.{}", cfg.domain_platform),
+            "docs": format!("https://docs.{}", cfg.domain_platform),
+            "apps": format!("https://<app>.{}", cfg.domain_apps),
+            "report": report,
+        }));
+    }
+
+    say!();
+    for line in theme::banner("SERVER READY!") {
+        say!("{}", style(line).green().bold());
+    }
+    say!();
 
-    let mut table = Table::new();
-    table.load_preset(UTF8_FULL_CONDENSED);
+    let mut table = theme::table();
 
     table.add_row(vec![
         Cell::new("SSH").fg(Color::Cyan),
@@ -1490,8 +3152,80 @@ fn print_success(cfg: &ResolvedConfig) {
         Cell::new(format!("https://<app>.{}", cfg.domain_apps)),
     ]);
 
-    println!("{table}");
-    println!();
+    say!("{table}");
+    say!();
 
-    println!("{SPARKLE} Deployment complete!");
+    say!("{SPARKLE} Deployment complete!");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ED25519: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIENlmiADcNoVkY6XEUvbnKvZsoGs/RVQAW8NrDB8GmME test@host";
+    const ECDSA: &str = "ecdsa-sha2-nistp256 AAAAE2VjZHNhLXNoYTItbmlzdHAyNTYAAAAIbmlzdHAyNTYAAABBBERfuUlCkvnJuvp6Hd7Q4fOKelYHm+Vn7ZSqOieYzbn9EMuWVT7xAxUqtQjHQpTU9snB3+B3TY36u/ByDEPPppA=";
+
+    #[test]
+    fn test_valid_ssh_public_keys() {
+        validate_ssh_public_key(ED25519).unwrap();
+        validate_ssh_public_key(ECDSA).unwrap();
+        validate_ssh_public_key(&format!("  {ED25519}\n")).unwrap();
+    }
+
+    #[test]
+    fn test_malformed_ssh_public_keys() {
+        let err = |key: &str| validate_ssh_public_key(key).unwrap_err().to_string();
+
+        assert!(err("").contains("expected an OpenSSH public key"));
+        assert!(err("AAAAC3NzaC1lZDI1NTE5").contains("expected an OpenSSH public key"));
+        assert!(err("ssh-dss AAAAB3NzaC1kc3M=").contains("unknown key type ssh-dss"));
+        assert!(err("ssh-ed25519 not*base64").contains("not valid base64"));
+        assert!(err("ssh-ed25519 AAAA").contains("truncated"));
+        // ECDSA blob under an ed25519 prefix
+        let swapped = ECDSA.replacen("ecdsa-sha2-nistp256", "ssh-ed25519", 1);
+        assert!(err(&swapped).contains("does not match"));
+        // The offending key is named in the message
+        assert!(err("ssh-rsa garbage").contains("`ssh-rsa garbage`"));
+    }
+
+    #[test]
+    fn test_resolve_sudo_password_non_interactive() {
+        let provider = SshProvider::new("deploy@203.0.113.7", 22);
+
+        // -y never prompts (nor connects): passwordless sudo is assumed
+        let args = Args::parse_from(["tengu-init", "-y"]);
+        assert!(resolve_sudo_password(&args, &provider).unwrap().is_none());
+
+        let args = Args::parse_from(["tengu-init", "-y", "--sudo-password", "hunter2"]);
+        let password = resolve_sudo_password(&args, &provider).unwrap().unwrap();
+        assert_eq!(password.as_str(), "hunter2");
+    }
+
+    #[test]
+    fn test_show_tengu_config_validates_ssh_key() {
+        let mut config = Config::default();
+        config.ssh.public_key = Some(ED25519.to_string());
+        assert_eq!(show_tengu_config(&config).unwrap().ssh_keys, [ED25519]);
+
+        config.ssh.public_key = Some("ssh-ed25519 AAAA".to_string());
+        let err = show_tengu_config(&config).unwrap_err().to_string();
+        assert!(err.contains("truncated"));
+    }
+
+    #[test]
+    fn test_lxc_manifest_skips_storage() {
+        let args = Args::parse_from(["tengu-init"]);
+        let config = show_tengu_config(&Config::default()).unwrap();
+
+        assert!(
+            build_manifest(&config, &args)
+                .unwrap()
+                .steps_in(Phase::Storage)
+                .count()
+                > 0
+        );
+        let lxc = lxc_manifest(&config, &args).unwrap();
+        assert_eq!(lxc.steps_in(Phase::Storage).count(), 0);
+    }
 }
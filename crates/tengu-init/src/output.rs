@@ -0,0 +1,60 @@
+//! Human-readable vs `--json` output
+//!
+//! With `--json`, stdout carries one JSON object per line; banners and
+//! spinners are dropped and progress meant for people goes to stderr.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{Context, Result};
+use indicatif::ProgressBar;
+use serde::Serialize;
+
+/// Set once from `--json` at startup
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// Switch to JSON output
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+}
+
+/// Whether `--json` is set
+pub fn json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// Print a line meant for people: stdout normally, stderr with `--json`
+macro_rules! say {
+    () => {
+        $crate::output::say_line(format_args!(""))
+    };
+    ($($arg:tt)*) => {
+        $crate::output::say_line(format_args!($($arg)*))
+    };
+}
+pub(crate) use say;
+
+/// Backend of [`say!`]
+pub fn say_line(line: fmt::Arguments) {
+    if json() {
+        eprintln!("{line}");
+    } else {
+        println!("{line}");
+    }
+}
+
+/// Print `value` as one line of JSON on stdout
+pub fn emit(value: &impl Serialize) -> Result<()> {
+    let line = serde_json::to_string(value).context("Failed to serialize JSON output")?;
+    println!("{line}");
+    Ok(())
+}
+
+/// A spinner, hidden with `--json`
+pub fn spinner() -> ProgressBar {
+    if json() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new_spinner()
+    }
+}
@@ -0,0 +1,322 @@
+//! AWS EC2 provider
+//!
+//! Uses the `aws` CLI. Requires:
+//! ```sh
+//! brew install awscli
+//! aws configure
+//! ```
+//!
+//! The instance is provisioned by cloud-init from `--user-data` on first
+//! boot, so no SSH connection is needed to launch it.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+
+use crate::output;
+use crate::theme::{self, CROSS, TICK, style};
+
+/// Instance launch parameters
+pub struct InstanceParams<'a> {
+    pub name: &'a str,
+    pub instance_type: &'a str,
+    pub ami: &'a str,
+    pub key_name: Option<&'a str>,
+    pub security_group: Option<&'a str>,
+}
+
+/// Instance states that count as an existing server
+const LIVE_STATES: &str = "pending,running,stopping,stopped";
+
+/// AWS EC2 provider (via the aws CLI)
+pub struct Ec2 {
+    /// Region for every call (default: the aws CLI's configured region)
+    pub region: Option<String>,
+}
+
+impl Ec2 {
+    /// Provider for `region`, or the CLI default
+    pub fn new(region: Option<String>) -> Self {
+        Self { region }
+    }
+
+    /// `aws ec2` argv for `args`, with `--region` when set
+    fn argv(&self, args: &[&str]) -> Vec<String> {
+        let mut command: Vec<String> = ["aws", "ec2"]
+            .iter()
+            .chain(args)
+            .map(|arg| (*arg).to_string())
+            .collect();
+        if let Some(region) = &self.region {
+            command.extend(["--region".to_string(), region.clone()]);
+        }
+        command
+    }
+
+    /// Run `aws ec2 <args>` and return its trimmed stdout
+    fn run(&self, args: &[&str]) -> Result<String> {
+        let command = self.argv(args);
+        let output = Command::new(&command[0])
+            .args(&command[1..])
+            .output()
+            .context("Failed to run aws - is it installed?")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("aws ec2 {} failed: {}", args[0], stderr.trim());
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// Get instance type info (vCPUs, RAM, architecture)
+    pub fn instance_type_info(&self, instance_type: &str) -> Result<String> {
+        let output = self
+            .run(&[
+                "describe-instance-types",
+                "--instance-types",
+                instance_type,
+                "--query",
+                "InstanceTypes[0].[VCpuInfo.DefaultVCpus,MemoryInfo.SizeInMiB,ProcessorInfo.SupportedArchitectures[0]]",
+                "--output",
+                "text",
+            ])
+            .with_context(|| format!("Unknown instance type: {instance_type}"))?;
+
+        describe_instance_type(&output)
+            .with_context(|| format!("Unknown instance type: {instance_type}"))
+    }
+
+    /// ID of the live instance tagged `Name=name`, if any
+    pub fn instance_id(&self, name: &str) -> Result<Option<String>> {
+        let output = self.run(&[
+            "describe-instances",
+            "--filters",
+            &format!("Name=tag:Name,Values={name}"),
+            &format!("Name=instance-state-name,Values={LIVE_STATES}"),
+            "--query",
+            "Reservations[].Instances[].InstanceId",
+            "--output",
+            "text",
+        ])?;
+
+        Ok(output.split_whitespace().next().map(String::from))
+    }
+
+    /// Check if an instance with the given Name tag exists
+    pub fn server_exists(&self, name: &str) -> Result<bool> {
+        Ok(self.instance_id(name)?.is_some())
+    }
+
+    /// Public IPv4 of an instance
+    pub fn server_ip(&self, instance_id: &str) -> Result<String> {
+        let ip = self.run(&[
+            "describe-instances",
+            "--instance-ids",
+            instance_id,
+            "--query",
+            "Reservations[0].Instances[0].PublicIpAddress",
+            "--output",
+            "text",
+        ])?;
+
+        if ip.is_empty() || ip == "None" {
+            bail!(
+                "Instance {instance_id} has no public IP; launch it in a subnet that assigns one"
+            );
+        }
+        Ok(ip)
+    }
+
+    /// `aws` argv that [`Ec2::delete_server`] runs
+    pub fn delete_server_command(&self, instance_id: &str) -> Vec<String> {
+        self.argv(&["terminate-instances", "--instance-ids", instance_id])
+    }
+
+    /// Terminate the instance tagged `Name=name` and wait until it is gone
+    pub fn delete_server(&self, name: &str) -> Result<()> {
+        let Some(instance_id) = self.instance_id(name)? else {
+            bail!("Instance {name} not found");
+        };
+
+        let spinner = spinner(format!("Terminating {name} ({instance_id})..."));
+
+        let argv = self.delete_server_command(&instance_id);
+        let status = Command::new(&argv[0])
+            .args(&argv[1..])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .context("Failed to terminate instance")?;
+
+        if !status.success() {
+            spinner.finish_with_message(format!(
+                "{} Failed to terminate instance",
+                style(CROSS).red()
+            ));
+            bail!("Failed to terminate instance {instance_id}");
+        }
+
+        if let Err(e) = self.run(&[
+            "wait",
+            "instance-terminated",
+            "--instance-ids",
+            &instance_id,
+        ]) {
+            spinner.finish_with_message(format!("{} Instance not terminated", style(CROSS).red()));
+            return Err(e);
+        }
+
+        spinner.finish_with_message(format!("{} Terminated {name}", style(TICK).green()));
+        Ok(())
+    }
+
+    /// `aws` argv that [`Ec2::create_server`] runs
+    pub fn create_server_command(
+        &self,
+        params: &InstanceParams,
+        user_data_file: &str,
+    ) -> Vec<String> {
+        let user_data = format!("file://{user_data_file}");
+        let tags = format!(
+            "ResourceType=instance,Tags=[{{Key=Name,Value={}}}]",
+            params.name
+        );
+        let mut args = vec![
+            "run-instances",
+            "--image-id",
+            params.ami,
+            "--instance-type",
+            params.instance_type,
+            "--count",
+            "1",
+            "--user-data",
+            &user_data,
+            "--tag-specifications",
+            &tags,
+        ];
+        if let Some(key_name) = params.key_name {
+            args.extend(["--key-name", key_name]);
+        }
+        if let Some(group) = params.security_group {
+            args.extend(["--security-group-ids", group]);
+        }
+        args.extend(["--query", "Instances[0].InstanceId", "--output", "text"]);
+        self.argv(&args)
+    }
+
+    /// Launch an instance with `user_data` as its cloud-init config,
+    /// returns the instance ID
+    pub fn create_server(&self, params: &InstanceParams, user_data: &str) -> Result<String> {
+        let mut file = tempfile::Builder::new()
+            .prefix("tengu-user-data-")
+            .suffix(".yaml")
+            .tempfile()
+            .context("Failed to create user data file")?;
+        file.write_all(user_data.as_bytes())
+            .context("Failed to write user data file")?;
+
+        let spinner = spinner(format!("Launching {} on EC2...", params.name));
+
+        let argv = self.create_server_command(params, &file.path().display().to_string());
+        let output = Command::new(&argv[0])
+            .args(&argv[1..])
+            .output()
+            .context("Failed to launch instance")?;
+
+        if !output.status.success() {
+            spinner
+                .finish_with_message(format!("{} Failed to launch instance", style(CROSS).red()));
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("Failed to launch instance: {stderr}");
+        }
+
+        let instance_id = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        spinner.finish_with_message(format!(
+            "{} Instance launched ({instance_id})",
+            style(TICK).green()
+        ));
+        Ok(instance_id)
+    }
+
+    /// Block until EC2 reports the instance `running`
+    ///
+    /// `aws ec2 wait` gives up after about ten minutes.
+    pub fn wait_until_running(&self, instance_id: &str) -> Result<()> {
+        let spinner = spinner(format!("Waiting for {instance_id} to start..."));
+
+        if let Err(e) = self.run(&["wait", "instance-running", "--instance-ids", instance_id]) {
+            spinner.finish_with_message(format!("{} Instance not running", style(CROSS).red()));
+            return Err(e).context(format!(
+                "Instance {instance_id} did not reach 'running'; check the EC2 console"
+            ));
+        }
+
+        spinner.finish_with_message(format!("{} Instance running", style(TICK).green()));
+        Ok(())
+    }
+}
+
+/// Summarize `describe-instance-types` text output (`vcpus\tmib\tarch`)
+fn describe_instance_type(output: &str) -> Option<String> {
+    let mut fields = output.split_whitespace();
+    let vcpus: u32 = fields.next()?.parse().ok()?;
+    let mib: u32 = fields.next()?.parse().ok()?;
+    let arch = fields.next()?;
+
+    let ram = if mib.is_multiple_of(1024) {
+        format!("{}GB", mib / 1024)
+    } else {
+        format!("{:.1}GB", f64::from(mib) / 1024.0)
+    };
+    Some(format!("{vcpus} vCPUs, {ram} RAM, {arch}"))
+}
+
+/// Spinner in the style used by the other providers
+fn spinner(message: String) -> indicatif::ProgressBar {
+    let spinner = output::spinner();
+    spinner.set_style(theme::spinner_style("{spinner:.cyan} {msg}"));
+    spinner.set_message(message);
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    spinner
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_instance_type() {
+        assert_eq!(
+            describe_instance_type("2\t4096\tx86_64").as_deref(),
+            Some("2 vCPUs, 4GB RAM, x86_64")
+        );
+        assert_eq!(
+            describe_instance_type("2\t512\tarm64").as_deref(),
+            Some("2 vCPUs, 0.5GB RAM, arm64")
+        );
+        assert_eq!(describe_instance_type("None"), None);
+    }
+
+    #[test]
+    fn test_create_server_command() {
+        let params = InstanceParams {
+            name: "tengu",
+            instance_type: "t3.medium",
+            ami: "ami-123",
+            key_name: Some("ops"),
+            security_group: None,
+        };
+        let argv = Ec2::new(Some("eu-central-1".into()))
+            .create_server_command(&params, "/tmp/user-data.yaml");
+        let command = argv.join(" ");
+        assert!(command.starts_with("aws ec2 run-instances --image-id ami-123"));
+        assert!(command.contains("--user-data file:///tmp/user-data.yaml"));
+        assert!(command.contains("Tags=[{Key=Name,Value=tengu}]"));
+        assert!(command.contains("--key-name ops"));
+        assert!(!command.contains("--security-group-ids"));
+        assert!(command.ends_with("--region eu-central-1"));
+    }
+}
@@ -6,13 +6,16 @@
 //! hcloud context create tengu
 //! ```
 
+use std::ffi::OsStr;
+use std::io::ErrorKind;
 use std::process::{Command, Stdio};
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result, bail};
-use console::style;
-use indicatif::{ProgressBar, ProgressStyle};
+
+use crate::output::{self, say};
+use crate::theme::{self, CROSS, TICK, style};
 
 /// Server creation parameters
 pub struct ServerParams<'a> {
@@ -21,12 +24,66 @@ pub struct ServerParams<'a> {
     pub image: &'a str,
     pub location: &'a str,
     pub ssh_key_name: &'a str,
+    pub volume: Option<&'a VolumeSpec>,
+}
+
+/// Block storage volume attached to a new server
+#[derive(Debug, Clone)]
+pub struct VolumeSpec {
+    /// Size in GB (only used when the volume is created)
+    pub size_gb: u32,
+    /// Where the volume is mounted on the server
+    pub mount_path: String,
 }
 
+/// Shown when `hcloud` is not on PATH
+const CLI_MISSING: &str = "hcloud CLI not found on PATH. Install it with:
+  brew install hcloud          # macOS / Linuxbrew
+  sudo apt install hcloud-cli  # Debian / Ubuntu
+then run `hcloud context create tengu` with an API token from the Hetzner Cloud console";
+
+/// Shown when `hcloud` has no active context and no `HCLOUD_TOKEN`
+const CLI_NO_CONTEXT: &str = "hcloud has no active context. Create one with an API token from the \
+Hetzner Cloud console (Security > API tokens):
+  hcloud context create tengu
+or set HCLOUD_TOKEN";
+
+/// How long a new server may take to reach `running`
+const RUNNING_TIMEOUT: Duration = Duration::from_mins(5);
+
 /// Hetzner Cloud provider (via hcloud CLI)
 pub struct Hetzner;
 
 impl Hetzner {
+    /// Check that `hcloud` is installed and configured, with install hints if not
+    ///
+    /// Call before any other `hcloud` use; they fail without a clear cause.
+    pub fn ensure_cli_available() -> Result<()> {
+        Self::ensure_cli_in(
+            &std::env::var_os("PATH").unwrap_or_default(),
+            std::env::var_os("HCLOUD_TOKEN").is_some_and(|token| !token.is_empty()),
+        )
+    }
+
+    /// [`Hetzner::ensure_cli_available`] with `hcloud` looked up on `path`
+    fn ensure_cli_in(path: &OsStr, has_token: bool) -> Result<()> {
+        let output = match Command::new("hcloud")
+            .args(["context", "active"])
+            .env("PATH", path)
+            .output()
+        {
+            Ok(output) => output,
+            Err(e) if e.kind() == ErrorKind::NotFound => bail!(CLI_MISSING),
+            Err(e) => return Err(e).context("Failed to run hcloud"),
+        };
+
+        // A token in the environment works without any context
+        if !has_token && (!output.status.success() || output.stdout.trim_ascii().is_empty()) {
+            bail!(CLI_NO_CONTEXT);
+        }
+        Ok(())
+    }
+
     /// Get server type info (cores, RAM, architecture)
     pub fn server_type_info(server_type: &str) -> Result<String> {
         let output = Command::new("hcloud")
@@ -59,30 +116,68 @@ impl Hetzner {
         Ok(status.success())
     }
 
+    /// Public IPv4 of an existing server
+    pub fn server_ip(name: &str) -> Result<String> {
+        let output = Command::new("hcloud")
+            .args(["server", "ip", name])
+            .output()
+            .context("Failed to run hcloud - is it installed?")?;
+
+        if !output.status.success() {
+            bail!("Server {name} not found");
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// One-line summary of a server from `hcloud server describe`
+    pub fn server_status_line(name: &str) -> Result<String> {
+        let output = Command::new("hcloud")
+            .args([
+                "server",
+                "describe",
+                name,
+                "-o",
+                "format={{.Name}}: {{.Status}} ({{.ServerType.Name}}, {{.Datacenter.Name}}, {{.PublicNet.IPv4.IP}})",
+            ])
+            .output()
+            .context("Failed to run hcloud - is it installed?")?;
+
+        if !output.status.success() {
+            bail!("Server {name} not found");
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// `hcloud` argv that [`Hetzner::delete_server`] runs
+    pub fn delete_server_command(name: &str) -> Vec<String> {
+        ["hcloud", "server", "delete", name]
+            .map(String::from)
+            .to_vec()
+    }
+
     /// Delete a server by name
     pub fn delete_server(name: &str) -> Result<()> {
-        let spinner = ProgressBar::new_spinner();
-        spinner.set_style(
-            ProgressStyle::default_spinner()
-                .template("{spinner:.cyan} {msg}")
-                .unwrap(),
-        );
+        let spinner = output::spinner();
+        spinner.set_style(theme::spinner_style("{spinner:.cyan} {msg}"));
         spinner.set_message(format!("Deleting {name}..."));
         spinner.enable_steady_tick(Duration::from_millis(100));
 
-        let status = Command::new("hcloud")
-            .args(["server", "delete", name])
+        let argv = Self::delete_server_command(name);
+        let status = Command::new(&argv[0])
+            .args(&argv[1..])
             .stdout(Stdio::null())
             .stderr(Stdio::null())
             .status()
             .context("Failed to delete server")?;
 
         if !status.success() {
-            spinner.finish_with_message(format!("{} Failed to delete server", style("✗").red()));
+            spinner.finish_with_message(format!("{} Failed to delete server", style(CROSS).red()));
             bail!("Failed to delete server");
         }
 
-        spinner.finish_with_message(format!("{} Deleted {}", style("✓").green(), name));
+        spinner.finish_with_message(format!("{} Deleted {}", style(TICK).green(), name));
         thread::sleep(Duration::from_secs(2));
         Ok(())
     }
@@ -196,45 +291,122 @@ impl Hetzner {
         Ok(None)
     }
 
-    /// Create a new server, returns the IP address
+    /// Name of the data volume belonging to a server
+    pub fn volume_name(server: &str) -> String {
+        format!("{server}-data")
+    }
+
+    /// Create a volume unless one with this name already exists
     ///
-    /// Creates a plain Ubuntu server with the specified SSH key.
-    /// No cloud-init - provisioning happens via SSH after creation.
-    pub fn create_server(params: &ServerParams) -> Result<String> {
-        let spinner = ProgressBar::new_spinner();
-        spinner.set_style(
-            ProgressStyle::default_spinner()
-                .template("{spinner:.cyan} {msg}")
-                .unwrap(),
-        );
-        spinner.set_message(format!("Creating {} on Hetzner...", params.name));
-        spinner.enable_steady_tick(Duration::from_millis(100));
+    /// An existing volume (e.g. left over from a deleted server) is reused
+    /// as-is, keeping its data and size.
+    pub fn ensure_volume(name: &str, size_gb: u32, location: &str) -> Result<()> {
+        let exists = Command::new("hcloud")
+            .args(["volume", "describe", name])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .context("Failed to run hcloud")?
+            .success();
+        if exists {
+            say!("  {} Volume '{name}' exists, reusing", style("*").dim());
+            return Ok(());
+        }
 
         let output = Command::new("hcloud")
             .args([
-                "server",
+                "volume",
                 "create",
                 "--name",
-                params.name,
-                "--type",
-                params.server_type,
-                "--image",
-                params.image,
+                name,
+                "--size",
+                &size_gb.to_string(),
                 "--location",
-                params.location,
-                "--ssh-key",
-                params.ssh_key_name,
+                location,
             ])
             .output()
+            .context("Failed to create volume")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("Failed to create volume: {stderr}");
+        }
+
+        say!(
+            "  {} Volume '{name}' created ({size_gb} GB)",
+            style(TICK).green()
+        );
+        Ok(())
+    }
+
+    /// Linux device path of a volume (e.g. `/dev/disk/by-id/scsi-0HC_Volume_123`)
+    pub fn volume_device(name: &str) -> Result<String> {
+        let output = Command::new("hcloud")
+            .args(["volume", "describe", name, "-o", "format={{.LinuxDevice}}"])
+            .output()
+            .context("Failed to describe volume")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("Failed to describe volume: {stderr}");
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// `hcloud` argv that [`Hetzner::create_server`] runs
+    pub fn create_server_command(params: &ServerParams) -> Vec<String> {
+        let mut argv = vec![
+            "hcloud",
+            "server",
+            "create",
+            "--name",
+            params.name,
+            "--type",
+            params.server_type,
+            "--image",
+            params.image,
+            "--location",
+            params.location,
+            "--ssh-key",
+            params.ssh_key_name,
+        ];
+        let volume_name = Self::volume_name(params.name);
+        if params.volume.is_some() {
+            argv.extend(["--volume", volume_name.as_str()]);
+        }
+        argv.into_iter().map(String::from).collect()
+    }
+
+    /// Create a new server, returns the IP address
+    ///
+    /// Creates a plain Ubuntu server with the specified SSH key, attaching
+    /// the data volume (created first if needed) when one is requested.
+    /// No cloud-init - provisioning happens via SSH after creation.
+    pub fn create_server(params: &ServerParams) -> Result<String> {
+        let volume_name = Self::volume_name(params.name);
+        if let Some(volume) = params.volume {
+            Self::ensure_volume(&volume_name, volume.size_gb, params.location)?;
+        }
+
+        let spinner = output::spinner();
+        spinner.set_style(theme::spinner_style("{spinner:.cyan} {msg}"));
+        spinner.set_message(format!("Creating {} on Hetzner...", params.name));
+        spinner.enable_steady_tick(Duration::from_millis(100));
+
+        let argv = Self::create_server_command(params);
+        let output = Command::new(&argv[0])
+            .args(&argv[1..])
+            .output()
             .context("Failed to create server")?;
 
         if !output.status.success() {
-            spinner.finish_with_message(format!("{} Failed to create server", style("✗").red()));
+            spinner.finish_with_message(format!("{} Failed to create server", style(CROSS).red()));
             let stderr = String::from_utf8_lossy(&output.stderr);
             bail!("Failed to create server: {stderr}");
         }
 
-        spinner.finish_with_message(format!("{} Server created", style("✓").green()));
+        spinner.finish_with_message(format!("{} Server created", style(TICK).green()));
 
         // Get IP
         let output = Command::new("hcloud")
@@ -246,12 +418,93 @@ impl Hetzner {
         Ok(ip)
     }
 
-    /// Remove old SSH host key for an IP
-    pub fn clear_host_key(ip: &str) {
-        let _ = Command::new("ssh-keygen")
-            .args(["-R", ip])
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status();
+    /// Poll the server status until Hetzner reports it `running`
+    ///
+    /// Separates a server still booting from one that is up but refusing
+    /// SSH, and fails instead of waiting forever if creation stalls.
+    pub fn wait_until_running(name: &str) -> Result<()> {
+        let spinner = output::spinner();
+        spinner.set_style(theme::spinner_style("{spinner:.cyan} {msg}"));
+        spinner.set_message(format!("Waiting for {name} to start..."));
+        spinner.enable_steady_tick(Duration::from_millis(100));
+
+        let started = Instant::now();
+        let mut status = String::new();
+        loop {
+            let output = Command::new("hcloud")
+                .args(["server", "describe", name, "-o", "format={{.Status}}"])
+                .output()
+                .context("Failed to run hcloud")?;
+
+            if output.status.success() {
+                status = String::from_utf8_lossy(&output.stdout).trim().to_string();
+                if status == "running" {
+                    break;
+                }
+                spinner.set_message(format!("Waiting for {name} to start ({status})..."));
+            }
+
+            if started.elapsed() >= RUNNING_TIMEOUT {
+                spinner.finish_with_message(format!(
+                    "{} Server not running after {}s",
+                    style(CROSS).red(),
+                    RUNNING_TIMEOUT.as_secs()
+                ));
+                let last = if status.is_empty() {
+                    "unknown"
+                } else {
+                    &status
+                };
+                bail!(
+                    "Server {name} did not reach 'running' within {}s (last status: {last}); \
+                     check the Hetzner console",
+                    RUNNING_TIMEOUT.as_secs()
+                );
+            }
+
+            thread::sleep(Duration::from_secs(3));
+        }
+
+        spinner.finish_with_message(format!("{} Server running", style(TICK).green()));
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    use std::os::unix::fs::PermissionsExt;
+
+    /// A PATH holding only a fake `hcloud` running `script`
+    fn fake_hcloud(script: &str) -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        let hcloud = dir.path().join("hcloud");
+        std::fs::write(&hcloud, format!("#!/bin/sh\n{script}\n")).unwrap();
+        std::fs::set_permissions(&hcloud, std::fs::Permissions::from_mode(0o755)).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_missing_hcloud_explains_install() {
+        let empty = tempfile::tempdir().unwrap();
+        let err = Hetzner::ensure_cli_in(empty.path().as_os_str(), false).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("hcloud CLI not found"));
+        assert!(message.contains("brew install hcloud"));
+        assert!(message.contains("apt install hcloud-cli"));
+        assert!(message.contains("hcloud context create"));
+    }
+
+    #[test]
+    fn test_hcloud_without_context() {
+        let path = fake_hcloud("exit 0");
+        let err = Hetzner::ensure_cli_in(path.path().as_os_str(), false).unwrap_err();
+        assert!(err.to_string().contains("no active context"));
+        // HCLOUD_TOKEN stands in for a context
+        assert!(Hetzner::ensure_cli_in(path.path().as_os_str(), true).is_ok());
+
+        let path = fake_hcloud("echo tengu");
+        assert!(Hetzner::ensure_cli_in(path.path().as_os_str(), false).is_ok());
     }
 }
@@ -1,7 +1,37 @@
 //! Server provisioning implementations
 
+use std::process::{Command, Stdio};
+
+pub mod ec2;
 pub mod hetzner;
+pub mod proxmox;
 pub mod ssh;
 
+pub use ec2::Ec2;
 pub use hetzner::Hetzner;
-pub use ssh::{SshProvider, TunnelConfig};
+pub use proxmox::Proxmox;
+pub use ssh::{CheckStatus, ProvisionReport, ServerStatus, SshProvider, StepStatus, TunnelConfig};
+
+/// Render an argv as a copy-pasteable shell command
+pub fn format_command(argv: &[String]) -> String {
+    let safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=@,+".contains(c);
+    argv.iter()
+        .map(|arg| {
+            if !arg.is_empty() && arg.chars().all(safe) {
+                arg.clone()
+            } else {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Remove the old SSH host key for the IP of a server just (re)created
+pub fn clear_host_key(ip: &str) {
+    let _ = Command::new("ssh-keygen")
+        .args(["-R", ip])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
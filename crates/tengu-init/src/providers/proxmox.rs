@@ -0,0 +1,248 @@
+//! Proxmox VE provider
+//!
+//! Creates an LXC container with `pct` and provisions it by pushing the bash
+//! script into the container and running it with `pct exec`, so neither
+//! cloud-init nor SSH is needed. Must run on the Proxmox host itself.
+//!
+//! Only containers are supported: provisioning a `qm` VM would depend on the
+//! guest agent being installed in the template.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, bail};
+use indicatif::ProgressBar;
+
+use crate::output;
+use crate::theme::{self, CROSS, TICK, style};
+
+/// Container creation parameters
+pub struct ContainerParams<'a> {
+    pub vmid: u32,
+    pub hostname: &'a str,
+    pub template: &'a str,
+    pub storage: &'a str,
+    pub disk_gb: u32,
+    pub memory_mb: u32,
+    pub cores: u32,
+    pub bridge: &'a str,
+}
+
+/// Where the provisioning script is pushed inside the container
+const SCRIPT_PATH: &str = "/root/tengu-provision.sh";
+
+/// How long a new container may take to get an IP via DHCP
+const NETWORK_TIMEOUT: Duration = Duration::from_mins(2);
+
+/// Proxmox VE provider (via the `pct` CLI)
+pub struct Proxmox;
+
+impl Proxmox {
+    /// Check if a container with the given ID exists
+    pub fn container_exists(vmid: u32) -> Result<bool> {
+        let status = Command::new("pct")
+            .args(["status", &vmid.to_string()])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .context("Failed to run pct - is this a Proxmox host?")?;
+
+        Ok(status.success())
+    }
+
+    /// `pct` argv that [`Proxmox::delete_container`] runs
+    pub fn delete_container_command(vmid: u32) -> Vec<String> {
+        [
+            "pct",
+            "destroy",
+            &vmid.to_string(),
+            "--force",
+            "1",
+            "--purge",
+            "1",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+
+    /// Stop and destroy a container by ID
+    pub fn delete_container(vmid: u32) -> Result<()> {
+        let spinner = spinner(format!("Deleting container {vmid}..."));
+
+        let argv = Self::delete_container_command(vmid);
+        let output = Command::new(&argv[0])
+            .args(&argv[1..])
+            .output()
+            .context("Failed to delete container")?;
+
+        if !output.status.success() {
+            spinner
+                .finish_with_message(format!("{} Failed to delete container", style(CROSS).red()));
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("Failed to delete container {vmid}: {stderr}");
+        }
+
+        spinner.finish_with_message(format!("{} Deleted container {vmid}", style(TICK).green()));
+        Ok(())
+    }
+
+    /// `pct` argv that [`Proxmox::create_container`] runs
+    ///
+    /// The container is unprivileged with nesting and keyctl enabled, which
+    /// Docker needs to run inside LXC. Without loop devices it can't hold
+    /// the Docker XFS image, so the Storage phase is left out.
+    pub fn create_container_command(params: &ContainerParams, ssh_keys_file: &str) -> Vec<String> {
+        vec![
+            "pct".into(),
+            "create".into(),
+            params.vmid.to_string(),
+            params.template.into(),
+            "--hostname".into(),
+            params.hostname.into(),
+            "--cores".into(),
+            params.cores.to_string(),
+            "--memory".into(),
+            params.memory_mb.to_string(),
+            "--rootfs".into(),
+            format!("{}:{}", params.storage, params.disk_gb),
+            "--net0".into(),
+            format!("name=eth0,bridge={},ip=dhcp", params.bridge),
+            "--unprivileged".into(),
+            "1".into(),
+            "--features".into(),
+            "nesting=1,keyctl=1".into(),
+            "--onboot".into(),
+            "1".into(),
+            "--ssh-public-keys".into(),
+            ssh_keys_file.into(),
+        ]
+    }
+
+    /// Create and start a container with `ssh_key` authorized for root
+    pub fn create_container(params: &ContainerParams, ssh_key: &str) -> Result<()> {
+        let mut keys = tempfile::Builder::new()
+            .prefix("tengu-pct-keys-")
+            .tempfile()
+            .context("Failed to create SSH key file")?;
+        writeln!(keys, "{ssh_key}").context("Failed to write SSH key file")?;
+
+        let spinner = spinner(format!("Creating container {}...", params.vmid));
+
+        let argv = Self::create_container_command(params, &keys.path().display().to_string());
+        let output = Command::new(&argv[0])
+            .args(&argv[1..])
+            .output()
+            .context("Failed to create container")?;
+
+        if !output.status.success() {
+            spinner
+                .finish_with_message(format!("{} Failed to create container", style(CROSS).red()));
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("Failed to create container: {stderr}");
+        }
+
+        spinner.set_message(format!("Starting container {}...", params.vmid));
+        let output = Command::new("pct")
+            .args(["start", &params.vmid.to_string()])
+            .output()
+            .context("Failed to start container")?;
+
+        if !output.status.success() {
+            spinner
+                .finish_with_message(format!("{} Failed to start container", style(CROSS).red()));
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("Failed to start container: {stderr}");
+        }
+
+        spinner.finish_with_message(format!("{} Container created", style(TICK).green()));
+        Ok(())
+    }
+
+    /// Poll the container until DHCP has given it an IPv4 address
+    pub fn wait_for_ip(vmid: u32) -> Result<String> {
+        let spinner = spinner(format!("Waiting for container {vmid} network..."));
+
+        let started = Instant::now();
+        loop {
+            let output = Command::new("pct")
+                .args(["exec", &vmid.to_string(), "--", "hostname", "-I"])
+                .output()
+                .context("Failed to run pct")?;
+
+            if output.status.success()
+                && let Some(ip) = String::from_utf8_lossy(&output.stdout)
+                    .split_whitespace()
+                    .find(|addr| addr.contains('.'))
+            {
+                spinner.finish_with_message(format!("{} Network up", style(TICK).green()));
+                return Ok(ip.to_string());
+            }
+
+            if started.elapsed() >= NETWORK_TIMEOUT {
+                spinner.finish_with_message(format!(
+                    "{} No IP after {}s",
+                    style(CROSS).red(),
+                    NETWORK_TIMEOUT.as_secs()
+                ));
+                bail!(
+                    "Container {vmid} got no IPv4 address within {}s; check the bridge and DHCP",
+                    NETWORK_TIMEOUT.as_secs()
+                );
+            }
+
+            thread::sleep(Duration::from_secs(2));
+        }
+    }
+
+    /// Copy a local file into the container
+    pub fn push_file(vmid: u32, local: &str, remote: &str) -> Result<()> {
+        let output = Command::new("pct")
+            .args(["push", &vmid.to_string(), local, remote])
+            .output()
+            .context("Failed to run pct push")?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!("Failed to copy {local} to container {vmid}: {stderr}");
+        }
+        Ok(())
+    }
+
+    /// Push the provisioning script into the container and run it as root
+    ///
+    /// Script output is streamed to the terminal (stderr with `--json`).
+    pub fn run_script(vmid: u32, script: &str) -> Result<()> {
+        let mut file = tempfile::Builder::new()
+            .prefix("tengu-provision-")
+            .suffix(".sh")
+            .tempfile()
+            .context("Failed to create script file")?;
+        file.write_all(script.as_bytes())
+            .context("Failed to write script file")?;
+
+        Self::push_file(vmid, &file.path().display().to_string(), SCRIPT_PATH)?;
+
+        let mut command = Command::new("pct");
+        command.args(["exec", &vmid.to_string(), "--", "bash", SCRIPT_PATH]);
+        if output::json() {
+            command.stdout(std::io::stderr());
+        }
+        let status = command.status().context("Failed to run pct exec")?;
+
+        if !status.success() {
+            bail!("Provisioning script failed in container {vmid} ({status})");
+        }
+        Ok(())
+    }
+}
+
+/// Spinner in the style used by the other providers
+fn spinner(message: String) -> ProgressBar {
+    let spinner = output::spinner();
+    spinner.set_style(theme::spinner_style("{spinner:.cyan} {msg}"));
+    spinner.set_message(message);
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    spinner
+}
@@ -3,15 +3,24 @@
 //! Connects to an existing server via SSH, uploads a bash script,
 //! and executes it with real-time progress streaming.
 
+use std::collections::{HashMap, HashSet};
 use std::io::{BufRead, BufReader, Write};
 use std::path::PathBuf;
-use std::process::{Command, Stdio};
-use std::time::Duration;
+use std::process::{Child, Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result, bail};
-use console::style;
-use indicatif::{ProgressBar, ProgressStyle};
+use indicatif::ProgressBar;
+use serde::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use tempfile::TempPath;
+use tengu_provision::render::decode_marker_description;
 use tengu_provision::{BashRenderer, Manifest, Renderer, TenguConfig};
+use zeroize::Zeroizing;
+
+use crate::output::{self, say};
+use crate::theme::{self, style};
 
 /// Configuration for Cloudflare Tunnel setup
 pub struct TunnelConfig {
@@ -23,6 +32,110 @@ pub struct TunnelConfig {
     pub tunnel_name: String,
 }
 
+/// Result of running a step's `check_command()` on the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CheckStatus {
+    /// Check passed, the step would be skipped
+    Satisfied,
+    /// Check failed, the step would run
+    WouldApply,
+    /// Step has no check and runs every time
+    AlwaysRuns,
+    /// Step writes a secret that isn't known locally, so its check can't tell
+    Unknown,
+}
+
+/// Services that must be active on a healthy server
+pub const CORE_SERVICES: [&str; 4] = ["docker", "postgresql", "caddy", "tengu"];
+
+/// Default `ConnectTimeout` for SSH connection attempts
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Services reported by `status` that don't fail it
+const OTHER_SERVICES: [&str; 3] = ["ollama", "fail2ban", "cloudflared"];
+
+/// Health snapshot of a provisioned server
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerStatus {
+    /// `cloud-init status` (e.g. "done"), empty if cloud-init is absent
+    pub cloud_init: String,
+    /// Service name and its `systemctl is-active` state, core services first
+    pub services: Vec<(String, String)>,
+    /// Root filesystem usage (e.g. "12G/75G (16%)")
+    pub disk: String,
+    /// Memory usage (e.g. "1.2Gi/3.7Gi")
+    pub memory: String,
+    /// Installed tengu package version
+    pub tengu_version: Option<String>,
+}
+
+impl ServerStatus {
+    /// Core services that are not active
+    pub fn down_services(&self) -> Vec<&str> {
+        self.services
+            .iter()
+            .filter(|(name, state)| CORE_SERVICES.contains(&name.as_str()) && state != "active")
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
+
+/// How a step ended in a provisioning run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepStatus {
+    /// Step ran and succeeded
+    Applied,
+    /// Step's check passed, so it didn't run
+    Skipped,
+    /// Step ran and failed
+    Failed,
+    /// Best-effort step failed without failing the run
+    Warned,
+}
+
+/// Outcome of one step, from its progress markers
+#[derive(Debug, Clone, Serialize)]
+pub struct StepOutcome {
+    /// Step number (1-based, as in the script)
+    pub step: usize,
+    /// Step description
+    pub description: String,
+    /// How the step ended
+    pub status: StepStatus,
+    /// Time from START to DONE/FAIL (zero for skipped steps)
+    #[serde(rename = "duration_secs", serialize_with = "serialize_secs")]
+    pub duration: Duration,
+}
+
+/// Machine-readable summary of a provisioning run
+#[derive(Debug, Clone, Serialize)]
+pub struct ProvisionReport {
+    /// SSH host provisioned
+    pub host: String,
+    /// Public IP, when known (e.g. a freshly created Hetzner server)
+    pub ip: Option<String>,
+    /// Script runs needed (a failed first run is retried once)
+    pub attempts: u32,
+    /// Wall-clock time of the whole provisioning run
+    #[serde(rename = "duration_secs", serialize_with = "serialize_secs")]
+    pub duration: Duration,
+    /// Per-step outcomes of the final run, in completion order
+    pub steps: Vec<StepOutcome>,
+}
+
+impl ProvisionReport {
+    /// Number of steps that ended with `status`
+    pub fn count(&self, status: StepStatus) -> usize {
+        self.steps.iter().filter(|s| s.status == status).count()
+    }
+}
+
+fn serialize_secs<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
 /// Server provisioning via SSH
 pub struct SshProvider {
     /// SSH host
@@ -31,6 +144,23 @@ pub struct SshProvider {
     pub user: String,
     /// SSH port
     pub port: u16,
+    /// Password fed to `sudo -S` (`None` for passwordless sudo)
+    pub sudo_password: Option<Zeroizing<String>>,
+    /// SSH private key to authenticate with (default: agent/default keys)
+    pub identity: Option<PathBuf>,
+    /// Prefix for output lines when several hosts provision concurrently
+    ///
+    /// Spinners are disabled when set, since they can't share a terminal.
+    pub label: Option<String>,
+    /// Timeout for each connection attempt (`ConnectTimeout`, `ssh-keyscan -T`)
+    pub connect_timeout: Duration,
+    /// Run the script with `--resume`, skipping steps a previous run finished
+    pub resume: bool,
+    /// The server was just created by this run: pin its host key on first
+    /// contact instead of going through the user's `known_hosts`
+    pub new_server: bool,
+    /// Per-run `known_hosts` file holding the host key pinned on first contact
+    known_hosts: Mutex<Option<TempPath>>,
 }
 
 impl SshProvider {
@@ -40,7 +170,11 @@ impl SshProvider {
     /// - `hostname` (uses current username)
     /// - `user@hostname` (extracts user)
     ///
-    /// The user must have passwordless sudo access on the target server.
+    /// The host is a name or address that SSH resolves; IPv6 literals may be
+    /// given with or without brackets.
+    ///
+    /// The user must have sudo access on the target server; set
+    /// `sudo_password` if sudo is not passwordless.
     pub fn new(host: &str, port: u16) -> Self {
         let (user, hostname) = if let Some((u, h)) = host.split_once('@') {
             (u.to_string(), h.to_string())
@@ -51,21 +185,83 @@ impl SshProvider {
                 .unwrap_or_else(|_| "chi".to_string());
             (current_user, host.to_string())
         };
+        let hostname = hostname
+            .strip_prefix('[')
+            .and_then(|h| h.strip_suffix(']'))
+            .map_or(hostname.clone(), str::to_string);
 
         Self {
             host: hostname,
             user,
             port,
+            sudo_password: None,
+            identity: None,
+            label: None,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            resume: false,
+            new_server: false,
+            known_hosts: Mutex::new(None),
+        }
+    }
+
+    /// Print a progress line, prefixed with the host label if set
+    fn say(&self, msg: &str) {
+        match &self.label {
+            None => say!("{msg}"),
+            Some(label) => {
+                for line in msg.lines().filter(|l| !l.is_empty()) {
+                    say!("{} {line}", style(format!("{label} |")).dim());
+                }
+            }
+        }
+    }
+
+    /// A spinner, or a hidden bar when output is labeled
+    fn spinner(&self) -> ProgressBar {
+        if self.label.is_some() {
+            ProgressBar::hidden()
+        } else {
+            output::spinner()
+        }
+    }
+
+    /// Remote sudo invocation (`sudo -S` reading the password from stdin if set)
+    fn sudo(&self) -> &'static str {
+        if self.sudo_password.is_some() {
+            "sudo -S -p ''"
+        } else {
+            "sudo"
         }
     }
 
+    /// stdin for a command run via [`Self::sudo`]
+    fn sudo_stdin(&self) -> Stdio {
+        if self.sudo_password.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::inherit()
+        }
+    }
+
+    /// Write the sudo password to the child's stdin and close it
+    fn feed_sudo_password(&self, child: &mut Child) -> Result<()> {
+        if let (Some(password), Some(mut stdin)) = (&self.sudo_password, child.stdin.take()) {
+            let line = Zeroizing::new(format!("{}\n", password.as_str()));
+            stdin
+                .write_all(line.as_bytes())
+                .context("Failed to send sudo password")?;
+        }
+        Ok(())
+    }
+
     /// Generate the provisioning bash script
-    pub fn generate_script(config: &TenguConfig) -> Result<String> {
-        let manifest = Manifest::tengu(config);
-        let renderer = BashRenderer::new().verbose(true).color(true);
-        renderer
-            .render(&manifest)
-            .map_err(|e| anyhow::anyhow!("Failed to render script: {e:?}"))
+    pub fn generate_script(manifest: &Manifest) -> Result<String> {
+        let renderer = BashRenderer::new()
+            .verbose(true)
+            .color(true)
+            .resume_support(true)
+            .log_file("/var/log/tengu/provision.log");
+        renderer.render(manifest).context("Failed to render script")
     }
 
     /// Generate a removal script that undoes everything tengu-init installed
@@ -211,7 +407,7 @@ echo ""
         self.wait_for_ssh()?;
 
         // Upload removal script
-        println!(
+        say!(
             "{} Uploading removal script to {}...",
             style("*").cyan(),
             self.ssh_destination()
@@ -219,7 +415,7 @@ echo ""
         self.upload_removal_script(&script)?;
 
         // Execute
-        println!("{} Executing removal...\n", style("*").cyan());
+        say!("{} Executing removal...\n", style("*").cyan());
         self.execute_removal()?;
 
         // Cleanup
@@ -230,7 +426,7 @@ echo ""
 
     /// Upload the removal script
     fn upload_removal_script(&self, script: &str) -> Result<()> {
-        let mut args = self.ssh_args();
+        let mut args = self.ssh_args()?;
         args.push(self.ssh_destination());
         args.push("cat > /tmp/tengu-remove.sh && chmod +x /tmp/tengu-remove.sh".into());
 
@@ -262,23 +458,25 @@ echo ""
 
     /// Execute the removal script with live output
     fn execute_removal(&self) -> Result<()> {
-        let mut args = self.ssh_args();
+        let mut args = self.ssh_args()?;
         args.push(self.ssh_destination());
-        args.push("sudo /tmp/tengu-remove.sh".into());
+        args.push(format!("{} /tmp/tengu-remove.sh", self.sudo()));
 
         let mut child = Command::new("ssh")
             .args(&args)
+            .stdin(self.sudo_stdin())
             .stdout(Stdio::piped())
             .stderr(Stdio::null())
             .spawn()
             .context("Failed to execute removal script")?;
+        self.feed_sudo_password(&mut child)?;
 
         let stdout = child.stdout.take().context("No stdout")?;
         let reader = BufReader::new(stdout);
 
         for line in reader.lines() {
             let Ok(line) = line else { continue };
-            println!("  {line}");
+            say!("  {line}");
         }
 
         let status = child.wait().context("Failed to wait for removal script")?;
@@ -293,7 +491,7 @@ echo ""
     /// Remove the temporary removal script
     #[allow(clippy::unnecessary_wraps)]
     fn cleanup_removal_script(&self) -> Result<()> {
-        let mut args = self.ssh_args();
+        let mut args = self.ssh_args()?;
         args.push(self.ssh_destination());
         args.push("rm -f /tmp/tengu-remove.sh".into());
 
@@ -308,58 +506,149 @@ echo ""
 
     /// Provision the server
     ///
-    /// 1. Generate bash script from config
+    /// 1. Generate bash script from the manifest
     /// 2. Upload to /tmp/tengu-provision.sh via SSH
     /// 3. Execute with sudo, streaming output
     /// 4. Parse progress markers and display pretty progress
     /// 5. Cleanup temp script
-    pub fn provision(&self, config: &TenguConfig) -> Result<()> {
+    ///
+    /// Returns a report of the step outcomes and timings.
+    pub fn provision(&self, config: &TenguConfig, manifest: &Manifest) -> Result<ProvisionReport> {
+        let started = Instant::now();
+
         // Generate script
-        println!("\n{} Generating provisioning script...", style("*").cyan());
-        let script = Self::generate_script(config)?;
+        self.say(&format!(
+            "\n{} Generating provisioning script...",
+            style("*").cyan()
+        ));
+        let script = Self::generate_script(manifest)?;
 
         // Count steps from manifest
-        let manifest = Manifest::tengu(config);
-        let total_steps = manifest.steps.len();
+        let total_steps = manifest.step_count();
 
         // Wait for SSH
         self.wait_for_ssh()?;
 
         // Upload local .deb if specified
         if let Some(deb_path) = &config.deb_path {
-            println!("{} Uploading local .deb package...", style("*").cyan());
+            self.say(&format!(
+                "{} Uploading local .deb package...",
+                style("*").cyan()
+            ));
             self.scp_file(deb_path, "/root/tengu-local.deb")?;
-            println!("  {} .deb uploaded", style("v").green());
+            self.say(&format!("  {} .deb uploaded", style("v").green()));
         }
 
         // Upload script
-        println!(
+        self.say(&format!(
             "{} Uploading script to {}...",
             style("*").cyan(),
             self.ssh_destination()
-        );
+        ));
         self.upload_script(&script)?;
+        self.verify_script(&script)?;
 
         // Execute script — retry once on failure (fresh VMs have timing issues with service starts)
-        println!("{} Executing provisioning script...\n", style("*").cyan());
-        println!("{}", style("-".repeat(50)).dim());
-        if let Err(e) = self.execute_script(total_steps) {
-            println!("{}", style("-".repeat(50)).dim());
-            println!(
-                "\n{} First run failed ({}), retrying (script is idempotent)...\n",
-                style("!").yellow().bold(),
-                style(&e).dim()
-            );
-            println!("{}", style("-".repeat(50)).dim());
-            self.execute_script(total_steps)?;
-        }
-        println!("{}", style("-".repeat(50)).dim());
+        self.say(&format!(
+            "{} Executing provisioning script...\n",
+            style("*").cyan()
+        ));
+        self.say(&style("-".repeat(50)).dim().to_string());
+        let mut attempts = 1;
+        let steps = match self.execute_script(total_steps) {
+            Ok(steps) => steps,
+            Err(e) => {
+                self.say(&style("-".repeat(50)).dim().to_string());
+                self.say(&format!(
+                    "\n{} First run failed ({}), retrying (script is idempotent)...\n",
+                    style("!").yellow().bold(),
+                    style(&e).dim()
+                ));
+                self.say(&style("-".repeat(50)).dim().to_string());
+                attempts += 1;
+                self.execute_script(total_steps)?
+            }
+        };
+        self.say(&style("-".repeat(50)).dim().to_string());
 
         // Cleanup
-        println!("{} Cleaning up...", style("*").cyan());
+        self.say(&format!("{} Cleaning up...", style("*").cyan()));
         self.cleanup_script()?;
 
-        Ok(())
+        Ok(ProvisionReport {
+            host: self.host.clone(),
+            ip: None,
+            attempts,
+            duration: started.elapsed(),
+            steps,
+        })
+    }
+
+    /// Run every step's check on the server without applying anything
+    ///
+    /// Checks are bundled into one script, uploaded and run with sudo; each
+    /// reports its exit status via a `TENGU_CHECK:index:status` line.
+    pub fn check_steps(&self, manifest: &Manifest) -> Result<Vec<CheckStatus>> {
+        const CHECK_SCRIPT: &str = "/tmp/tengu-check.sh";
+
+        let mut script = String::from("#!/bin/bash\n");
+        for (i, (_, step)) in manifest.steps.iter().enumerate() {
+            if let Some(check) = step.check_command() {
+                script.push_str(&format!(
+                    "if (set +e; {check}) >/dev/null 2>&1; then echo TENGU_CHECK:{i}:ok; \
+                     else echo TENGU_CHECK:{i}:apply; fi\n"
+                ));
+            }
+        }
+
+        self.wait_for_ssh()?;
+        self.upload_file_content(&script, CHECK_SCRIPT)?;
+
+        let mut args = self.ssh_args()?;
+        args.push(self.ssh_destination());
+        args.push(format!(
+            "{} bash {CHECK_SCRIPT}; status=$?; rm -f {CHECK_SCRIPT}; exit $status",
+            self.sudo()
+        ));
+        let mut child = Command::new("ssh")
+            .args(&args)
+            .stdin(self.sudo_stdin())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("Failed to run checks")?;
+        self.feed_sudo_password(&mut child)?;
+        let output = child.wait_with_output().context("Failed to run checks")?;
+        if !output.status.success() {
+            bail!(
+                "Checks failed on {}: {}",
+                self.host,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+
+        parse_checks(manifest, &String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// Collect cloud-init state, service states, disk/memory usage and the
+    /// installed tengu version in one SSH round trip (no sudo needed)
+    pub fn status(&self) -> Result<ServerStatus> {
+        let services = CORE_SERVICES
+            .iter()
+            .chain(&OTHER_SERVICES)
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ");
+        let script = format!(
+            r#"echo "cloud_init=$(cloud-init status 2>/dev/null | sed -n 's/^status: //p')"
+for s in {services}; do echo "service:$s=$(systemctl is-active $s 2>/dev/null)"; done
+echo "disk=$(df -h --output=used,size,pcent / | tail -1 | awk '{{print $1"/"$2" ("$3")"}}')"
+echo "memory=$(free -h | awk '/^Mem:/ {{print $3"/"$2}}')"
+echo "tengu=$(dpkg -s tengu 2>/dev/null | sed -n 's/^Version: //p')""#
+        );
+
+        let output = self.run_ssh_command_output(&script)?;
+        Ok(parse_status(&output))
     }
 
     /// Set up a Cloudflare Tunnel on the remote server
@@ -382,7 +671,7 @@ echo ""
         let cf_dir = format!("{home}/.cloudflared");
 
         // Step 1: Install cloudflared
-        println!("\n{} Installing cloudflared...", style("*").cyan());
+        say!("\n{} Installing cloudflared...", style("*").cyan());
         self.run_ssh_command(
             "if command -v cloudflared >/dev/null 2>&1; then \
                 echo 'cloudflared already installed'; \
@@ -394,10 +683,10 @@ echo ""
                 rm -f /tmp/cloudflared.deb; \
             fi",
         )?;
-        println!("  {} cloudflared installed", style("v").green());
+        say!("  {} cloudflared installed", style("v").green());
 
         // Step 2: Upload cert.pem
-        println!(
+        say!(
             "{} Uploading Cloudflare tunnel credentials...",
             style("*").cyan()
         );
@@ -416,10 +705,10 @@ echo ""
         // Create remote .cloudflared dir and write cert.pem
         self.run_ssh_command(&format!("mkdir -p {cf_dir}"))?;
         self.upload_file_content(&cert_content, &format!("{cf_dir}/cert.pem"))?;
-        println!("  {} cert.pem uploaded", style("v").green());
+        say!("  {} cert.pem uploaded", style("v").green());
 
         // Step 3: Clean up any previous tunnel installation
-        println!(
+        say!(
             "{} Configuring tunnel '{}'...",
             style("*").cyan(),
             tunnel_config.tunnel_name
@@ -445,7 +734,7 @@ echo ""
 
         let tunnel_id = parse_tunnel_id(&create_output)
             .context("Failed to parse tunnel ID from cloudflared output")?;
-        println!(
+        say!(
             "  {} Tunnel created (ID: {})",
             style("v").green(),
             &tunnel_id[..8]
@@ -472,17 +761,17 @@ echo ""
             apps = tunnel_config.domain_apps,
         );
         self.upload_file_content(&config_yml, &format!("{cf_dir}/config.yml"))?;
-        println!("  {} config.yml written", style("v").green());
+        say!("  {} config.yml written", style("v").green());
 
         // Step 6: Create DNS routes (delete stale records first)
-        println!("{} Creating DNS routes...", style("*").cyan());
+        say!("{} Creating DNS routes...", style("*").cyan());
         for subdomain in &["api", "docs", "git", "ssh"] {
             let hostname = format!("{subdomain}.{}", tunnel_config.domain_platform);
             self.run_ssh_command(&format!(
                 "cloudflared tunnel route dns --overwrite-dns {} {}",
                 tunnel_config.tunnel_name, hostname
             ))?;
-            println!("  {} {}", style("v").green(), hostname);
+            say!("  {} {}", style("v").green(), hostname);
         }
         // SSH route on apps domain (bare domain)
         let apps_ssh = &tunnel_config.domain_apps;
@@ -490,22 +779,27 @@ echo ""
             "cloudflared tunnel route dns --overwrite-dns {} {}",
             tunnel_config.tunnel_name, apps_ssh
         ))?;
-        println!("  {} {}", style("v").green(), apps_ssh);
+        say!("  {} {}", style("v").green(), apps_ssh);
 
         // Step 7: Install systemd service and start
-        println!("{} Installing cloudflared service...", style("*").cyan());
+        say!("{} Installing cloudflared service...", style("*").cyan());
         self.run_ssh_command(&format!(
             "sudo cloudflared --config {cf_dir}/config.yml service install && \
              sudo systemctl enable --now cloudflared"
         ))?;
-        println!("  {} Cloudflare Tunnel ready", style("v").green());
+        say!("  {} Cloudflare Tunnel ready", style("v").green());
 
         Ok(())
     }
 
+    /// Whether the user can sudo on the server without a password
+    pub fn has_passwordless_sudo(&self) -> bool {
+        self.run_ssh_command("sudo -n true").is_ok()
+    }
+
     /// Run a command on the remote server via SSH (discard output)
     fn run_ssh_command(&self, command: &str) -> Result<()> {
-        let mut args = self.ssh_args();
+        let mut args = self.ssh_args()?;
         args.push(self.ssh_destination());
         args.push(command.to_string());
 
@@ -527,7 +821,7 @@ echo ""
 
     /// Run a command on the remote server via SSH and return stdout
     fn run_ssh_command_output(&self, command: &str) -> Result<String> {
-        let mut args = self.ssh_args();
+        let mut args = self.ssh_args()?;
         args.push(self.ssh_destination());
         args.push(command.to_string());
 
@@ -549,7 +843,7 @@ echo ""
 
     /// Upload file content to a remote path via SSH stdin
     fn upload_file_content(&self, content: &str, remote_path: &str) -> Result<()> {
-        let mut args = self.ssh_args();
+        let mut args = self.ssh_args()?;
         args.push(self.ssh_destination());
         args.push(format!("cat > {remote_path}"));
 
@@ -578,40 +872,129 @@ echo ""
     }
 
     /// SSH destination string (user@host)
+    ///
+    /// `ssh` takes IPv6 literals without brackets; `scp` needs them, see
+    /// [`format_host`].
     fn ssh_destination(&self) -> String {
         format!("{}@{}", self.user, self.host)
     }
 
-    /// SSH command arguments (common options)
-    fn ssh_args(&self) -> Vec<String> {
-        vec![
-            "-o".into(),
-            "StrictHostKeyChecking=no".into(),
-            "-o".into(),
-            "UserKnownHostsFile=/dev/null".into(),
+    /// `host:port` for messages
+    fn address(&self) -> String {
+        format!("{}:{}", format_host(&self.host), self.port)
+    }
+
+    /// SSH command arguments (common options), pinning the host key first
+    fn ssh_args(&self) -> Result<Vec<String>> {
+        let mut args = self.host_key_args()?;
+        args.extend([
             "-o".into(),
             "LogLevel=ERROR".into(),
             "-p".into(),
             self.port.to_string(),
-        ]
+        ]);
+        args.extend(self.identity_args());
+        Ok(args)
+    }
+
+    /// Host key checking options
+    ///
+    /// An existing server is checked against the user's `known_hosts` and
+    /// `~/.ssh/config` (accepting a host seen for the first time); a
+    /// [`new_server`](Self::new_server) against its pinned host key.
+    fn host_key_args(&self) -> Result<Vec<String>> {
+        let Some(known_hosts) = self.pin_host_key()? else {
+            return Ok(vec!["-o".into(), "StrictHostKeyChecking=accept-new".into()]);
+        };
+        Ok(vec![
+            "-o".into(),
+            "StrictHostKeyChecking=yes".into(),
+            "-o".into(),
+            format!("UserKnownHostsFile={}", known_hosts.display()),
+        ])
+    }
+
+    /// Fetch a new server's host keys with `ssh-keyscan` (once per run) into
+    /// a temporary `known_hosts` file
+    ///
+    /// Later connections use `StrictHostKeyChecking=yes` against that file,
+    /// so a different key mid-run (a MITM after first contact) is refused.
+    /// The host and port are resolved with `ssh -G`, so keys are stored
+    /// under the names ssh looks them up by. Existing servers aren't pinned.
+    fn pin_host_key(&self) -> Result<Option<PathBuf>> {
+        if !self.new_server {
+            return Ok(None);
+        }
+        let mut known_hosts = self.known_hosts.lock().unwrap();
+        if let Some(path) = known_hosts.as_ref() {
+            return Ok(Some(path.to_path_buf()));
+        }
+
+        let (host, port) = self.resolve_host();
+        let output = Command::new("ssh-keyscan")
+            .args([
+                "-T",
+                &self.connect_timeout.as_secs().max(1).to_string(),
+                "-p",
+                &port.to_string(),
+                &host,
+            ])
+            .stderr(Stdio::null())
+            .output()
+            .context("Failed to run ssh-keyscan")?;
+        if output.stdout.is_empty() {
+            bail!("Could not fetch SSH host key from {}", self.address());
+        }
+
+        let mut file = tempfile::Builder::new()
+            .prefix("tengu-known-hosts-")
+            .tempfile()
+            .context("Failed to create known_hosts file")?;
+        file.write_all(&output.stdout)
+            .context("Failed to write known_hosts file")?;
+        let path = file.into_temp_path();
+        let pinned = path.to_path_buf();
+        *known_hosts = Some(path);
+        Ok(Some(pinned))
+    }
+
+    /// Host name and port ssh actually connects to, after `~/.ssh/config`
+    /// (`HostName`, `Port`); the host as given if `ssh -G` fails
+    fn resolve_host(&self) -> (String, u16) {
+        let output = Command::new("ssh")
+            .args(["-G", "-p", &self.port.to_string(), &self.ssh_destination()])
+            .stderr(Stdio::null())
+            .output();
+        match output {
+            Ok(output) if output.status.success() => parse_ssh_config(
+                &String::from_utf8_lossy(&output.stdout),
+                &self.host,
+                self.port,
+            ),
+            _ => (self.host.clone(), self.port),
+        }
+    }
+
+    /// `-i` and `IdentitiesOnly` options when an explicit identity is set
+    fn identity_args(&self) -> Vec<String> {
+        self.identity.as_ref().map_or_else(Vec::new, |path| {
+            vec![
+                "-i".into(),
+                path.display().to_string(),
+                "-o".into(),
+                "IdentitiesOnly=yes".into(),
+            ]
+        })
     }
 
     /// Copy a local file to the remote server via SCP
     fn scp_file(&self, local_path: &str, remote_path: &str) -> Result<()> {
-        let dest = format!("{}:{}", self.ssh_destination(), remote_path);
+        let dest = format!("{}@{}:{}", self.user, format_host(&self.host), remote_path);
         let output = Command::new("scp")
-            .args([
-                "-o",
-                "StrictHostKeyChecking=no",
-                "-o",
-                "UserKnownHostsFile=/dev/null",
-                "-o",
-                "LogLevel=ERROR",
-                "-P",
-                &self.port.to_string(),
-                local_path,
-                &dest,
-            ])
+            .args(self.host_key_args()?)
+            .args(["-o", "LogLevel=ERROR", "-P", &self.port.to_string()])
+            .args(self.identity_args())
+            .args([local_path, &dest])
             .output()
             .context("Failed to run scp")?;
 
@@ -624,37 +1007,36 @@ echo ""
 
     /// Wait for SSH to become available
     fn wait_for_ssh(&self) -> Result<()> {
-        let spinner = ProgressBar::new_spinner();
-        spinner.set_style(
-            ProgressStyle::default_spinner()
-                .template("{spinner:.cyan} {msg}")
-                .unwrap(),
-        );
-        spinner.set_message(format!("Connecting to {}:{}...", self.host, self.port));
+        let spinner = self.spinner();
+        spinner.set_style(theme::spinner_style("{spinner:.cyan} {msg}"));
+        spinner.set_message(format!("Connecting to {}...", self.address()));
         spinner.enable_steady_tick(Duration::from_millis(100));
 
         let mut attempts = 0;
         let max_attempts = 24;
 
         loop {
-            let mut args = self.ssh_args();
-            args.extend([
-                "-o".into(),
-                "ConnectTimeout=10".into(),
-                "-o".into(),
-                "BatchMode=yes".into(),
-                self.ssh_destination(),
-                "true".into(),
-            ]);
-
-            let status = Command::new("ssh")
-                .args(&args)
-                .stdout(Stdio::null())
-                .stderr(Stdio::null())
-                .status();
-
-            if status.map(|s| s.success()).unwrap_or(false) {
-                break;
+            // A fresh server may not answer ssh-keyscan yet; retry like the connect
+            if self.pin_host_key().is_ok() {
+                let mut args = self.ssh_args()?;
+                args.extend([
+                    "-o".into(),
+                    format!("ConnectTimeout={}", self.connect_timeout.as_secs().max(1)),
+                    "-o".into(),
+                    "BatchMode=yes".into(),
+                    self.ssh_destination(),
+                    "true".into(),
+                ]);
+
+                let status = Command::new("ssh")
+                    .args(&args)
+                    .stdout(Stdio::null())
+                    .stderr(Stdio::null())
+                    .status();
+
+                if status.is_ok_and(|s| s.success()) {
+                    break;
+                }
             }
 
             attempts += 1;
@@ -664,7 +1046,7 @@ echo ""
                     style("x").red(),
                     max_attempts
                 ));
-                bail!("Could not connect to {}:{} via SSH", self.host, self.port);
+                bail!("Could not connect to {} via SSH", self.address());
             }
 
             std::thread::sleep(Duration::from_secs(5));
@@ -676,7 +1058,7 @@ echo ""
 
     /// Upload script to remote server
     fn upload_script(&self, script: &str) -> Result<()> {
-        let mut args = self.ssh_args();
+        let mut args = self.ssh_args()?;
         args.push(self.ssh_destination());
         args.push("cat > /tmp/tengu-provision.sh && chmod +x /tmp/tengu-provision.sh".into());
 
@@ -706,26 +1088,55 @@ echo ""
         Ok(())
     }
 
-    /// Execute script and stream progress
-    fn execute_script(&self, total_steps: usize) -> Result<()> {
-        let mut args = self.ssh_args();
+    /// Verify the uploaded script matches the local one byte for byte
+    fn verify_script(&self, script: &str) -> Result<()> {
+        let expected = hex::encode(Sha256::digest(script.as_bytes()));
+        let output = self.run_ssh_command_output("sha256sum /tmp/tengu-provision.sh")?;
+        let actual = output.split_whitespace().next().unwrap_or_default();
+
+        if actual != expected {
+            bail!(
+                "Uploaded script checksum mismatch (expected {expected}, got {actual}); \
+                 the transfer was likely truncated"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Execute script and stream progress, collecting each step's outcome
+    #[allow(clippy::too_many_lines)]
+    fn execute_script(&self, total_steps: usize) -> Result<Vec<StepOutcome>> {
+        let mut args = self.ssh_args()?;
         args.push(self.ssh_destination());
         // Redirect stderr to /dev/null on remote — we parse progress from stdout markers.
         // Without this, stderr fills the pipe buffer and deadlocks the SSH process.
-        args.push("sudo /tmp/tengu-provision.sh 2>/tmp/tengu-provision.err".into());
+        // The password goes to sudo's stdin only; -p '' keeps any prompt out of the stream.
+        args.push(format!(
+            "{} /tmp/tengu-provision.sh{} 2>/tmp/tengu-provision.err",
+            self.sudo(),
+            if self.resume { " --resume" } else { "" }
+        ));
 
         let mut child = Command::new("ssh")
             .args(&args)
+            .stdin(self.sudo_stdin())
             .stdout(Stdio::piped())
             .stderr(Stdio::null())
             .spawn()
             .context("Failed to execute script")?;
+        self.feed_sudo_password(&mut child)?;
 
         let stdout = child.stdout.take().context("No stdout")?;
         let reader = BufReader::new(stdout);
 
         // Track current step for spinner
         let mut current_spinner: Option<ProgressBar> = None;
+        let mut current_step: Option<(usize, String)> = None;
+        // Keyed by step, since parallel groups interleave markers
+        let mut started: HashMap<usize, Instant> = HashMap::new();
+        let mut outcomes = Vec::new();
+        let mut complete = None;
 
         for line in reader.lines() {
             let Ok(line) = line else { continue };
@@ -740,47 +1151,92 @@ echo ""
                         }
 
                         // Start new spinner
-                        let spinner = ProgressBar::new_spinner();
-                        spinner.set_style(
-                            ProgressStyle::default_spinner()
-                                .template(&format!(
-                                    "{{spinner:.cyan}} [{step}/{total_steps}] {{msg}}"
-                                ))
-                                .unwrap(),
-                        );
-                        spinner.set_message(desc);
+                        let spinner = self.spinner();
+                        spinner.set_style(theme::spinner_style(&format!(
+                            "{{spinner:.cyan}} [{step}/{total_steps}] {{msg}}"
+                        )));
+                        spinner.set_message(desc.clone());
                         spinner.enable_steady_tick(Duration::from_millis(100));
                         current_spinner = Some(spinner);
+                        current_step = Some((step, desc));
+                        started.insert(step, Instant::now());
                     }
-                    ProgressMarker::Done { step, desc } => {
+                    ProgressMarker::Progress { step, msg } => {
+                        // Parallel steps report too; only the spinner's own step updates it
+                        if let (Some(spinner), Some((current, desc))) =
+                            (&current_spinner, &current_step)
+                            && *current == step
+                        {
+                            spinner.set_message(format!("{desc} {}", style(msg).dim()));
+                        }
+                    }
+                    ProgressMarker::Done {
+                        step,
+                        desc,
+                        elapsed,
+                    } => {
                         if let Some(spinner) = current_spinner.take() {
                             spinner.finish_and_clear();
                         }
-                        println!("[{}/{}] {} {}", step, total_steps, style("v").green(), desc);
+                        outcomes.push(step_outcome(&mut started, step, &desc, StepStatus::Applied));
+                        let elapsed = elapsed
+                            .map(|secs| format!(" {}", style(format!("({secs}s)")).dim()))
+                            .unwrap_or_default();
+                        self.say(&format!(
+                            "[{}/{}] {} {}{}",
+                            step,
+                            total_steps,
+                            style("v").green(),
+                            desc,
+                            elapsed
+                        ));
                     }
                     ProgressMarker::Skip { step, desc } => {
                         if let Some(spinner) = current_spinner.take() {
                             spinner.finish_and_clear();
                         }
-                        println!(
+                        outcomes.push(step_outcome(&mut started, step, &desc, StepStatus::Skipped));
+                        self.say(&format!(
                             "[{}/{}] {} {} {}",
                             step,
                             total_steps,
                             style("o").yellow(),
                             desc,
                             style("(skipped)").dim()
-                        );
+                        ));
                     }
                     ProgressMarker::Fail { step, desc } => {
                         if let Some(spinner) = current_spinner.take() {
                             spinner.finish_and_clear();
                         }
-                        println!("[{}/{}] {} {}", step, total_steps, style("x").red(), desc);
+                        outcomes.push(step_outcome(&mut started, step, &desc, StepStatus::Failed));
+                        self.say(&format!(
+                            "[{}/{}] {} {}",
+                            step,
+                            total_steps,
+                            style("x").red(),
+                            desc
+                        ));
+                    }
+                    ProgressMarker::Warn { step, desc } => {
+                        if let Some(spinner) = current_spinner.take() {
+                            spinner.finish_and_clear();
+                        }
+                        outcomes.push(step_outcome(&mut started, step, &desc, StepStatus::Warned));
+                        self.say(&format!(
+                            "[{}/{}] {} {} {}",
+                            step,
+                            total_steps,
+                            style("!").yellow(),
+                            desc,
+                            style("(failed, allowed)").dim()
+                        ));
                     }
-                    ProgressMarker::Complete { .. } => {
+                    ProgressMarker::Complete { total } => {
                         if let Some(spinner) = current_spinner.take() {
                             spinner.finish_and_clear();
                         }
+                        complete = Some(total);
                     }
                 }
             }
@@ -798,12 +1254,16 @@ echo ""
             bail!("Provisioning script failed with exit code: {status}");
         }
 
-        Ok(())
+        if let Some(warning) = completion_warning(total_steps, complete, &outcomes) {
+            self.say(&format!("{} Warning: {warning}", style("!").yellow()));
+        }
+
+        Ok(outcomes)
     }
 
     /// Remove the temporary script
     fn cleanup_script(&self) -> Result<()> {
-        let mut args = self.ssh_args();
+        let mut args = self.ssh_args()?;
         args.push(self.ssh_destination());
         args.push("rm -f /tmp/tengu-provision.sh".into());
 
@@ -828,21 +1288,103 @@ echo ""
 
 /// Progress marker types
 enum ProgressMarker {
-    Start { step: usize, desc: String },
-    Done { step: usize, desc: String },
-    Skip { step: usize, desc: String },
-    Fail { step: usize, desc: String },
-    Complete { _total: usize },
+    Start {
+        step: usize,
+        desc: String,
+    },
+    Progress {
+        step: usize,
+        msg: String,
+    },
+    /// `elapsed` is absent in markers from scripts predating step timing
+    Done {
+        step: usize,
+        desc: String,
+        elapsed: Option<u64>,
+    },
+    Skip {
+        step: usize,
+        desc: String,
+    },
+    Fail {
+        step: usize,
+        desc: String,
+    },
+    /// A step allowed to fail did
+    Warn {
+        step: usize,
+        desc: String,
+    },
+    Complete {
+        total: usize,
+    },
+}
+
+/// Host as it appears in `host:port` and `scp` destinations: IPv6 literals
+/// (including zoned ones like `fe80::1%eth0`) are bracketed, names and IPv4
+/// addresses are left alone
+fn format_host(host: &str) -> String {
+    // Names and IPv4 addresses never contain ':'
+    if host.contains(':') {
+        format!("[{host}]")
+    } else {
+        host.to_string()
+    }
+}
+
+/// Warning when the progress markers don't account for every step
+///
+/// A clean exit without `COMPLETE`, a `COMPLETE` total that differs from the
+/// manifest, or fewer DONE/SKIP/WARN markers than steps all mean a step was lost.
+fn completion_warning(
+    total_steps: usize,
+    complete: Option<usize>,
+    outcomes: &[StepOutcome],
+) -> Option<String> {
+    let finished = outcomes
+        .iter()
+        .filter(|o| o.status != StepStatus::Failed)
+        .count();
+    match complete {
+        None => Some(format!(
+            "script exited without a completion marker ({finished} of {total_steps} steps reported)"
+        )),
+        Some(total) if total != total_steps => Some(format!(
+            "script reported {total} steps, expected {total_steps}"
+        )),
+        Some(_) if finished != total_steps => Some(format!(
+            "only {finished} of {total_steps} steps reported done or skipped; a step may have been dropped"
+        )),
+        Some(_) => None,
+    }
+}
+
+/// Build a step's outcome, timed from its START marker if one was seen
+fn step_outcome(
+    started: &mut HashMap<usize, Instant>,
+    step: usize,
+    desc: &str,
+    status: StepStatus,
+) -> StepOutcome {
+    StepOutcome {
+        step,
+        description: desc.to_string(),
+        status,
+        duration: started
+            .remove(&step)
+            .map_or(Duration::ZERO, |t| t.elapsed()),
+    }
 }
 
 /// Parse a progress marker from a line
 ///
-/// Format: `TENGU_STEP:ACTION:step_num:description`
+/// Format: `TENGU_STEP:ACTION:step_num:description`, with `DONE` markers
+/// optionally ending in `:elapsed_secs`
 fn parse_progress_marker(line: &str) -> Option<ProgressMarker> {
-    let line = line.trim();
-
-    // Strip ANSI escape codes for parsing
+    // Strip ANSI escape codes for parsing; text after a carriage return
+    // overwrote whatever a spinner drew before it
     let clean = strip_ansi_codes(line);
+    let clean = clean.rsplit('\r').next().unwrap_or_default().trim();
 
     if !clean.starts_with("TENGU_STEP:") {
         return None;
@@ -855,18 +1397,133 @@ fn parse_progress_marker(line: &str) -> Option<ProgressMarker> {
 
     let action = parts[1];
     let step: usize = parts[2].parse().ok()?;
-    let desc = parts.get(3).unwrap_or(&"").to_string();
+    // The description is the last field, so any colons in it survive the split
+    let raw = parts.get(3).unwrap_or(&"");
+    let desc = decode_marker_description(raw);
 
     match action {
         "START" => Some(ProgressMarker::Start { step, desc }),
-        "DONE" => Some(ProgressMarker::Done { step, desc }),
+        "PROGRESS" => Some(ProgressMarker::Progress { step, msg: desc }),
+        "DONE" => {
+            // A trailing all-digit field is the elapsed time
+            let timed = raw
+                .rsplit_once(':')
+                .and_then(|(desc, secs)| Some((desc, secs.parse().ok()?)));
+            Some(match timed {
+                Some((desc, secs)) => ProgressMarker::Done {
+                    step,
+                    desc: decode_marker_description(desc),
+                    elapsed: Some(secs),
+                },
+                None => ProgressMarker::Done {
+                    step,
+                    desc,
+                    elapsed: None,
+                },
+            })
+        }
         "SKIP" => Some(ProgressMarker::Skip { step, desc }),
         "FAIL" => Some(ProgressMarker::Fail { step, desc }),
-        "COMPLETE" => Some(ProgressMarker::Complete { _total: step }),
+        "WARN" => Some(ProgressMarker::Warn { step, desc }),
+        "COMPLETE" => Some(ProgressMarker::Complete { total: step }),
         _ => None,
     }
 }
 
+/// `hostname` and `port` from `ssh -G` output, defaulting to the given ones
+fn parse_ssh_config(output: &str, host: &str, port: u16) -> (String, u16) {
+    let mut resolved = (host.to_string(), port);
+    for line in output.lines() {
+        match line.split_once(' ') {
+            Some(("hostname", value)) => resolved.0 = value.to_string(),
+            Some(("port", value)) => {
+                if let Ok(port) = value.parse() {
+                    resolved.1 = port;
+                }
+            }
+            _ => {}
+        }
+    }
+    resolved
+}
+
+/// Map `TENGU_CHECK:index:status` lines to a status per step
+///
+/// Every step with a check must report; a missing line means the check
+/// script died part-way, which would otherwise read as "would apply".
+fn parse_checks(manifest: &Manifest, output: &str) -> Result<Vec<CheckStatus>> {
+    let mut statuses: Vec<CheckStatus> = manifest
+        .steps
+        .iter()
+        .map(|(_, step)| {
+            if step.check_command().is_some() {
+                CheckStatus::WouldApply
+            } else {
+                CheckStatus::AlwaysRuns
+            }
+        })
+        .collect();
+    let expected = statuses
+        .iter()
+        .filter(|s| **s == CheckStatus::WouldApply)
+        .count();
+
+    let mut reported = HashSet::new();
+    for line in output.lines() {
+        let mut parts = line.splitn(3, ':');
+        if parts.next() != Some("TENGU_CHECK") {
+            continue;
+        }
+        let Some(index) = parts.next().and_then(|i| i.parse::<usize>().ok()) else {
+            continue;
+        };
+        if index >= statuses.len() || manifest.steps[index].1.check_command().is_none() {
+            continue;
+        }
+        reported.insert(index);
+        if parts.next() == Some("ok") {
+            statuses[index] = CheckStatus::Satisfied;
+        }
+    }
+
+    if reported.len() < expected {
+        bail!(
+            "Only {} of {expected} checks reported a result",
+            reported.len()
+        );
+    }
+    Ok(statuses)
+}
+
+/// Parse the `key=value` lines printed by the [`SshProvider::status`] script
+fn parse_status(output: &str) -> ServerStatus {
+    let mut status = ServerStatus::default();
+    for line in output.lines() {
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().to_string();
+        match key {
+            "cloud_init" => status.cloud_init = value,
+            "disk" => status.disk = value,
+            "memory" => status.memory = value,
+            "tengu" => status.tengu_version = (!value.is_empty()).then_some(value),
+            _ => {
+                if let Some(service) = key.strip_prefix("service:") {
+                    // is-active prints nothing when systemctl itself fails
+                    let state = if value.is_empty() {
+                        "unknown".to_string()
+                    } else {
+                        value
+                    };
+                    status.services.push((service.to_string(), state));
+                }
+            }
+        }
+    }
+    status
+}
+
 /// Parse tunnel ID (UUID) from `cloudflared tunnel create` output
 ///
 /// The output contains a line like: "Created tunnel tengu with id abcdef12-3456-7890-abcd-ef1234567890"
@@ -878,27 +1535,218 @@ fn parse_tunnel_id(output: &str) -> Option<String> {
 }
 
 /// Strip ANSI escape codes from a string
+///
+/// Handles CSI (`ESC [ params final`), OSC and other string sequences
+/// (`ESC ] ... BEL` or `ESC ] ... ESC \`), and two-char escapes like `ESC 7`.
 fn strip_ansi_codes(s: &str) -> String {
     let mut result = String::with_capacity(s.len());
     let mut chars = s.chars().peekable();
 
     while let Some(c) = chars.next() {
-        if c == '\x1b' {
-            // Skip escape sequence
-            if chars.peek() == Some(&'[') {
-                chars.next(); // consume '['
-                // Skip until we hit a letter (end of sequence)
-                while let Some(&next) = chars.peek() {
-                    chars.next();
-                    if next.is_ascii_alphabetic() {
+        if c != '\x1b' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            // CSI: parameter/intermediate bytes up to a final byte in @..=~
+            Some('[') => {
+                for next in chars.by_ref() {
+                    if ('@'..='~').contains(&next) {
                         break;
                     }
                 }
             }
-        } else {
-            result.push(c);
+            // OSC, DCS, SOS, PM, APC: until BEL or ST (ESC \)
+            Some(']' | 'P' | 'X' | '^' | '_') => {
+                while let Some(next) = chars.next() {
+                    if next == '\x07' {
+                        break;
+                    }
+                    if next == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            // Two-char escapes, with optional intermediates (e.g. `ESC ( B`)
+            Some(next) if (' '..='/').contains(&next) => {
+                while chars.next_if(|c| (' '..='/').contains(c)).is_some() {}
+                chars.next();
+            }
+            Some(_) | None => {}
         }
     }
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_ansi_codes() {
+        assert_eq!(strip_ansi_codes("\x1b[0;32m[3]\x1b[0m ok"), "[3] ok");
+        assert_eq!(strip_ansi_codes("\x1b[38;5;208;1mhi\x1b[m"), "hi");
+        assert_eq!(strip_ansi_codes("\x1b]0;title\x07text"), "text");
+        assert_eq!(strip_ansi_codes("\x1b]8;;http://x\x1b\\link"), "link");
+        assert_eq!(strip_ansi_codes("\x1b7saved\x1b8"), "saved");
+        assert_eq!(strip_ansi_codes("\x1b(Bplain"), "plain");
+    }
+
+    #[test]
+    fn test_parse_progress_marker_after_spinner_output() {
+        let line = "\x1b7\x1b[2K\x1b[36m⠋\x1b[0m [2/9] Installing\r\x1b[2K\x1b]0;tengu\x07\
+                    TENGU_STEP:DONE:2:Install curl\x1b8";
+
+        let Some(ProgressMarker::Done { step, desc, .. }) = parse_progress_marker(line) else {
+            panic!("marker not recovered from {line:?}");
+        };
+        assert_eq!(step, 2);
+        assert_eq!(desc, "Install curl");
+    }
+
+    #[test]
+    fn test_format_host() {
+        assert_eq!(format_host("203.0.113.7"), "203.0.113.7");
+        assert_eq!(format_host("2001:db8::1"), "[2001:db8::1]");
+        assert_eq!(format_host("fe80::1%eth0"), "[fe80::1%eth0]");
+        assert_eq!(format_host("tengu.example.com"), "tengu.example.com");
+
+        let provider = SshProvider::new("root@[2001:db8::1]", 2222);
+        assert_eq!(provider.user, "root");
+        assert_eq!(provider.ssh_destination(), "root@2001:db8::1");
+        assert_eq!(provider.address(), "[2001:db8::1]:2222");
+
+        let provider = SshProvider::new("chi@tengu.example.com", 22);
+        assert_eq!(provider.ssh_destination(), "chi@tengu.example.com");
+        assert_eq!(provider.address(), "tengu.example.com:22");
+    }
+
+    #[test]
+    fn test_parse_status() {
+        let output = "cloud_init=done\n\
+                      service:docker=active\n\
+                      service:postgresql=active\n\
+                      service:caddy=failed\n\
+                      service:tengu=\n\
+                      service:ollama=inactive\n\
+                      disk=12G/75G (16%)\n\
+                      memory=1.2Gi/3.7Gi\n\
+                      tengu=0.4.1\n";
+        let status = parse_status(output);
+
+        assert_eq!(status.cloud_init, "done");
+        assert_eq!(status.disk, "12G/75G (16%)");
+        assert_eq!(status.memory, "1.2Gi/3.7Gi");
+        assert_eq!(status.tengu_version.as_deref(), Some("0.4.1"));
+        assert_eq!(
+            status.services[3],
+            ("tengu".to_string(), "unknown".to_string())
+        );
+        // ollama isn't a core service
+        assert_eq!(status.down_services(), ["caddy", "tengu"]);
+
+        assert_eq!(parse_status("tengu=\n").tengu_version, None);
+    }
+
+    #[test]
+    fn test_parse_ssh_config() {
+        let output = "user chi\nhostname 203.0.113.7\nport 2222\nidentityfile ~/.ssh/id_ed25519\n";
+        assert_eq!(
+            parse_ssh_config(output, "box", 22),
+            ("203.0.113.7".to_string(), 2222)
+        );
+        assert_eq!(parse_ssh_config("", "box", 22), ("box".to_string(), 22));
+
+        // Only servers this run created are pinned
+        let provider = SshProvider::new("chi@box", 22);
+        assert_eq!(
+            provider.host_key_args().unwrap(),
+            ["-o", "StrictHostKeyChecking=accept-new"]
+        );
+        assert!(provider.pin_host_key().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_parse_checks() {
+        use tengu_provision::steps::RunCommand;
+
+        let manifest = Manifest::new("test")
+            .with_step(RunCommand::new("Checked", "true").unless("true"))
+            .with_step(RunCommand::new("Always", "true"))
+            .with_step(RunCommand::new("Pending", "true").unless("false"));
+        let statuses =
+            parse_checks(&manifest, "motd\nTENGU_CHECK:0:ok\nTENGU_CHECK:2:apply\n").unwrap();
+        assert_eq!(
+            statuses,
+            [
+                CheckStatus::Satisfied,
+                CheckStatus::AlwaysRuns,
+                CheckStatus::WouldApply
+            ]
+        );
+
+        // A check script that died part-way doesn't read as "would apply"
+        let err = parse_checks(&manifest, "TENGU_CHECK:0:ok\n").unwrap_err();
+        assert_eq!(err.to_string(), "Only 1 of 2 checks reported a result");
+        assert!(parse_checks(&manifest, "").is_err());
+    }
+
+    #[test]
+    fn test_parse_done_marker_elapsed() {
+        let Some(ProgressMarker::Done { desc, elapsed, .. }) =
+            parse_progress_marker("TENGU_STEP:DONE:5:Run: setup:42")
+        else {
+            panic!("marker not parsed");
+        };
+        assert_eq!(desc, "Run: setup");
+        assert_eq!(elapsed, Some(42));
+
+        // Older scripts end DONE markers with the description
+        let Some(ProgressMarker::Done { desc, elapsed, .. }) =
+            parse_progress_marker("TENGU_STEP:DONE:5:Run: setup")
+        else {
+            panic!("marker not parsed");
+        };
+        assert_eq!(desc, "Run: setup");
+        assert_eq!(elapsed, None);
+
+        let Some(ProgressMarker::Warn { step, desc }) =
+            parse_progress_marker("TENGU_STEP:WARN:7:Install Ollama")
+        else {
+            panic!("marker not parsed");
+        };
+        assert_eq!((step, desc.as_str()), (7, "Install Ollama"));
+    }
+
+    #[test]
+    fn test_completion_warning() {
+        let Some(ProgressMarker::Complete { total }) =
+            parse_progress_marker("TENGU_STEP:COMPLETE:2:all steps")
+        else {
+            panic!("marker not parsed");
+        };
+        assert_eq!(total, 2);
+
+        let outcome = |step, status| StepOutcome {
+            step,
+            description: format!("Step {step}"),
+            status,
+            duration: Duration::ZERO,
+        };
+        let both = [
+            outcome(1, StepStatus::Skipped),
+            outcome(2, StepStatus::Applied),
+        ];
+        assert_eq!(completion_warning(2, Some(2), &both), None);
+        assert!(
+            completion_warning(2, Some(2), &both[..1])
+                .unwrap()
+                .contains("only 1 of 2")
+        );
+        assert!(completion_warning(3, Some(2), &both).is_some());
+        assert!(completion_warning(2, None, &both).is_some());
+    }
+}
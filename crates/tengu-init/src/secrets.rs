@@ -0,0 +1,77 @@
+//! Secret reference resolution for config values
+//!
+//! A secret in `init.toml` may be a reference instead of the literal value:
+//!
+//! - `env:NAME` - read from an environment variable
+//! - `file:/path` - read from a file (e.g. an agenix or systemd credential),
+//!   trailing newline stripped
+//! - `op://vault/item/field` - read with the 1Password CLI (`op read`)
+//!
+//! Anything else is returned unchanged, so existing literal values keep working.
+
+use std::process::Command;
+use std::{env, fs};
+
+use anyhow::{Context, Result, bail};
+
+/// Resolve a possibly-referenced secret value
+pub fn resolve_secret(value: &str) -> Result<String> {
+    if let Some(name) = value.strip_prefix("env:") {
+        env::var(name).with_context(|| format!("Environment variable {name} is not set"))
+    } else if let Some(path) = value.strip_prefix("file:") {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read secret file: {path}"))?;
+        Ok(content.trim_end_matches(['\n', '\r']).to_string())
+    } else if value.starts_with("op://") {
+        let output = Command::new("op")
+            .args(["read", "--no-newline", value])
+            .output()
+            .context("Failed to run 1Password CLI (op)")?;
+        if !output.status.success() {
+            bail!(
+                "op read {value} failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        String::from_utf8(output.stdout).context("op returned non-UTF-8 output")
+    } else {
+        Ok(value.to_string())
+    }
+}
+
+/// Resolve an optional secret in place
+pub fn resolve_in_place(value: &mut Option<String>) -> Result<()> {
+    if let Some(v) = value {
+        *v = resolve_secret(v)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    #[test]
+    fn test_literal_passthrough() {
+        assert_eq!(resolve_secret("abc123").unwrap(), "abc123");
+        assert_eq!(resolve_secret("vault:foo").unwrap(), "vault:foo");
+    }
+
+    #[test]
+    fn test_env_resolver() {
+        let path = env::var("PATH").unwrap();
+        assert_eq!(resolve_secret("env:PATH").unwrap(), path);
+        assert!(resolve_secret("env:TENGU_TEST_SURELY_UNSET").is_err());
+    }
+
+    #[test]
+    fn test_file_resolver() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "s3cret").unwrap();
+        let reference = format!("file:{}", file.path().display());
+        assert_eq!(resolve_secret(&reference).unwrap(), "s3cret");
+        assert!(resolve_secret("file:/nonexistent/tengu-secret").is_err());
+    }
+}
@@ -0,0 +1,108 @@
+//! Terminal styling, with a plain-ASCII mode for CI logs
+//!
+//! `--plain`, `NO_COLOR` or `TERM=dumb` turn off colors, emoji and
+//! box-drawing characters here, so callers don't branch on it.
+
+use std::env;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use comfy_table::Table;
+use comfy_table::presets::{ASCII_FULL_CONDENSED, UTF8_FULL_CONDENSED};
+use console::StyledObject;
+use indicatif::ProgressStyle;
+
+/// Set once at startup
+static PLAIN: AtomicBool = AtomicBool::new(false);
+
+/// Inner width of [`banner`] boxes
+const BANNER_WIDTH: usize = 39;
+
+/// Success mark
+pub const TICK: Glyph = Glyph("✓", "v");
+/// Failure mark
+pub const CROSS: Glyph = Glyph("✗", "x");
+/// Points at a target (e.g. an IP)
+pub const ARROW: Glyph = Glyph("→", "->");
+/// Dash between clauses or in headings
+pub const DASH: Glyph = Glyph("\u{2014}", "-");
+
+/// Whether `--plain` or the environment (`NO_COLOR`, `TERM=dumb`) asks for plain output
+pub fn plain_requested(flag: bool) -> bool {
+    flag || env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty())
+        || env::var("TERM").is_ok_and(|term| term == "dumb")
+}
+
+/// Switch to plain output
+pub fn set_plain(plain: bool) {
+    PLAIN.store(plain, Ordering::Relaxed);
+    if plain {
+        // Also covers indicatif templates and comfy_table cell colors
+        console::set_colors_enabled(false);
+        console::set_colors_enabled_stderr(false);
+    }
+}
+
+/// Whether output is plain ASCII without colors
+pub fn plain() -> bool {
+    PLAIN.load(Ordering::Relaxed)
+}
+
+/// A symbol with an ASCII stand-in for plain output
+#[derive(Debug, Clone, Copy)]
+pub struct Glyph(pub &'static str, pub &'static str);
+
+impl fmt::Display for Glyph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(if plain() { self.1 } else { self.0 })
+    }
+}
+
+/// [`console::style`], never colored in plain mode
+pub fn style<D>(val: D) -> StyledObject<D> {
+    let styled = console::style(val);
+    if plain() {
+        styled.force_styling(false)
+    } else {
+        styled
+    }
+}
+
+/// The three lines of a boxed, centered `title`
+pub fn banner(title: &str) -> [String; 3] {
+    let bar = |c: &str| c.repeat(BANNER_WIDTH);
+    if plain() {
+        [
+            format!("+{}+", bar("=")),
+            format!("|{title:^BANNER_WIDTH$}|"),
+            format!("+{}+", bar("=")),
+        ]
+    } else {
+        [
+            format!("╔{}╗", bar("═")),
+            format!("║{title:^BANNER_WIDTH$}║"),
+            format!("╚{}╝", bar("═")),
+        ]
+    }
+}
+
+/// An empty table with the box-drawing (or ASCII) preset
+pub fn table() -> Table {
+    let mut table = Table::new();
+    if plain() {
+        table.load_preset(ASCII_FULL_CONDENSED);
+    } else {
+        table.load_preset(UTF8_FULL_CONDENSED);
+    }
+    table
+}
+
+/// Spinner style for `template`, with ASCII ticks in plain mode
+pub fn spinner_style(template: &str) -> ProgressStyle {
+    let style = ProgressStyle::default_spinner().template(template).unwrap();
+    if plain() {
+        style.tick_chars("-\\|/ ")
+    } else {
+        style
+    }
+}
@@ -0,0 +1,48 @@
+//! `--plain` output stays ASCII
+
+use std::process::Command;
+
+const CONFIG: &str = r#"
+[mode]
+tls = "direct"
+
+[server]
+release = "v1"
+admin_user = "tengu"
+
+[domains]
+platform = "example.com"
+apps = "example.app"
+
+[ssh]
+public_key = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIENlmiADcNoVkY6XEUvbnKvZsoGs/RVQAW8NrDB8GmME test@host"
+
+[resend]
+api_key = "re_test"
+
+[notifications]
+email = "admin@example.com"
+"#;
+
+#[test]
+fn test_plain_dry_run_is_ascii() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = dir.path().join("config.toml");
+    std::fs::write(&config, CONFIG).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_tengu-init"))
+        .args(["-y", "--plain", "--config"])
+        .arg(&config)
+        .args(["root@203.0.113.10", "--dry-run"])
+        .env("HOME", dir.path())
+        .env_remove("NO_COLOR")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("TENGU PROVISIONING"));
+    assert!(stdout.contains("Dry run"));
+    let offending: Vec<_> = stdout.lines().filter(|l| !l.is_ascii()).collect();
+    assert!(offending.is_empty(), "non-ASCII output: {offending:#?}");
+}
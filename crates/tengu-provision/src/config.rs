@@ -1,5 +1,13 @@
 //! Configuration types for Tengu provisioning
 
+use serde::{Deserialize, Serialize};
+
+/// GitHub repository (`owner/repo`) publishing the tengu .deb
+pub const DEFAULT_TENGU_REPO: &str = "tengu-apps/tengu-deb";
+
+/// GitHub repository (`owner/repo`) publishing the tengu-caddy .deb
+pub const DEFAULT_TENGU_CADDY_REPO: &str = "tengu-apps/tengu-caddy";
+
 /// TLS provisioning mode
 #[derive(Debug, Clone)]
 pub enum TlsMode {
@@ -25,8 +33,48 @@ impl Default for TlsMode {
     }
 }
 
+/// How Docker is installed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DockerInstallMode {
+    /// `docker.io` and `docker-compose` from the Ubuntu repositories
+    #[default]
+    AptRepo,
+    /// Docker CE via the get.docker.com convenience script
+    ConvenienceScript,
+    /// Convenience script, then rootless mode for the admin user
+    Rootless,
+}
+
+/// An extra file to write, declared in the config (`[[files]]` in TOML)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileSpec {
+    /// Destination path
+    pub path: String,
+    /// File content
+    pub content: String,
+    /// Permissions (e.g., "0644")
+    pub permissions: Option<String>,
+    /// Owner (e.g., "root:root")
+    pub owner: Option<String>,
+}
+
+/// An additional login user, declared in the config (`[[users]]` in TOML)
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserSpec {
+    /// Username
+    pub name: String,
+    /// Supplementary groups (e.g., "docker", "sudo")
+    #[serde(default)]
+    pub groups: Vec<String>,
+    /// Sudoers rule (e.g., "ALL=(ALL) NOPASSWD:ALL"); no sudoers entry when unset
+    pub sudo: Option<String>,
+    /// SSH public keys
+    #[serde(default)]
+    pub ssh_keys: Vec<String>,
+}
+
 /// Configuration for a Tengu installation
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone)]
 pub struct TenguConfig {
     /// System username
     pub user: String,
@@ -42,12 +90,93 @@ pub struct TenguConfig {
     pub notify_email: String,
     /// SSH public keys
     pub ssh_keys: Vec<String>,
+    /// Supplementary groups of `user` (default: docker and sudo)
+    pub user_groups: Option<Vec<String>>,
+    /// Additional users (e.g., other operators) created alongside `user`
+    pub users: Vec<UserSpec>,
     /// Tengu release tag
     pub release: String,
+    /// Where the tengu .deb is downloaded from: a GitHub `owner/repo`, or a
+    /// URL serving the same `releases/...` paths (e.g. an internal mirror)
+    pub tengu_repo: String,
+    /// Where the tengu-caddy .deb is downloaded from, like `tengu_repo`
+    pub tengu_caddy_repo: String,
+    /// Internal mirror serving apt repositories, keys and .deb downloads
+    /// (see [`Manifest::mirror`](crate::Manifest::mirror)); Ollama isn't
+    /// mirrored, so it's neither installed nor given models and must come
+    /// with the image
+    pub mirror: Option<String>,
+    /// System timezone (default: UTC)
+    pub timezone: String,
+    /// System locale (default: `en_US.UTF-8`)
+    pub locale: String,
     /// Enable UFW firewall configuration (CF mode only; direct always enables)
     pub enable_ufw: bool,
+    /// Install unattended-upgrades for automatic security patches
+    pub auto_upgrades: bool,
+    /// Add a swap file sized to the host's RAM (none above 16G)
+    pub auto_swap: bool,
     /// Path to local .deb package (skips download when set)
     pub deb_path: Option<String>,
+    /// Block device and mount point for `PostgreSQL` data (e.g., a Hetzner volume)
+    pub data_volume: Option<(String, String)>,
+    /// How Docker is installed (default: Ubuntu apt packages)
+    pub docker_install: DockerInstallMode,
+    /// Extra files written after the built-in configuration files
+    pub files: Vec<FileSpec>,
+    /// Caddy platform routes (subdomain → upstream port); mode defaults when empty
+    pub caddy_routes: Vec<(String, u16)>,
+    /// fail2ban `[sshd]` maxretry (default: 3)
+    pub fail2ban_maxretry: u32,
+    /// fail2ban `[sshd]` bantime in seconds (default: 3600)
+    pub fail2ban_bantime: u32,
+    /// fail2ban `[sshd]` findtime in seconds (default: 600)
+    pub fail2ban_findtime: u32,
+    /// Extra fail2ban jails (name → jail body)
+    pub fail2ban_jails: Vec<(String, String)>,
+    /// Ollama models pulled once the service is running
+    pub ollama_models: Vec<String>,
+    /// Raw commands run at the end of provisioning (cloud-init `runcmd`)
+    pub extra_runcmd: Vec<String>,
+    /// Raw commands run at the end of provisioning (cloud-init `bootcmd`)
+    pub extra_bootcmd: Vec<String>,
+}
+
+impl Default for TenguConfig {
+    fn default() -> Self {
+        Self {
+            user: String::new(),
+            domain_platform: String::new(),
+            domain_apps: String::new(),
+            tls_mode: TlsMode::default(),
+            resend_api_key: String::new(),
+            notify_email: String::new(),
+            ssh_keys: vec![],
+            user_groups: None,
+            users: vec![],
+            release: String::new(),
+            tengu_repo: DEFAULT_TENGU_REPO.into(),
+            tengu_caddy_repo: DEFAULT_TENGU_CADDY_REPO.into(),
+            mirror: None,
+            timezone: "UTC".into(),
+            locale: "en_US.UTF-8".into(),
+            enable_ufw: false,
+            auto_upgrades: false,
+            auto_swap: false,
+            deb_path: None,
+            data_volume: None,
+            docker_install: DockerInstallMode::default(),
+            files: vec![],
+            caddy_routes: vec![],
+            fail2ban_maxretry: 3,
+            fail2ban_bantime: 3600,
+            fail2ban_findtime: 600,
+            fail2ban_jails: vec![],
+            ollama_models: vec![],
+            extra_runcmd: vec![],
+            extra_bootcmd: vec![],
+        }
+    }
 }
 
 impl TenguConfig {
@@ -56,11 +185,54 @@ impl TenguConfig {
         TenguConfigBuilder::default()
     }
 
+    /// Every user to create: the primary `user` (`user_groups`,
+    /// passwordless sudo, `ssh_keys`) followed by `users`
+    pub fn user_specs(&self) -> Vec<UserSpec> {
+        let primary = UserSpec {
+            name: self.user.clone(),
+            groups: self
+                .user_groups
+                .clone()
+                .unwrap_or_else(|| vec!["docker".into(), "sudo".into()]),
+            sudo: Some("ALL=(ALL) NOPASSWD:ALL".into()),
+            ssh_keys: self.ssh_keys.clone(),
+        };
+        std::iter::once(primary)
+            .chain(self.users.iter().cloned())
+            .collect()
+    }
+
+    /// Base URL of `repo`'s releases (`https://github.com/{repo}/releases`)
+    ///
+    /// A `repo` that is already a URL is used as the base as is.
+    pub fn releases_url(repo: &str) -> String {
+        if repo.contains("://") {
+            repo.trim_end_matches('/').to_string()
+        } else {
+            format!("https://github.com/{repo}/releases")
+        }
+    }
+
     /// Whether this config uses Cloudflare mode
     pub fn is_cloudflare(&self) -> bool {
         matches!(self.tls_mode, TlsMode::Cloudflare { .. })
     }
 
+    /// Secret values (API keys) that must not appear in shared output
+    ///
+    /// Used by renderers with redaction enabled; empty values are omitted.
+    pub fn secrets(&self) -> Vec<&str> {
+        let cf_api_key = match &self.tls_mode {
+            TlsMode::Cloudflare { api_key, .. } => Some(api_key.as_str()),
+            TlsMode::Direct { .. } => None,
+        };
+        cf_api_key
+            .into_iter()
+            .chain([self.resend_api_key.as_str()])
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+
     /// ACME email address (from CF email or direct `acme_email`)
     pub fn acme_email(&self) -> &str {
         match &self.tls_mode {
@@ -71,16 +243,24 @@ impl TenguConfig {
 
     /// Generate fail2ban configuration
     pub fn fail2ban_config(&self) -> String {
-        r"[sshd]
+        let mut config = format!(
+            "[sshd]
 enabled = true
 port = ssh
 filter = sshd
 logpath = /var/log/auth.log
-maxretry = 3
-bantime = 3600
-findtime = 600
-"
-        .to_string()
+maxretry = {}
+bantime = {}
+findtime = {}
+",
+            self.fail2ban_maxretry, self.fail2ban_bantime, self.fail2ban_findtime
+        );
+
+        for (name, body) in &self.fail2ban_jails {
+            config.push_str(&format!("\n[{name}]\n{}\n", body.trim_end()));
+        }
+
+        config
     }
 
     /// Generate Tengu config.toml content
@@ -127,62 +307,50 @@ tunnel = false
         }
     }
 
+    /// Caddy routes in effect: configured routes, or the mode defaults when empty
+    ///
+    /// Defaults: `api`, `docs` (and `git` in Cloudflare mode) → `localhost:8080`.
+    pub fn effective_caddy_routes(&self) -> Vec<(String, u16)> {
+        if !self.caddy_routes.is_empty() {
+            return self.caddy_routes.clone();
+        }
+        let defaults: &[&str] = if self.is_cloudflare() {
+            &["api", "docs", "git"]
+        } else {
+            &["api", "docs"]
+        };
+        defaults.iter().map(|s| ((*s).to_string(), 8080)).collect()
+    }
+
     /// Generate Caddyfile content (mode-aware)
     pub fn caddyfile(&self) -> String {
-        match &self.tls_mode {
-            TlsMode::Cloudflare { email, .. } => format!(
-                r"{{
-    email {email}
-    # App sites are behind CF tunnel — TLS terminated at Cloudflare edge.
-    # Only platform routes (api/docs/git) use Caddy-managed TLS via DNS challenge.
-    auto_https disable_redirects
-}}
-
-(cf_tls) {{
-    tls {{
-        dns cloudflare {{env.CF_API_TOKEN}}
-    }}
-}}
-
-import sites/*.caddy
-
-api.{dp} {{
-    import cf_tls
-    reverse_proxy localhost:8080
-}}
-
-docs.{dp} {{
-    import cf_tls
-    reverse_proxy localhost:8080
-}}
-
-git.{dp} {{
-    import cf_tls
-    reverse_proxy localhost:8080
-}}
-",
-                email = email,
-                dp = self.domain_platform,
-            ),
-            TlsMode::Direct { acme_email } => format!(
-                r"{{
-    email {acme_email}
-}}
+        let mut caddyfile = format!("{{\n    email {}\n", self.acme_email());
+        if self.is_cloudflare() {
+            caddyfile.push_str(
+                "    # App sites are behind CF tunnel — TLS terminated at Cloudflare edge.\n    \
+                 # Only platform routes (api/docs/git) use Caddy-managed TLS via DNS challenge.\n    \
+                 auto_https disable_redirects\n",
+            );
+        }
+        caddyfile.push_str("}\n");
 
-import sites/*.caddy
+        if self.is_cloudflare() {
+            caddyfile.push_str(
+                "\n(cf_tls) {\n    tls {\n        dns cloudflare {env.CF_API_TOKEN}\n    }\n}\n",
+            );
+        }
 
-api.{dp} {{
-    reverse_proxy localhost:8080
-}}
+        caddyfile.push_str("\nimport sites/*.caddy\n");
 
-docs.{dp} {{
-    reverse_proxy localhost:8080
-}}
-",
-                acme_email = acme_email,
-                dp = self.domain_platform,
-            ),
+        for (subdomain, port) in self.effective_caddy_routes() {
+            caddyfile.push_str(&format!("\n{subdomain}.{} {{\n", self.domain_platform));
+            if self.is_cloudflare() {
+                caddyfile.push_str("    import cf_tls\n");
+            }
+            caddyfile.push_str(&format!("    reverse_proxy localhost:{port}\n}}\n"));
         }
+
+        caddyfile
     }
 
     /// Generate Caddy systemd drop-in for Cloudflare API credentials
@@ -223,6 +391,7 @@ docs.{dp} {{
             release: "v0.1.0-test".into(),
             enable_ufw: true,
             deb_path: None,
+            ..Self::default()
         }
     }
 
@@ -242,6 +411,7 @@ docs.{dp} {{
             release: "v0.1.0-test".into(),
             enable_ufw: true,
             deb_path: None,
+            ..Self::default()
         }
     }
 }
@@ -295,24 +465,144 @@ impl TenguConfigBuilder {
         self
     }
 
+    /// Set the primary user's groups (`None` keeps docker and sudo)
+    pub fn user_groups(mut self, groups: Option<Vec<String>>) -> Self {
+        self.config.user_groups = groups;
+        self
+    }
+
+    /// Set the additional users created alongside the primary user
+    pub fn users(mut self, users: impl IntoIterator<Item = UserSpec>) -> Self {
+        self.config.users = users.into_iter().collect();
+        self
+    }
+
     /// Set the release tag
     pub fn release(mut self, release: impl Into<String>) -> Self {
         self.config.release = release.into();
         self
     }
 
+    /// Set where the tengu .deb is downloaded from (GitHub `owner/repo` or URL)
+    pub fn tengu_repo(mut self, repo: impl Into<String>) -> Self {
+        self.config.tengu_repo = repo.into();
+        self
+    }
+
+    /// Set where the tengu-caddy .deb is downloaded from (GitHub `owner/repo` or URL)
+    pub fn tengu_caddy_repo(mut self, repo: impl Into<String>) -> Self {
+        self.config.tengu_caddy_repo = repo.into();
+        self
+    }
+
+    /// Download every external package, key and repository from `mirror`
+    pub fn mirror(mut self, mirror: Option<String>) -> Self {
+        self.config.mirror = mirror;
+        self
+    }
+
+    /// Set the system timezone (e.g., "Europe/Berlin")
+    pub fn timezone(mut self, timezone: impl Into<String>) -> Self {
+        self.config.timezone = timezone.into();
+        self
+    }
+
+    /// Set the system locale (e.g., "de_DE.UTF-8")
+    pub fn locale(mut self, locale: impl Into<String>) -> Self {
+        self.config.locale = locale.into();
+        self
+    }
+
     /// Enable or disable UFW firewall configuration
     pub fn enable_ufw(mut self, enable: bool) -> Self {
         self.config.enable_ufw = enable;
         self
     }
 
+    /// Enable or disable automatic security upgrades
+    pub fn auto_upgrades(mut self, enable: bool) -> Self {
+        self.config.auto_upgrades = enable;
+        self
+    }
+
+    /// Enable or disable a RAM-sized swap file
+    pub fn auto_swap(mut self, enable: bool) -> Self {
+        self.config.auto_swap = enable;
+        self
+    }
+
     /// Set local .deb path
     pub fn deb_path(mut self, path: Option<String>) -> Self {
         self.config.deb_path = path;
         self
     }
 
+    /// Mount a block device for `PostgreSQL` data before it is installed
+    pub fn data_volume(mut self, device: impl Into<String>, mount_path: impl Into<String>) -> Self {
+        self.config.data_volume = Some((device.into(), mount_path.into()));
+        self
+    }
+
+    /// Set how Docker is installed
+    pub fn docker_install(mut self, mode: DockerInstallMode) -> Self {
+        self.config.docker_install = mode;
+        self
+    }
+
+    /// Set the extra files to write
+    pub fn files(mut self, files: impl IntoIterator<Item = FileSpec>) -> Self {
+        self.config.files = files.into_iter().collect();
+        self
+    }
+
+    /// Set the Ollama models to pre-pull
+    pub fn ollama_models(mut self, models: impl IntoIterator<Item = String>) -> Self {
+        self.config.ollama_models = models.into_iter().collect();
+        self
+    }
+
+    /// Set raw commands appended after all other steps
+    pub fn extra_runcmd(mut self, commands: impl IntoIterator<Item = String>) -> Self {
+        self.config.extra_runcmd = commands.into_iter().collect();
+        self
+    }
+
+    /// Set raw commands appended after all other steps, run from cloud-init `bootcmd`
+    pub fn extra_bootcmd(mut self, commands: impl IntoIterator<Item = String>) -> Self {
+        self.config.extra_bootcmd = commands.into_iter().collect();
+        self
+    }
+
+    /// Add a Caddy route proxying `<subdomain>.<domain_platform>` to a local port
+    pub fn caddy_route(mut self, subdomain: impl Into<String>, port: u16) -> Self {
+        self.config.caddy_routes.push((subdomain.into(), port));
+        self
+    }
+
+    /// Set fail2ban `[sshd]` maxretry
+    pub fn fail2ban_maxretry(mut self, maxretry: u32) -> Self {
+        self.config.fail2ban_maxretry = maxretry;
+        self
+    }
+
+    /// Set fail2ban `[sshd]` bantime in seconds
+    pub fn fail2ban_bantime(mut self, seconds: u32) -> Self {
+        self.config.fail2ban_bantime = seconds;
+        self
+    }
+
+    /// Set fail2ban `[sshd]` findtime in seconds
+    pub fn fail2ban_findtime(mut self, seconds: u32) -> Self {
+        self.config.fail2ban_findtime = seconds;
+        self
+    }
+
+    /// Add an extra fail2ban jail to `jail.local`
+    pub fn add_fail2ban_jail(mut self, name: impl Into<String>, body: impl Into<String>) -> Self {
+        self.config.fail2ban_jails.push((name.into(), body.into()));
+        self
+    }
+
     /// Build the configuration
     pub fn build(self) -> TenguConfig {
         self.config
@@ -0,0 +1,50 @@
+//! Error type for rendering and building manifests
+
+use crate::steps::TemplateError;
+
+/// Error from a [`Renderer`](crate::Renderer) or manifest operation
+#[derive(Debug, thiserror::Error)]
+pub enum ProvisionError {
+    /// A templated file failed to render
+    #[error(transparent)]
+    Template(#[from] TemplateError),
+    /// Rendered output couldn't be serialized
+    #[error("failed to serialize {format}: {source}")]
+    Serialization {
+        /// Output format (e.g. "cloud-init YAML")
+        format: &'static str,
+        /// Underlying serializer error
+        #[source]
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// The manifest is inconsistent (e.g. a step before its dependencies)
+    #[error("invalid manifest: {0}")]
+    Validation(String),
+    /// Writing rendered files failed
+    #[error("failed to write {path}: {source}")]
+    Io {
+        /// File that couldn't be written
+        path: String,
+        /// Underlying I/O error
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+impl From<serde_yaml::Error> for ProvisionError {
+    fn from(source: serde_yaml::Error) -> Self {
+        Self::Serialization {
+            format: "cloud-init YAML",
+            source: Box::new(source),
+        }
+    }
+}
+
+impl From<serde_json::Error> for ProvisionError {
+    fn from(source: serde_json::Error) -> Self {
+        Self::Serialization {
+            format: "JSON",
+            source: Box::new(source),
+        }
+    }
+}
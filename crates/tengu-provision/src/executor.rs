@@ -0,0 +1,98 @@
+//! Local step execution
+//!
+//! Runs a manifest on the current host instead of rendering it for a remote
+//! one. Each step is checked first (exit 0 skips it), then its bash commands
+//! run in a single `bash` process.
+
+use std::process::{Command, Output};
+
+use crate::manifest::Manifest;
+use crate::steps::{Step, StepResult, TRACK_PKG_FN};
+
+/// Execute manifest steps on the local host via `bash`
+#[derive(Debug, Clone, Default)]
+pub struct LocalExecutor {
+    /// Keep running the remaining steps after a failure
+    pub continue_on_error: bool,
+}
+
+impl LocalExecutor {
+    /// Create an executor that stops at the first failed step
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keep running the remaining steps after a failure
+    pub fn continue_on_error(mut self, continue_on_error: bool) -> Self {
+        self.continue_on_error = continue_on_error;
+        self
+    }
+
+    /// Run every step, returning each step's description and result
+    ///
+    /// Steps after the first failure are not run (and not returned) unless
+    /// `continue_on_error` is set. Failures of [`Step::allow_failure`] steps
+    /// are returned but don't stop the run.
+    pub fn run(&self, manifest: &Manifest) -> Vec<(String, StepResult)> {
+        self.run_with(manifest, |_, _, _| {})
+    }
+
+    /// Like [`run`](Self::run), calling `on_step(index, description, result)`
+    /// as each step finishes
+    pub fn run_with(
+        &self,
+        manifest: &Manifest,
+        mut on_step: impl FnMut(usize, &str, &StepResult),
+    ) -> Vec<(String, StepResult)> {
+        let mut results = Vec::with_capacity(manifest.steps.len());
+
+        for (i, (_, step)) in manifest.steps.iter().enumerate() {
+            let result = self.run_step(step.as_ref());
+            on_step(i, step.description(), &result);
+
+            let failed = matches!(result, StepResult::Failed(_)) && !step.allow_failure();
+            results.push((step.description().to_string(), result));
+            if failed && !self.continue_on_error {
+                break;
+            }
+        }
+
+        results
+    }
+
+    /// Run a single step: skip it if its check passes, otherwise apply it
+    pub fn run_step(&self, step: &dyn Step) -> StepResult {
+        if let Some(check) = step.check_command()
+            && bash(&check).is_ok_and(|output| output.status.success())
+        {
+            return StepResult::Skipped;
+        }
+
+        // The helpers the bash renderer defines up front, e.g. for .deb steps
+        let script = format!(
+            "set -eo pipefail\n{TRACK_PKG_FN}\n{}",
+            step.to_bash().join("\n")
+        );
+        match bash(&script) {
+            Ok(output) if output.status.success() => StepResult::Applied,
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let stderr = stderr.trim();
+                StepResult::Failed(if stderr.is_empty() {
+                    output.status.to_string()
+                } else {
+                    stderr.to_string()
+                })
+            }
+            Err(e) => StepResult::Failed(format!("failed to run bash: {e}")),
+        }
+    }
+}
+
+/// Run a script with `bash -c`, capturing its output
+fn bash(script: &str) -> std::io::Result<Output> {
+    Command::new("bash")
+        .args(["-c", script])
+        .env("DEBIAN_FRONTEND", "noninteractive")
+        .output()
+}
@@ -7,7 +7,8 @@
 //!
 //! - [`Step`] trait: Common interface for all installation steps
 //! - [`steps`] module: Concrete step implementations (packages, users, files, etc.)
-//! - [`render`] module: Output renderers (bash)
+//! - [`render`] module: Output renderers (bash, cloud-init, JSON, Dockerfile,
+//!   systemd first boot)
 //! - [`Manifest`]: Complete installation manifest combining multiple steps
 //! - [`Config`]: Configuration types for Tengu installation
 //!
@@ -21,26 +22,40 @@
 //!     .domain_platform("tengu.to")
 //!     .build();
 //!
-//! let manifest = Manifest::tengu(&config);
+//! let manifest = Manifest::tengu(&config).unwrap();
 //! let renderer = BashRenderer::new().verbose(true);
 //! let script = renderer.render(&manifest)?;
 //! ```
 
 pub mod config;
+pub mod error;
+pub mod executor;
 pub mod manifest;
 pub mod render;
 pub mod steps;
 
-pub use config::{TenguConfig, TlsMode};
-pub use manifest::Manifest;
-pub use render::{BashRenderer, Renderer};
-pub use steps::Step;
+pub use config::{
+    DEFAULT_TENGU_CADDY_REPO, DEFAULT_TENGU_REPO, DockerInstallMode, FileSpec, TenguConfig,
+    TlsMode, UserSpec,
+};
+pub use error::ProvisionError;
+pub use executor::LocalExecutor;
+pub use manifest::{Manifest, ManifestTomlError, OrderingIssue, Phase, UnknownPhase};
+pub use render::{
+    BashRenderer, CloudInitRenderer, DockerfileRenderer, JsonRenderer, PlanRenderer, Renderer,
+    STATE_FILE, SystemdFirstBootRenderer,
+};
+pub use steps::{Step, StepResult};
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::steps::{
-        EnsureDirectory, EnsureService, EnsureUser, InstallPackage, RunCommand, WriteFile,
+        CaddySite, EnsureAptHold, EnsureAptPreference, EnsureBlockInFile, EnsureDirectory,
+        EnsureDockerNetwork, EnsureDockerVolume, EnsureFirewall, EnsureHostEntry, EnsureMount,
+        EnsureService, EnsureServiceLimits, EnsureSwap, EnsureUser, GitClone, InstallDebFromUrl,
+        InstallPackage, RemovePackage, ReplaceInFile, Repository, RunCommand, RunComposeFile,
+        TemplateFile, WaitForAptLock, WriteFile,
     };
 
     #[test]
@@ -53,6 +68,67 @@ mod tests {
         assert!(bash[0].contains("|| true"));
     }
 
+    #[test]
+    fn test_install_package_repo_guarded_by_list_file() {
+        let step = InstallPackage::new("postgresql-16").with_repository(Repository::postgresql());
+        let bash = step.to_bash();
+
+        assert!(
+            bash[1].starts_with("if [ ! -f /etc/apt/sources.list.d/postgresql-16.list ]; then")
+        );
+        assert!(!bash[1].contains("grep"));
+        assert!(bash[1].contains("apt-get update"));
+    }
+
+    #[test]
+    fn test_install_package_deb822_repository() {
+        let repo = Repository::deb822(
+            "https://example.com/key.asc",
+            "https://example.com/apt",
+            "$(lsb_release -cs)",
+            "main",
+            "/usr/share/keyrings/example.gpg",
+        );
+        let bash = InstallPackage::new("example")
+            .with_repository(repo)
+            .to_bash();
+
+        assert!(bash[0].contains("gpg --dearmor -o /usr/share/keyrings/example.gpg"));
+        assert!(bash[1].starts_with("if [ ! -f /etc/apt/sources.list.d/example.sources ]; then"));
+        assert!(bash[1].contains(
+            r#""Types: deb" "URIs: https://example.com/apt" "Suites: $(lsb_release -cs)" "Components: main" "Signed-By: /usr/share/keyrings/example.gpg" > /etc/apt/sources.list.d/example.sources"#
+        ));
+    }
+
+    #[test]
+    fn test_repository_binary_key_not_dearmored() {
+        let repo = Repository::postgresql();
+        assert!(repo.key_is_armored);
+
+        let bash = InstallPackage::new("postgresql-16")
+            .with_repository(repo.key_is_armored(false))
+            .to_bash();
+
+        assert!(!bash[0].contains("gpg --dearmor"));
+        assert!(bash[0].contains(
+            "curl -fsSL -o /usr/share/keyrings/postgresql-archive-keyring.gpg https://www.postgresql.org/media/keys/ACCC4CF8.asc"
+        ));
+    }
+
+    #[test]
+    fn test_remove_package_noop_when_absent() {
+        let step = RemovePackage::new("snapd").purge(true);
+        let bash = step.to_bash();
+
+        assert!(bash[0].starts_with("if dpkg -s snapd >/dev/null 2>&1; then"));
+        assert!(bash[0].contains("apt-get purge -y snapd"));
+        assert!(bash[0].trim_end().ends_with("fi"));
+        assert_eq!(
+            step.check_command(),
+            Some("! dpkg -s snapd >/dev/null 2>&1".into())
+        );
+    }
+
     #[test]
     fn test_ensure_user_creates_user() {
         let step = EnsureUser::new("testuser")
@@ -75,6 +151,28 @@ mod tests {
         assert!(bash.iter().any(|c| c.contains("/etc/sudoers.d/testuser")));
     }
 
+    #[test]
+    fn test_ensure_system_user_has_no_login() {
+        let step = EnsureUser::new("tengu")
+            .with_ssh_keys(["ssh-ed25519 AAAA... ops@laptop"])
+            .system(true);
+
+        let bash = step.to_bash().join("\n");
+        assert!(bash.contains("useradd -r -s /usr/sbin/nologin -M tengu"));
+        assert!(!bash.contains("useradd -m"));
+        // Keys are ignored for an account nobody logs into
+        assert!(!bash.contains(".ssh"));
+        assert_eq!(
+            step.check_command(),
+            Some("id tengu >/dev/null 2>&1".into())
+        );
+
+        let user = &step.to_cloud_init().users[0];
+        assert!(user.system);
+        assert_eq!(user.shell, "/usr/sbin/nologin");
+        assert!(user.ssh_authorized_keys.is_empty());
+    }
+
     #[test]
     fn test_write_file_uses_checksum() {
         let step = WriteFile::new("/etc/test.conf", "test content").with_permissions("0644");
@@ -89,6 +187,840 @@ mod tests {
         assert!(check.unwrap().contains("sha256sum"));
     }
 
+    #[test]
+    fn test_write_file_content_cannot_terminate_script() {
+        use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+        // Lines that would end a heredoc early are embedded only as base64
+        let content =
+            "TENGU_EOF\n__TENGU_FILE_END__\n__FILE_CONTENT_END_MARKER__\n'; rm -rf /; '\n";
+        let step = WriteFile::new("/etc/evil.conf", content);
+        let bash = step.to_bash().join("\n");
+
+        assert!(!bash.lines().any(|l| content.lines().any(|c| l == c)));
+        let encoded = bash
+            .split("\necho '")
+            .nth(1)
+            .and_then(|rest| rest.split("' | base64 -d").next())
+            .unwrap();
+        assert_eq!(STANDARD.decode(encoded).unwrap(), content.as_bytes());
+    }
+
+    #[test]
+    fn test_write_file_check_matches_written_bytes() {
+        for (name, content) in [("newline", "a = 1\n"), ("no-newline", "a = 1")] {
+            let path =
+                std::env::temp_dir().join(format!("tengu-write-{name}-{}", std::process::id()));
+            let path_str = path.to_str().unwrap();
+            let step = WriteFile::new(path_str, content);
+            let bash = |script: &str| {
+                std::process::Command::new("bash")
+                    .args(["-c", script])
+                    .status()
+                    .unwrap()
+                    .success()
+            };
+
+            assert!(bash(&step.to_bash().join("\n")));
+            assert_eq!(std::fs::read(&path).unwrap(), content.as_bytes());
+
+            // The hash the check expects is the sha256sum of the written file
+            let sha256sum = std::process::Command::new("sha256sum")
+                .arg(&path)
+                .output()
+                .unwrap();
+            let written_hash = String::from_utf8_lossy(&sha256sum.stdout)
+                .split_whitespace()
+                .next()
+                .unwrap()
+                .to_string();
+            let check = step.check_command().unwrap();
+            assert!(check.contains(&format!("= \"{written_hash}\"")));
+            assert!(bash(&check));
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_git_clone_pull_and_pinned_rev() {
+        let root = std::env::temp_dir().join(format!("tengu-git-{}", std::process::id()));
+        let origin = root.join("origin");
+        std::fs::create_dir_all(&origin).unwrap();
+        let sh = |script: &str| {
+            std::process::Command::new("bash")
+                .args(["-c", script])
+                .env("GIT_AUTHOR_NAME", "t")
+                .env("GIT_AUTHOR_EMAIL", "t@example.com")
+                .env("GIT_COMMITTER_NAME", "t")
+                .env("GIT_COMMITTER_EMAIL", "t@example.com")
+                .output()
+                .unwrap()
+        };
+        let commit = |msg: &str| {
+            let out = sh(&format!(
+                "cd {} && git commit -q --allow-empty -m {msg} && git rev-parse HEAD",
+                origin.display()
+            ));
+            String::from_utf8_lossy(&out.stdout).trim().to_string()
+        };
+        sh(&format!("git init -q -b main {}", origin.display()));
+        let first = commit("first");
+        let url = format!("file://{}", origin.display());
+
+        // Tracking a branch: clone, then pull --ff-only once the remote moves
+        let dest = root.join("tracking");
+        let step = GitClone::new(&url, dest.to_str().unwrap()).branch("main");
+        let check = step.check_command().unwrap();
+        assert!(!sh(&check).status.success());
+        assert!(sh(&step.to_bash().join("\n")).status.success());
+        assert!(sh(&check).status.success());
+        let second = commit("second");
+        assert!(!sh(&check).status.success());
+        assert!(sh(&step.to_bash().join("\n")).status.success());
+        assert!(sh(&check).status.success());
+        let head = sh(&format!("git -C {} rev-parse HEAD", dest.display()));
+        assert_eq!(String::from_utf8_lossy(&head.stdout).trim(), second);
+
+        // Pinned: check fails until HEAD is the requested commit
+        let dest = root.join("pinned");
+        let step = GitClone::new(&url, dest.to_str().unwrap()).rev(&first);
+        let check = step.check_command().unwrap();
+        assert!(sh(&step.to_bash().join("\n")).status.success());
+        assert!(sh(&check).status.success());
+        let head = sh(&format!("git -C {} rev-parse HEAD", dest.display()));
+        assert_eq!(String::from_utf8_lossy(&head.stdout).trim(), first);
+
+        let step = GitClone::new(&url, dest.to_str().unwrap()).rev(&second);
+        assert!(!sh(&step.check_command().unwrap()).status.success());
+        assert!(sh(&step.to_bash().join("\n")).status.success());
+        assert!(sh(&step.check_command().unwrap()).status.success());
+        assert_eq!(
+            step.requires(),
+            [crate::steps::Dependency::Package("git".into())]
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_replace_in_file() {
+        let step = ReplaceInFile::new(
+            "/etc/postgresql/16/main/postgresql.conf",
+            "^#?max_connections = [0-9]+",
+            "max_connections = 200",
+        );
+        assert_eq!(
+            step.to_bash(),
+            [
+                "sed -i -E 's|^#?max_connections = [0-9]+|max_connections = 200|' '/etc/postgresql/16/main/postgresql.conf'"
+            ]
+        );
+        assert_eq!(
+            step.check_command().unwrap(),
+            "grep -qF -- 'max_connections = 200' '/etc/postgresql/16/main/postgresql.conf' && \
+             sed -E 's|^#?max_connections = [0-9]+|max_connections = 200|' '/etc/postgresql/16/main/postgresql.conf' \
+             | cmp -s - '/etc/postgresql/16/main/postgresql.conf'"
+        );
+    }
+
+    #[test]
+    fn test_replace_in_file_escapes_delimiters() {
+        let step = ReplaceInFile::new("/etc/app.conf", "^(path|dir) = .*", r"\1 = /srv/a|b");
+        assert_eq!(
+            step.to_bash(),
+            [r"sed -i -E 's|^(path\|dir) = .*|\1 = /srv/a\|b|' '/etc/app.conf'"]
+        );
+
+        let step =
+            ReplaceInFile::new("/etc/app.conf", "^url = .*", "url = http://x/a|b").first_only();
+        assert_eq!(
+            step.to_bash(),
+            [r"sed -i -E '0,\|^url = .*|s|^url = .*|url = http://x/a\|b|' '/etc/app.conf'"]
+        );
+        assert_eq!(
+            step.check_command().unwrap(),
+            "grep -qF -- 'url = http://x/a|b' '/etc/app.conf'"
+        );
+    }
+
+    #[test]
+    fn test_owner_validation() {
+        use crate::steps::{InvalidOwner, Owner};
+
+        for valid in [
+            "root",
+            "root:root",
+            "www-data:www-data",
+            "_apt",
+            "1000:1000",
+        ] {
+            let owner: Owner = valid.parse().unwrap();
+            assert_eq!(owner.to_string(), valid);
+        }
+        assert_eq!("0:0".parse(), Ok(Owner::Id { uid: 0, gid: 0 }));
+        for invalid in [
+            "",
+            "root:",
+            ":root",
+            "Root",
+            "root:root:root",
+            "1000",
+            "root:1000",
+            "a b",
+        ] {
+            assert_eq!(
+                invalid.parse::<Owner>(),
+                Err(InvalidOwner(invalid.to_string())),
+                "{invalid}"
+            );
+        }
+
+        let file = WriteFile::new("/etc/x", "x")
+            .with_permissions("0640")
+            .try_with_owner("root:tengu")
+            .unwrap();
+        assert_eq!(
+            file.to_bash()[2..],
+            ["chown root:tengu '/etc/x'", "chmod 0640 '/etc/x'"]
+        );
+        assert!(
+            WriteFile::new("/etc/x", "x")
+                .try_with_owner("root;rm")
+                .is_err()
+        );
+
+        let dir = EnsureDirectory::new("/srv/data").with_owner_ids(1000, 1001);
+        assert_eq!(
+            dir.to_bash(),
+            ["mkdir -p /srv/data", "chown 1000:1001 /srv/data"]
+        );
+        assert!(
+            EnsureDirectory::new("/srv")
+                .try_with_owner("root:")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_cloud_init_deferred_write_file() {
+        let manifest = Manifest::new("test")
+            .with_step(WriteFile::new("/etc/early.conf", "a"))
+            .with_step(WriteFile::new("/etc/systemd/system/worker.service", "b").deferred(true));
+        let yaml = CloudInitRenderer::new().render(&manifest).unwrap();
+        let doc: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+
+        let files = doc["write_files"].as_sequence().unwrap();
+        assert!(files[0].get("defer").is_none());
+        assert_eq!(files[1]["defer"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn test_ensure_apt_hold() {
+        let step = EnsureAptHold::new("docker-ce");
+        assert_eq!(
+            step.to_bash(),
+            ["apt-mark showhold | grep -qx docker-ce || apt-mark hold docker-ce"]
+        );
+        assert_eq!(
+            step.check_command().unwrap(),
+            "apt-mark showhold | grep -qx docker-ce"
+        );
+
+        // Holding before the package is installed is an ordering issue
+        let manifest = Manifest::new("test")
+            .with_step(EnsureAptHold::new("docker-ce"))
+            .with_step(InstallPackage::new("docker-ce"));
+        assert_eq!(manifest.validate_ordering().len(), 1);
+    }
+
+    #[test]
+    fn test_ensure_apt_preference() {
+        let step = EnsureAptPreference::new("postgresql*", "origin apt.postgresql.org", 700);
+        assert_eq!(step.path(), "/etc/apt/preferences.d/postgresql");
+        assert_eq!(
+            step.content(),
+            "Package: postgresql*\nPin: origin apt.postgresql.org\nPin-Priority: 700\n"
+        );
+        assert_eq!(
+            step.check_command(),
+            WriteFile::new(step.path(), step.content()).check_command()
+        );
+
+        let fragment = step.to_cloud_init();
+        assert_eq!(fragment.write_files[0].path, step.path());
+        assert!(
+            fragment.write_files[0]
+                .content
+                .contains("Pin-Priority: 700")
+        );
+
+        let step = step.with_name("pgdg");
+        assert_eq!(step.path(), "/etc/apt/preferences.d/pgdg");
+    }
+
+    #[test]
+    fn test_install_deb_verifies_checksum() {
+        let name = format!("tengu-deb-{}", std::process::id());
+        let step = InstallDebFromUrl::new(&name, "https://example.com/tool_{arch}.deb")
+            .with_sha256_url("https://example.com/tool_{arch}.deb.sha256");
+        let bash = step.to_bash().join("\n");
+        assert!(bash.contains(
+            "SHA256_URL=$(echo 'https://example.com/tool_{arch}.deb.sha256' | sed \"s/{arch}/$ARCH/g\")"
+        ));
+        assert!(!bash.contains("gpg"));
+
+        // Run it with wget serving a local package and checksum, and a fake dpkg
+        let dir = std::env::temp_dir().join(&name);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("pkg"), "package").unwrap();
+        let stubs = format!(
+            "cd {}\n\
+             wget() {{ if [ \"$1\" = -qO- ]; then cat sums; else cp pkg \"${{@: -1}}\"; fi; }}\n\
+             dpkg() {{ [ \"$1\" = --print-architecture ] && echo amd64 || echo INSTALLED; }}\n\
+             fuser() {{ return 1; }}\n\
+             track_pkg() {{ :; }}\n",
+            dir.display()
+        );
+        let run = |sums: &str| {
+            std::fs::write(dir.join("sums"), sums).unwrap();
+            let output = std::process::Command::new("bash")
+                .args(["-c", &format!("{stubs}{bash}")])
+                .output()
+                .unwrap();
+            (
+                output.status.success(),
+                String::from_utf8_lossy(&output.stdout).into_owned(),
+            )
+        };
+        let sum = std::process::Command::new("sha256sum")
+            .arg(dir.join("pkg"))
+            .output()
+            .unwrap();
+        let (ok, stdout) = run(&String::from_utf8_lossy(&sum.stdout));
+        assert!(ok);
+        assert!(stdout.contains("INSTALLED"));
+        // A bad or missing checksum fails the step without installing
+        for sums in [format!("{}  pkg\n", "0".repeat(64)), String::new()] {
+            let (ok, stdout) = run(&sums);
+            assert!(!ok);
+            assert!(!stdout.contains("INSTALLED"));
+        }
+        assert!(!std::path::Path::new(&format!("/tmp/{name}.deb")).exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // The tengu package is checked against its release checksum
+        let tengu = Manifest::tengu(&TenguConfig::test_config()).unwrap();
+        let install = tengu
+            .steps_in(Phase::Tengu)
+            .find(|s| s.kind() == "InstallDebFromUrl")
+            .unwrap();
+        assert!(install.to_bash()[0].contains("tengu_{arch}.deb.sha256"));
+    }
+
+    #[test]
+    fn test_install_deb_arch_map() {
+        // The URL each dpkg architecture resolves to
+        let url = |step: &InstallDebFromUrl, arch: &str| {
+            let bash = step.to_bash()[0].clone();
+            let script = &bash[..bash.find("wget").unwrap()];
+            let output = std::process::Command::new("bash")
+                .args([
+                    "-c",
+                    &format!("dpkg() {{ echo {arch}; }}\n{script}echo \"$URL\""),
+                ])
+                .output()
+                .unwrap();
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        };
+
+        let step = InstallDebFromUrl::new("tool", "https://example.com/tool-{arch}.deb")
+            .with_arch_map([("amd64", "x86_64"), ("arm64", "aarch64")]);
+        assert_eq!(url(&step, "amd64"), "https://example.com/tool-x86_64.deb");
+        assert_eq!(url(&step, "arm64"), "https://example.com/tool-aarch64.deb");
+        // Unmapped architectures pass through
+        assert_eq!(
+            url(&step, "riscv64"),
+            "https://example.com/tool-riscv64.deb"
+        );
+
+        // No map by default, and the doubled-brace placeholder is accepted too
+        let step = InstallDebFromUrl::new("tool", "https://example.com/tool-{{arch}}.deb");
+        assert!(!step.to_bash()[0].contains("case"));
+        assert_eq!(url(&step, "arm64"), "https://example.com/tool-arm64.deb");
+
+        // The map survives a manifest round trip
+        let manifest = Manifest::new("test").with_step(
+            InstallDebFromUrl::new("tool", "https://example.com/tool-{arch}.deb")
+                .with_arch_map([("amd64", "x86_64")]),
+        );
+        let restored = Manifest::from_toml(&manifest.to_toml().unwrap()).unwrap();
+        assert!(restored.steps[0].1.to_bash()[0].contains("amd64) ARCH=x86_64 ;;"));
+    }
+
+    #[test]
+    fn test_tengu_repo_sets_download_urls() {
+        let download = |manifest: &Manifest, phase| {
+            manifest
+                .steps_in(phase)
+                .find(|s| s.kind() == "InstallDebFromUrl")
+                .unwrap()
+                .to_bash()[0]
+                .clone()
+        };
+
+        let default = Manifest::tengu(&TenguConfig::test_config()).unwrap();
+        assert!(download(&default, Phase::Tengu).contains(
+            "https://github.com/tengu-apps/tengu-deb/releases/download/current/tengu_{arch}.deb"
+        ));
+        assert!(
+            download(&default, Phase::Caddy)
+                .contains("https://github.com/tengu-apps/tengu-caddy/releases/latest/download/")
+        );
+
+        let mut config = TenguConfig::test_config();
+        config.tengu_repo = "fork/tengu-deb".into();
+        config.tengu_caddy_repo = "https://artifacts.internal/caddy/".into();
+        let manifest = Manifest::tengu(&config).unwrap();
+        assert!(download(&manifest, Phase::Tengu).contains(
+            "'https://github.com/fork/tengu-deb/releases/download/current/tengu_{arch}.deb'"
+        ));
+        assert!(download(&manifest, Phase::Tengu).contains("tengu_{arch}.deb.sha256"));
+        assert!(download(&manifest, Phase::Caddy).contains(
+            "'https://artifacts.internal/caddy/latest/download/tengu-caddy_2.11.2-3_{arch}.deb'"
+        ));
+    }
+
+    #[test]
+    fn test_mirror_rewrites_external_urls() {
+        let mut config = TenguConfig::test_config();
+        config.mirror = Some("https://mirror.internal/".into());
+        config.ollama_models = vec!["llama3".into()];
+        let manifest = Manifest::tengu(&config)
+            .unwrap()
+            .with_step(InstallPackage::new("docker-ce").with_repository(Repository::docker()))
+            .with_step(
+                InstallDebFromUrl::new("tool", "https://github.com/o/r/tool_{arch}.deb")
+                    .with_signature(
+                        "https://github.com/o/r/key.asc",
+                        "https://github.com/o/r/sig",
+                    ),
+            )
+            .mirror("https://mirror.internal");
+
+        let bash = BashRenderer::new().render(&manifest).unwrap();
+        let yaml = CloudInitRenderer::new()
+            .with_config(&config)
+            .render(&manifest)
+            .unwrap();
+        // Nothing is fetched from outside the mirror (the health check only
+        // calls the server itself)
+        for output in [&bash, &yaml] {
+            for (i, _) in output.match_indices("https://") {
+                let url = &output[i..];
+                assert!(
+                    url.starts_with("https://mirror.internal/")
+                        || url.starts_with("https://api.test.example.com/"),
+                    "unmirrored URL: {}",
+                    url.lines().next().unwrap()
+                );
+            }
+            assert!(!output.contains("ollama pull"));
+        }
+        assert!(bash.contains("https://mirror.internal/apt.postgresql.org/pub/repos/apt"));
+        assert!(bash.contains("https://mirror.internal/www.postgresql.org/media/keys/"));
+        assert!(bash.contains("https://mirror.internal/download.docker.com/linux/ubuntu/gpg"));
+        assert!(bash.contains(
+            "https://mirror.internal/github.com/tengu-apps/tengu-deb/releases/download/current/tengu_{arch}.deb"
+        ));
+        // Applying the same mirror again changes nothing
+        assert!(!bash.contains("mirror.internal/mirror.internal"));
+
+        // Coalescing keeps the mirrored repositories
+        let coalesced = BashRenderer::new()
+            .render(&manifest.coalesce_apt_updates())
+            .unwrap();
+        assert!(!coalesced.contains("https://download.docker.com"));
+        assert!(coalesced.contains("https://mirror.internal/download.docker.com/linux/ubuntu"));
+    }
+
+    #[test]
+    fn test_install_deb_verifies_signature() {
+        let step = InstallDebFromUrl::new("tool", "https://example.com/tool_{arch}.deb")
+            .with_signature(
+                "https://example.com/key.asc",
+                "https://example.com/tool_{arch}.deb.sig",
+            );
+        let bash = step.to_bash().join("\n");
+        assert!(bash.contains("wget -q \"$SIG_URL\" -O /tmp/tool.deb.sig"));
+        assert!(bash.contains(
+            "wget -qO- 'https://example.com/key.asc' | gpg --homedir \"$GNUPG_DIR\" --batch --import"
+        ));
+        let verify = bash
+            .find("gpg --homedir \"$GNUPG_DIR\" --batch --verify /tmp/tool.deb.sig /tmp/tool.deb")
+            .unwrap();
+        assert!(verify < bash.find("dpkg -i").unwrap());
+        assert!(bash.contains("rm -rf \"$GNUPG_DIR\" /tmp/tool.deb.sig"));
+
+        // Same verification on first boot
+        let runcmd = &step.to_cloud_init().runcmd[0];
+        assert!(runcmd.contains("--batch --verify"));
+
+        // Survives a manifest round trip
+        let manifest = Manifest::new("test").with_step(step);
+        let restored = Manifest::from_toml(&manifest.to_toml().unwrap()).unwrap();
+        assert_eq!(restored.steps[0].1.to_bash().join("\n"), bash);
+    }
+
+    #[test]
+    fn test_ensure_service_limits_drop_in() {
+        let step = EnsureServiceLimits::new("ollama")
+            .memory_max("8G")
+            .cpu_quota("200%");
+        assert_eq!(
+            step.path(),
+            "/etc/systemd/system/ollama.service.d/limits.conf"
+        );
+        assert_eq!(step.content(), "[Service]\nMemoryMax=8G\nCPUQuota=200%\n");
+        assert_eq!(
+            step.check_command(),
+            WriteFile::new(step.path(), step.content()).check_command()
+        );
+
+        // Only runs when the drop-in changed, then reloads and restarts
+        let bash = step.to_bash();
+        assert_eq!(
+            bash[bash.len() - 2..],
+            ["systemctl daemon-reload", "systemctl try-restart ollama"]
+        );
+        let fragment = step.to_cloud_init();
+        assert!(fragment.write_files[0].content.contains("MemoryMax=8G"));
+    }
+
+    #[test]
+    fn test_local_executor_skip_apply_and_stop() {
+        let marker = std::env::temp_dir().join(format!("tengu-exec-{}", std::process::id()));
+        let marker = marker.to_str().unwrap();
+
+        let manifest = Manifest::new("test")
+            .with_step(RunCommand::new("Already done", "exit 1").unless("true"))
+            .with_step(RunCommand::new("Touch marker", format!("touch {marker}")))
+            .with_step(RunCommand::new("Break", "echo boom >&2; false"))
+            .with_step(RunCommand::new("Never reached", "true"));
+
+        let results = LocalExecutor::new().run(&manifest);
+        assert_eq!(
+            results,
+            [
+                ("Already done".to_string(), StepResult::Skipped),
+                ("Touch marker".to_string(), StepResult::Applied),
+                ("Break".to_string(), StepResult::Failed("boom".into())),
+            ]
+        );
+        assert!(std::path::Path::new(marker).exists());
+
+        let step = RunCommand::new("Touch marker", format!("touch {marker}"))
+            .unless(format!("test -f {marker}"));
+        assert_eq!(LocalExecutor::new().run_step(&step), StepResult::Skipped);
+        std::fs::remove_file(marker).unwrap();
+
+        let results = LocalExecutor::new().continue_on_error(true).run(&manifest);
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[3].1, StepResult::Applied);
+        // Package steps' track_pkg is defined, as in rendered scripts
+        let step = RunCommand::new("Has track_pkg", "type track_pkg");
+        assert_eq!(LocalExecutor::new().run_step(&step), StepResult::Applied);
+        let script = BashRenderer::new()
+            .render(&Manifest::new("test").with_step(step))
+            .unwrap();
+        assert!(script.find("track_pkg() {").unwrap() < script.find("type track_pkg").unwrap());
+    }
+
+    #[test]
+    fn test_bash_complete_marker_counts_steps() {
+        let manifest = Manifest::new("test")
+            .with_step(RunCommand::new("Already done", "true").unless("true"))
+            .with_step(RunCommand::new("Say hi", "echo hi"));
+        let script = BashRenderer::new()
+            .verbose(true)
+            .dry_run_support(true)
+            .render(&manifest)
+            .unwrap();
+        let run = |script: &str| {
+            std::process::Command::new("bash")
+                .args(["-c", script, "provision.sh", "--dry-run"])
+                .output()
+                .unwrap()
+        };
+
+        let output = run(&script);
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("TENGU_STEP:SKIP:1:Already done\n"));
+        assert!(stdout.contains("TENGU_STEP:COMPLETE:2:all steps\n"));
+
+        // A step that never reports (e.g. a dead parallel job) withholds COMPLETE
+        let dropped = script.replacen("step_done \"2\"", "true", 1);
+        let output = run(&dropped);
+        assert!(!output.status.success());
+        assert!(!String::from_utf8_lossy(&output.stdout).contains("TENGU_STEP:COMPLETE"));
+        assert!(String::from_utf8_lossy(&output.stderr).contains("only 1 of 2 steps finished"));
+    }
+
+    #[test]
+    fn test_failed_step_withholds_complete() {
+        let manifest = Manifest::new("test")
+            .with_step(RunCommand::new("Break", "false && echo unreachable"))
+            .with_step(RunCommand::new("Say hi", "echo hi"));
+        for verbose in [true, false] {
+            let script = BashRenderer::new()
+                .verbose(verbose)
+                .color(false)
+                .render(&manifest)
+                .unwrap();
+            // Just the steps and the closing checks, without service verification
+            let steps = format!(
+                "{}{}{}",
+                &script[..script.find("# Kill unattended-upgrades").unwrap()],
+                &script[script.find("dpkg --configure -a").unwrap()
+                    ..script.find("\n# Final verification").unwrap()],
+                &script[script.find("if [ \"$TENGU_FAILED\" -ne 0 ]").unwrap()..]
+            );
+
+            let output = std::process::Command::new("bash")
+                .args(["-c", &steps])
+                .output()
+                .unwrap();
+            assert!(!output.status.success());
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            assert!(!stdout.contains("unreachable"));
+            assert!(!stdout.contains("TENGU_STEP:COMPLETE"));
+            assert!(!stdout.contains("installation complete"));
+            // Later steps still run
+            assert!(stdout.contains("hi\n"));
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            assert!(stderr.contains("one or more steps failed"));
+            if verbose {
+                assert!(stdout.contains("TENGU_STEP:FAIL:1:Break\n"));
+                assert!(!stdout.contains("TENGU_STEP:DONE:1:"));
+                assert!(stdout.contains("TENGU_STEP:DONE:2:Say hi"));
+            } else {
+                assert!(stderr.contains("ERROR: Break failed"));
+            }
+        }
+    }
+
+    #[test]
+    fn test_allow_failure_warns_and_continues() {
+        let manifest = Manifest::new("test")
+            .with_step(
+                RunCommand::new("Best effort", "false && echo unreachable").allow_failure(true),
+            )
+            .with_step(RunCommand::new("Say hi", "echo hi"));
+        let script = BashRenderer::new()
+            .verbose(true)
+            .color(false)
+            .render(&manifest)
+            .unwrap();
+        // Just the steps: no apt lock handling or service verification
+        let start = script.find("# Kill unattended-upgrades").unwrap();
+        let end = script.find("dpkg --configure -a").unwrap();
+        let steps = format!(
+            "{}{}",
+            &script[..start],
+            &script[end..script.find("\n# Final verification").unwrap()]
+        );
+
+        let output = std::process::Command::new("bash")
+            .args(["-c", &steps])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("TENGU_STEP:WARN:1:Best effort\n"));
+        assert!(!stdout.contains("TENGU_STEP:DONE:1:"));
+        assert!(!stdout.contains("unreachable"));
+        assert!(stdout.contains("TENGU_STEP:DONE:2:Say hi"));
+
+        // The executor records the failure but keeps going
+        let results = LocalExecutor::new().run(&manifest);
+        assert!(matches!(results[0].1, StepResult::Failed(_)));
+        assert_eq!(results[1].1, StepResult::Applied);
+
+        // The ollama steps use it instead of `|| true`
+        let tengu = Manifest::tengu(&TenguConfig::test_config()).unwrap();
+        let ollama: Vec<_> = tengu.steps_in(Phase::Ollama).collect();
+        assert!(ollama.iter().all(|s| s.allow_failure()));
+        assert!(!ollama[0].to_bash()[0].contains("exit 0"));
+    }
+
+    #[test]
+    fn test_resume_skips_recorded_steps() {
+        let manifest = Manifest::new("test")
+            .with_step(RunCommand::new("First", "echo first"))
+            .with_step(RunCommand::new("Second", "echo second"));
+        let script = BashRenderer::new()
+            .verbose(true)
+            .color(false)
+            .resume_support(true)
+            .render(&manifest)
+            .unwrap();
+        assert!(script.contains("tengu_record_step 1\n"));
+        assert!(script.contains("tengu_record_step 2\n"));
+        assert!(script.contains(&format!("TENGU_STATE_FILE={STATE_FILE}\n")));
+
+        // Run just the steps against a scratch state file
+        let state = std::env::temp_dir().join(format!("tengu-state-{}", std::process::id()));
+        let start = script.find("# Kill unattended-upgrades").unwrap();
+        let end = script.find("dpkg --configure -a").unwrap();
+        let steps = format!(
+            "{}{}",
+            &script[..start],
+            &script[end..script.find("\n# Final verification").unwrap()]
+        )
+        .replace(STATE_FILE, &state.display().to_string());
+        let run = |args: &[&str]| {
+            let output = std::process::Command::new("bash")
+                .args(["-c", &steps, "bash"])
+                .args(args)
+                .output()
+                .unwrap();
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        };
+
+        std::fs::write(&state, format!("1 {}\n", manifest.fingerprint())).unwrap();
+        let stdout = run(&["--resume"]);
+        assert!(stdout.contains("TENGU_STEP:SKIP:1:First"));
+        assert!(stdout.contains("TENGU_STEP:DONE:2:Second"));
+        assert_eq!(
+            std::fs::read_to_string(&state).unwrap(),
+            format!("2 {}\n", manifest.fingerprint())
+        );
+
+        // A state file from another manifest, or no --resume, runs everything
+        std::fs::write(&state, "1 other\n").unwrap();
+        assert!(run(&["--resume"]).contains("TENGU_STEP:DONE:1:First"));
+        std::fs::write(&state, format!("1 {}\n", manifest.fingerprint())).unwrap();
+        assert!(run(&[]).contains("TENGU_STEP:DONE:1:First"));
+        std::fs::remove_file(&state).unwrap();
+
+        // A failed step and everything after it stay unrecorded
+        let manifest = Manifest::new("test")
+            .with_step(RunCommand::new("First", "echo first"))
+            .with_step(RunCommand::new("Break", "false"))
+            .with_step(RunCommand::new("Third", "echo third"));
+        let script = BashRenderer::new()
+            .verbose(true)
+            .color(false)
+            .resume_support(true)
+            .render(&manifest)
+            .unwrap();
+        let steps = format!(
+            "{}{}",
+            &script[..script.find("# Kill unattended-upgrades").unwrap()],
+            &script[script.find("dpkg --configure -a").unwrap()..]
+        )
+        .replace(STATE_FILE, &state.display().to_string());
+        let output = std::process::Command::new("bash")
+            .args(["-c", &steps])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("TENGU_STEP:DONE:3:Third"));
+        assert_eq!(
+            std::fs::read_to_string(&state).unwrap(),
+            format!("1 {}\n", manifest.fingerprint())
+        );
+        std::fs::remove_file(&state).unwrap();
+    }
+
+    #[test]
+    fn test_plan_renderer_lists_every_step() {
+        let manifest = Manifest::tengu(&TenguConfig::test_config()).unwrap();
+        let plan = PlanRenderer::new().render(&manifest).unwrap();
+
+        for (_, step) in &manifest.steps {
+            assert!(
+                plan.contains(&step.description().replace(['\r', '\n'], " ")),
+                "missing {}",
+                step.description()
+            );
+        }
+        assert!(plan.contains("== system ("));
+        assert!(plan.contains("no check: always runs"));
+        assert!(!plan.contains('\x1b'));
+
+        // A report, not a script
+        let output = std::process::Command::new("bash")
+            .args(["-n", "-c", &plan])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+
+        let colored = PlanRenderer::new().color(true).render(&manifest).unwrap();
+        assert!(colored.contains("\x1b[1;36m== system ("));
+    }
+
+    #[test]
+    fn test_ensure_block_in_file_replaces_existing_block() {
+        let path = std::env::temp_dir().join(format!("tengu-block-{}", std::process::id()));
+        std::fs::write(&path, "alias ll='ls -l'\n").unwrap();
+        let path_str = path.to_str().unwrap();
+
+        let run = |step: &EnsureBlockInFile| {
+            let status = std::process::Command::new("bash")
+                .args(["-c", &step.to_bash().join("\n")])
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        let check = |step: &EnsureBlockInFile| {
+            std::process::Command::new("bash")
+                .args(["-c", &step.check_command().unwrap()])
+                .status()
+                .unwrap()
+                .success()
+        };
+
+        let old = EnsureBlockInFile::new(path_str, "export A=1\n");
+        assert!(!check(&old));
+        run(&old);
+        assert!(check(&old));
+
+        let new = EnsureBlockInFile::new(path_str, "export A=2\nexport B='x/y'");
+        assert!(!check(&new));
+        run(&new);
+        assert!(check(&new));
+        assert!(!check(&old));
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(content.matches("# BEGIN tengu").count(), 1);
+        assert_eq!(content.matches("# END tengu").count(), 1);
+        assert!(content.starts_with("alias ll='ls -l'\n# BEGIN tengu "));
+        assert!(content.ends_with("export A=2\nexport B='x/y'\n# END tengu\n"));
+        assert!(!content.contains("A=1"));
+    }
+
+    #[test]
+    fn test_template_file_renders_to_write_file() {
+        let step = TemplateFile::new("/etc/app.conf", "port = {{ port }}\n")
+            .context(serde_json::json!({ "port": 3000 }))
+            .with_permissions("0600")
+            .render()
+            .unwrap();
+
+        assert_eq!(step.content, "port = 3000\n");
+        assert_eq!(step.permissions.as_deref(), Some("0600"));
+        // Rendered content is what gets hashed
+        let expected = WriteFile::new("/etc/app.conf", "port = 3000\n").check_command();
+        assert_eq!(step.check_command(), expected);
+
+        let err = TemplateFile::new("/etc/bad.conf", "{{ missing }}")
+            .render()
+            .unwrap_err();
+        assert!(err.to_string().contains("/etc/bad.conf"));
+    }
+
     #[test]
     fn test_ensure_directory_idempotent() {
         let step = EnsureDirectory::new("/var/lib/tengu")
@@ -100,6 +1032,190 @@ mod tests {
         assert!(check.unwrap().contains("[ -d /var/lib/tengu ]"));
     }
 
+    #[test]
+    fn test_ensure_swap_idempotent() {
+        let step = EnsureSwap::new("2G").swappiness(10);
+        let bash = step.to_bash();
+
+        assert_eq!(
+            step.check_command(),
+            Some("swapon --show | grep -q /swapfile".into())
+        );
+        assert!(bash.iter().any(|c| c.contains("fallocate -l 2G /swapfile")));
+        assert!(
+            bash.iter()
+                .any(|c| c.contains("grep -q '^/swapfile ' /etc/fstab"))
+        );
+        assert!(bash.iter().any(|c| c.contains("vm.swappiness = 10")));
+
+        let bootcmd = step.to_cloud_init().bootcmd;
+        assert!(bootcmd[0].starts_with("if ! (swapon --show | grep -q /swapfile); then"));
+    }
+
+    #[test]
+    fn test_manifest_auto_swap_opt_in() {
+        let manifest = Manifest::tengu(&TenguConfig::test_config()).unwrap();
+        assert!(!manifest.steps.iter().any(|(_, s)| s.kind() == "EnsureSwap"));
+
+        let mut config = TenguConfig::test_config();
+        config.auto_swap = true;
+        let manifest = Manifest::tengu(&config).unwrap();
+        let step = manifest
+            .steps_in(Phase::System)
+            .find(|s| s.kind() == "EnsureSwap")
+            .unwrap();
+
+        // Sized from /proc/meminfo at run time, capped at 4G
+        let bash = step.to_bash();
+        assert!(bash[0].starts_with("TENGU_SWAP_MB=$(awk '/^MemTotal:/"));
+        assert!(bash[0].contains("mb < 4096 ? mb : 4096"));
+        assert!(
+            bash.iter()
+                .any(|c| c.contains("fallocate -l ${TENGU_SWAP_MB}M /swapfile"))
+        );
+        // Hosts above 16G of RAM skip the step
+        let check = step.check_command().unwrap();
+        assert!(check.starts_with("swapon --show | grep -q /swapfile || "));
+        assert!(check.contains("$2 / 1024 > 16384"));
+    }
+
+    #[test]
+    fn test_ensure_host_entry() {
+        let step = EnsureHostEntry::new("10.0.0.5", "db.internal").with_aliases(["db"]);
+        let bash = step.to_bash();
+
+        assert_eq!(
+            step.check_command(),
+            Some(r"grep -qE '^10\.0\.0\.5\s+db\.internal db$' /etc/hosts".into())
+        );
+        // Existing lines for the hostname are replaced, not duplicated
+        assert!(bash[0].starts_with("awk -v h='db.internal'"));
+        assert_eq!(
+            bash[2],
+            "printf '%s\\n' '10.0.0.5\tdb.internal db' >> /etc/hosts"
+        );
+    }
+
+    #[test]
+    fn test_ensure_firewall_ipv6_and_logging() {
+        let plain = EnsureFirewall::new().allow("22/tcp");
+        assert_eq!(
+            plain.to_bash(),
+            [
+                "ufw default deny incoming",
+                "ufw default allow outgoing",
+                "ufw allow 22/tcp",
+                "ufw status | grep -q 'Status: active' || ufw --force enable",
+            ]
+        );
+        assert_eq!(
+            plain.check_command().unwrap(),
+            "ufw status | grep -q 'Status: active'"
+        );
+
+        let step = EnsureFirewall::new()
+            .allow("22/tcp")
+            .ipv6(true)
+            .logging("low");
+        let bash = step.to_bash();
+
+        // IPv6 is set before any rule is added, and reloads an active firewall
+        assert!(bash[0].starts_with("grep -qx 'IPV6=yes' /etc/default/ufw || {"));
+        assert!(bash[0].contains("sed -i 's/^IPV6=.*/IPV6=yes/' /etc/default/ufw"));
+        assert!(bash[0].contains("ufw reload"));
+        assert_eq!(bash.last().unwrap(), "ufw logging low");
+
+        let check = step.check_command().unwrap();
+        assert!(check.contains("grep -qx 'IPV6=yes' /etc/default/ufw"));
+        assert!(check.contains("grep -q 'Logging: on (low)'"));
+    }
+
+    #[test]
+    fn test_ensure_firewall_reset_before_apply() {
+        let step = EnsureFirewall::new()
+            .allow("443/tcp")
+            .allow("22/tcp")
+            .reset_before_apply(true);
+        assert_eq!(
+            step.to_bash(),
+            [
+                "ufw --force reset",
+                "ufw default deny incoming",
+                "ufw default allow outgoing",
+                "ufw allow 22/tcp",
+                "ufw allow 443/tcp",
+                "ufw status | grep -q 'Status: active' || ufw --force enable",
+            ]
+        );
+        assert!(step.check_command().unwrap().contains(
+            r#"[ "$(ufw show added | grep '^ufw ' | sort)" = "$(printf '%s\n' 'ufw allow 22/tcp' 'ufw allow 443/tcp' | sort)" ]"#
+        ));
+
+        // SSH stays reachable even when not declared
+        let bash = EnsureFirewall::new()
+            .allow("80/tcp")
+            .reset_before_apply(true)
+            .to_bash();
+        assert_eq!(bash[3], "ufw allow 22/tcp");
+        assert_eq!(bash[4], "ufw allow 80/tcp");
+    }
+
+    #[test]
+    fn test_ensure_firewall_app_profiles() {
+        let step = EnsureFirewall::new()
+            .allow_app("OpenSSH")
+            .allow_app("Nginx Full");
+        let bash = step.to_bash();
+        assert_eq!(bash[2], "ufw allow OpenSSH");
+        assert_eq!(bash[3], "ufw allow 'Nginx Full'");
+        assert!(!bash[3].chars().any(|c| c.is_ascii_digit()));
+        assert_eq!(
+            step.check_command().unwrap(),
+            "ufw status | grep -q 'Status: active'"
+        );
+
+        // With reset, the profile counts as the SSH rule and is quoted in the check
+        let step = step.reset_before_apply(true);
+        assert_eq!(step.to_bash()[3], "ufw allow OpenSSH");
+        assert!(
+            step.check_command()
+                .unwrap()
+                .contains(r"'ufw allow OpenSSH' 'ufw allow '\''Nginx Full'\'''")
+        );
+
+        let profile = EnsureFirewall::app_profile("Tengu Git", "Tengu git daemon", "9418/tcp");
+        assert_eq!(profile.path, "/etc/ufw/applications.d/tengu-git");
+        assert_eq!(
+            profile.content,
+            "[Tengu Git]\ntitle=Tengu git daemon\ndescription=Tengu git daemon\nports=9418/tcp\n"
+        );
+    }
+
+    #[test]
+    fn test_ensure_mount_formats_only_empty_device() {
+        let step = EnsureMount::new("/dev/sdb", "/var/lib/postgresql");
+        let bash = step.to_bash();
+
+        assert_eq!(
+            bash[0],
+            r#"[ -n "$(blkid -o value -s TYPE /dev/sdb)" ] || mkfs.ext4 -q /dev/sdb"#
+        );
+        assert!(bash[2].ends_with(
+            "|| echo '/dev/sdb /var/lib/postgresql ext4 defaults,nofail 0 2' >> /etc/fstab"
+        ));
+        assert_eq!(
+            step.check_command().unwrap(),
+            r"mountpoint -q /var/lib/postgresql && grep -qE '^\S+\s+/var/lib/postgresql\s' /etc/fstab"
+        );
+
+        // Mounted before PostgreSQL is installed
+        let mut config = TenguConfig::test_config();
+        config.data_volume = Some(("/dev/sdb".into(), "/var/lib/postgresql".into()));
+        let manifest = Manifest::tengu(&config).unwrap();
+        let postgres: Vec<&str> = manifest.steps_in(Phase::Postgres).map(Step::kind).collect();
+        assert_eq!(postgres[0], "EnsureMount");
+    }
+
     #[test]
     fn test_ensure_service_idempotent() {
         let step = EnsureService::new("docker");
@@ -122,27 +1238,135 @@ mod tests {
     }
 
     #[test]
-    fn test_manifest_tengu_has_all_phases() {
-        let config = TenguConfig::test_config();
-        let manifest = Manifest::tengu(&config);
+    fn test_run_command_retries() {
+        let step = RunCommand::new("Flaky", "curl -fsSL https://example.com")
+            .unless("[ -f /done ]")
+            .retries(3);
 
-        // Should have many steps
+        let bash = step.to_bash();
+
+        // Guard short-circuits before the retry loop
         assert!(
-            manifest.steps.len() > 20,
-            "Expected many steps, got {}",
-            manifest.steps.len()
+            bash[0]
+                .starts_with("[ -f /done ] || { tengu_ok=0; for tengu_attempt in $(seq 1 3); do")
         );
+        assert!(bash[0].contains("curl -fsSL https://example.com && { tengu_ok=1; break; };"));
+        // Last attempt skips the sleep
+        assert!(bash[0].contains("[ \"$tengu_attempt\" -lt 3 ] && sleep 2;"));
 
-        // Check for key steps by description
-        let descriptions: Vec<&str> = manifest.steps.iter().map(|s| s.description()).collect();
+        // An exhausted retry fails, even under errexit
+        let run = |cmd: &str| {
+            let script = RunCommand::new("Retry", cmd).retries(2).to_bash()[0].clone();
+            std::process::Command::new("bash")
+                .args(["-ec", &format!("{script}\necho after")])
+                .output()
+                .unwrap()
+        };
+        let output = run("false");
+        assert!(!output.status.success());
+        assert!(!String::from_utf8_lossy(&output.stdout).contains("after"));
+        assert!(run("true").status.success());
+    }
 
-        // User setup
-        assert!(descriptions.iter().any(|d| d.contains("user")));
-        // Packages
-        assert!(descriptions.iter().any(|d| d.contains("curl")));
-        // Docker
-        assert!(descriptions.iter().any(|d| d.contains("docker")));
-        // PostgreSQL
+    #[test]
+    fn test_run_command_timeout() {
+        let step = RunCommand::new("Slow", "echo 'hi' && sleep 100").timeout_secs(30);
+
+        let bash = step.to_bash();
+
+        assert_eq!(
+            bash[0],
+            r"timeout 30 bash -c 'echo '\''hi'\'' && sleep 100'"
+        );
+    }
+
+    #[test]
+    fn test_run_command_as_user_with_env() {
+        let step = RunCommand::new(
+            "Enable pgvector",
+            r#"psql -d tengu -c "CREATE EXTENSION IF NOT EXISTS vector;""#,
+        )
+        .as_user("postgres")
+        .env("PGCONNECT_TIMEOUT", "5")
+        .unless(r#"psql -d tengu -tAc "SELECT 1 FROM pg_extension WHERE extname='vector'""#);
+
+        let bash = step.to_bash();
+        let check = step.check_command().unwrap();
+
+        assert_eq!(
+            check,
+            r#"sudo -u 'postgres' env PGCONNECT_TIMEOUT='5' sh -c 'psql -d tengu -tAc "SELECT 1 FROM pg_extension WHERE extname='\''vector'\''"'"#
+        );
+        assert!(bash[0].starts_with(&format!("{check} || {{ sudo -u 'postgres' env")));
+        assert!(
+            bash[0].contains(
+                r#"sh -c 'psql -d tengu -c "CREATE EXTENSION IF NOT EXISTS vector;"'; }"#
+            )
+        );
+
+        // The tengu database steps run psql through as_user, not inline sudo
+        let manifest = Manifest::tengu(&TenguConfig::test_config()).unwrap();
+        let database: Vec<String> = manifest
+            .steps
+            .iter()
+            .filter(|(_, step)| step.description().contains("PostgreSQL"))
+            .flat_map(|(_, step)| step.to_bash())
+            .filter(|cmd| cmd.contains("psql -c"))
+            .collect();
+        assert_eq!(database.len(), 3);
+        for cmd in &database {
+            assert!(cmd.contains("sudo -u 'postgres' sh -c 'psql -c"), "{cmd}");
+            assert!(!cmd.contains("sudo -u postgres psql"), "{cmd}");
+        }
+    }
+
+    #[test]
+    fn test_run_command_quotes_user() {
+        // A user with shell metacharacters stays one sudo argument
+        let step = RunCommand::new("Whoami", "whoami").as_user("app; rm -rf /");
+        assert_eq!(step.to_bash(), ["sudo -u 'app; rm -rf /' sh -c 'whoami'"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid environment variable name")]
+    fn test_run_command_rejects_invalid_env_name() {
+        let _ = RunCommand::new("Print", "env").env("A=B; touch /tmp/x", "1");
+    }
+
+    #[test]
+    fn test_run_command_accepts_env_names() {
+        for key in ["PATH", "_private", "PG_CONNECT_2"] {
+            let step = RunCommand::new("Print", "env").env(key, "1");
+            assert!(step.to_bash()[0].contains(&format!("env {key}='1' ")));
+        }
+    }
+
+    #[test]
+    fn test_manifest_tengu_has_all_phases() {
+        let config = TenguConfig::test_config();
+        let manifest = Manifest::tengu(&config).unwrap();
+
+        // Should have many steps
+        assert!(
+            manifest.steps.len() > 20,
+            "Expected many steps, got {}",
+            manifest.steps.len()
+        );
+
+        // Check for key steps by description
+        let descriptions: Vec<&str> = manifest
+            .steps
+            .iter()
+            .map(|(_, s)| s.description())
+            .collect();
+
+        // User setup
+        assert!(descriptions.iter().any(|d| d.contains("user")));
+        // Packages
+        assert!(descriptions.iter().any(|d| d.contains("curl")));
+        // Docker
+        assert!(descriptions.iter().any(|d| d.contains("docker")));
+        // PostgreSQL
         assert!(descriptions.iter().any(|d| d.contains("postgresql")));
         // Firewall
         assert!(
@@ -156,10 +1380,304 @@ mod tests {
         assert!(descriptions.iter().any(|d| d.contains("/bin/bash")));
     }
 
+    #[test]
+    fn test_system_basics_bash_only() {
+        let config = TenguConfig::test_config();
+        let manifest = Manifest::tengu(&config).unwrap();
+
+        let script = BashRenderer::new().render(&manifest).unwrap();
+        assert!(script.contains("hostnamectl set-hostname tengu"));
+        assert!(script.contains("timedatectl set-timezone UTC"));
+        assert!(script.contains("update-locale LANG=en_US.UTF-8"));
+
+        // Cloud-init uses its native keys instead of runcmd
+        let yaml = CloudInitRenderer::new().render(&manifest).unwrap();
+        assert!(yaml.contains("hostname: tengu"));
+        assert!(yaml.contains("timezone: UTC"));
+        assert!(!yaml.contains("hostnamectl"));
+        assert!(!yaml.contains("timedatectl"));
+        assert!(!yaml.contains("update-locale"));
+    }
+
+    #[test]
+    fn test_manifest_custom_timezone_and_locale() {
+        let config = TenguConfig {
+            timezone: "Europe/Berlin".into(),
+            locale: "de_DE.UTF-8".into(),
+            ..TenguConfig::test_config()
+        };
+        let manifest = Manifest::tengu(&config).unwrap();
+
+        let yaml = CloudInitRenderer::new().render(&manifest).unwrap();
+        let doc: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(doc["timezone"].as_str(), Some("Europe/Berlin"));
+        assert_eq!(doc["locale"].as_str(), Some("de_DE.UTF-8"));
+
+        let script = BashRenderer::new().render(&manifest).unwrap();
+        assert!(script.contains("timedatectl set-timezone Europe/Berlin"));
+        assert!(script.contains("update-locale LANG=de_DE.UTF-8"));
+    }
+
+    #[test]
+    fn test_manifest_minimal_omits_database_and_ai() {
+        let config = TenguConfig::test_config();
+        let is_postgres = |s: &dyn Step| s.to_bash().join("\n").contains("postgresql");
+
+        let tengu = Manifest::tengu(&config).unwrap();
+        assert!(tengu.steps.iter().any(|(_, s)| is_postgres(s.as_ref())));
+
+        let minimal = Manifest::minimal(&config);
+        assert!(!minimal.steps.iter().any(|(_, s)| is_postgres(s.as_ref())));
+        for phase in [
+            Phase::Postgres,
+            Phase::Ollama,
+            Phase::Tengu,
+            Phase::HealthCheck,
+        ] {
+            assert_eq!(minimal.steps_in(phase).count(), 0, "{phase}");
+        }
+        for phase in [
+            Phase::UserSetup,
+            Phase::BasePackages,
+            Phase::Docker,
+            Phase::Caddy,
+        ] {
+            assert!(minimal.steps_in(phase).count() > 0, "{phase}");
+        }
+        assert!(minimal.validate_ordering().is_empty());
+        // Only the services each manifest starts are verified at the end
+        let verified = |manifest: &Manifest| {
+            let script = BashRenderer::new().render(manifest).unwrap();
+            script
+                .lines()
+                .find(|line| line.starts_with("for svc in "))
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            verified(&tengu),
+            "for svc in 'docker' 'postgresql' 'caddy' 'tengu'; do"
+        );
+        assert!(!verified(&minimal).contains("postgresql"));
+        let script = BashRenderer::new().render(&Manifest::new("test")).unwrap();
+        assert!(!script.contains("for svc in"));
+    }
+
+    #[test]
+    fn test_manifest_filter_tags() {
+        let config = TenguConfig::test_config();
+        let manifest = Manifest::tengu(&config)
+            .unwrap()
+            .filter_tags(&["database".into()], &[]);
+
+        let descriptions: Vec<&str> = manifest
+            .steps
+            .iter()
+            .map(|(_, step)| step.description())
+            .collect();
+        assert!(descriptions.contains(&"Install postgresql-16"));
+        assert!(descriptions.contains(&"Start PostgreSQL"));
+        assert!(descriptions.contains(&"Enable pgvector extension"));
+        assert!(!descriptions.contains(&"Install Ollama"));
+        assert!(!descriptions.iter().any(|d| d.contains("docker")));
+        for i in 0..manifest.step_count() {
+            assert_eq!(manifest.tags(i), ["database"]);
+        }
+
+        // Progress markers are renumbered for the remaining steps
+        let total = manifest.step_count();
+        let script = BashRenderer::new().verbose(true).render(&manifest).unwrap();
+        assert!(script.contains("step_start \"1\" 'Install postgresql-16'"));
+        assert!(script.contains(&format!("step_start \"{total}\"")));
+        assert!(!script.contains(&format!("step_start \"{}\"", total + 1)));
+        assert!(script.contains(&format!("TENGU_STEP:COMPLETE:{total}:")));
+
+        let without_ai = Manifest::tengu(&config)
+            .unwrap()
+            .filter_tags(&[], &["ai".into()]);
+        assert_eq!(
+            without_ai.step_count(),
+            Manifest::tengu(&config).unwrap().step_count() - 3
+        );
+    }
+
+    #[test]
+    fn test_manifest_waits_for_apt_lock_before_packages() {
+        let config = TenguConfig::test_config();
+        let manifest = Manifest::tengu(&config).unwrap();
+
+        let kinds: Vec<&str> = manifest.steps.iter().map(|(_, s)| s.kind()).collect();
+        let wait = kinds.iter().position(|k| *k == "WaitForAptLock").unwrap();
+        let first_apt = kinds
+            .iter()
+            .position(|k| ["InstallPackage", "InstallDebFromUrl", "RemovePackage"].contains(k))
+            .unwrap();
+        assert!(wait < first_apt);
+
+        let step = WaitForAptLock::new().timeout_secs(30);
+        let bash = step.to_bash().join("\n");
+        assert!(bash.contains("while fuser /var/lib/dpkg/lock-frontend"));
+        assert!(bash.contains("-ge 30"));
+
+        let script = BashRenderer::new().render(&manifest).unwrap();
+        let phase_start = script.find("# === Phase: base-packages ===").unwrap();
+        let wait_loop = script[phase_start..]
+            .find("while fuser /var/lib/dpkg/lock-frontend /var/lib/dpkg/lock ");
+        let install = script[phase_start..].find("apt-get install -y");
+        assert!(wait_loop.unwrap() < install.unwrap());
+    }
+
+    #[test]
+    fn test_coalesce_apt_updates_runs_one_update() {
+        let mut manifest = Manifest::new("test");
+        manifest.add_step(InstallPackage::new("docker-ce").with_repository(Repository::docker()));
+        manifest
+            .add_step(InstallPackage::new("docker-ce-cli").from_repository(&Repository::docker()));
+        manifest.add_tagged(
+            &["database"],
+            InstallPackage::new("postgresql-16").with_repository(Repository::postgresql()),
+        );
+        let script = BashRenderer::new().render(&manifest).unwrap();
+        assert_eq!(script.matches("apt-get update").count(), 2);
+
+        let manifest = manifest.coalesce_apt_updates();
+        let kinds: Vec<_> = manifest.steps.iter().map(|(_, s)| s.kind()).collect();
+        assert_eq!(kinds[0], "AddAptRepositories");
+        assert_eq!(manifest.tags(0), ["database"]);
+        assert!(manifest.validate_ordering().is_empty());
+
+        let script = BashRenderer::new().render(&manifest).unwrap();
+        assert_eq!(script.matches("apt-get update").count(), 1);
+        // Same source files as the per-package setup
+        assert!(script.contains("/etc/apt/sources.list.d/docker-ce.list"));
+        assert!(script.contains("/etc/apt/sources.list.d/postgresql-16.list"));
+        let update = script.find("apt-get update").unwrap();
+        assert!(update < script.find("apt-get install -y docker-ce").unwrap());
+
+        // The new step survives a TOML round trip
+        let toml = manifest.to_toml().unwrap();
+        assert_eq!(
+            Manifest::from_toml(&toml).unwrap().fingerprint(),
+            manifest.fingerprint()
+        );
+
+        // A single repository is left alone
+        let config = TenguConfig::test_config();
+        assert_eq!(
+            Manifest::tengu(&config)
+                .unwrap()
+                .coalesce_apt_updates()
+                .fingerprint(),
+            Manifest::tengu(&config).unwrap().fingerprint()
+        );
+    }
+
+    #[test]
+    fn test_manifest_packages_only() {
+        let config = TenguConfig::test_config();
+        let manifest = Manifest::tengu(&config).unwrap().packages_only();
+
+        assert_eq!(manifest.step_count(), 1);
+        assert_eq!(manifest.steps[0].1.kind(), "InstallPackages");
+
+        let script = BashRenderer::new().render(&manifest).unwrap();
+        assert_eq!(script.matches("apt-get update").count(), 1);
+        assert_eq!(script.matches("apt-get install").count(), 1);
+        assert!(script.contains("postgresql"));
+        assert!(script.contains("/usr/share/keyrings/postgresql-archive-keyring.gpg"));
+        for unrelated in ["useradd", "ufw allow", "/etc/caddy/Caddyfile"] {
+            assert!(!script.contains(unrelated), "{unrelated}");
+        }
+        for cmd in manifest.steps[0].1.to_bash() {
+            assert!(
+                [
+                    "apt-get",
+                    "/etc/apt/",
+                    "/usr/share/keyrings/",
+                    "/var/lib/dpkg/"
+                ]
+                .iter()
+                .any(|p| cmd.contains(p)),
+                "{cmd}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_manifest_ollama_model_pull() {
+        let config = TenguConfig {
+            ollama_models: vec!["llama3".into()],
+            ..TenguConfig::test_config()
+        };
+        let manifest = Manifest::tengu(&config).unwrap();
+        let steps: Vec<_> = manifest.steps_in(Phase::Services).collect();
+
+        let start = steps
+            .iter()
+            .position(|s| s.description() == "Start ollama service")
+            .unwrap();
+        let pull = steps[start + 1];
+        assert_eq!(pull.description(), "Pull ollama model llama3");
+        assert!(!pull.parallel_safe());
+        assert_eq!(
+            pull.check_command().unwrap(),
+            "ollama show 'llama3' >/dev/null 2>&1"
+        );
+        let bash = pull.to_bash().join("\n");
+        assert!(bash.contains("ollama pull 'llama3' && { tengu_ok=1; break; }"));
+
+        let manifest = Manifest::tengu(&TenguConfig::test_config()).unwrap();
+        assert!(
+            !manifest
+                .steps
+                .iter()
+                .any(|(_, s)| s.description().starts_with("Pull ollama"))
+        );
+    }
+
+    #[test]
+    fn test_manifest_extra_commands_appended() {
+        let config = TenguConfig {
+            extra_runcmd: vec!["ollama pull llama3".into()],
+            extra_bootcmd: vec!["mount /dev/sdb /mnt/models".into()],
+            ..TenguConfig::test_config()
+        };
+        let manifest = Manifest::tengu(&config).unwrap();
+
+        let (phase, last) = manifest.steps.last().unwrap();
+        assert_eq!(*phase, Phase::Custom);
+        assert_eq!(last.to_bash(), ["ollama pull llama3"]);
+
+        let script = BashRenderer::new().render(&manifest).unwrap();
+        let custom = script.find("# === Phase: custom ===").unwrap();
+        let boot = script.find("mount /dev/sdb /mnt/models").unwrap();
+        let extra = script.find("ollama pull llama3").unwrap();
+        let verify = script.find("# Final verification").unwrap();
+        assert!(custom < boot && boot < extra && extra < verify);
+
+        let yaml = CloudInitRenderer::new().render(&manifest).unwrap();
+        let doc: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        let bootcmd = doc["bootcmd"].as_sequence().unwrap();
+        assert!(
+            bootcmd
+                .iter()
+                .any(|c| c.as_str() == Some("mount /dev/sdb /mnt/models"))
+        );
+        let runcmd = doc["runcmd"].as_sequence().unwrap();
+        assert!(
+            runcmd
+                .last()
+                .unwrap()
+                .as_str()
+                .unwrap()
+                .contains("ollama pull llama3")
+        );
+    }
+
     #[test]
     fn test_bash_renderer_verbose() {
         let config = TenguConfig::test_config();
-        let manifest = Manifest::tengu(&config);
+        let manifest = Manifest::tengu(&config).unwrap();
         let renderer = BashRenderer::new().verbose(true);
 
         let script = renderer.render(&manifest).unwrap();
@@ -168,14 +1686,157 @@ mod tests {
         assert!(script.contains("TENGU_STEP:START"));
         assert!(script.contains("TENGU_STEP:DONE"));
         assert!(script.contains("TENGU_STEP:SKIP"));
+        // DONE carries the elapsed seconds; long steps report progress
+        assert!(script.contains(r#"echo "TENGU_STEP:DONE:${step_num}:${desc}:${elapsed}""#));
+        assert!(script.contains("step_heartbeat \"$step_num\" &"));
         // Should have color codes by default
         assert!(script.contains("GREEN="));
     }
 
+    #[test]
+    fn test_bash_renderer_fingerprint_header() {
+        let config = TenguConfig::test_config();
+        let a = Manifest::tengu(&config).unwrap();
+        let b = Manifest::tengu(&config).unwrap();
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        assert_eq!(a.fingerprint().len(), 64);
+
+        let script = BashRenderer::new().render(&a).unwrap();
+        let header = script.lines().nth(1).unwrap();
+        let prefix = format!("# tengu-provision manifest {} generated ", a.fingerprint());
+        assert!(header.starts_with(&prefix), "{header}");
+
+        // Any change to the steps changes the fingerprint
+        let c = Manifest::tengu(&config)
+            .unwrap()
+            .with_step(RunCommand::new("Extra", "true"));
+        assert_ne!(a.fingerprint(), c.fingerprint());
+    }
+
+    #[test]
+    fn test_bash_renderer_dry_run_support() {
+        let manifest = Manifest::new("test")
+            .with_step(RunCommand::new("Create directory", "mkdir /test").unless("[ -d /test ]"));
+
+        let script = BashRenderer::new()
+            .dry_run_support(true)
+            .render(&manifest)
+            .unwrap();
+
+        assert!(script.contains(r#"if [ "${1:-}" = "--dry-run" ]; then DRY_RUN=1; fi"#));
+        // Check still runs, body is echoed in dry-run
+        assert!(script.contains("if (set +e; [ -d /test ]); then"));
+        assert!(script.contains("        echo '[dry-run] [ -d /test ] || { mkdir /test; }'"));
+
+        let plain = BashRenderer::new().render(&manifest).unwrap();
+        assert!(!plain.contains("DRY_RUN"));
+    }
+
+    #[test]
+    fn test_bash_renderer_log_file() {
+        let manifest = Manifest::new("test").with_step(RunCommand::new("Hello", "echo hi"));
+
+        let script = BashRenderer::new()
+            .verbose(true)
+            .log_file("/var/log/tengu/provision.log")
+            .render(&manifest)
+            .unwrap();
+
+        let mkdir = script
+            .find("mkdir -p \"$(dirname '/var/log/tengu/provision.log')\"")
+            .unwrap();
+        let exec = script.find("exec > >(tee >(").unwrap();
+        assert!(mkdir < exec);
+        assert!(script.contains("done >> '/var/log/tengu/provision.log')) 2>&1"));
+        // Markers on stdout are unchanged
+        assert!(script.contains("echo \"TENGU_STEP:START:${step_num}:${desc}\""));
+    }
+
+    #[test]
+    fn test_bash_renderer_parallel() {
+        let manifest = Manifest::new("test")
+            .with_step(InstallPackage::new("curl"))
+            .with_step(RunCommand::new("Fetch", "curl -fsSL x").parallel_safe(true))
+            .with_step(InstallPackage::new("git"))
+            .with_step(RunCommand::new("After", "echo done"));
+
+        let script = BashRenderer::new()
+            .parallel(true)
+            .render(&manifest)
+            .unwrap();
+
+        // apt steps share one job, the command gets its own
+        assert_eq!(script.matches(") &\n").count(), 2);
+        let curl = script.find("apt-get install -y curl").unwrap();
+        let git = script.find("apt-get install -y git").unwrap();
+        let fetch = script.find("curl -fsSL x").unwrap();
+        assert!(curl < git && git < fetch);
+
+        // The non-parallel step runs after all jobs finished
+        let wait = script.find("wait \"$pid\"").unwrap();
+        assert!(wait < script.find("echo done").unwrap());
+
+        // Serial by default
+        let serial = BashRenderer::new().render(&manifest).unwrap();
+        assert!(!serial.contains(") &"));
+        // A failed job fails the script, after the other jobs finished
+        let manifest = Manifest::new("test")
+            .with_step(RunCommand::new("Break", "false").parallel_safe(true))
+            .with_step(RunCommand::new("Fine", "true").parallel_safe(true))
+            .with_step(RunCommand::new("After", "echo after"));
+        let script = BashRenderer::new()
+            .verbose(true)
+            .color(false)
+            .parallel(true)
+            .render(&manifest)
+            .unwrap();
+        let steps = format!(
+            "{}{}{}",
+            &script[..script.find("# Kill unattended-upgrades").unwrap()],
+            &script[script.find("dpkg --configure -a").unwrap()
+                ..script.find("\n# Final verification").unwrap()],
+            &script[script.find("if [ \"$TENGU_FAILED\" -ne 0 ]").unwrap()..]
+        );
+        let output = std::process::Command::new("bash")
+            .args(["-c", &steps])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("TENGU_STEP:FAIL:1:Break\n"));
+        assert!(stdout.contains("TENGU_STEP:DONE:2:Fine:"));
+        assert!(stdout.contains("TENGU_STEP:DONE:3:After:"));
+        assert!(!stdout.contains("TENGU_STEP:COMPLETE"));
+    }
+
+    #[test]
+    fn test_bash_renderer_marker_description_round_trip() {
+        use crate::render::decode_marker_description;
+
+        let desc = "Create tengu PostgreSQL database: primary\n100% done: $HOME";
+        let manifest = Manifest::new("test").with_step(RunCommand::new(desc, "true"));
+
+        let script = BashRenderer::new().verbose(true).render(&manifest).unwrap();
+        let line = script
+            .lines()
+            .find(|l| l.starts_with("step_start \"1\" "))
+            .unwrap();
+        // Single-quoted, so nothing expands in the shell
+        let arg = line
+            .strip_prefix("step_start \"1\" '")
+            .and_then(|rest| rest.strip_suffix('\''))
+            .unwrap();
+
+        let marker = format!("TENGU_STEP:START:1:{arg}");
+        let parts: Vec<&str> = marker.splitn(4, ':').collect();
+        assert_eq!(decode_marker_description(parts[3]), desc);
+        assert!(!script.contains("# Step 1/1: Create tengu PostgreSQL database: primary\n"));
+    }
+
     #[test]
     fn test_bash_renderer_no_color() {
         let config = TenguConfig::test_config();
-        let manifest = Manifest::tengu(&config);
+        let manifest = Manifest::tengu(&config).unwrap();
         let renderer = BashRenderer::new().verbose(true).color(false);
 
         let script = renderer.render(&manifest).unwrap();
@@ -185,4 +1846,875 @@ mod tests {
         // Should NOT have color codes
         assert!(!script.contains("GREEN="));
     }
+
+    #[test]
+    fn test_cloud_init_repo_setup_in_bootcmd() {
+        let manifest = Manifest::new("test").with_step(
+            InstallPackage::new("postgresql-16").with_repository(Repository::postgresql()),
+        );
+
+        let yaml = CloudInitRenderer::new().render(&manifest).unwrap();
+        let doc: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+
+        let bootcmd = doc["bootcmd"].as_sequence().unwrap();
+        assert!(bootcmd[0].as_str().unwrap().contains("gpg --dearmor"));
+        assert!(doc.get("runcmd").is_none());
+        // bootcmd is emitted before the package phase
+        assert!(yaml.find("bootcmd:").unwrap() < yaml.find("packages:").unwrap());
+    }
+
+    #[test]
+    fn test_cloud_init_package_upgrade_toggle() {
+        let manifest = Manifest::new("test");
+
+        let yaml = CloudInitRenderer::new().render(&manifest).unwrap();
+        assert!(yaml.contains("package_update: true"));
+        assert!(yaml.contains("package_upgrade: true"));
+
+        let yaml = CloudInitRenderer::new()
+            .package_update(false)
+            .package_upgrade(false)
+            .render(&manifest)
+            .unwrap();
+        assert!(yaml.contains("package_update: false"));
+        assert!(yaml.contains("package_upgrade: false"));
+    }
+
+    #[test]
+    fn test_renderers_export_noninteractive_before_apt() {
+        let export = "export DEBIAN_FRONTEND=noninteractive NEEDRESTART_MODE=a";
+        let manifest = Manifest::tengu(&TenguConfig::test_config()).unwrap();
+
+        let script = BashRenderer::new().render(&manifest).unwrap();
+        let exported = script.find(export).unwrap();
+        assert!(exported < script.find("apt-get").unwrap());
+        assert_eq!(script.matches(export).count(), 1);
+        let script = BashRenderer::new()
+            .noninteractive(false)
+            .render(&manifest)
+            .unwrap();
+        assert!(!script.contains(export));
+
+        let yaml = CloudInitRenderer::new().render(&manifest).unwrap();
+        let doc: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(doc["runcmd"][0].as_str(), Some(export));
+        let yaml = CloudInitRenderer::new()
+            .noninteractive(false)
+            .render(&manifest)
+            .unwrap();
+        assert!(!yaml.contains("NEEDRESTART_MODE"));
+    }
+
+    #[test]
+    fn test_cloud_init_final_message() {
+        let manifest = Manifest::new("test");
+
+        let yaml = CloudInitRenderer::new().render(&manifest).unwrap();
+        let doc: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(
+            doc["final_message"].as_str(),
+            Some("Tengu PaaS server ready!")
+        );
+
+        let yaml = CloudInitRenderer::new()
+            .final_message("Ready after $UPTIME seconds")
+            .render(&manifest)
+            .unwrap();
+        let doc: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(
+            doc["final_message"].as_str(),
+            Some("Ready after $UPTIME seconds")
+        );
+    }
+
+    #[test]
+    fn test_systemd_first_boot_unit() {
+        let manifest = Manifest::new("test").with_step(RunCommand::new("Say hi", "echo hi"));
+        let files = SystemdFirstBootRenderer::new().render(&manifest).unwrap();
+
+        let unit: Vec<&str> = files.unit.lines().collect();
+        assert!(unit.contains(&"Type=oneshot"));
+        assert!(unit.contains(&"RemainAfterExit=yes"));
+        assert!(unit.contains(&"After=network-online.target"));
+        assert!(unit.contains(&"ExecStart=/bin/bash /usr/local/sbin/tengu-firstboot.sh"));
+        assert!(unit.contains(&"ConditionPathExists=!/var/lib/tengu/firstboot.done"));
+
+        assert!(files.script.starts_with("#!/bin/bash"));
+        assert!(files.script.contains("echo hi"));
+        assert!(!files.script.contains("\\033["));
+    }
+
+    #[test]
+    fn test_cloud_init_runcmd_guarded_by_check() {
+        let manifest = Manifest::new("test").with_step(EnsureService::new("nginx"));
+
+        let yaml = CloudInitRenderer::new().render(&manifest).unwrap();
+        let doc: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+
+        let runcmd = doc["runcmd"].as_sequence().unwrap();
+        assert_eq!(runcmd.len(), 2);
+        let cmd = runcmd[1].as_str().unwrap();
+        assert!(cmd.starts_with("if ! (systemctl is-active nginx >/dev/null 2>&1); then\n"));
+        assert!(cmd.contains("systemctl enable nginx"));
+        assert!(cmd.ends_with("\nfi"));
+    }
+
+    #[test]
+    fn test_cloud_init_primary_user_not_in_runcmd() {
+        let config = TenguConfig::test_config();
+        let manifest = Manifest::tengu(&config).unwrap();
+        let renderer = CloudInitRenderer::new().with_config(&config);
+
+        let yaml = renderer.render(&manifest).unwrap();
+
+        assert!(yaml.starts_with("#cloud-config\n"));
+        // Primary user is declared natively, exactly once
+        assert_eq!(yaml.matches("name: testuser").count(), 1);
+        assert!(yaml.contains("ssh-ed25519 AAAA... test@test"));
+        // ...and never created via shell
+        assert!(!yaml.contains("useradd"));
+    }
+
+    #[test]
+    fn test_cloud_init_user_groups_follow_config() {
+        let config = TenguConfig {
+            user_groups: Some(vec!["sudo".into()]),
+            ..TenguConfig::test_config()
+        };
+        let manifest = Manifest::tengu(&config).unwrap();
+
+        let yaml = CloudInitRenderer::new()
+            .with_config(&config)
+            .render(&manifest)
+            .unwrap();
+        let doc: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        let groups: Vec<_> = doc["users"][0]["groups"]
+            .as_sequence()
+            .unwrap()
+            .iter()
+            .map(|g| g.as_str().unwrap())
+            .collect();
+        assert_eq!(groups, ["sudo"]);
+
+        // The bash renderer's EnsureUser step agrees
+        let script = BashRenderer::new().render(&manifest).unwrap();
+        assert!(script.contains("for g in sudo; do"));
+        assert!(!script.contains("for g in docker sudo; do"));
+    }
+
+    #[test]
+    fn test_manifest_additional_users() {
+        let config = TenguConfig {
+            users: vec![UserSpec {
+                name: "alice".into(),
+                groups: vec!["docker".into()],
+                sudo: None,
+                ssh_keys: vec!["ssh-ed25519 BBBB... alice@laptop".into()],
+            }],
+            ..TenguConfig::test_config()
+        };
+        let manifest = Manifest::tengu(&config).unwrap();
+
+        let yaml = CloudInitRenderer::new()
+            .with_config(&config)
+            .render(&manifest)
+            .unwrap();
+        let doc: serde_yaml::Value = serde_yaml::from_str(&yaml).unwrap();
+        let users = doc["users"].as_sequence().unwrap();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[0]["name"].as_str(), Some("testuser"));
+        assert_eq!(users[1]["name"].as_str(), Some("alice"));
+        assert!(users[1].get("sudo").is_none());
+        assert_eq!(
+            users[1]["ssh_authorized_keys"][0].as_str(),
+            Some("ssh-ed25519 BBBB... alice@laptop")
+        );
+
+        let script = BashRenderer::new().render(&manifest).unwrap();
+        assert!(script.contains("useradd -m -s /bin/bash testuser"));
+        assert!(script.contains("useradd -m -s /bin/bash alice"));
+        assert!(script.contains("ssh-ed25519 BBBB... alice@laptop"));
+        assert!(!script.contains("/etc/sudoers.d/alice"));
+    }
+
+    #[test]
+    fn test_manifest_harden_ssh() {
+        let mut manifest = Manifest::new("test");
+        manifest.harden_ssh();
+
+        let script = BashRenderer::new().render(&manifest).unwrap();
+
+        let files = manifest.steps[0].1.to_cloud_init().write_files;
+        assert_eq!(files[0].path, "/etc/ssh/sshd_config.d/99-tengu.conf");
+        assert!(files[0].content.contains("PasswordAuthentication no\n"));
+        assert!(files[0].content.contains("PermitRootLogin no\n"));
+        // Reload only runs after sshd -t succeeded
+        let validate = script.find("if sshd -t; then").unwrap();
+        let reload = script.find("systemctl reload ssh").unwrap();
+        assert!(validate < reload);
+
+        // A failed sshd -t fails the step without ending later runcmd entries
+        let reload_cmd = manifest.steps[1].1.to_cloud_init().runcmd.join("\n");
+        let output = std::process::Command::new("bash")
+            .arg("-c")
+            .arg(format!(
+                "mkdir() {{ :; }}; sshd() {{ return 1; }}; systemctl() {{ echo reloaded; }}\n\
+                 {reload_cmd}\necho \"status $?\"\necho next runcmd"
+            ))
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert_eq!(stdout, "status 1\nnext runcmd\n");
+    }
+
+    #[test]
+    fn test_manifest_phases() {
+        let config = TenguConfig::test_config();
+        let manifest = Manifest::tengu(&config).unwrap();
+
+        let docker: Vec<&str> = manifest
+            .steps_in(Phase::Docker)
+            .map(Step::description)
+            .collect();
+        assert_eq!(docker, ["Install docker.io", "Install docker-compose"]);
+        assert_eq!(manifest.steps[0].0, Phase::System);
+
+        let script = BashRenderer::new().render(&manifest).unwrap();
+        assert_eq!(script.matches("# === Phase: docker ===").count(), 1);
+        assert!(
+            script.find("# === Phase: docker ===").unwrap()
+                < script.find("# === Phase: postgres ===").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_manifest_health_checks() {
+        let config = TenguConfig::test_config();
+        let manifest = Manifest::tengu(&config)
+            .unwrap()
+            .filter_phases(&["healthcheck".parse::<Phase>().unwrap()], &[]);
+
+        // Final phase, re-runnable on its own
+        assert_eq!(manifest.step_count(), 6);
+        assert!(
+            manifest
+                .steps
+                .iter()
+                .all(|(_, s)| s.check_command().is_none())
+        );
+
+        let bash: Vec<String> = manifest
+            .steps
+            .iter()
+            .flat_map(|(_, s)| s.to_bash())
+            .collect();
+        assert!(bash[0].contains("systemctl is-active --quiet docker"));
+        assert!(bash[4].contains("extname='\\''vector'\\''"));
+        assert!(bash[5].contains(
+            "curl -fsS -o /dev/null --max-time 10 --resolve api.test.example.com:443:127.0.0.1 https://api.test.example.com/health"
+        ));
+
+        let full = Manifest::tengu(&config).unwrap();
+        assert_eq!(full.steps.last().unwrap().0, Phase::HealthCheck);
+
+        // A failing check fails its step and the script
+        let script = BashRenderer::new()
+            .verbose(true)
+            .color(false)
+            .render(&manifest)
+            .unwrap();
+        let stubs = "systemctl() { [ \"$3\" != docker ]; }\n\
+                     sudo() { :; }\n\
+                     curl() { :; }\n\
+                     sleep() { [ \"$1\" = 2 ] || command sleep \"$@\"; }\n";
+        let steps = format!(
+            "{stubs}{}{}",
+            &script[script.find("dpkg --configure -a").unwrap()
+                ..script.find("\n# Final verification").unwrap()],
+            &script[script.find("if [ \"$TENGU_FAILED\" -ne 0 ]").unwrap()..]
+        );
+        let output = std::process::Command::new("bash")
+            .args(["-c", &steps])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("TENGU_STEP:FAIL:1:Check docker is active\n"));
+        assert!(stdout.contains("TENGU_STEP:DONE:3:Check caddy is active:"));
+        assert!(!stdout.contains("TENGU_STEP:COMPLETE"));
+    }
+
+    #[test]
+    fn test_manifest_docker_install_mode() {
+        let mut config = TenguConfig::test_config();
+        let docker = |config: &TenguConfig| -> Vec<String> {
+            Manifest::tengu(config)
+                .unwrap()
+                .steps_in(Phase::Docker)
+                .map(|s| s.description().to_string())
+                .collect()
+        };
+
+        assert_eq!(
+            docker(&config),
+            ["Install docker.io", "Install docker-compose"]
+        );
+
+        config.docker_install = DockerInstallMode::ConvenienceScript;
+        assert_eq!(docker(&config), ["Install Docker via get.docker.com"]);
+
+        config.docker_install = DockerInstallMode::Rootless;
+        let manifest = Manifest::tengu(&config).unwrap();
+        let rootless = manifest
+            .steps_in(Phase::Docker)
+            .find(|s| s.description() == "Set up rootless Docker for testuser")
+            .unwrap();
+        let bash = rootless.to_bash().join("\n");
+        assert!(bash.contains("sudo -u 'testuser' sh -c"));
+        assert!(bash.contains("dockerd-rootless-setuptool.sh install"));
+        assert!(
+            manifest
+                .steps_in(Phase::Docker)
+                .any(|s| s.description() == "Enable lingering for testuser")
+        );
+    }
+
+    #[test]
+    fn test_manifest_config_files() {
+        let mut config = TenguConfig::test_config();
+        config.files = vec![FileSpec {
+            path: "/etc/systemd/system/worker.service".into(),
+            content: "[Service]\nExecStart=/usr/local/bin/worker\n".into(),
+            permissions: Some("0644".into()),
+            owner: None,
+        }];
+        let manifest = Manifest::tengu(&config).unwrap();
+
+        let step = manifest
+            .steps_in(Phase::Configuration)
+            .find(|s| s.description() == "Write /etc/systemd/system/worker.service")
+            .unwrap();
+        let bash = step.to_bash();
+        assert!(bash.contains(&"chmod 0644 '/etc/systemd/system/worker.service'".to_string()));
+        assert!(!bash.iter().any(|c| c.starts_with("chown")));
+    }
+
+    #[test]
+    fn test_manifest_auto_upgrades_opt_in() {
+        let kind = "EnsureUnattendedUpgrades";
+        let manifest = Manifest::tengu(&TenguConfig::test_config()).unwrap();
+        assert!(!manifest.steps.iter().any(|(_, s)| s.kind() == kind));
+
+        let mut config = TenguConfig::test_config();
+        config.auto_upgrades = true;
+        let manifest = Manifest::tengu(&config).unwrap();
+        let step = manifest
+            .steps_in(Phase::PostInstall)
+            .find(|s| s.kind() == kind)
+            .unwrap();
+
+        let bash = step.to_bash().join("\n");
+        assert!(bash.contains("apt-get install -y unattended-upgrades"));
+        assert!(bash.contains("systemctl enable unattended-upgrades"));
+        assert!(
+            step.check_command()
+                .unwrap()
+                .starts_with("systemctl is-enabled unattended-upgrades")
+        );
+
+        let fragment = step.to_cloud_init();
+        assert_eq!(fragment.packages, ["unattended-upgrades"]);
+        let conf = fragment
+            .write_files
+            .iter()
+            .find(|f| f.path == "/etc/apt/apt.conf.d/50unattended-upgrades")
+            .unwrap();
+        assert!(conf.content.contains("${distro_codename}-security"));
+        assert!(!conf.content.contains("-updates"));
+        assert_eq!(
+            step.provides(),
+            [
+                steps::Dependency::Package("unattended-upgrades".into()),
+                steps::Dependency::Service("unattended-upgrades".into()),
+            ]
+        );
+        // The apt lock handling only stops the service, so the step's check
+        // can pass and skipped runs leave security updates on
+        let script = BashRenderer::new().render(&manifest).unwrap();
+        assert!(script.contains("systemctl stop unattended-upgrades"));
+        assert!(!script.contains("systemctl disable unattended-upgrades"));
+    }
+
+    #[test]
+    fn test_docker_network_and_volume() {
+        let network = EnsureDockerNetwork::new("tengu").driver("bridge");
+        assert_eq!(
+            network.check_command().unwrap(),
+            "docker network inspect 'tengu' >/dev/null 2>&1"
+        );
+        assert_eq!(
+            network.to_bash(),
+            ["docker network inspect 'tengu' >/dev/null 2>&1 || \
+              docker network create --driver 'bridge' 'tengu'"]
+        );
+
+        let volume = EnsureDockerVolume::new("pgdata");
+        assert_eq!(
+            volume.check_command().unwrap(),
+            "docker volume inspect 'pgdata' >/dev/null 2>&1"
+        );
+        assert_eq!(
+            volume.to_bash(),
+            ["docker volume inspect 'pgdata' >/dev/null 2>&1 || docker volume create 'pgdata'"]
+        );
+
+        // Both need Docker running first
+        let config = TenguConfig::test_config();
+        let manifest = Manifest::tengu(&config)
+            .unwrap()
+            .with_step(network.clone())
+            .with_step(volume);
+        assert_eq!(manifest.validate_ordering(), []);
+        let early = Manifest::new("test").with_step(network);
+        let issues = early.validate_ordering();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(
+            issues[0].dependency,
+            steps::Dependency::Service("docker".into())
+        );
+    }
+
+    #[test]
+    fn test_run_compose_file() {
+        let step = RunComposeFile::new("/opt/app/docker-compose.yml").project_name("app");
+        assert_eq!(
+            step.to_bash(),
+            [
+                "docker compose -f '/opt/app/docker-compose.yml' -p 'app' up -d",
+                "docker compose -f '/opt/app/docker-compose.yml' -p 'app' config 2>/dev/null \
+                 | sha256sum > '/opt/app/docker-compose.yml.sha256'"
+            ]
+        );
+        let check = step.check_command().unwrap();
+        assert!(check.contains(
+            "docker compose -f '/opt/app/docker-compose.yml' -p 'app' ps --status running --services"
+        ));
+        assert!(check.contains("config --services"));
+
+        // A changed compose file brings the project up again, even with every
+        // service running (stub docker: services are up once `up -d` ran)
+        let dir = std::env::temp_dir().join(format!("tengu-compose-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("docker"),
+            "#!/bin/sh\nfile=$3; shift 3\n[ \"$1\" = -p ] && shift 2\ncase \"$*\" in\n\
+             'config --services') echo web ;;\n\
+             config) cat \"$file\" ;;\n\
+             'ps --status running --services') [ -f \"$file.up\" ] && echo web ;;\n\
+             'up -d') touch \"$file.up\" ;;\nesac\n",
+        )
+        .unwrap();
+        let compose = dir.join("docker-compose.yml");
+        std::fs::write(&compose, "services: {web: {image: nginx:1}}\n").unwrap();
+        let run = |script: &str| {
+            std::process::Command::new("bash")
+                .args([
+                    "-c",
+                    &format!("chmod +x docker; PATH=\"$PWD:$PATH\"; {script}"),
+                ])
+                .current_dir(&dir)
+                .status()
+                .unwrap()
+                .success()
+        };
+        let local = RunComposeFile::new(compose.to_str().unwrap());
+        let check = local.check_command().unwrap();
+        assert!(!run(&check));
+        assert!(run(&local.to_bash().join(" && ")));
+        assert!(run(&check));
+        std::fs::write(&compose, "services: {web: {image: nginx:2}}\n").unwrap();
+        assert!(!run(&check));
+        assert!(run(&local.to_bash().join(" && ")));
+        assert!(run(&check));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let config = TenguConfig::test_config();
+        let manifest = Manifest::tengu(&config)
+            .unwrap()
+            .with_step(WriteFile::new(
+                "/opt/app/docker-compose.yml",
+                "services: {}\n",
+            ))
+            .with_step(step.clone());
+        assert_eq!(manifest.validate_ordering(), []);
+        assert_eq!(
+            Manifest::new("test").with_step(step).validate_ordering()[0].dependency,
+            steps::Dependency::Service("docker".into())
+        );
+    }
+
+    #[test]
+    fn test_manifest_toml_round_trip() {
+        let mut config = TenguConfig::test_config();
+        config.auto_upgrades = true;
+        let manifest = Manifest::tengu(&config).unwrap();
+        let toml = manifest.to_toml().unwrap();
+        assert!(toml.contains("kind = \"EnsureUnattendedUpgrades\""));
+        let loaded = Manifest::from_toml(&toml).unwrap();
+
+        assert_eq!(loaded.fingerprint(), manifest.fingerprint());
+        assert_eq!(loaded.secrets, manifest.secrets);
+        assert_eq!(loaded.tags(0), manifest.tags(0));
+        assert!(
+            loaded
+                .steps
+                .iter()
+                .zip(&manifest.steps)
+                .all(|(a, b)| a.0 == b.0)
+        );
+        // Identical apart from the generated-at timestamp
+        let renderer = BashRenderer::new().verbose(true);
+        let body = |m: &Manifest| {
+            let script = renderer.render(m).unwrap();
+            script.lines().skip(2).collect::<Vec<_>>().join("\n")
+        };
+        assert_eq!(body(&loaded), body(&manifest));
+
+        assert!(matches!(
+            Manifest::from_toml("hostname = \"x\""),
+            Err(ManifestTomlError::Deserialize(_))
+        ));
+    }
+
+    #[test]
+    fn test_manifest_validate_ordering() {
+        let config = TenguConfig::test_config();
+        assert_eq!(Manifest::tengu(&config).unwrap().validate_ordering(), []);
+
+        let manifest = Manifest::new("test")
+            .with_step(EnsureService::new("postgresql").from_package("postgresql-16"))
+            .with_step(
+                InstallPackage::new("postgresql-16-pgvector")
+                    .from_repository(&Repository::postgresql()),
+            )
+            .with_step(
+                InstallPackage::new("postgresql-16").with_repository(Repository::postgresql()),
+            )
+            .with_step(InstallPackage::new("nginx").from_repository(&Repository::docker()));
+
+        let issues = manifest.validate_ordering();
+        assert_eq!(issues.len(), 3);
+        assert_eq!(issues[0].step, 1);
+        assert_eq!(
+            issues[0].dependency,
+            steps::Dependency::Package("postgresql-16".into())
+        );
+        assert_eq!(issues[0].provided_by, Some(3));
+        assert_eq!(
+            issues[1].to_string(),
+            "step 2 (Install postgresql-16-pgvector) needs repository \
+             /usr/share/keyrings/postgresql-archive-keyring.gpg, which is only provided by step 3"
+        );
+        assert_eq!(issues[2].step, 4);
+        assert_eq!(issues[2].provided_by, None);
+    }
+
+    #[test]
+    fn test_manifest_filter_phases() {
+        let config = TenguConfig::test_config();
+        let only: Vec<Phase> = "postgres,services"
+            .split(',')
+            .map(|p| p.parse().unwrap())
+            .collect();
+        assert!("dockr".parse::<Phase>().is_err());
+
+        let manifest = Manifest::tengu(&config).unwrap().filter_phases(&only, &[]);
+        assert!(
+            manifest
+                .steps
+                .iter()
+                .all(|(p, _)| matches!(p, Phase::Postgres | Phase::Services))
+        );
+
+        // Markers are numbered 1..=n over the filtered steps
+        let script = BashRenderer::new().verbose(true).render(&manifest).unwrap();
+        let total = manifest.step_count();
+        assert!(script.contains("step_start \"1\" 'Install postgresql-16'"));
+        assert!(script.contains(&format!("# Step {total}/{total}:")));
+
+        let skipped = Manifest::tengu(&config)
+            .unwrap()
+            .filter_phases(&[], &[Phase::Docker]);
+        assert_eq!(skipped.steps_in(Phase::Docker).count(), 0);
+    }
+
+    #[test]
+    fn test_dockerfile_renderer() {
+        let config = TenguConfig::test_config();
+        let manifest = Manifest::tengu(&config).unwrap();
+
+        let dockerfile = DockerfileRenderer::new().render(&manifest).unwrap();
+
+        assert!(dockerfile.starts_with("# syntax=docker/dockerfile:1\nFROM ubuntu:24.04\n"));
+        // Base packages, docker and postgres share one apt layer
+        assert_eq!(dockerfile.matches("RUN apt-get update").count(), 1);
+        let apt = dockerfile
+            .lines()
+            .find(|l| l.starts_with("RUN apt-get update"))
+            .unwrap();
+        assert!(apt.contains("curl wget git"));
+        assert!(apt.contains("postgresql-16 postgresql-16-pgvector"));
+        // Services are left to the booted host
+        assert!(dockerfile.contains("# Skipped at build time (runtime only): Start Docker"));
+        assert!(!dockerfile.contains("RUN systemctl"));
+    }
+
+    #[test]
+    fn test_json_renderer_round_trip() {
+        let config = TenguConfig::test_config();
+        let manifest = Manifest::tengu(&config).unwrap();
+        let renderer = JsonRenderer::new();
+
+        let json = renderer.render(&manifest).unwrap();
+
+        // Stable output for diffing
+        assert_eq!(json, renderer.render(&manifest).unwrap());
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let reparsed: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string(&value).unwrap()).unwrap();
+        assert_eq!(reparsed, value);
+
+        let steps = value["steps"].as_array().unwrap();
+        assert_eq!(steps.len(), manifest.steps.len());
+        for (parsed, (_, step)) in steps.iter().zip(&manifest.steps) {
+            assert_eq!(parsed["type"], step.kind());
+            assert_eq!(parsed["description"], step.description());
+        }
+        assert_eq!(steps[0]["type"], "SetHostname");
+    }
+
+    #[test]
+    fn test_renderers_redact_secrets() {
+        use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+        let config = TenguConfig::test_config();
+        let manifest = Manifest::tengu(&config).unwrap();
+        assert_eq!(manifest.secrets, ["test-api-key", "re_test"]);
+
+        let rendered = [
+            BashRenderer::new().redact(true).render(&manifest).unwrap(),
+            CloudInitRenderer::new()
+                .redact(true)
+                .render(&manifest)
+                .unwrap(),
+            JsonRenderer::new().redact(true).render(&manifest).unwrap(),
+            DockerfileRenderer::new()
+                .redact(true)
+                .render(&manifest)
+                .unwrap(),
+        ];
+        for output in &rendered {
+            assert!(!output.contains("test-api-key"));
+            assert!(!output.contains("re_test"));
+        }
+        assert!(rendered[1].contains("api_key = \"***REDACTED***\""));
+
+        // Base64-encoded file contents are redacted too
+        let script = &rendered[0];
+        let encoded = script
+            .split("echo '")
+            .skip(1)
+            .filter_map(|s| s.split_once("' | base64 -d"))
+            .map(|(payload, _)| String::from_utf8(STANDARD.decode(payload).unwrap()).unwrap())
+            .find(|content| content.contains("[cloudflare]"))
+            .unwrap();
+        assert!(encoded.contains("api_key = \"***REDACTED***\""));
+
+        // Off by default
+        let script = BashRenderer::new().render(&manifest).unwrap();
+        assert!(!script.contains("***REDACTED***"));
+    }
+
+    #[test]
+    fn test_caddyfile_custom_route() {
+        let mut config = TenguConfig::test_config_direct();
+        config.caddy_routes = vec![("grafana".into(), 3000)];
+
+        let caddyfile = config.caddyfile();
+
+        assert!(
+            caddyfile.contains("grafana.test.example.com {\n    reverse_proxy localhost:3000\n}")
+        );
+        // Custom routes replace the defaults, global sections stay
+        assert!(!caddyfile.contains("api.test.example.com"));
+        assert!(caddyfile.contains("email admin@example.com"));
+        assert!(caddyfile.contains("import sites/*.caddy"));
+    }
+
+    #[test]
+    fn test_dedup_steps_keeps_first_occurrence() {
+        let mut manifest = Manifest::new("test");
+        manifest.add_step(InstallPackage::new("curl"));
+        manifest.add_step(EnsureDirectory::new("/opt/tengu"));
+        manifest.add_step(RunCommand::new("Say hi", "echo hi"));
+        manifest.add_tagged(&["net"], InstallPackage::new("curl"));
+        manifest.add_step(EnsureDirectory::new("/opt/tengu"));
+        manifest.add_step(RunCommand::new("Say hi", "echo hi"));
+
+        let manifest = manifest.dedup_steps();
+
+        let kinds: Vec<_> = manifest.steps.iter().map(|(_, s)| s.kind()).collect();
+        assert_eq!(
+            kinds,
+            [
+                "InstallPackage",
+                "EnsureDirectory",
+                "RunCommand",
+                "RunCommand"
+            ]
+        );
+        // The surviving curl step picks up the duplicate's tag
+        assert_eq!(manifest.tags(0), ["net"]);
+
+        // A shared check isn't enough: both rules and both users' groups stay
+        let manifest = Manifest::new("test")
+            .with_step(EnsureFirewall::new().allow("22/tcp"))
+            .with_step(EnsureFirewall::new().allow("443/tcp"))
+            .with_step(EnsureUser::new("ops"))
+            .with_step(EnsureUser::new("ops").with_groups(["docker"]))
+            .dedup_steps();
+        assert_eq!(manifest.step_count(), 4);
+    }
+
+    #[test]
+    fn test_caddy_site_file() {
+        let site = CaddySite::new("blog", 3000)
+            .with_domain("test.example.com")
+            .with_directives(["encode gzip"]);
+
+        assert_eq!(site.path(), "/etc/caddy/sites/blog.caddy");
+        assert_eq!(
+            site.content(),
+            "blog.test.example.com {\n    reverse_proxy localhost:3000\n    encode gzip\n}\n"
+        );
+
+        // Reloads a running Caddy after writing the file
+        let bash = site.to_bash();
+        assert!(
+            bash.iter()
+                .any(|c| c.contains("/etc/caddy/sites/blog.caddy"))
+        );
+        assert!(bash.last().unwrap().contains("systemctl reload caddy"));
+        assert!(site.check_command().unwrap().contains("sha256sum"));
+
+        let fragment = site.to_cloud_init();
+        assert_eq!(fragment.write_files[0].path, "/etc/caddy/sites/blog.caddy");
+        assert!(
+            fragment.write_files[0]
+                .content
+                .contains("reverse_proxy localhost:3000")
+        );
+    }
+
+    #[test]
+    fn test_fail2ban_custom_bantime() {
+        let config = TenguConfig::builder()
+            .fail2ban_bantime(86400)
+            .add_fail2ban_jail("caddy", "enabled = true\nport = http,https")
+            .build();
+
+        let jail = config.fail2ban_config();
+
+        assert!(jail.contains("bantime = 86400"));
+        assert!(jail.contains("maxretry = 3"));
+        assert!(jail.contains("\n[caddy]\nenabled = true\nport = http,https\n"));
+    }
+
+    #[test]
+    fn test_format_utc() {
+        use crate::render::format_utc;
+        assert_eq!(format_utc(0), "1970-01-01T00:00:00Z");
+        // Leap days, including a century leap year
+        assert_eq!(format_utc(1_709_210_096), "2024-02-29T12:34:56Z");
+        assert_eq!(format_utc(951_782_400), "2000-02-29T00:00:00Z");
+        // Year boundary
+        assert_eq!(format_utc(1_704_067_199), "2023-12-31T23:59:59Z");
+        assert_eq!(format_utc(1_704_067_200), "2024-01-01T00:00:00Z");
+    }
+
+    #[test]
+    fn test_provision_error_template() {
+        let mut manifest = Manifest::new("test");
+        let err = manifest
+            .add_template(&TemplateFile::new("/etc/app.conf", "{% if %}"))
+            .unwrap_err();
+        assert!(matches!(err, ProvisionError::Template(_)));
+        assert_eq!(manifest.step_count(), 0);
+    }
+
+    #[test]
+    fn test_provision_error_serialization() {
+        /// A value whose serializer fails, as renderers' `?` would see it
+        struct Unserializable;
+        impl serde::Serialize for Unserializable {
+            fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+                Err(serde::ser::Error::custom("unsupported value"))
+            }
+        }
+        let json =
+            || -> Result<String, ProvisionError> { Ok(serde_json::to_string(&Unserializable)?) };
+        let yaml =
+            || -> Result<String, ProvisionError> { Ok(serde_yaml::to_string(&Unserializable)?) };
+
+        let err = json().unwrap_err();
+        assert!(matches!(
+            err,
+            ProvisionError::Serialization { format: "JSON", .. }
+        ));
+        assert_eq!(
+            err.to_string(),
+            "failed to serialize JSON: unsupported value"
+        );
+        assert!(std::error::Error::source(&err).is_some());
+
+        let err = yaml().unwrap_err();
+        assert!(matches!(
+            err,
+            ProvisionError::Serialization {
+                format: "cloud-init YAML",
+                ..
+            }
+        ));
+        assert!(
+            err.to_string()
+                .starts_with("failed to serialize cloud-init YAML: unsupported value")
+        );
+    }
+
+    #[test]
+    fn test_provision_error_validation() {
+        assert!(Manifest::new("test").validate().is_ok());
+
+        let manifest = Manifest::new("test")
+            .with_step(EnsureAptHold::new("docker-ce"))
+            .with_step(InstallPackage::new("docker-ce"));
+        let err = manifest.validate().unwrap_err();
+        assert!(matches!(err, ProvisionError::Validation(_)));
+        assert!(err.to_string().starts_with("invalid manifest: "));
+        assert!(err.to_string().contains("docker-ce"));
+    }
+
+    #[test]
+    fn test_provision_error_io() {
+        let err = ProvisionError::Io {
+            path: "/etc/systemd/system/tengu-firstboot.service".into(),
+            source: std::io::Error::from(std::io::ErrorKind::PermissionDenied),
+        };
+        assert_eq!(
+            err.to_string(),
+            "failed to write /etc/systemd/system/tengu-firstboot.service: permission denied"
+        );
+        assert!(std::error::Error::source(&err).is_some());
+    }
 }
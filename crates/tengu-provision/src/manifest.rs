@@ -1,11 +1,212 @@
 //! Installation manifest - complete step sequence
 
-use crate::config::TenguConfig;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::ProvisionError;
+use crate::config::{DockerInstallMode, TenguConfig};
 use crate::steps::{
-    EnsureDirectory, EnsureFirewall, EnsureService, EnsureUser, InstallDebFromUrl, InstallPackage,
-    Repository, RunCommand, Step, WriteFile,
+    AddAptRepositories, Dependency, EnsureDirectory, EnsureFirewall, EnsureMount, EnsureService,
+    EnsureSwap, EnsureUnattendedUpgrades, EnsureUser, InstallDebFromUrl, InstallPackage,
+    InstallPackages, KnownStep, Repository, RunCommand, SetHostname, SetLocale, SetTimezone, Step,
+    TemplateFile, WaitForAptLock, WriteFile, mirror_urls, shell_quote,
 };
 
+/// sshd drop-in written by [`Manifest::harden_ssh`]
+const SSH_HARDENING_CONF: &str = "/etc/ssh/sshd_config.d/99-tengu.conf";
+
+/// Installation phase a step belongs to
+///
+/// Phases group related steps so consumers can filter or re-run part of a
+/// manifest. Steps added before any phase is started belong to [`Phase::Custom`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    /// Steps not assigned to a named phase
+    Custom,
+    /// Hostname, timezone and locale
+    System,
+    /// Admin user with SSH keys and sudo
+    UserSetup,
+    /// Base apt packages
+    BasePackages,
+    /// Docker engine and compose
+    Docker,
+    /// `PostgreSQL` with pgvector
+    Postgres,
+    /// Ollama runtime
+    Ollama,
+    /// tengu-caddy reverse proxy
+    Caddy,
+    /// Tengu directories
+    Directories,
+    /// Configuration files
+    Configuration,
+    /// Docker XFS backing storage
+    Storage,
+    /// Firewall rules
+    Firewall,
+    /// Enable and start services
+    Services,
+    /// Tengu .deb package and service
+    Tengu,
+    /// `OpenSSH` configuration for git operations
+    OpenSsh,
+    /// Post-install setup
+    PostInstall,
+    /// Tengu admin user
+    AdminUser,
+    /// Post-provisioning health checks
+    HealthCheck,
+}
+
+/// Error parsing a [`Phase`] from its name
+#[derive(Debug, thiserror::Error)]
+#[error("unknown phase '{0}' (expected one of: {names})", names = phase_names())]
+pub struct UnknownPhase(pub String);
+
+fn phase_names() -> String {
+    Phase::ALL.map(Phase::as_str).join(", ")
+}
+
+impl Phase {
+    /// All phases in installation order
+    pub const ALL: [Self; 18] = [
+        Self::Custom,
+        Self::System,
+        Self::UserSetup,
+        Self::BasePackages,
+        Self::Docker,
+        Self::Postgres,
+        Self::Ollama,
+        Self::Caddy,
+        Self::Directories,
+        Self::Configuration,
+        Self::Storage,
+        Self::Firewall,
+        Self::Services,
+        Self::Tengu,
+        Self::OpenSsh,
+        Self::PostInstall,
+        Self::AdminUser,
+        Self::HealthCheck,
+    ];
+
+    /// Stable kebab-case name (e.g. `"base-packages"`)
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Custom => "custom",
+            Self::System => "system",
+            Self::UserSetup => "user-setup",
+            Self::BasePackages => "base-packages",
+            Self::Docker => "docker",
+            Self::Postgres => "postgres",
+            Self::Ollama => "ollama",
+            Self::Caddy => "caddy",
+            Self::Directories => "directories",
+            Self::Configuration => "configuration",
+            Self::Storage => "storage",
+            Self::Firewall => "firewall",
+            Self::Services => "services",
+            Self::Tengu => "tengu",
+            Self::OpenSsh => "openssh",
+            Self::PostInstall => "post-install",
+            Self::AdminUser => "admin-user",
+            Self::HealthCheck => "healthcheck",
+        }
+    }
+}
+
+impl FromStr for Phase {
+    type Err = UnknownPhase;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|p| p.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| UnknownPhase(s.to_string()))
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A step that comes before the step providing something it depends on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderingIssue {
+    /// 1-based position of the step with the unmet dependency
+    pub step: usize,
+    /// Description of that step
+    pub description: String,
+    /// What the step depends on
+    pub dependency: Dependency,
+    /// 1-based position of a later step providing it, if any
+    pub provided_by: Option<usize>,
+}
+
+impl fmt::Display for OrderingIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "step {} ({}) needs {}, ",
+            self.step, self.description, self.dependency
+        )?;
+        match self.provided_by {
+            Some(later) => write!(f, "which is only provided by step {later}"),
+            None => f.write_str("which no step provides"),
+        }
+    }
+}
+
+/// Error converting a [`Manifest`] to or from TOML
+#[derive(Debug, thiserror::Error)]
+pub enum ManifestTomlError {
+    /// A step type defined outside this crate has no TOML form
+    #[error("step {step} ({kind}) is not a built-in step type and can't be serialized")]
+    UnknownStep {
+        /// 1-based position of the step
+        step: usize,
+        /// Its [`Step::kind`]
+        kind: &'static str,
+    },
+    /// Serializing failed
+    #[error("failed to serialize manifest: {0}")]
+    Serialize(#[from] toml::ser::Error),
+    /// The TOML isn't a valid manifest
+    #[error("invalid manifest TOML: {0}")]
+    Deserialize(#[from] toml::de::Error),
+}
+
+/// TOML form of a [`Manifest`]
+#[derive(Serialize, Deserialize)]
+struct ManifestToml {
+    hostname: String,
+    fqdn: Option<String>,
+    timezone: String,
+    locale: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    secrets: Vec<String>,
+    #[serde(default)]
+    steps: Vec<StepToml>,
+}
+
+/// TOML form of one manifest step: its phase and tags plus the step's fields
+#[derive(Serialize, Deserialize)]
+struct StepToml {
+    phase: Phase,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(flatten)]
+    step: KnownStep,
+}
+
 /// Complete Tengu installation manifest
 pub struct Manifest {
     /// Server hostname
@@ -16,8 +217,14 @@ pub struct Manifest {
     pub timezone: String,
     /// Locale
     pub locale: String,
-    /// Ordered list of installation steps
-    pub steps: Vec<Box<dyn Step>>,
+    /// Ordered list of installation steps with the phase each belongs to
+    pub steps: Vec<(Phase, Box<dyn Step>)>,
+    /// Secret values masked by renderers with redaction enabled
+    pub secrets: Vec<String>,
+    /// Phase assigned to steps added from now on
+    phase: Phase,
+    /// Tags of each step, parallel to `steps`
+    tags: Vec<Vec<String>>,
 }
 
 impl Manifest {
@@ -29,6 +236,9 @@ impl Manifest {
             timezone: "UTC".into(),
             locale: "en_US.UTF-8".into(),
             steps: vec![],
+            secrets: vec![],
+            phase: Phase::Custom,
+            tags: vec![],
         }
     }
 
@@ -50,9 +260,436 @@ impl Manifest {
         self
     }
 
-    /// Add a step to the manifest
+    /// Start a phase; subsequently added steps belong to it
+    pub fn begin_phase(&mut self, phase: Phase) {
+        self.phase = phase;
+    }
+
+    /// Add a step to the manifest in the current phase
     pub fn add_step<S: Step + 'static>(&mut self, step: S) {
-        self.steps.push(Box::new(step));
+        self.add_tagged(&[], step);
+    }
+
+    /// Add a step with tags (e.g. `security`, `database`) in the current phase
+    pub fn add_tagged<S: Step + 'static>(&mut self, tags: &[&str], step: S) {
+        self.steps.push((self.phase, Box::new(step)));
+        self.tags
+            .push(tags.iter().map(|tag| (*tag).to_string()).collect());
+    }
+
+    /// Tags of the step at `index` (empty for untagged steps)
+    pub fn tags(&self, index: usize) -> &[String] {
+        self.tags.get(index).map_or(&[], Vec::as_slice)
+    }
+
+    /// Keep only steps in `only` (when non-empty) and not in `skip`
+    ///
+    /// Step numbers are positional, so a filtered manifest still renders
+    /// contiguous `1..=step_count()` progress markers.
+    #[must_use]
+    pub fn filter_phases(self, only: &[Phase], skip: &[Phase]) -> Self {
+        self.retain_steps(|phase, _| {
+            (only.is_empty() || only.contains(&phase)) && !skip.contains(&phase)
+        })
+    }
+
+    /// Keep only steps tagged with any of `include` (when non-empty) and
+    /// with none of `exclude`
+    ///
+    /// Like [`filter_phases`](Self::filter_phases), step numbers are
+    /// recomputed for the remaining steps.
+    #[must_use]
+    pub fn filter_tags(self, include: &[String], exclude: &[String]) -> Self {
+        self.retain_steps(|_, tags| {
+            (include.is_empty() || tags.iter().any(|tag| include.contains(tag)))
+                && !tags.iter().any(|tag| exclude.contains(tag))
+        })
+    }
+
+    /// Keep the steps (and their tags) for which `keep(phase, tags)` holds
+    fn retain_steps(mut self, mut keep: impl FnMut(Phase, &[String]) -> bool) -> Self {
+        let tags = std::mem::take(&mut self.tags);
+        let steps = std::mem::take(&mut self.steps);
+        for ((phase, step), step_tags) in steps
+            .into_iter()
+            .zip(tags.into_iter().chain(std::iter::repeat_with(Vec::new)))
+        {
+            if keep(phase, &step_tags) {
+                self.steps.push((phase, step));
+                self.tags.push(step_tags);
+            }
+        }
+        self
+    }
+
+    /// Drop steps that repeat an earlier step's kind, check and commands
+    ///
+    /// Composed manifests pick up duplicate package and directory steps; the
+    /// first occurrence stays where it is and gains the duplicates' tags.
+    /// Steps sharing only a check (e.g. two firewall rules) are both kept.
+    /// `RunCommand`s and steps without a check are never dropped, since a
+    /// repeated command may be meant to run twice.
+    #[must_use]
+    pub fn dedup_steps(mut self) -> Self {
+        let tags = std::mem::take(&mut self.tags);
+        let steps = std::mem::take(&mut self.steps);
+        let mut seen: HashMap<(&'static str, String, Vec<String>), usize> = HashMap::new();
+        for ((phase, step), step_tags) in steps
+            .into_iter()
+            .zip(tags.into_iter().chain(std::iter::repeat_with(Vec::new)))
+        {
+            let key = step
+                .check_command()
+                .filter(|_| step.kind() != "RunCommand")
+                .map(|check| (step.kind(), check, step.to_bash()));
+            if let Some(key) = key {
+                if let Some(&first) = seen.get(&key) {
+                    for tag in step_tags {
+                        if !self.tags[first].contains(&tag) {
+                            self.tags[first].push(tag);
+                        }
+                    }
+                    continue;
+                }
+                seen.insert(key, self.steps.len());
+            }
+            self.steps.push((phase, step));
+            self.tags.push(step_tags);
+        }
+        self
+    }
+
+    /// Download external packages from `mirror` instead of the internet
+    ///
+    /// Rewrites the apt repositories and keys of package steps and the
+    /// [`InstallDebFromUrl`] downloads with [`mirror_urls`], so
+    /// `https://apt.postgresql.org/pub/repos/apt` is fetched from
+    /// `{mirror}/apt.postgresql.org/pub/repos/apt`. Commands of
+    /// [`RunCommand`] steps (e.g. install scripts piped to `sh`) are left as
+    /// they are; [`Manifest::tengu`] leaves out the Ollama installer and model
+    /// pulls when [`TenguConfig::mirror`] is set.
+    #[must_use]
+    pub fn mirror(mut self, mirror: &str) -> Self {
+        for (_, step) in &mut self.steps {
+            let mirrored: Option<Box<dyn Step>> = match step.to_known() {
+                Some(KnownStep::InstallPackage(mut package)) => {
+                    package.repository = package.repository.map(|r| r.mirrored(mirror));
+                    Some(Box::new(package))
+                }
+                Some(KnownStep::AddAptRepositories(step)) => {
+                    Some(Box::new(AddAptRepositories::new(
+                        step.repositories
+                            .into_iter()
+                            .map(|(name, repo)| (name, repo.mirrored(mirror)))
+                            .collect(),
+                    )))
+                }
+                Some(KnownStep::InstallPackages(mut packages)) => {
+                    for command in &mut packages.repository_setup {
+                        *command = mirror_urls(command, mirror);
+                    }
+                    Some(Box::new(packages))
+                }
+                Some(KnownStep::InstallDebFromUrl(deb)) => Some(Box::new(deb.mirrored(mirror))),
+                _ => None,
+            };
+            if let Some(mirrored) = mirrored {
+                *step = mirrored;
+            }
+        }
+        self
+    }
+
+    /// Add every package repository up front with a single `apt-get update`
+    ///
+    /// Each repository-backed [`InstallPackage`] otherwise runs its own
+    /// update. Their repositories move into one [`AddAptRepositories`] step
+    /// at the first such package (carrying all their tags), and the packages
+    /// then only require it. With fewer than two repositories the manifest
+    /// is returned unchanged. Apply after phase and tag filters.
+    #[must_use]
+    pub fn coalesce_apt_updates(mut self) -> Self {
+        let repo_steps: Vec<usize> = self
+            .steps
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, step))| {
+                matches!(step.to_known(), Some(KnownStep::InstallPackage(p)) if p.repository.is_some())
+            })
+            .map(|(i, _)| i)
+            .collect();
+        if repo_steps.len() < 2 {
+            return self;
+        }
+
+        let mut repositories: Vec<(String, Repository)> = vec![];
+        let mut tags: Vec<String> = vec![];
+        for &i in &repo_steps {
+            let Some(KnownStep::InstallPackage(mut package)) = self.steps[i].1.to_known() else {
+                continue;
+            };
+            if let Some(repo) = package.take_repository()
+                && !repositories
+                    .iter()
+                    .any(|(_, r)| r.keyring_path == repo.keyring_path)
+            {
+                repositories.push((package.name.clone(), repo));
+            }
+            self.steps[i].1 = Box::new(package);
+            for tag in self.tags(i).to_vec() {
+                if !tags.contains(&tag) {
+                    tags.push(tag);
+                }
+            }
+        }
+
+        let first = repo_steps[0];
+        let phase = self.steps[first].0;
+        self.tags.resize(self.steps.len(), vec![]);
+        self.steps.insert(
+            first,
+            (phase, Box::new(AddAptRepositories::new(repositories))),
+        );
+        self.tags.insert(first, tags);
+        self
+    }
+
+    /// Collapse the manifest into a single batched apt install
+    ///
+    /// Every [`InstallPackage`] step is merged into one [`InstallPackages`]
+    /// step (repository setup, one `apt-get update`, one `apt-get install`);
+    /// all other steps (users, files, services, ...) are dropped. Meant for
+    /// quickly re-running package installs while iterating.
+    #[must_use]
+    pub fn packages_only(mut self) -> Self {
+        let mut names: Vec<String> = vec![];
+        let mut repository_setup: Vec<String> = vec![];
+        self.tags.clear();
+        for (_, step) in self.steps.drain(..) {
+            if step.kind() != "InstallPackage" {
+                continue;
+            }
+            let fragment = step.to_cloud_init();
+            for cmd in fragment.bootcmd {
+                if !repository_setup.contains(&cmd) {
+                    repository_setup.push(cmd);
+                }
+            }
+            for name in fragment.packages {
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+        }
+
+        if !names.is_empty() {
+            self.begin_phase(Phase::BasePackages);
+            self.add_step(InstallPackages::new(names).with_repository_setup(repository_setup));
+        }
+        self
+    }
+
+    /// Number of steps in the manifest
+    pub fn step_count(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Steps belonging to `phase`, in manifest order
+    pub fn steps_in(&self, phase: Phase) -> impl Iterator<Item = &dyn Step> {
+        self.steps
+            .iter()
+            .filter(move |(p, _)| *p == phase)
+            .map(|(_, step)| step.as_ref())
+    }
+
+    /// Steps whose [`Step::requires`] isn't provided by an earlier step
+    ///
+    /// Catches e.g. a package from an external repository installed before
+    /// the step that adds the repository, which would fail at run time.
+    pub fn validate_ordering(&self) -> Vec<OrderingIssue> {
+        let mut provided = HashSet::new();
+        let mut issues = vec![];
+        for (i, (_, step)) in self.steps.iter().enumerate() {
+            for dependency in step.requires() {
+                if provided.contains(&dependency) {
+                    continue;
+                }
+                let provided_by = self.steps[i + 1..]
+                    .iter()
+                    .position(|(_, later)| later.provides().contains(&dependency))
+                    .map(|offset| i + offset + 2);
+                issues.push(OrderingIssue {
+                    step: i + 1,
+                    description: step.description().to_string(),
+                    dependency,
+                    provided_by,
+                });
+            }
+            provided.extend(step.provides());
+        }
+        issues
+    }
+
+    /// Fail with [`ProvisionError::Validation`] listing any
+    /// [`Manifest::validate_ordering`] issues
+    pub fn validate(&self) -> Result<(), ProvisionError> {
+        let issues = self.validate_ordering();
+        if issues.is_empty() {
+            return Ok(());
+        }
+        Err(ProvisionError::Validation(
+            issues
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("; "),
+        ))
+    }
+
+    /// SHA-256 over the ordered kind, description and bash commands of all steps
+    ///
+    /// Two servers provisioned from manifests with the same fingerprint ran
+    /// the same steps. Fields are NUL-separated so they can't run together.
+    pub fn fingerprint(&self) -> String {
+        let mut hasher = Sha256::new();
+        for (_, step) in &self.steps {
+            hasher.update(step.kind().as_bytes());
+            hasher.update([0]);
+            hasher.update(step.description().as_bytes());
+            hasher.update([0]);
+            hasher.update(step.to_bash().join("\n").as_bytes());
+            hasher.update([0]);
+        }
+        hex::encode(hasher.finalize())
+    }
+
+    /// Serialize as TOML, to store a reviewed manifest and render it later
+    ///
+    /// Fails if a step isn't one of this crate's step types.
+    pub fn to_toml(&self) -> Result<String, ManifestTomlError> {
+        let steps = self
+            .steps
+            .iter()
+            .zip(&self.tags)
+            .enumerate()
+            .map(|(i, ((phase, step), tags))| {
+                let step = step.to_known().ok_or(ManifestTomlError::UnknownStep {
+                    step: i + 1,
+                    kind: step.kind(),
+                })?;
+                Ok(StepToml {
+                    phase: *phase,
+                    tags: tags.clone(),
+                    step,
+                })
+            })
+            .collect::<Result<_, ManifestTomlError>>()?;
+        Ok(toml::to_string(&ManifestToml {
+            hostname: self.hostname.clone(),
+            fqdn: self.fqdn.clone(),
+            timezone: self.timezone.clone(),
+            locale: self.locale.clone(),
+            secrets: self.secrets.clone(),
+            steps,
+        })?)
+    }
+
+    /// Load a manifest written by [`Self::to_toml`]
+    pub fn from_toml(toml: &str) -> Result<Self, ManifestTomlError> {
+        let parsed: ManifestToml = toml::from_str(toml)?;
+        let mut manifest = Self::new(parsed.hostname);
+        manifest.fqdn = parsed.fqdn;
+        manifest.timezone = parsed.timezone;
+        manifest.locale = parsed.locale;
+        manifest.secrets = parsed.secrets;
+        for entry in parsed.steps {
+            manifest.steps.push((entry.phase, entry.step.into_step()));
+            manifest.tags.push(entry.tags);
+        }
+        Ok(manifest)
+    }
+
+    /// Render a templated file and add it as a step
+    pub fn add_template(&mut self, template: &TemplateFile) -> Result<(), ProvisionError> {
+        self.add_step(template.render()?);
+        Ok(())
+    }
+
+    /// Add sshd hardening: no passwords, no root login, no challenge-response
+    ///
+    /// The drop-in is validated with `sshd -t` and sshd is only reloaded if
+    /// that passes; otherwise the step fails rather than risk a lockout. It
+    /// fails without `exit`, which would also end every later cloud-init
+    /// `runcmd` entry (they run as one script).
+    pub fn harden_ssh(&mut self) {
+        self.add_tagged(
+            &["security"],
+            WriteFile::new(
+                SSH_HARDENING_CONF,
+                "PasswordAuthentication no\n\
+                 PermitRootLogin no\n\
+                 ChallengeResponseAuthentication no\n\
+                 KbdInteractiveAuthentication no\n",
+            )
+            .with_permissions("0644")
+            .with_owner("root:root"),
+        );
+        self.add_tagged(
+            &["security"],
+            RunCommand::new(
+                "Validate and reload sshd",
+                "mkdir -p /run/sshd; \
+                 if sshd -t; then systemctl reload ssh 2>/dev/null || systemctl reload sshd; \
+                 else echo 'sshd -t failed, not reloading sshd' >&2; false; fi",
+            ),
+        );
+    }
+
+    /// Add post-install health checks as the final [`Phase::HealthCheck`] phase
+    ///
+    /// The checks have no guard, so `--only healthcheck` re-runs them against
+    /// a provisioned server. A check that still fails after its retries ends
+    /// its step with a `FAIL` marker and the script with a non-zero exit.
+    pub fn health_checks(&mut self) {
+        self.begin_phase(Phase::HealthCheck);
+
+        for service in ["docker", "postgresql", "caddy", "tengu"] {
+            let tags: &[&str] = if service == "postgresql" {
+                &["database"]
+            } else {
+                &[]
+            };
+            self.add_tagged(
+                tags,
+                RunCommand::new(
+                    format!("Check {service} is active"),
+                    format!("systemctl is-active --quiet {service}"),
+                )
+                .retries(5),
+            );
+        }
+
+        self.add_tagged(
+            &["database"],
+            RunCommand::new(
+                "Check pgvector extension is loaded",
+                r#"psql -d tengu -tAc "SELECT 1 FROM pg_extension WHERE extname='vector'" | grep -q 1"#,
+            )
+            .as_user("postgres"),
+        );
+
+        // Resolve to this host: public DNS (and the CF tunnel) may not exist yet
+        if let Some(fqdn) = self.fqdn.clone() {
+            self.add_step(
+                RunCommand::new(
+                    format!("Check https://{fqdn}/health"),
+                    format!(
+                        "curl -fsS -o /dev/null --max-time 10 --resolve {fqdn}:443:127.0.0.1 https://{fqdn}/health"
+                    ),
+                )
+                .retries(10),
+            );
+        }
     }
 
     /// Add a step fluently
@@ -64,9 +701,10 @@ impl Manifest {
     /// Create a complete Tengu installation manifest
     ///
     /// This builds the full installation sequence including:
+    /// - Hostname, timezone and locale (native keys under cloud-init)
     /// - User setup with SSH keys and sudo
     /// - Base packages (curl, wget, git, jq, htop, vim, fail2ban, ufw)
-    /// - Docker from Ubuntu repositories (docker.io)
+    /// - Docker from Ubuntu repositories (docker.io), or Docker CE / rootless
     /// - `PostgreSQL` 16 with pgvector extension
     /// - Ollama for AI/ML
     /// - tengu-caddy (custom Caddy build, works with or without Cloudflare)
@@ -74,78 +712,64 @@ impl Manifest {
     /// - Firewall rules (always enabled in direct mode)
     /// - Tengu .deb package installation
     /// - OpenSSH configuration for git operations
+    /// - Health checks for services, pgvector and the API endpoint
+    ///
+    /// `PostgreSQL` steps are tagged `database`, Ollama steps `ai`, and
+    /// firewall, fail2ban and unattended-upgrades steps `security`.
+    ///
+    /// Fails with [`ProvisionError::Template`] if a templated config file
+    /// doesn't render.
     #[allow(clippy::too_many_lines)]
-    pub fn tengu(config: &TenguConfig) -> Self {
-        let mut manifest = Self::new("tengu")
-            .with_fqdn(format!("api.{}", config.domain_platform))
-            .with_timezone("UTC");
-
-        // =========================================================
-        // Phase 1: User Setup
-        // =========================================================
-        manifest.add_step(
-            EnsureUser::new(&config.user)
-                .with_groups(["docker", "sudo"])
-                .with_sudo("ALL=(ALL) NOPASSWD:ALL")
-                .with_ssh_keys(config.ssh_keys.clone()),
-        );
-
-        // =========================================================
-        // Phase 2: Base Packages
-        // =========================================================
-        let base_packages = [
-            "curl",
-            "wget",
-            "git",
-            "jq",
-            "htop",
-            "vim",
-            "fail2ban",
-            "ufw",
-            "ca-certificates",
-            "gnupg",
-            "lsb-release",
-            "unzip",
-            "xfsprogs",
-        ];
-
-        for pkg in base_packages {
-            manifest.add_step(InstallPackage::new(pkg));
-        }
-
-        // =========================================================
-        // Phase 3: Docker from Ubuntu Repositories
-        // =========================================================
-        manifest.add_step(InstallPackage::new("docker.io"));
-        manifest.add_step(InstallPackage::new("docker-compose"));
+    pub fn tengu(config: &TenguConfig) -> Result<Self, ProvisionError> {
+        let mut manifest = Self::for_config(config);
+        manifest.add_system_phase(config);
+        manifest.add_user_phase(config);
+        manifest.add_base_packages_phase();
+        manifest.add_docker_phase(config);
 
         // =========================================================
         // Phase 4: PostgreSQL 16 with pgvector
         // =========================================================
-        manifest.add_step(
+        manifest.begin_phase(Phase::Postgres);
+        // Data volume goes first so the cluster is initialized on it
+        if let Some((device, mount_path)) = &config.data_volume {
+            manifest.add_tagged(&["database"], EnsureMount::new(device, mount_path));
+        }
+        manifest.add_tagged(
+            &["database"],
             InstallPackage::new("postgresql-16").with_repository(Repository::postgresql()),
         );
-        manifest.add_step(InstallPackage::new("postgresql-16-pgvector"));
+        manifest.add_tagged(
+            &["database"],
+            InstallPackage::new("postgresql-16-pgvector")
+                .from_repository(&Repository::postgresql()),
+        );
 
         // =========================================================
         // Phase 5: Ollama
         // =========================================================
-        manifest.add_step(
-            RunCommand::new(
-                "Install Ollama",
-                "bash -c 'set +e; curl -fsSL https://ollama.com/install.sh | sh; exit 0'",
-            )
-            .unless("command -v ollama >/dev/null 2>&1"),
-        );
+        // Behind a mirror Ollama must come with the image: its installer and
+        // models are only served from ollama.com
+        manifest.begin_phase(Phase::Ollama);
+        if config.mirror.is_none() {
+            manifest.add_tagged(
+                &["ai"],
+                RunCommand::new(
+                    "Install Ollama",
+                    "curl -fsSL https://ollama.com/install.sh | sh",
+                )
+                .unless("command -v ollama >/dev/null 2>&1")
+                .parallel_safe(true)
+                .allow_failure(true),
+            );
+        }
 
-        // =========================================================
-        // Phase 6: tengu-caddy (Caddy with Cloudflare DNS plugin)
-        // =========================================================
-        manifest.add_step(InstallDebFromUrl::tengu_caddy());
+        manifest.add_caddy_phase(config);
 
         // =========================================================
         // Phase 7: Tengu Directories
         // =========================================================
+        manifest.begin_phase(Phase::Directories);
         manifest.add_step(
             EnsureDirectory::new("/etc/tengu")
                 .with_permissions("0750")
@@ -180,6 +804,7 @@ impl Manifest {
         // =========================================================
         // Phase 8: Configuration Files
         // =========================================================
+        manifest.begin_phase(Phase::Configuration);
 
         // Tengu config.toml — permissions fixed after tengu user is created by .deb install
         manifest.add_step(
@@ -188,48 +813,14 @@ impl Manifest {
                 .with_owner("root:root"),
         );
 
-        // Caddyfile
-        manifest.add_step(
-            WriteFile::new("/etc/caddy/Caddyfile", config.caddyfile())
-                .with_permissions("0644")
-                .with_owner("root:root"),
-        );
-
-        // Caddy systemd drop-in for Cloudflare API token (CF mode only)
-        if config.is_cloudflare() {
-            manifest.add_step(
-                EnsureDirectory::new("/etc/systemd/system/caddy.service.d")
-                    .with_permissions("0755")
-                    .with_owner("root:root"),
-            );
-            manifest.add_step(
-                WriteFile::new(
-                    "/etc/systemd/system/caddy.service.d/cloudflare.conf",
-                    config.caddy_cloudflare_env(),
-                )
-                .with_permissions("0644")
-                .with_owner("root:root"),
-            );
-
-            // Reload systemd after drop-in
-            manifest.add_step(RunCommand::new(
-                "Reload systemd daemon",
-                "systemctl daemon-reload",
-            ));
-        }
-
-        // fail2ban configuration
-        manifest.add_step(
-            WriteFile::new("/etc/fail2ban/jail.local", config.fail2ban_config())
-                .with_permissions("0644")
-                .with_owner("root:root"),
-        );
+        manifest.add_config_files(config);
 
         // =========================================================
         // Phase 8b: Docker XFS Backing Storage
         // Create XFS loopback image for /var/lib/docker so overlay2
         // can enforce per-container storage quotas via --storage-opt
         // =========================================================
+        manifest.begin_phase(Phase::Storage);
 
         // Stop Docker before XFS mount (apt install docker.io auto-starts it)
         // Docker will be properly started in Phase 10 after XFS is mounted
@@ -304,96 +895,72 @@ impl Manifest {
             .with_owner("root:root"),
         );
 
-        // =========================================================
-        // Phase 9: Firewall Rules
-        // Direct mode: always enabled (server directly exposed)
-        // Cloudflare mode: optional (traffic may go through tunnel)
-        // =========================================================
-        let enable_firewall = if config.is_cloudflare() {
-            config.enable_ufw
-        } else {
-            true // Direct mode always needs UFW
-        };
-
-        if enable_firewall {
-            manifest.add_step(
-                EnsureFirewall::new()
-                    .allow("22/tcp") // SSH
-                    .allow("80/tcp") // HTTP
-                    .allow("443/tcp"), // HTTPS
-            );
-        }
+        manifest.add_firewall_phase(config);
 
         // =========================================================
         // Phase 10: Enable and Start Services
         // =========================================================
-        // Reload systemd and wait for units to settle after package installs.
-        // Ubuntu 24.04 can delay unit file creation during post-install scripts.
-        manifest.add_step(RunCommand::new(
-            "Reload systemd and settle",
-            "systemctl daemon-reload && sleep 2",
-        ));
-
-        // Start Docker — try socket activation first (Docker CE), fall back to service (Ubuntu docker.io).
-        // Ubuntu 24.04's docker.io package may not ship docker.socket.
-        // Wait up to 60s for Docker to become ready (XFS backing init takes time on first boot).
-        manifest.add_step(
-            RunCommand::new(
-                "Start Docker",
-                "systemctl enable docker.socket 2>/dev/null || true; \
-                 systemctl enable docker 2>/dev/null || true; \
-                 systemctl start docker.socket 2>/dev/null || systemctl start docker 2>/dev/null || true; \
-                 for i in $(seq 1 30); do docker info >/dev/null 2>&1 && break; sleep 2; done",
-            )
-            .unless("docker info >/dev/null 2>&1"),
-        );
+        manifest.begin_phase(Phase::Services);
+        manifest.add_step(Self::settle_systemd_step());
+        manifest.add_step(Self::start_docker_step());
 
         // Start PostgreSQL
-        manifest.add_step(
+        manifest.add_tagged(
+            &["database"],
             RunCommand::new(
                 "Start PostgreSQL",
                 "systemctl enable postgresql 2>/dev/null || true; \
                  systemctl start postgresql 2>/dev/null || true; \
                  for i in $(seq 1 15); do pg_isready -q 2>/dev/null && break; sleep 2; done",
             )
-            .unless("pg_isready -q 2>/dev/null"),
+            .unless("pg_isready -q 2>/dev/null")
+            .starts_service("postgresql"),
         );
 
-        // Start fail2ban
-        manifest.add_step(
-            RunCommand::new(
-                "Start fail2ban",
-                "systemctl enable fail2ban 2>/dev/null || true; \
-                 systemctl start fail2ban 2>/dev/null || true; \
-                 for i in $(seq 1 5); do systemctl is-active fail2ban >/dev/null 2>&1 && break; sleep 2; done",
-            )
-            .unless("systemctl is-active fail2ban >/dev/null 2>&1"),
-        );
-
-        // Start Caddy
-        manifest.add_step(
-            RunCommand::new(
-                "Start Caddy",
-                "systemctl enable caddy 2>/dev/null || true; \
-                 systemctl start caddy 2>/dev/null || true; \
-                 for i in $(seq 1 5); do systemctl is-active caddy >/dev/null 2>&1 && break; sleep 2; done",
-            )
-            .unless("systemctl is-active caddy >/dev/null 2>&1"),
-        );
+        manifest.add_tagged(&["security"], Self::start_fail2ban_step());
+        manifest.add_step(Self::start_caddy_step());
 
         // Ollama runs as a user service by default, or systemd service if installed via deb
-        manifest.add_step(
-            RunCommand::new("Enable ollama service", "systemctl enable ollama || true")
-                .unless("systemctl is-enabled ollama >/dev/null 2>&1"),
+        manifest.add_tagged(
+            &["ai"],
+            RunCommand::new("Enable ollama service", "systemctl enable ollama")
+                .unless("systemctl is-enabled ollama >/dev/null 2>&1")
+                .allow_failure(true),
         );
-        manifest.add_step(
-            RunCommand::new("Start ollama service", "systemctl start ollama || true")
-                .unless("systemctl is-active ollama >/dev/null 2>&1"),
+        manifest.add_tagged(
+            &["ai"],
+            RunCommand::new("Start ollama service", "systemctl start ollama")
+                .unless("systemctl is-active ollama >/dev/null 2>&1")
+                .allow_failure(true),
         );
 
+        // Pre-pull models one at a time (multi-GB each) once the API answers
+        let models = if config.mirror.is_none() {
+            config.ollama_models.as_slice()
+        } else {
+            &[]
+        };
+        for model in models {
+            let quoted = shell_quote(model);
+            manifest.add_tagged(
+                &["ai"],
+                RunCommand::new(
+                    format!("Pull ollama model {model}"),
+                    format!(
+                        "for i in $(seq 1 30); do ollama list >/dev/null 2>&1 && break; sleep 2; done; \
+                         ollama pull {quoted}"
+                    ),
+                )
+                .unless(format!("ollama show {quoted} >/dev/null 2>&1"))
+                .retries(3)
+                .parallel_safe(false),
+            );
+        }
+
         // =========================================================
         // Phase 11: Install Tengu .deb Package
         // =========================================================
+        manifest.begin_phase(Phase::Tengu);
         if config.deb_path.is_some() {
             // Local .deb was SCP'd to /root/tengu-local.deb before provisioning
             // Wait for apt lock and use --force-confold to keep existing config.toml
@@ -405,11 +972,18 @@ impl Manifest {
                      { while fuser /var/lib/dpkg/lock-frontend >/dev/null 2>&1; do sleep 3; done; \
                        DEBIAN_FRONTEND=noninteractive apt-get install -f -y --allow-downgrades; }",
                 )
-                .unless("tengu version >/dev/null 2>&1"),
+                .unless("tengu version >/dev/null 2>&1")
+                .installs_package("tengu"),
             );
         } else {
-            let tengu_deb_url = "https://github.com/tengu-apps/tengu-deb/releases/download/current/tengu_{arch}.deb";
-            manifest.add_step(InstallDebFromUrl::new("tengu", tengu_deb_url));
+            let tengu_deb_url = format!(
+                "{}/download/current/tengu_{{arch}}.deb",
+                TenguConfig::releases_url(&config.tengu_repo)
+            );
+            manifest.add_step(
+                InstallDebFromUrl::new("tengu", &tengu_deb_url)
+                    .with_sha256_url(format!("{tengu_deb_url}.sha256")),
+            );
         }
 
         // Fix /etc/tengu ownership — tengu user/group created by .deb install
@@ -423,7 +997,7 @@ impl Manifest {
         );
 
         // Enable and start tengu service
-        manifest.add_step(EnsureService::new("tengu"));
+        manifest.add_step(EnsureService::new("tengu").from_package("tengu"));
 
         // Set tengu user shell to /bin/bash — tengu is a normal user and
         // the setup SSH key can log in directly. The command= prefix in
@@ -476,18 +1050,20 @@ impl Manifest {
         // =========================================================
         // Phase 11a: OpenSSH Configuration for Git Operations
         // =========================================================
+        manifest.begin_phase(Phase::OpenSsh);
 
         // Write sshd drop-in config for tengu user
-        manifest.add_step(
-            WriteFile::new(
+        manifest.add_template(
+            &TemplateFile::new(
                 "/etc/ssh/sshd_config.d/tengu.conf",
-                "Match User tengu\n    \
-                 AuthorizedKeysCommand /usr/bin/tengu auth-keys %t %k\n    \
-                 AuthorizedKeysCommandUser root\n",
+                "Match User {{ user }}\n    \
+                     AuthorizedKeysCommand {{ tengu_bin }} auth-keys %t %k\n    \
+                     AuthorizedKeysCommandUser root\n",
             )
+            .context(serde_json::json!({ "user": "tengu", "tengu_bin": "/usr/bin/tengu" }))
             .with_permissions("0644")
             .with_owner("root:root"),
-        );
+        )?;
 
         // Restart sshd to pick up the new configuration
         // Ubuntu 24.04 uses ssh.service, older versions use sshd.service
@@ -499,43 +1075,60 @@ impl Manifest {
         // =========================================================
         // Phase 12: Post-Install Setup
         // =========================================================
+        manifest.begin_phase(Phase::PostInstall);
 
         // Initialize PostgreSQL database for Tengu
-        manifest.add_step(
+        manifest.add_tagged(
+            &["database"],
             RunCommand::new(
                 "Create tengu PostgreSQL database",
-                r#"sudo -u postgres psql -c "CREATE DATABASE tengu;" 2>/dev/null || true"#,
+                r#"psql -c "CREATE DATABASE tengu;" 2>/dev/null || true"#,
             )
-            .unless(r"sudo -u postgres psql -lqt | cut -d \| -f 1 | grep -qw tengu"),
+            .as_user("postgres")
+            .unless(r"psql -lqt | cut -d \| -f 1 | grep -qw tengu"),
         );
 
         // Create tengu PostgreSQL user (or ensure password is set if user exists)
-        manifest.add_step(
+        manifest.add_tagged(
+            &["database"],
             RunCommand::new(
                 "Create tengu PostgreSQL user",
-                r#"sudo -u postgres psql -c "CREATE USER tengu WITH PASSWORD 'tengu';" 2>/dev/null || sudo -u postgres psql -c "ALTER USER tengu WITH PASSWORD 'tengu';""#,
+                r#"psql -c "CREATE USER tengu WITH PASSWORD 'tengu';" 2>/dev/null || psql -c "ALTER USER tengu WITH PASSWORD 'tengu';""#,
             )
+            .as_user("postgres")
             .unless(r#"PGPASSWORD=tengu psql -U tengu -h 127.0.0.1 -d tengu -c "SELECT 1" >/dev/null 2>&1"#),
         );
 
         // Grant privileges
-        manifest.add_step(RunCommand::new(
-            "Grant PostgreSQL privileges to tengu",
-            r#"sudo -u postgres psql -c "GRANT ALL PRIVILEGES ON DATABASE tengu TO tengu;""#,
-        ));
+        manifest.add_tagged(
+            &["database"],
+            RunCommand::new(
+                "Grant PostgreSQL privileges to tengu",
+                r#"psql -c "GRANT ALL PRIVILEGES ON DATABASE tengu TO tengu;""#,
+            )
+            .as_user("postgres"),
+        );
 
         // Enable pgvector extension
-        manifest.add_step(
+        manifest.add_tagged(
+            &["database"],
             RunCommand::new(
                 "Enable pgvector extension",
-                r#"sudo -u postgres psql -d tengu -c "CREATE EXTENSION IF NOT EXISTS vector;""#,
+                r#"psql -d tengu -c "CREATE EXTENSION IF NOT EXISTS vector;""#,
             )
-            .unless(r#"sudo -u postgres psql -d tengu -tAc "SELECT 1 FROM pg_extension WHERE extname='vector'" | grep -q 1"#),
+            .as_user("postgres")
+            .unless(r#"psql -d tengu -tAc "SELECT 1 FROM pg_extension WHERE extname='vector'" | grep -q 1"#),
         );
 
+        // Automatic security patches, last so they can't grab the dpkg lock mid-install
+        if config.auto_upgrades {
+            manifest.add_tagged(&["security"], EnsureUnattendedUpgrades::new());
+        }
+
         // =========================================================
         // Phase 13: Create Tengu Admin User
         // =========================================================
+        manifest.begin_phase(Phase::AdminUser);
 
         // Create admin user with SSH key and save token
         let ssh_key = config
@@ -558,8 +1151,322 @@ impl Manifest {
                 .unless(format!(r#"tengu user list --json 2>/dev/null | jq -e '.[] | select(.name == "{}")' >/dev/null"#, config.user)),
         );
 
+        // =========================================================
+        // Phase 14: Health Checks
+        // =========================================================
+        manifest.health_checks();
+
+        manifest.add_extra_commands(config);
+
+        Ok(match &config.mirror {
+            Some(mirror) => manifest.mirror(mirror),
+            None => manifest,
+        })
+    }
+
+    /// Create a lightweight edge node manifest
+    ///
+    /// Same building blocks as [`Manifest::tengu`], without the database,
+    /// AI and Tengu phases:
+    /// - Hostname, timezone and locale
+    /// - User setup with SSH keys and sudo
+    /// - Base packages
+    /// - Docker (per `docker_install`)
+    /// - tengu-caddy with the Caddyfile, fail2ban config and extra files
+    /// - Firewall rules (always enabled in direct mode)
+    pub fn minimal(config: &TenguConfig) -> Self {
+        let mut manifest = Self::for_config(config);
+        manifest.add_system_phase(config);
+        manifest.add_user_phase(config);
+        manifest.add_base_packages_phase();
+        manifest.add_docker_phase(config);
+        manifest.add_caddy_phase(config);
+
+        manifest.begin_phase(Phase::Directories);
+        manifest.add_step(
+            EnsureDirectory::new("/etc/caddy/sites")
+                .with_permissions("0755")
+                .with_owner("root:root"),
+        );
+
+        manifest.begin_phase(Phase::Configuration);
+        manifest.add_config_files(config);
+
+        manifest.add_firewall_phase(config);
+
+        manifest.begin_phase(Phase::Services);
+        manifest.add_step(Self::settle_systemd_step());
+        manifest.add_step(Self::start_docker_step());
+        manifest.add_tagged(&["security"], Self::start_fail2ban_step());
+        manifest.add_step(Self::start_caddy_step());
+
+        if config.auto_upgrades {
+            manifest.begin_phase(Phase::PostInstall);
+            manifest.add_tagged(&["security"], EnsureUnattendedUpgrades::new());
+        }
+
+        manifest.add_extra_commands(config);
+
+        match &config.mirror {
+            Some(mirror) => manifest.mirror(mirror),
+            None => manifest,
+        }
+    }
+
+    /// Empty manifest with host identity and secrets taken from `config`
+    fn for_config(config: &TenguConfig) -> Self {
+        let mut manifest = Self::new("tengu")
+            .with_fqdn(format!("api.{}", config.domain_platform))
+            .with_timezone(&config.timezone)
+            .with_locale(&config.locale);
+        manifest.secrets = config.secrets().into_iter().map(Into::into).collect();
         manifest
     }
+
+    /// Add the [`Phase::System`] phase
+    ///
+    /// Cloud-init applies these natively; the steps only emit bash.
+    fn add_system_phase(&mut self, config: &TenguConfig) {
+        self.begin_phase(Phase::System);
+        self.add_step(SetHostname::new(self.hostname.clone()));
+        self.add_step(SetTimezone::new(&self.timezone));
+        self.add_step(SetLocale::new(&self.locale));
+        // Before any package installs, so small hosts don't run out of memory
+        if config.auto_swap {
+            self.add_step(EnsureSwap::auto());
+        }
+    }
+
+    /// Add the [`Phase::UserSetup`] phase: admin user with SSH keys and sudo
+    fn add_user_phase(&mut self, config: &TenguConfig) {
+        self.begin_phase(Phase::UserSetup);
+        for spec in config.user_specs() {
+            let mut user = EnsureUser::new(&spec.name)
+                .with_groups(spec.groups)
+                .with_ssh_keys(spec.ssh_keys);
+            if let Some(sudo) = spec.sudo {
+                user = user.with_sudo(sudo);
+            }
+            self.add_step(user);
+        }
+    }
+
+    /// Add the [`Phase::BasePackages`] phase
+    fn add_base_packages_phase(&mut self) {
+        self.begin_phase(Phase::BasePackages);
+        // unattended-upgrades may still hold the dpkg lock on a fresh image
+        self.add_step(WaitForAptLock::new());
+        let base_packages = [
+            "curl",
+            "wget",
+            "git",
+            "jq",
+            "htop",
+            "vim",
+            "fail2ban",
+            "ufw",
+            "ca-certificates",
+            "gnupg",
+            "lsb-release",
+            "unzip",
+            "xfsprogs",
+        ];
+
+        for pkg in base_packages {
+            self.add_step(InstallPackage::new(pkg));
+        }
+    }
+
+    /// Add the [`Phase::Docker`] phase (Ubuntu repositories unless configured otherwise)
+    fn add_docker_phase(&mut self, config: &TenguConfig) {
+        self.begin_phase(Phase::Docker);
+        match config.docker_install {
+            DockerInstallMode::AptRepo => {
+                self.add_step(InstallPackage::new("docker.io"));
+                self.add_step(InstallPackage::new("docker-compose"));
+            }
+            DockerInstallMode::ConvenienceScript | DockerInstallMode::Rootless => {
+                self.add_step(
+                    RunCommand::new(
+                        "Install Docker via get.docker.com",
+                        "curl -fsSL https://get.docker.com | sh",
+                    )
+                    .unless("command -v docker >/dev/null 2>&1")
+                    .retries(3),
+                );
+            }
+        }
+        if config.docker_install == DockerInstallMode::Rootless {
+            // Rootless dockerd runs as a systemd user service, which needs
+            // the user's manager to run without a login session
+            self.add_step(InstallPackage::new("uidmap"));
+            self.add_step(
+                RunCommand::new(
+                    format!("Enable lingering for {}", config.user),
+                    format!("loginctl enable-linger {}", config.user),
+                )
+                .unless(format!("test -f /var/lib/systemd/linger/{}", config.user)),
+            );
+            self.add_step(
+                RunCommand::new(
+                    format!("Set up rootless Docker for {}", config.user),
+                    "XDG_RUNTIME_DIR=/run/user/$(id -u) dockerd-rootless-setuptool.sh install",
+                )
+                .as_user(&config.user)
+                .unless(
+                    "XDG_RUNTIME_DIR=/run/user/$(id -u) systemctl --user is-active --quiet docker",
+                ),
+            );
+        }
+    }
+
+    /// Add the [`Phase::Caddy`] phase: tengu-caddy (Caddy with Cloudflare DNS plugin)
+    fn add_caddy_phase(&mut self, config: &TenguConfig) {
+        self.begin_phase(Phase::Caddy);
+        self.add_step(InstallDebFromUrl::tengu_caddy(&TenguConfig::releases_url(
+            &config.tengu_caddy_repo,
+        )));
+    }
+
+    /// Add the Caddyfile, Cloudflare drop-in, fail2ban config and extra files
+    /// to the current phase
+    fn add_config_files(&mut self, config: &TenguConfig) {
+        // Caddyfile
+        self.add_step(
+            WriteFile::new("/etc/caddy/Caddyfile", config.caddyfile())
+                .with_permissions("0644")
+                .with_owner("root:root"),
+        );
+
+        // Caddy systemd drop-in for Cloudflare API token (CF mode only)
+        if config.is_cloudflare() {
+            self.add_step(
+                EnsureDirectory::new("/etc/systemd/system/caddy.service.d")
+                    .with_permissions("0755")
+                    .with_owner("root:root"),
+            );
+            self.add_step(
+                WriteFile::new(
+                    "/etc/systemd/system/caddy.service.d/cloudflare.conf",
+                    config.caddy_cloudflare_env(),
+                )
+                .with_permissions("0644")
+                .with_owner("root:root"),
+            );
+
+            // Reload systemd after drop-in
+            self.add_step(RunCommand::new(
+                "Reload systemd daemon",
+                "systemctl daemon-reload",
+            ));
+        }
+
+        // fail2ban configuration
+        self.add_tagged(
+            &["security"],
+            WriteFile::new("/etc/fail2ban/jail.local", config.fail2ban_config())
+                .with_permissions("0644")
+                .with_owner("root:root"),
+        );
+
+        // Extra files from the config
+        for file in &config.files {
+            let mut step = WriteFile::new(&file.path, &file.content);
+            if let Some(permissions) = &file.permissions {
+                step = step.with_permissions(permissions);
+            }
+            if let Some(owner) = &file.owner {
+                step = step.with_owner(owner);
+            }
+            self.add_step(step);
+        }
+    }
+
+    /// Add the [`Phase::Firewall`] phase
+    ///
+    /// Direct mode: always enabled (server directly exposed).
+    /// Cloudflare mode: optional (traffic may go through tunnel).
+    fn add_firewall_phase(&mut self, config: &TenguConfig) {
+        self.begin_phase(Phase::Firewall);
+        let enable_firewall = if config.is_cloudflare() {
+            config.enable_ufw
+        } else {
+            true // Direct mode always needs UFW
+        };
+
+        if enable_firewall {
+            self.add_tagged(
+                &["security"],
+                EnsureFirewall::new()
+                    .allow("22/tcp") // SSH
+                    .allow("80/tcp") // HTTP
+                    .allow("443/tcp"), // HTTPS
+            );
+        }
+    }
+
+    /// Add the config's extra commands, run verbatim after everything else
+    fn add_extra_commands(&mut self, config: &TenguConfig) {
+        self.begin_phase(Phase::Custom);
+
+        for (i, cmd) in config.extra_bootcmd.iter().enumerate() {
+            self.add_step(
+                RunCommand::new(format!("Run extra boot command {}", i + 1), cmd).at_boot(),
+            );
+        }
+        for (i, cmd) in config.extra_runcmd.iter().enumerate() {
+            self.add_step(RunCommand::new(format!("Run extra command {}", i + 1), cmd));
+        }
+    }
+
+    /// Reload systemd and wait for units to settle after package installs
+    ///
+    /// Ubuntu 24.04 can delay unit file creation during post-install scripts.
+    fn settle_systemd_step() -> RunCommand {
+        RunCommand::new(
+            "Reload systemd and settle",
+            "systemctl daemon-reload && sleep 2",
+        )
+    }
+
+    /// Start Docker: socket activation first (Docker CE), falling back to the
+    /// service (Ubuntu docker.io may not ship docker.socket)
+    ///
+    /// Waits up to 60s, as XFS backing init takes time on first boot.
+    fn start_docker_step() -> RunCommand {
+        RunCommand::new(
+            "Start Docker",
+            "systemctl enable docker.socket 2>/dev/null || true; \
+             systemctl enable docker 2>/dev/null || true; \
+             systemctl start docker.socket 2>/dev/null || systemctl start docker 2>/dev/null || true; \
+             for i in $(seq 1 30); do docker info >/dev/null 2>&1 && break; sleep 2; done",
+        )
+        .unless("docker info >/dev/null 2>&1")
+        .starts_service("docker")
+    }
+
+    /// Start fail2ban
+    fn start_fail2ban_step() -> RunCommand {
+        RunCommand::new(
+            "Start fail2ban",
+            "systemctl enable fail2ban 2>/dev/null || true; \
+             systemctl start fail2ban 2>/dev/null || true; \
+             for i in $(seq 1 5); do systemctl is-active fail2ban >/dev/null 2>&1 && break; sleep 2; done",
+        )
+        .unless("systemctl is-active fail2ban >/dev/null 2>&1")
+    }
+
+    /// Start Caddy
+    fn start_caddy_step() -> RunCommand {
+        RunCommand::new(
+            "Start Caddy",
+            "systemctl enable caddy 2>/dev/null || true; \
+             systemctl start caddy 2>/dev/null || true; \
+             for i in $(seq 1 5); do systemctl is-active caddy >/dev/null 2>&1 && break; sleep 2; done",
+        )
+        .unless("systemctl is-active caddy >/dev/null 2>&1")
+        .starts_service("caddy")
+    }
 }
 
 impl Default for Manifest {
@@ -1,8 +1,81 @@
 //! Bash script renderer
 
-use crate::Manifest;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use super::Renderer;
+use crate::steps::{Dependency, Step, TRACK_PKG_FN, shell_quote};
+use crate::{Manifest, ProvisionError};
+
+use super::{NONINTERACTIVE_EXPORT, Renderer, redact_secrets};
+
+/// Encode a step description for a `TENGU_STEP` progress marker
+///
+/// The description is the last un-split marker field (only followed by the
+/// all-digit elapsed time on `DONE`), so colons survive as-is; `%`, CR and
+/// LF are percent-encoded to keep the marker on one line.
+pub fn encode_marker_description(desc: &str) -> String {
+    desc.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Decode a description produced by [`encode_marker_description`]
+pub fn decode_marker_description(encoded: &str) -> String {
+    encoded
+        .replace("%0A", "\n")
+        .replace("%0D", "\r")
+        .replace("%25", "%")
+}
+
+/// Format seconds since the Unix epoch as `YYYY-MM-DDTHH:MM:SSZ`
+pub(crate) fn format_utc(secs: u64) -> String {
+    let (days, rem) = (secs / 86_400, secs % 86_400);
+    // Civil date from a day count (Howard Hinnant's days_from_civil inverse)
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = era * 400 + yoe + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Services started by the manifest's steps, checked once every step ran
+///
+/// Best-effort ([`Step::allow_failure`]) steps are left out, since their
+/// services may legitimately be down.
+fn verified_services(manifest: &Manifest) -> Vec<String> {
+    let mut services = Vec::new();
+    for (_, step) in &manifest.steps {
+        if step.allow_failure() {
+            continue;
+        }
+        for dependency in step.provides() {
+            if let Dependency::Service(name) = dependency
+                && !services.contains(&name)
+            {
+                services.push(name);
+            }
+        }
+    }
+    services
+}
+
+/// Step kinds that take the dpkg lock and must never run concurrently
+const APT_STEP_KINDS: &[&str] = &[
+    "AddAptRepositories",
+    "InstallPackage",
+    "InstallDebFromUrl",
+    "RemovePackage",
+    "WaitForAptLock",
+];
 
 /// Renders a manifest as an idempotent bash script
 #[derive(Debug, Clone, Default)]
@@ -11,14 +84,35 @@ pub struct BashRenderer {
     pub verbose: bool,
     /// Use color output (ANSI escape codes)
     pub color: bool,
+    /// Honor a `--dry-run` argument that echoes step commands instead of running them
+    pub dry_run_support: bool,
+    /// Append all script output to this file (step markers timestamped)
+    pub log_file: Option<String>,
+    /// Run consecutive parallel-safe steps as background jobs
+    pub parallel: bool,
+    /// Mask secret values in the output
+    pub redact: bool,
+    /// Export `DEBIAN_FRONTEND=noninteractive` and `NEEDRESTART_MODE=a` up front
+    pub noninteractive: bool,
+    /// Record progress in [`STATE_FILE`] and honor a `--resume` argument
+    pub resume_support: bool,
 }
 
+/// Last finished step and manifest fingerprint, written by resumable scripts
+pub const STATE_FILE: &str = "/var/lib/tengu/provision.state";
+
 impl BashRenderer {
     /// Create a new bash renderer
     pub fn new() -> Self {
         Self {
             verbose: false,
             color: true,
+            dry_run_support: false,
+            log_file: None,
+            parallel: false,
+            redact: false,
+            noninteractive: true,
+            resume_support: false,
         }
     }
 
@@ -33,31 +127,297 @@ impl BashRenderer {
         self.color = color;
         self
     }
+
+    /// Enable runtime `--dry-run` support in the generated script
+    ///
+    /// Idempotency checks still run in dry-run mode so skip markers stay accurate.
+    pub fn dry_run_support(mut self, enabled: bool) -> Self {
+        self.dry_run_support = enabled;
+        self
+    }
+
+    /// Record each finished step and honor a runtime `--resume` argument
+    ///
+    /// The script writes the number of the last finished step to
+    /// [`STATE_FILE`], up to the first failed one; `--resume` skips steps up
+    /// to it, as long as the file was written by a script for the same
+    /// manifest. A completed run removes the file.
+    pub fn resume_support(mut self, enabled: bool) -> Self {
+        self.resume_support = enabled;
+        self
+    }
+
+    /// Run consecutive [`Step::parallel_safe`] steps concurrently
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.parallel = parallel;
+        self
+    }
+
+    /// Replace [`Manifest::secrets`] with a placeholder (for sharing output)
+    pub fn redact(mut self, redact: bool) -> Self {
+        self.redact = redact;
+        self
+    }
+
+    /// Keep apt, dpkg and needrestart from prompting (default true)
+    ///
+    /// Exported once at the top, so every package operation inherits it.
+    pub fn noninteractive(mut self, noninteractive: bool) -> Self {
+        self.noninteractive = noninteractive;
+        self
+    }
+
+    /// Tee all output to a log file (e.g. `/var/log/tengu/provision.log`)
+    ///
+    /// Stdout is unchanged so marker parsing keeps working; only the copy
+    /// written to the file gets a timestamp on each `TENGU_STEP` line.
+    pub fn log_file(mut self, path: impl Into<String>) -> Self {
+        self.log_file = Some(path.into());
+        self
+    }
+
+    /// Append a single step, wrapped in its idempotency check if it has one
+    fn push_step(&self, script: &mut String, step: &dyn Step, step_num: usize, total_steps: usize) {
+        let desc = step.description();
+        // Single-line, single-quoted description for the progress markers
+        let desc_escaped = shell_quote(&encode_marker_description(desc));
+
+        if self.verbose {
+            script.push_str(&format!(
+                "\n# Step {step_num}/{total_steps}: {}\n",
+                desc.replace(['\r', '\n'], " ")
+            ));
+        }
+
+        // Wrap in idempotency check if available
+        // Use subshell for check to isolate from set -e + pipefail interaction
+        let check = step
+            .check_command()
+            .map(|check| format!("(set +e; {check})"));
+        let resumed = self
+            .resume_support
+            .then(|| format!("[ \"$TENGU_RESUME_FROM\" -ge {step_num} ]"));
+        let skip = match (resumed, check) {
+            (Some(resumed), Some(check)) => Some(format!("{resumed} || {check}")),
+            (resumed, check) => resumed.or(check),
+        };
+        if let Some(skip) = skip {
+            script.push_str(&format!("if {skip}; then\n"));
+            if self.verbose {
+                script.push_str(&format!("    step_skip \"{step_num}\" {desc_escaped}\n"));
+            } else {
+                script.push_str("    :\n");
+            }
+            script.push_str("else\n");
+            if self.verbose {
+                script.push_str(&format!("    step_start \"{step_num}\" {desc_escaped}\n"));
+            }
+            // Indent commands
+            self.push_step_body(script, step, step_num, &desc_escaped, "    ");
+            script.push_str("fi\n");
+        } else {
+            if self.verbose {
+                script.push_str(&format!("step_start \"{step_num}\" {desc_escaped}\n"));
+            }
+            self.push_step_body(script, step, step_num, &desc_escaped, "");
+        }
+    }
+
+    /// Append a step's commands and its closing marker
+    ///
+    /// The commands run as an `&&` chain, so a step stops at its first
+    /// failing command. A failed step ends with a `FAIL` marker and sets
+    /// `TENGU_FAILED`, which withholds `COMPLETE`; an [`Step::allow_failure`]
+    /// step ends with a `WARN` marker instead and doesn't count as failed.
+    fn push_step_body(
+        &self,
+        script: &mut String,
+        step: &dyn Step,
+        step_num: usize,
+        desc_escaped: &str,
+        indent: &str,
+    ) {
+        // `a && b || x` keeps errexit from firing, so this is safe under set -e
+        let cmds = step.to_bash();
+        let chain = cmds
+            .iter()
+            .map(|cmd| {
+                let body: Vec<String> = cmd.lines().map(|line| format!("    {line}")).collect();
+                format!("{{\n{}\n}}", body.join("\n"))
+            })
+            .collect::<Vec<_>>()
+            .join(" && ");
+        script.push_str(&format!("{indent}tengu_failed=0\n"));
+        self.push_commands(script, &cmds, &format!("{chain} || tengu_failed=1"), indent);
+
+        let (marker, failed) = if step.allow_failure() {
+            ("step_warn", String::new())
+        } else {
+            ("step_fail", format!("{indent}    TENGU_FAILED=1\n"))
+        };
+        if self.verbose {
+            script.push_str(&format!(
+                "{indent}if [ \"$tengu_failed\" = 1 ]; then\n\
+                 {indent}    {marker} \"{step_num}\" {desc_escaped}\n\
+                 {failed}\
+                 {indent}else\n\
+                 {indent}    step_done \"{step_num}\" {desc_escaped}\n\
+                 {indent}fi\n"
+            ));
+        } else {
+            let description = step.description().replace(['\r', '\n'], " ");
+            let echo = if step.allow_failure() {
+                shell_quote(&format!(
+                    "WARNING: {description} failed (allowed to fail), continuing"
+                ))
+            } else {
+                format!(
+                    "{} >&2",
+                    shell_quote(&format!("ERROR: {description} failed"))
+                )
+            };
+            script.push_str(&format!(
+                "{indent}if [ \"$tengu_failed\" = 1 ]; then\n\
+                 {indent}    echo {echo}\n\
+                 {failed}\
+                 {indent}fi\n"
+            ));
+        }
+    }
+
+    /// Append a group of parallel-safe steps as background jobs followed by `wait`
+    ///
+    /// apt/dpkg steps share the dpkg lock, so they run serially in a single
+    /// background job; every other step gets a job of its own.
+    fn push_parallel_group(
+        &self,
+        script: &mut String,
+        manifest: &Manifest,
+        start: usize,
+        len: usize,
+    ) {
+        let total_steps = manifest.steps.len();
+        let (apt, other): (Vec<_>, Vec<_>) = (start..start + len)
+            .partition(|&i| APT_STEP_KINDS.contains(&manifest.steps[i].1.kind()));
+
+        script.push_str(&format!(
+            "\n# Parallel group: steps {}-{}\n",
+            start + 1,
+            start + len
+        ));
+        script.push_str("tengu_pids=()\n");
+
+        let mut jobs: Vec<Vec<usize>> = other.into_iter().map(|i| vec![i]).collect();
+        if !apt.is_empty() {
+            jobs.insert(0, apt);
+        }
+
+        for job in jobs {
+            let mut body = String::new();
+            for i in job {
+                self.push_step(&mut body, manifest.steps[i].1.as_ref(), i + 1, total_steps);
+            }
+            script.push_str("(\n");
+            for line in body.trim_start_matches('\n').lines() {
+                if line.is_empty() {
+                    script.push('\n');
+                } else {
+                    script.push_str(&format!("    {line}\n"));
+                }
+            }
+            // A job's TENGU_FAILED dies with its subshell, so it becomes the exit status
+            script.push_str("    [ \"$TENGU_FAILED\" = 0 ]\n");
+            script.push_str(") &\n");
+            script.push_str("tengu_pids+=($!)\n");
+        }
+
+        // Wait on every job, carrying a failed job's status over to the script
+        script.push_str(
+            "for pid in \"${tengu_pids[@]}\"; do wait \"$pid\" || TENGU_FAILED=1; done\n",
+        );
+    }
+
+    /// Append `run` at the given indent, honoring dry-run support
+    ///
+    /// In dry-run mode the step's own `cmds` are echoed instead.
+    fn push_commands(&self, script: &mut String, cmds: &[String], run: &str, indent: &str) {
+        if self.dry_run_support {
+            script.push_str(&format!("{indent}if [ \"$DRY_RUN\" = \"1\" ]; then\n"));
+            for cmd in cmds {
+                let echo = shell_quote(&format!("[dry-run] {cmd}"));
+                script.push_str(&format!("{indent}    echo {echo}\n"));
+            }
+            script.push_str(&format!("{indent}else\n"));
+            for line in run.lines() {
+                script.push_str(&format!("{indent}    {line}\n"));
+            }
+            script.push_str(&format!("{indent}fi\n"));
+        } else {
+            for line in run.lines() {
+                script.push_str(&format!("{indent}{line}\n"));
+            }
+        }
+    }
 }
 
 impl Renderer for BashRenderer {
     type Output = String;
-    type Error = std::convert::Infallible;
 
     #[allow(clippy::too_many_lines)]
-    fn render(&self, manifest: &Manifest) -> Result<String, Self::Error> {
+    fn render(&self, manifest: &Manifest) -> Result<String, ProvisionError> {
         let mut script = String::new();
 
         script.push_str("#!/bin/bash\n");
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        script.push_str(&format!(
+            "# tengu-provision manifest {} generated {}\n",
+            manifest.fingerprint(),
+            format_utc(now)
+        ));
         script.push_str("# Tengu PaaS Installation Script\n");
         script.push_str("# Generated by tengu-provision\n");
         script.push_str("# Idempotent - safe to re-run\n\n");
         script.push_str("set -uo pipefail\n");
-        script.push_str("export DEBIAN_FRONTEND=noninteractive\n\n");
+        if self.noninteractive {
+            script.push_str(NONINTERACTIVE_EXPORT);
+            script.push('\n');
+        }
+        script.push('\n');
+
+        if let Some(log_file) = &self.log_file {
+            let log_file = shell_quote(log_file);
+            script.push_str(&format!(
+                "# Log all output, timestamping progress markers in the file copy\n\
+                 mkdir -p \"$(dirname {log_file})\"\n\
+                 exec > >(tee >(while IFS= read -r line; do \
+                 case \"$line\" in \
+                 TENGU_STEP:*) printf '%s %s\\n' \"$(date -Is)\" \"$line\" ;; \
+                 *) printf '%s\\n' \"$line\" ;; \
+                 esac; \
+                 done >> {log_file})) 2>&1\n\n"
+            ));
+        }
+
+        if self.dry_run_support {
+            script.push_str(
+                "# Dry-run: ./provision.sh --dry-run echoes commands instead of running them\n\
+                 DRY_RUN=0\n\
+                 if [ \"${1:-}\" = \"--dry-run\" ]; then DRY_RUN=1; fi\n\n\
+                 if [ \"$DRY_RUN\" != \"1\" ]; then\n",
+            );
+        }
 
         // Kill unattended-upgrades and wait for apt locks
         // On fresh Ubuntu 24.04 VMs, unattended-upgrades runs immediately and holds
         // dpkg locks, causing every apt operation to fail with exit code 1/100.
         // Killing it and waiting for the lock to release prevents cascading failures.
+        // It's only stopped, not disabled: a disabled service would fail the
+        // EnsureUnattendedUpgrades check and stay off after a filtered run.
         script.push_str(
             "# Kill unattended-upgrades and wait for apt locks\n\
              systemctl stop unattended-upgrades 2>/dev/null || true\n\
-             systemctl disable unattended-upgrades 2>/dev/null || true\n\
              killall -9 unattended-upgrade 2>/dev/null || true\n\
              while fuser /var/lib/dpkg/lock-frontend >/dev/null 2>&1; do\n\
              \x20   echo \"Waiting for dpkg lock...\"\n\
@@ -66,6 +426,39 @@ impl Renderer for BashRenderer {
              dpkg --configure -a 2>/dev/null || true\n\n",
         );
 
+        if self.dry_run_support {
+            script.push_str("fi\n\n");
+        }
+
+        if self.resume_support {
+            script.push_str(&format!(
+                "# Resume: --resume skips the steps a run of this same manifest finished\n\
+                 TENGU_STATE_FILE={STATE_FILE}\n\
+                 TENGU_MANIFEST={fingerprint}\n\
+                 TENGU_RESUME_FROM=0\n\
+                 for arg in \"$@\"; do\n\
+                 \x20   if [ \"$arg\" = \"--resume\" ] && [ -f \"$TENGU_STATE_FILE\" ]; then\n\
+                 \x20       read -r tengu_state_step tengu_state_manifest < \"$TENGU_STATE_FILE\" || true\n\
+                 \x20       if [ \"${{tengu_state_manifest:-}}\" = \"$TENGU_MANIFEST\" ]; then\n\
+                 \x20           TENGU_RESUME_FROM=${{tengu_state_step:-0}}\n\
+                 \x20       fi\n\
+                 \x20   fi\n\
+                 done\n\
+                 tengu_record_step() {{\n\
+                 \x20   [ \"${{DRY_RUN:-0}}\" = \"1\" ] && return 0\n\
+                 \x20   # Stop advancing at the first failure, so --resume retries it\n\
+                 \x20   [ \"$TENGU_FAILED\" = 0 ] || return 0\n\
+                 \x20   mkdir -p \"$(dirname \"$TENGU_STATE_FILE\")\"\n\
+                 \x20   echo \"$1 $TENGU_MANIFEST\" > \"$TENGU_STATE_FILE\"\n\
+                 }}\n\n",
+                fingerprint = manifest.fingerprint()
+            ));
+        }
+
+        // Package steps record what they install with track_pkg
+        script.push_str(TRACK_PKG_FN);
+        script.push('\n');
+
         // Progress tracking functions with machine-parseable markers
         if self.verbose {
             if self.color {
@@ -77,35 +470,60 @@ BLUE='\033[0;34m'
 RED='\033[0;31m'
 NC='\033[0m'
 
-# Track installed packages for clean uninstall
-TENGU_INSTALLED_FILE="/etc/tengu/installed-by-tengu.txt"
-track_pkg() {
-    local pkg="$1"
-    mkdir -p /etc/tengu
-    if ! grep -qxF "$pkg" "$TENGU_INSTALLED_FILE" 2>/dev/null; then
-        echo "$pkg" >> "$TENGU_INSTALLED_FILE"
+# Progress markers for machine parsing
+# Format: TENGU_STEP:ACTION:step_num:description[:elapsed_secs]
+# Intermediate sub-progress: TENGU_STEP:PROGRESS:step_num:message
+step_progress() {
+    echo "TENGU_STEP:PROGRESS:$1:$2"
+}
+
+# Report progress every 30s while a step runs. Polls each second so it
+# exits promptly with the script, and sleep must not hold stdout or the
+# SSH session would outlive the script.
+step_heartbeat() {
+    local step_num="$1"
+    local ticks=0
+    while sleep 1 >/dev/null 2>&1 && kill -0 "$$" 2>/dev/null; do
+        ticks=$((ticks + 1))
+        if [ $((ticks % 30)) -eq 0 ]; then
+            step_progress "$step_num" "running for ${ticks}s"
+        fi
+    done
+}
+
+step_heartbeat_stop() {
+    if [ -n "${TENGU_HEARTBEAT_PID:-}" ]; then
+        kill "$TENGU_HEARTBEAT_PID" 2>/dev/null
+        wait "$TENGU_HEARTBEAT_PID" 2>/dev/null
+        TENGU_HEARTBEAT_PID=
     fi
 }
 
-# Progress markers for machine parsing
-# Format: TENGU_STEP:ACTION:step_num:description
 step_start() {
     local step_num="$1"
     local desc="$2"
+    printf -v "TENGU_STEP_START_${step_num}" '%s' "$SECONDS"
     echo "TENGU_STEP:START:${step_num}:${desc}"
     echo -e "${BLUE}[$step_num]${NC} ${desc}..."
+    step_heartbeat "$step_num" &
+    TENGU_HEARTBEAT_PID=$!
 }
 
 step_done() {
     local step_num="$1"
     local desc="$2"
-    echo "TENGU_STEP:DONE:${step_num}:${desc}"
-    echo -e "${GREEN}[$step_num]${NC} ${desc} ${GREEN}[done]${NC}"
+    local start_var="TENGU_STEP_START_${step_num}"
+    local elapsed=$((SECONDS - ${!start_var:-$SECONDS}))
+    step_heartbeat_stop
+    step_finished "$step_num"
+    echo "TENGU_STEP:DONE:${step_num}:${desc}:${elapsed}"
+    echo -e "${GREEN}[$step_num]${NC} ${desc} ${GREEN}[done ${elapsed}s]${NC}"
 }
 
 step_skip() {
     local step_num="$1"
     local desc="$2"
+    step_finished "$step_num"
     echo "TENGU_STEP:SKIP:${step_num}:${desc}"
     echo -e "${YELLOW}[$step_num]${NC} ${desc} ${YELLOW}[skipped]${NC}"
 }
@@ -113,43 +531,79 @@ step_skip() {
 step_fail() {
     local step_num="$1"
     local desc="$2"
+    step_heartbeat_stop
     echo "TENGU_STEP:FAIL:${step_num}:${desc}"
     echo -e "${RED}[$step_num]${NC} ${desc} ${RED}[FAILED]${NC}"
 }
 
+# A best-effort step failed; counts as finished
+step_warn() {
+    local step_num="$1"
+    local desc="$2"
+    step_heartbeat_stop
+    step_finished "$step_num"
+    echo "TENGU_STEP:WARN:${step_num}:${desc}"
+    echo -e "${YELLOW}[$step_num]${NC} ${desc} ${YELLOW}[failed, allowed]${NC}"
+}
+
 "#,
                 );
             } else {
                 script.push_str(
-                    r#"# Track installed packages for clean uninstall
-TENGU_INSTALLED_FILE="/etc/tengu/installed-by-tengu.txt"
-track_pkg() {
-    local pkg="$1"
-    mkdir -p /etc/tengu
-    if ! grep -qxF "$pkg" "$TENGU_INSTALLED_FILE" 2>/dev/null; then
-        echo "$pkg" >> "$TENGU_INSTALLED_FILE"
+                    r#"# Progress markers for machine parsing (no color)
+# Format: TENGU_STEP:ACTION:step_num:description[:elapsed_secs]
+# Intermediate sub-progress: TENGU_STEP:PROGRESS:step_num:message
+step_progress() {
+    echo "TENGU_STEP:PROGRESS:$1:$2"
+}
+
+# Report progress every 30s while a step runs. Polls each second so it
+# exits promptly with the script, and sleep must not hold stdout or the
+# SSH session would outlive the script.
+step_heartbeat() {
+    local step_num="$1"
+    local ticks=0
+    while sleep 1 >/dev/null 2>&1 && kill -0 "$$" 2>/dev/null; do
+        ticks=$((ticks + 1))
+        if [ $((ticks % 30)) -eq 0 ]; then
+            step_progress "$step_num" "running for ${ticks}s"
+        fi
+    done
+}
+
+step_heartbeat_stop() {
+    if [ -n "${TENGU_HEARTBEAT_PID:-}" ]; then
+        kill "$TENGU_HEARTBEAT_PID" 2>/dev/null
+        wait "$TENGU_HEARTBEAT_PID" 2>/dev/null
+        TENGU_HEARTBEAT_PID=
     fi
 }
 
-# Progress markers for machine parsing (no color)
-# Format: TENGU_STEP:ACTION:step_num:description
 step_start() {
     local step_num="$1"
     local desc="$2"
+    printf -v "TENGU_STEP_START_${step_num}" '%s' "$SECONDS"
     echo "TENGU_STEP:START:${step_num}:${desc}"
     echo "[$step_num] ${desc}..."
+    step_heartbeat "$step_num" &
+    TENGU_HEARTBEAT_PID=$!
 }
 
 step_done() {
     local step_num="$1"
     local desc="$2"
-    echo "TENGU_STEP:DONE:${step_num}:${desc}"
-    echo "[$step_num] ${desc} [done]"
+    local start_var="TENGU_STEP_START_${step_num}"
+    local elapsed=$((SECONDS - ${!start_var:-$SECONDS}))
+    step_heartbeat_stop
+    step_finished "$step_num"
+    echo "TENGU_STEP:DONE:${step_num}:${desc}:${elapsed}"
+    echo "[$step_num] ${desc} [done ${elapsed}s]"
 }
 
 step_skip() {
     local step_num="$1"
     local desc="$2"
+    step_finished "$step_num"
     echo "TENGU_STEP:SKIP:${step_num}:${desc}"
     echo "[$step_num] ${desc} [skipped]"
 }
@@ -157,87 +611,141 @@ step_skip() {
 step_fail() {
     local step_num="$1"
     local desc="$2"
+    step_heartbeat_stop
     echo "TENGU_STEP:FAIL:${step_num}:${desc}"
     echo "[$step_num] ${desc} [FAILED]"
 }
 
+# A best-effort step failed; counts as finished
+step_warn() {
+    local step_num="$1"
+    local desc="$2"
+    step_heartbeat_stop
+    step_finished "$step_num"
+    echo "TENGU_STEP:WARN:${step_num}:${desc}"
+    echo "[$step_num] ${desc} [failed, allowed]"
+}
+
 "#,
                 );
             }
+            script.push_str(
+                r#"# Steps that reported DONE, SKIP or WARN, checked before COMPLETE is emitted
+# (a file rather than a counter, since parallel steps run in subshells)
+TENGU_FINISHED_FILE=$(mktemp)
+# A heartbeat killed right after fork can still run the inherited trap, so
+# only the main shell cleans up
+tengu_cleanup() {
+    [ "$BASHPID" = "$$" ] || return 0
+    step_heartbeat_stop
+    rm -f "$TENGU_FINISHED_FILE"
+}
+trap tengu_cleanup EXIT
+step_finished() {
+    echo "$1" >> "$TENGU_FINISHED_FILE"
+}
+
+"#,
+            );
         }
 
         let total_steps = manifest.steps.len();
 
-        for (i, step) in manifest.steps.iter().enumerate() {
-            let step_num = i + 1;
-            let desc = step.description();
-            // Escape description for use in shell strings
-            let desc_escaped = desc.replace('"', "\\\"");
+        // Set by a failed step: later steps still run, but the script fails
+        script.push_str("# Set when a step fails; the remaining steps still run\nTENGU_FAILED=0\n");
 
-            if self.verbose {
-                script.push_str(&format!("\n# Step {step_num}/{total_steps}: {desc}\n"));
-            }
+        let mut phase = None;
+        let mut i = 0;
+        while i < total_steps {
+            // Run of consecutive parallel-safe steps (only when parallel rendering)
+            let run_len = if self.parallel {
+                manifest.steps[i..]
+                    .iter()
+                    .take_while(|(_, s)| s.parallel_safe())
+                    .count()
+            } else {
+                0
+            };
+            let len = if run_len > 1 { run_len } else { 1 };
 
-            // Wrap in idempotency check if available
-            // Use subshell for check to isolate from set -e + pipefail interaction
-            if let Some(check) = step.check_command() {
-                script.push_str(&format!("if (set +e; {check}); then\n"));
-                if self.verbose {
-                    script.push_str(&format!(
-                        "    step_skip \"{step_num}\" \"{desc_escaped}\"\n"
-                    ));
-                }
-                script.push_str("else\n");
-                if self.verbose {
-                    script.push_str(&format!(
-                        "    step_start \"{step_num}\" \"{desc_escaped}\"\n"
-                    ));
+            for (step_phase, _) in &manifest.steps[i..i + len] {
+                if phase != Some(*step_phase) {
+                    script.push_str(&format!("\n# === Phase: {step_phase} ===\n"));
+                    phase = Some(*step_phase);
                 }
-                for cmd in step.to_bash() {
-                    // Indent commands
-                    for line in cmd.lines() {
-                        script.push_str(&format!("    {line}\n"));
-                    }
-                }
-                if self.verbose {
-                    script.push_str(&format!(
-                        "    step_done \"{step_num}\" \"{desc_escaped}\"\n"
-                    ));
-                }
-                script.push_str("fi\n");
+            }
+
+            if len > 1 {
+                self.push_parallel_group(&mut script, manifest, i, len);
             } else {
-                if self.verbose {
-                    script.push_str(&format!("step_start \"{step_num}\" \"{desc_escaped}\"\n"));
-                }
-                for cmd in step.to_bash() {
-                    script.push_str(&format!("{cmd}\n"));
-                }
-                if self.verbose {
-                    script.push_str(&format!("step_done \"{step_num}\" \"{desc_escaped}\"\n"));
-                }
+                self.push_step(
+                    &mut script,
+                    manifest.steps[i].1.as_ref(),
+                    i + 1,
+                    total_steps,
+                );
+            }
+            i += len;
+            // Parallel jobs finish in any order, so a group is recorded as a whole
+            if self.resume_support {
+                script.push_str(&format!("tengu_record_step {i}\n"));
+            }
+        }
+
+        // Final verification — check the services the manifest started are running
+        // A step that fails is caught by TENGU_FAILED; this catches services
+        // that started and then died
+        script.push_str("\n# Final verification of critical services\n");
+        let services = verified_services(manifest);
+        if !services.is_empty() {
+            if self.dry_run_support {
+                script.push_str("if [ \"$DRY_RUN\" != \"1\" ]; then\n");
+            }
+            script.push_str(&format!(
+                "FAIL=0\n\
+                 for svc in {}; do\n\
+                 \x20   if ! systemctl is-active --quiet $svc 2>/dev/null; then\n\
+                 \x20       echo \"FATAL: $svc is not running\"\n\
+                 \x20       FAIL=1\n\
+                 \x20   fi\n\
+                 done\n\
+                 if [ \"$FAIL\" -ne 0 ]; then\n\
+                 \x20   echo \"Critical service check failed\"\n\
+                 \x20   exit 1\n\
+                 fi\n",
+                services
+                    .iter()
+                    .map(|s| shell_quote(s))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ));
+            if self.dry_run_support {
+                script.push_str("fi\n");
             }
         }
+        script.push('\n');
 
-        // Final verification — check critical services are running
-        // Without set -e, errors are non-fatal, so we verify at the end
         script.push_str(
-            "\n# Final verification of critical services\n\
-             FAIL=0\n\
-             for svc in docker postgresql caddy; do\n\
-             \x20   if ! systemctl is-active --quiet $svc 2>/dev/null; then\n\
-             \x20       echo \"FATAL: $svc is not running\"\n\
-             \x20       FAIL=1\n\
-             \x20   fi\n\
-             done\n\
-             if [ \"$FAIL\" -ne 0 ]; then\n\
-             \x20   echo \"Critical service check failed\"\n\
+            "if [ \"$TENGU_FAILED\" -ne 0 ]; then\n\
+             \x20   echo \"ERROR: one or more steps failed\" >&2\n\
              \x20   exit 1\n\
-             fi\n\n",
+             fi\n",
         );
 
+        if self.resume_support {
+            script.push_str("[ \"${DRY_RUN:-0}\" = \"1\" ] || rm -f \"$TENGU_STATE_FILE\"\n");
+        }
+
         if self.verbose {
+            // COMPLETE only once every step has reported (failures exited above);
+            // a missing step means the script lost one (e.g. a parallel job died)
             script.push_str(&format!(
-                "echo \"TENGU_STEP:COMPLETE:{total_steps}:all steps\"\n"
+                "TENGU_FINISHED=$(sort -u \"$TENGU_FINISHED_FILE\" | wc -l)\n\
+                 if [ \"$TENGU_FINISHED\" -ne {total_steps} ]; then\n\
+                 \x20   echo \"ERROR: only $TENGU_FINISHED of {total_steps} steps finished\" >&2\n\
+                 \x20   exit 1\n\
+                 fi\n\
+                 echo \"TENGU_STEP:COMPLETE:{total_steps}:all steps\"\n"
             ));
             if self.color {
                 script.push_str("echo -e \"${GREEN}Tengu PaaS installation complete!${NC}\"\n");
@@ -248,6 +756,10 @@ step_fail() {
             script.push_str("echo 'Tengu PaaS installation complete!'\n");
         }
 
+        if self.redact {
+            script = redact_secrets(&script, &manifest.secrets);
+        }
+
         Ok(script)
     }
 }
@@ -0,0 +1,187 @@
+//! Cloud-init YAML renderer
+
+use serde::Serialize;
+
+use crate::config::TenguConfig;
+use crate::steps::{CloudInitFile, CloudInitUser};
+use crate::{Manifest, ProvisionError};
+
+use super::{NONINTERACTIVE_EXPORT, Renderer, redact_secrets};
+
+/// Renders a manifest as a `#cloud-config` YAML document
+#[derive(Debug, Clone)]
+pub struct CloudInitRenderer {
+    /// Primary user declared natively in the `users:` section
+    pub primary_user: Option<CloudInitUser>,
+    /// Mask secret values in the output
+    pub redact: bool,
+    /// Refresh apt package lists on first boot (default true)
+    pub package_update: bool,
+    /// Upgrade installed packages on first boot (default true)
+    pub package_upgrade: bool,
+    /// Line cloud-init logs when the first boot has finished
+    pub final_message: String,
+    /// Start `runcmd` by exporting `DEBIAN_FRONTEND=noninteractive` and `NEEDRESTART_MODE=a`
+    pub noninteractive: bool,
+}
+
+/// Default [`CloudInitRenderer::final_message`]
+const DEFAULT_FINAL_MESSAGE: &str = "Tengu PaaS server ready!";
+
+impl Default for CloudInitRenderer {
+    fn default() -> Self {
+        Self {
+            primary_user: None,
+            redact: false,
+            package_update: true,
+            package_upgrade: true,
+            final_message: DEFAULT_FINAL_MESSAGE.into(),
+            noninteractive: true,
+        }
+    }
+}
+
+/// Top-level cloud-config document
+#[derive(Debug, Serialize)]
+struct CloudConfig {
+    hostname: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fqdn: Option<String>,
+    timezone: String,
+    locale: String,
+    package_update: bool,
+    package_upgrade: bool,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    users: Vec<CloudInitUser>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    bootcmd: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    packages: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    write_files: Vec<CloudInitFile>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    runcmd: Vec<String>,
+    final_message: String,
+}
+
+impl CloudInitRenderer {
+    /// Create a new cloud-init renderer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace [`Manifest::secrets`] with a placeholder (for sharing output)
+    pub fn redact(mut self, redact: bool) -> Self {
+        self.redact = redact;
+        self
+    }
+
+    /// Set whether apt package lists are refreshed on first boot
+    pub fn package_update(mut self, enabled: bool) -> Self {
+        self.package_update = enabled;
+        self
+    }
+
+    /// Set whether installed packages are upgraded on first boot
+    ///
+    /// Upgrading can add many minutes to first boot on large images.
+    pub fn package_upgrade(mut self, enabled: bool) -> Self {
+        self.package_upgrade = enabled;
+        self
+    }
+
+    /// Set the completion line cloud-init logs (supports `$UPTIME`, `$TIMESTAMP`)
+    pub fn final_message(mut self, message: impl Into<String>) -> Self {
+        self.final_message = message.into();
+        self
+    }
+
+    /// Keep apt, dpkg and needrestart from prompting in `runcmd` (default true)
+    ///
+    /// cloud-init runs all `runcmd` entries as one script, so a single
+    /// export up front covers every package operation.
+    pub fn noninteractive(mut self, noninteractive: bool) -> Self {
+        self.noninteractive = noninteractive;
+        self
+    }
+
+    /// Declare the configured admin user natively in the `users:` section
+    ///
+    /// Any `EnsureUser` step for the same user is then trusted to this entry
+    /// and contributes nothing else to the output.
+    ///
+    /// Additional `users` come from their own `EnsureUser` steps.
+    pub fn with_config(mut self, config: &TenguConfig) -> Self {
+        self.primary_user = config
+            .user_specs()
+            .into_iter()
+            .next()
+            .map(|spec| CloudInitUser {
+                name: spec.name,
+                groups: spec.groups,
+                shell: "/bin/bash".into(),
+                sudo: spec.sudo,
+                ssh_authorized_keys: spec.ssh_keys,
+                system: false,
+            });
+        self
+    }
+}
+
+impl Renderer for CloudInitRenderer {
+    type Output = String;
+
+    fn render(&self, manifest: &Manifest) -> Result<String, ProvisionError> {
+        let primary = self.primary_user.as_ref().map(|u| u.name.as_str());
+
+        let mut doc = CloudConfig {
+            hostname: manifest.hostname.clone(),
+            fqdn: manifest.fqdn.clone(),
+            timezone: manifest.timezone.clone(),
+            locale: manifest.locale.clone(),
+            package_update: self.package_update,
+            package_upgrade: self.package_upgrade,
+            users: self.primary_user.iter().cloned().collect(),
+            bootcmd: vec![],
+            packages: vec![],
+            write_files: vec![],
+            runcmd: vec![],
+            final_message: self.final_message.clone(),
+        };
+
+        for (_, step) in &manifest.steps {
+            let fragment = step.to_cloud_init();
+
+            // The primary user is already declared above with groups, sudo, shell and keys
+            doc.users.extend(
+                fragment
+                    .users
+                    .into_iter()
+                    .filter(|u| Some(u.name.as_str()) != primary),
+            );
+            doc.bootcmd.extend(fragment.bootcmd);
+            doc.packages.extend(fragment.packages);
+            doc.write_files.extend(fragment.write_files);
+
+            // runcmd has no idempotency of its own, so guard it with the same
+            // check the bash renderer uses to skip the step
+            match step.check_command() {
+                Some(check) if !fragment.runcmd.is_empty() => doc.runcmd.push(format!(
+                    "if ! ({check}); then\n{}\nfi",
+                    fragment.runcmd.join("\n")
+                )),
+                _ => doc.runcmd.extend(fragment.runcmd),
+            }
+        }
+
+        if self.noninteractive && !doc.runcmd.is_empty() {
+            doc.runcmd.insert(0, NONINTERACTIVE_EXPORT.into());
+        }
+
+        let mut yaml = serde_yaml::to_string(&doc)?;
+        if self.redact {
+            yaml = redact_secrets(&yaml, &manifest.secrets);
+        }
+        Ok(format!("#cloud-config\n{yaml}"))
+    }
+}
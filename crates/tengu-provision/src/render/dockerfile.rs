@@ -0,0 +1,161 @@
+//! Dockerfile renderer for baking images instead of provisioning live hosts
+
+use crate::ProvisionError;
+use crate::manifest::{Manifest, Phase};
+use crate::steps::Step;
+
+use super::{Renderer, redact_secrets};
+
+/// Step kinds that configure the running host rather than the filesystem
+const RUNTIME_ONLY_KINDS: &[&str] = &[
+    "EnsureService",
+    "EnsureFirewall",
+    "EnsureMount",
+    "EnsureSwap",
+    "EnsureUnattendedUpgrades",
+    "SetHostname",
+    "SetTimezone",
+    "WaitForAptLock",
+];
+
+/// Phases that need a booted system (systemd, loop mounts, netfilter)
+const RUNTIME_ONLY_PHASES: &[Phase] = &[
+    Phase::Storage,
+    Phase::Firewall,
+    Phase::Services,
+    Phase::HealthCheck,
+];
+
+/// Renders a manifest as a Dockerfile
+///
+/// Each step becomes one `RUN` layer; consecutive `InstallPackage` steps are
+/// collapsed into a single apt layer. Steps that only make sense on a booted
+/// host (services, firewall, hostname) are kept as comments. Multi-line
+/// commands use `RUN` heredocs, so the output opens with a
+/// `# syntax=docker/dockerfile:1` line for builders that predate them.
+#[derive(Debug, Clone)]
+pub struct DockerfileRenderer {
+    /// Base image
+    pub base_image: String,
+    /// Mask secret values in the output
+    pub redact: bool,
+}
+
+impl Default for DockerfileRenderer {
+    fn default() -> Self {
+        Self {
+            base_image: "ubuntu:24.04".into(),
+            redact: false,
+        }
+    }
+}
+
+impl DockerfileRenderer {
+    /// Create a new Dockerfile renderer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the base image
+    pub fn base_image(mut self, image: impl Into<String>) -> Self {
+        self.base_image = image.into();
+        self
+    }
+
+    /// Replace [`Manifest::secrets`] with a placeholder (for sharing output)
+    pub fn redact(mut self, redact: bool) -> Self {
+        self.redact = redact;
+        self
+    }
+
+    /// Whether a step can run during `docker build`
+    fn build_time(phase: Phase, step: &dyn Step) -> bool {
+        !RUNTIME_ONLY_KINDS.contains(&step.kind())
+            && !RUNTIME_ONLY_PHASES.contains(&phase)
+            && !step.to_bash().iter().any(|cmd| cmd.contains("systemctl"))
+    }
+
+    /// Append one `RUN` instruction, using a heredoc for multi-line commands
+    fn push_run(out: &mut String, cmds: &[String]) {
+        let joined = cmds.join(" && ");
+        if joined.contains('\n') {
+            out.push_str(&format!("RUN <<'TENGU_RUN'\n{joined}\nTENGU_RUN\n"));
+        } else {
+            out.push_str(&format!("RUN {joined}\n"));
+        }
+    }
+
+    /// Append a single apt layer for a run of `InstallPackage` steps
+    ///
+    /// Repository setup is interleaved in order, so packages needed to fetch
+    /// a repository key are installed before it.
+    fn push_apt_layer(out: &mut String, steps: &[&dyn Step]) {
+        let mut cmds = vec!["apt-get update".to_string()];
+        let mut pending: Vec<String> = vec![];
+
+        for step in steps {
+            let fragment = step.to_cloud_init();
+            if !fragment.bootcmd.is_empty() || !fragment.runcmd.is_empty() {
+                if !pending.is_empty() {
+                    cmds.push(format!("apt-get install -y {}", pending.join(" ")));
+                    pending.clear();
+                }
+                cmds.extend(fragment.bootcmd);
+                cmds.extend(fragment.runcmd);
+                cmds.push("apt-get update".into());
+            }
+            pending.extend(fragment.packages);
+        }
+        if !pending.is_empty() {
+            cmds.push(format!("apt-get install -y {}", pending.join(" ")));
+        }
+
+        out.push_str(&format!("\n# Install packages ({} steps)\n", steps.len()));
+        Self::push_run(out, &cmds);
+    }
+}
+
+impl Renderer for DockerfileRenderer {
+    type Output = String;
+
+    fn render(&self, manifest: &Manifest) -> Result<String, ProvisionError> {
+        let mut out = format!("# syntax=docker/dockerfile:1\nFROM {}\n\n", self.base_image);
+        out.push_str("ENV DEBIAN_FRONTEND=noninteractive\n");
+        out.push_str("SHELL [\"/bin/bash\", \"-o\", \"pipefail\", \"-c\"]\n");
+        // Package tracking only matters for uninstalling from live servers
+        out.push_str("RUN ln -s /bin/true /usr/local/bin/track_pkg\n");
+
+        let mut i = 0;
+        while i < manifest.steps.len() {
+            let (phase, step) = &manifest.steps[i];
+
+            if step.kind() == "InstallPackage" {
+                let apt: Vec<&dyn Step> = manifest.steps[i..]
+                    .iter()
+                    .take_while(|(_, s)| s.kind() == "InstallPackage")
+                    .map(|(_, s)| s.as_ref())
+                    .collect();
+                i += apt.len();
+                Self::push_apt_layer(&mut out, &apt);
+                continue;
+            }
+
+            if Self::build_time(*phase, step.as_ref()) {
+                out.push_str(&format!("\n# {}\n", step.description()));
+                Self::push_run(&mut out, &step.to_bash());
+            } else {
+                out.push_str(&format!(
+                    "\n# Skipped at build time (runtime only): {}\n",
+                    step.description()
+                ));
+            }
+            i += 1;
+        }
+
+        out.push_str("\nRUN rm /usr/local/bin/track_pkg\n");
+        if self.redact {
+            out = redact_secrets(&out, &manifest.secrets);
+        }
+        Ok(out)
+    }
+}
@@ -0,0 +1,87 @@
+//! JSON renderer for tooling and diffing
+
+use serde::Serialize;
+
+use crate::ProvisionError;
+use crate::manifest::{Manifest, Phase};
+use crate::steps::CloudInitFragment;
+
+use super::{Renderer, redact_secrets};
+
+/// Renders a manifest as a stable, pretty-printed JSON document
+///
+/// Field order is fixed and steps keep manifest order, so two renders of the
+/// same manifest produce byte-identical output.
+#[derive(Debug, Clone, Default)]
+pub struct JsonRenderer {
+    /// Mask secret values in the output
+    pub redact: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonManifest<'a> {
+    hostname: &'a str,
+    fqdn: Option<&'a str>,
+    timezone: &'a str,
+    locale: &'a str,
+    steps: Vec<JsonStep<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonStep<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    phase: Phase,
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    tags: &'a [String],
+    description: &'a str,
+    check: Option<String>,
+    bash: Vec<String>,
+    cloud_init: CloudInitFragment,
+}
+
+impl JsonRenderer {
+    /// Create a new JSON renderer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace [`Manifest::secrets`] with a placeholder (for sharing output)
+    pub fn redact(mut self, redact: bool) -> Self {
+        self.redact = redact;
+        self
+    }
+}
+
+impl Renderer for JsonRenderer {
+    type Output = String;
+
+    fn render(&self, manifest: &Manifest) -> Result<String, ProvisionError> {
+        let doc = JsonManifest {
+            hostname: &manifest.hostname,
+            fqdn: manifest.fqdn.as_deref(),
+            timezone: &manifest.timezone,
+            locale: &manifest.locale,
+            steps: manifest
+                .steps
+                .iter()
+                .enumerate()
+                .map(|(i, (phase, step))| JsonStep {
+                    kind: step.kind(),
+                    phase: *phase,
+                    tags: manifest.tags(i),
+                    description: step.description(),
+                    check: step.check_command(),
+                    bash: step.to_bash(),
+                    cloud_init: step.to_cloud_init(),
+                })
+                .collect(),
+        };
+
+        let json = serde_json::to_string_pretty(&doc)?;
+        if self.redact {
+            return Ok(redact_secrets(&json, &manifest.secrets));
+        }
+        Ok(json)
+    }
+}
@@ -1,18 +1,78 @@
 //! Output renderers for installation manifests
 
 mod bash;
+mod cloud_init;
+mod dockerfile;
+mod json;
+mod plan;
+mod systemd;
 
-pub use bash::BashRenderer;
+#[cfg(test)]
+pub(crate) use bash::format_utc;
+pub use bash::{BashRenderer, STATE_FILE, decode_marker_description, encode_marker_description};
+pub use cloud_init::CloudInitRenderer;
+pub use dockerfile::DockerfileRenderer;
+pub use json::JsonRenderer;
+pub use plan::PlanRenderer;
+pub use systemd::{FirstBootFiles, SystemdFirstBootRenderer};
 
-use crate::Manifest;
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+use crate::{Manifest, ProvisionError};
+
+/// Replacement for secret values in redacted output
+pub(crate) const REDACTED: &str = "***REDACTED***";
+
+/// Keeps apt, dpkg and needrestart from waiting on a prompt
+pub(crate) const NONINTERACTIVE_EXPORT: &str =
+    "export DEBIAN_FRONTEND=noninteractive NEEDRESTART_MODE=a";
 
 /// A renderer that can convert a manifest to some output format
 pub trait Renderer {
     /// Output type
     type Output;
-    /// Error type
-    type Error;
 
     /// Render the manifest to the output format
-    fn render(&self, manifest: &Manifest) -> Result<Self::Output, Self::Error>;
+    fn render(&self, manifest: &Manifest) -> Result<Self::Output, ProvisionError>;
+}
+
+/// Replace every secret in rendered output with [`REDACTED`]
+///
+/// File contents written by bash are base64-encoded, so `echo '...' | base64 -d`
+/// payloads are decoded, redacted and re-encoded before the plain-text pass.
+pub(crate) fn redact_secrets(text: &str, secrets: &[String]) -> String {
+    const PAYLOAD_START: &str = "echo '";
+    const PAYLOAD_END: &str = "' | base64 -d";
+
+    let redact = |s: &str| {
+        secrets
+            .iter()
+            .filter(|secret| !secret.is_empty())
+            .fold(s.to_string(), |acc, secret| {
+                acc.replace(secret.as_str(), REDACTED)
+            })
+    };
+
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(PAYLOAD_START) {
+        let (head, tail) = rest.split_at(start + PAYLOAD_START.len());
+        out.push_str(head);
+        rest = tail;
+
+        let Some(end) = rest.find(PAYLOAD_END) else {
+            continue;
+        };
+        let decoded = STANDARD
+            .decode(&rest[..end])
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok());
+        if let Some(decoded) = decoded {
+            out.push_str(&STANDARD.encode(redact(&decoded)));
+            rest = &rest[end..];
+        }
+    }
+    out.push_str(rest);
+
+    redact(&out)
 }
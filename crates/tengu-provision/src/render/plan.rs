@@ -0,0 +1,133 @@
+//! Plan renderer: a human-readable preview of what a manifest will do
+
+use crate::ProvisionError;
+use crate::manifest::{Manifest, Phase};
+
+use super::{Renderer, redact_secrets};
+
+const BOLD_CYAN: &str = "\x1b[1;36m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Renders a manifest as a report of each step's check and apply commands
+///
+/// Steps are grouped by phase and numbered like the bash script's progress
+/// markers. Steps without a check command run on every provision and are
+/// marked as such. The output is for reading, not for running.
+#[derive(Debug, Clone, Default)]
+pub struct PlanRenderer {
+    /// Use color output (ANSI escape codes)
+    pub color: bool,
+    /// Mask secret values in the output
+    pub redact: bool,
+}
+
+impl PlanRenderer {
+    /// Create a new plan renderer (no color)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Enable/disable color output
+    pub fn color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// Replace [`Manifest::secrets`] with a placeholder (for sharing output)
+    pub fn redact(mut self, redact: bool) -> Self {
+        self.redact = redact;
+        self
+    }
+
+    /// Wrap `text` in an ANSI style when color is on
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{style}{text}{RESET}")
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+impl Renderer for PlanRenderer {
+    type Output = String;
+
+    fn render(&self, manifest: &Manifest) -> Result<String, ProvisionError> {
+        let total = manifest.steps.len();
+        let unchecked = manifest
+            .steps
+            .iter()
+            .filter(|(_, step)| step.check_command().is_none())
+            .count();
+
+        let mut plan = self.paint(
+            BOLD,
+            &format!(
+                "Plan for {} ({total} steps, {unchecked} always run)",
+                manifest.hostname
+            ),
+        );
+        plan.push('\n');
+
+        let mut phase: Option<Phase> = None;
+        for (i, (step_phase, step)) in manifest.steps.iter().enumerate() {
+            if phase != Some(*step_phase) {
+                phase = Some(*step_phase);
+                let count = manifest.steps[i..]
+                    .iter()
+                    .take_while(|(p, _)| p == step_phase)
+                    .count();
+                let steps = if count == 1 { "step" } else { "steps" };
+                plan.push('\n');
+                plan.push_str(
+                    &self.paint(BOLD_CYAN, &format!("== {step_phase} ({count} {steps}) ==")),
+                );
+                plan.push('\n');
+            }
+
+            let description = step.description().replace(['\r', '\n'], " ");
+            plan.push_str(&format!(
+                "\n  {} {description}",
+                self.paint(DIM, &format!("[{}/{total}]", i + 1))
+            ));
+            let tags = manifest.tags(i);
+            if !tags.is_empty() {
+                plan.push_str(&self.paint(DIM, &format!(" [{}]", tags.join(", "))));
+            }
+            plan.push('\n');
+
+            match step.check_command() {
+                Some(check) => {
+                    plan.push_str(&format!("      {} {check}\n", self.paint(GREEN, "check:")));
+                }
+                None => {
+                    plan.push_str(&format!(
+                        "      {}\n",
+                        self.paint(YELLOW, "no check: always runs")
+                    ));
+                }
+            }
+            if step.allow_failure() {
+                plan.push_str(&format!(
+                    "      {}\n",
+                    self.paint(YELLOW, "allowed to fail")
+                ));
+            }
+            plan.push_str(&format!("      {}\n", self.paint(GREEN, "apply:")));
+            for command in step.to_bash() {
+                for line in command.lines() {
+                    plan.push_str(&format!("        {line}\n"));
+                }
+            }
+        }
+
+        if self.redact {
+            return Ok(redact_secrets(&plan, &manifest.secrets));
+        }
+        Ok(plan)
+    }
+}
@@ -0,0 +1,110 @@
+//! systemd first-boot renderer for images without cloud-init
+
+use crate::{Manifest, ProvisionError};
+
+use super::{BashRenderer, Renderer};
+
+/// Files that provision a host on its first boot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirstBootFiles {
+    /// The `.service` unit, to install as [`SystemdFirstBootRenderer::unit_path`]
+    pub unit: String,
+    /// The provisioning script, to install as [`SystemdFirstBootRenderer::script_path`]
+    pub script: String,
+}
+
+/// Renders a manifest as a oneshot systemd service plus its bash script
+///
+/// The unit runs once the network is online and writes a stamp file on
+/// success, so later boots skip it; a failed run is retried on the next boot.
+/// Script output goes to the journal (`journalctl -u <unit>`).
+#[derive(Debug, Clone)]
+pub struct SystemdFirstBootRenderer {
+    /// Renderer for the script body
+    pub bash: BashRenderer,
+    /// Unit name without the `.service` suffix
+    pub unit_name: String,
+    /// Where the script is installed on the image
+    pub script_path: String,
+    /// Created after a successful run; the unit doesn't start while it exists
+    pub stamp_path: String,
+}
+
+impl Default for SystemdFirstBootRenderer {
+    fn default() -> Self {
+        Self {
+            bash: BashRenderer::new().verbose(true).color(false),
+            unit_name: "tengu-firstboot".into(),
+            script_path: "/usr/local/sbin/tengu-firstboot.sh".into(),
+            stamp_path: "/var/lib/tengu/firstboot.done".into(),
+        }
+    }
+}
+
+impl SystemdFirstBootRenderer {
+    /// Create a new first-boot renderer
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the renderer for the script body (default: verbose, no color)
+    pub fn bash(mut self, bash: BashRenderer) -> Self {
+        self.bash = bash;
+        self
+    }
+
+    /// Set the unit name (without `.service`)
+    pub fn unit_name(mut self, name: impl Into<String>) -> Self {
+        self.unit_name = name.into();
+        self
+    }
+
+    /// Set where the script is installed
+    pub fn script_path(mut self, path: impl Into<String>) -> Self {
+        self.script_path = path.into();
+        self
+    }
+
+    /// Where the unit is installed (`/etc/systemd/system/<name>.service`)
+    pub fn unit_path(&self) -> String {
+        format!("/etc/systemd/system/{}.service", self.unit_name)
+    }
+
+    /// The `.service` unit running [`Self::script_path`]
+    fn unit(&self, manifest: &Manifest) -> String {
+        let stamp = &self.stamp_path;
+        let stamp_dir = stamp.rsplit_once('/').map_or("/", |(dir, _)| dir);
+        format!(
+            "[Unit]\n\
+             Description=Tengu first-boot provisioning ({hostname})\n\
+             Wants=network-online.target\n\
+             After=network-online.target\n\
+             ConditionPathExists=!{stamp}\n\
+             \n\
+             [Service]\n\
+             Type=oneshot\n\
+             RemainAfterExit=yes\n\
+             ExecStart=/bin/bash {script}\n\
+             ExecStartPost=/bin/sh -c 'mkdir -p {stamp_dir} && touch {stamp}'\n\
+             StandardOutput=journal\n\
+             StandardError=journal\n\
+             TimeoutStartSec=0\n\
+             \n\
+             [Install]\n\
+             WantedBy=multi-user.target\n",
+            hostname = manifest.hostname,
+            script = self.script_path,
+        )
+    }
+}
+
+impl Renderer for SystemdFirstBootRenderer {
+    type Output = FirstBootFiles;
+
+    fn render(&self, manifest: &Manifest) -> Result<FirstBootFiles, ProvisionError> {
+        Ok(FirstBootFiles {
+            unit: self.unit(manifest),
+            script: self.bash.render(manifest)?,
+        })
+    }
+}
@@ -0,0 +1,128 @@
+//! Managed block steps
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{CloudInitFragment, KnownStep, Step, shell_quote};
+
+/// Keep a marker-delimited block in a shared file (e.g. `.bashrc`, a crontab)
+///
+/// The begin marker line carries a hash of the block, so a changed block is
+/// detected and the old one (everything between the markers) is replaced in
+/// place of being appended a second time. Lines outside the markers are
+/// never touched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnsureBlockInFile {
+    /// File to edit (created if missing)
+    pub path: String,
+    /// Block content, without markers
+    pub block: String,
+    /// Line opening the block
+    pub begin_marker: String,
+    /// Line closing the block
+    pub end_marker: String,
+    /// Description
+    description: String,
+}
+
+impl EnsureBlockInFile {
+    /// Create a new managed block step (markers `# BEGIN tengu` / `# END tengu`)
+    pub fn new(path: impl Into<String>, block: impl Into<String>) -> Self {
+        let path = path.into();
+        let description = format!("Update managed block in {path}");
+        Self {
+            path,
+            block: block.into(),
+            begin_marker: "# BEGIN tengu".into(),
+            end_marker: "# END tengu".into(),
+            description,
+        }
+    }
+
+    /// Set the lines delimiting the block
+    pub fn markers(mut self, begin: impl Into<String>, end: impl Into<String>) -> Self {
+        self.begin_marker = begin.into();
+        self.end_marker = end.into();
+        self
+    }
+
+    /// Begin marker line including the block hash
+    fn begin_line(&self) -> String {
+        let hash = hex::encode(Sha256::digest(self.block.as_bytes()));
+        format!("{} {hash}", self.begin_marker)
+    }
+
+    /// Markers, hash and block as appended to the file
+    fn managed_text(&self) -> String {
+        let mut text = format!("{}\n{}", self.begin_line(), self.block);
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str(&self.end_marker);
+        text.push('\n');
+        text
+    }
+}
+
+/// Escape ERE metacharacters and the `/` sed delimiter
+fn sed_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if "\\^$.|?*+()[]{}/".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+impl Step for EnsureBlockInFile {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "EnsureBlockInFile"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::EnsureBlockInFile(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        CloudInitFragment {
+            runcmd: self.to_bash(),
+            ..Default::default()
+        }
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+        let path = shell_quote(&self.path);
+        // The begin line matches with any (or no) hash after the marker
+        let delete = format!(
+            "/^{}( .*)?$/,/^{}$/d",
+            sed_escape(&self.begin_marker),
+            sed_escape(&self.end_marker)
+        );
+        vec![
+            format!("mkdir -p \"$(dirname {path})\" && touch {path}"),
+            format!("sed -i -E {} {path}", shell_quote(&delete)),
+            // Don't glue the block onto an unterminated last line
+            format!("[ -z \"$(tail -c1 {path})\" ] || echo >> {path}"),
+            format!(
+                "echo '{}' | base64 -d >> {path}",
+                STANDARD.encode(self.managed_text())
+            ),
+        ]
+    }
+
+    fn check_command(&self) -> Option<String> {
+        Some(format!(
+            "grep -qxF -- {} {}",
+            shell_quote(&self.begin_line()),
+            shell_quote(&self.path)
+        ))
+    }
+}
@@ -0,0 +1,123 @@
+//! Caddy site steps
+
+use serde::{Deserialize, Serialize};
+
+use super::{CloudInitFragment, KnownStep, Step, WriteFile};
+
+/// Directory the Caddyfile imports `*.caddy` site files from
+pub const CADDY_SITES_DIR: &str = "/etc/caddy/sites";
+
+/// Reload Caddy if it is running; it reads the file on start otherwise
+const RELOAD_CADDY: &str = "! systemctl is-active --quiet caddy || systemctl reload caddy";
+
+/// Write a `/etc/caddy/sites/{subdomain}.caddy` reverse proxy site and reload Caddy
+///
+/// The step only runs when the file differs, so Caddy is reloaded only on
+/// a change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaddySite {
+    /// Subdomain, also the file name
+    pub subdomain: String,
+    /// Parent domain; the site address is just `subdomain` when unset
+    pub domain: Option<String>,
+    /// Port on localhost to proxy to
+    pub upstream_port: u16,
+    /// Extra Caddyfile lines inside the site block
+    pub directives: Vec<String>,
+    /// Description
+    description: String,
+}
+
+impl CaddySite {
+    /// Create a site proxying `subdomain` to `localhost:upstream_port`
+    pub fn new(subdomain: impl Into<String>, upstream_port: u16) -> Self {
+        let subdomain = subdomain.into();
+        let description = format!("Write Caddy site {subdomain}");
+        Self {
+            subdomain,
+            domain: None,
+            upstream_port,
+            directives: vec![],
+            description,
+        }
+    }
+
+    /// Serve the site at `{subdomain}.{domain}`
+    pub fn with_domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    /// Add Caddyfile lines to the site block (e.g. `encode gzip`)
+    pub fn with_directives(
+        mut self,
+        directives: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.directives = directives.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Site address (e.g. `blog.tengu.host`)
+    pub fn address(&self) -> String {
+        match &self.domain {
+            Some(domain) => format!("{}.{domain}", self.subdomain),
+            None => self.subdomain.clone(),
+        }
+    }
+
+    /// Path of the site file
+    pub fn path(&self) -> String {
+        format!("{CADDY_SITES_DIR}/{}.caddy", self.subdomain)
+    }
+
+    /// Caddyfile site block
+    pub fn content(&self) -> String {
+        let mut content = format!(
+            "{} {{\n    reverse_proxy localhost:{}\n",
+            self.address(),
+            self.upstream_port
+        );
+        for directive in &self.directives {
+            content.push_str(&format!("    {directive}\n"));
+        }
+        content.push_str("}\n");
+        content
+    }
+
+    /// The site file write
+    fn file(&self) -> WriteFile {
+        WriteFile::new(self.path(), self.content())
+            .with_permissions("0644")
+            .with_owner("root:root")
+            .deferred(true)
+    }
+}
+
+impl Step for CaddySite {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "CaddySite"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::CaddySite(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        // Caddy reads sites/*.caddy when runcmd starts it
+        self.file().to_cloud_init()
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        let mut cmds = self.file().to_bash();
+        cmds.push(RELOAD_CADDY.into());
+        cmds
+    }
+
+    fn check_command(&self) -> Option<String> {
+        self.file().check_command()
+    }
+}
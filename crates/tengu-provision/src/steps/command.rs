@@ -1,9 +1,11 @@
 //! Generic command execution steps
 
-use super::{CloudInitFragment, Step};
+use serde::{Deserialize, Serialize};
+
+use super::{CloudInitFragment, Dependency, KnownStep, Step};
 
 /// Run a command with optional idempotency guard
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RunCommand {
     /// Human-readable description
     pub description: String,
@@ -11,6 +13,24 @@ pub struct RunCommand {
     pub command: String,
     /// If this command succeeds (exit 0), skip running `command`
     pub unless: Option<String>,
+    /// Maximum number of attempts (retried with a 2s pause between attempts)
+    pub retries: Option<u32>,
+    /// Kill the command if it runs longer than this many seconds
+    pub timeout_secs: Option<u32>,
+    /// Run the command (and guard) as this user via sudo
+    pub user: Option<String>,
+    /// Environment variables set for the command (and guard)
+    pub env: Vec<(String, String)>,
+    /// May run concurrently with neighbouring parallel-safe steps
+    pub parallel_safe: bool,
+    /// A failure is reported but doesn't fail provisioning
+    pub allow_failure: bool,
+    /// Packages the command installs (for ordering validation)
+    pub packages: Vec<String>,
+    /// Services the command starts (for ordering validation)
+    pub services: Vec<String>,
+    /// Run from cloud-init `bootcmd` (early, every boot) instead of `runcmd`
+    pub boot: bool,
 }
 
 impl RunCommand {
@@ -20,6 +40,15 @@ impl RunCommand {
             description: description.into(),
             command: command.into(),
             unless: None,
+            retries: None,
+            timeout_secs: None,
+            user: None,
+            env: vec![],
+            parallel_safe: false,
+            allow_failure: false,
+            packages: vec![],
+            services: vec![],
+            boot: false,
         }
     }
 
@@ -28,6 +57,134 @@ impl RunCommand {
         self.unless = Some(check.into());
         self
     }
+
+    /// Retry the command up to `attempts` times until it succeeds
+    pub fn retries(mut self, attempts: u32) -> Self {
+        self.retries = Some(attempts);
+        self
+    }
+
+    /// Abort the command (per attempt) after `seconds`
+    pub fn timeout_secs(mut self, seconds: u32) -> Self {
+        self.timeout_secs = Some(seconds);
+        self
+    }
+
+    /// Run the command and its guard as a different user
+    pub fn as_user(mut self, user: impl Into<String>) -> Self {
+        self.user = Some(user.into());
+        self
+    }
+
+    /// Set an environment variable for the command and its guard
+    ///
+    /// # Panics
+    ///
+    /// If `key` isn't a shell variable name (`[A-Za-z_][A-Za-z0-9_]*`).
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        let key = key.into();
+        assert!(
+            is_env_name(&key),
+            "invalid environment variable name {key:?}"
+        );
+        self.env.push((key, value.into()));
+        self
+    }
+
+    /// Mark the command as independent of its neighbours
+    pub fn parallel_safe(mut self, parallel_safe: bool) -> Self {
+        self.parallel_safe = parallel_safe;
+        self
+    }
+
+    /// Tolerate failure of a best-effort command (see [`Step::allow_failure`])
+    pub fn allow_failure(mut self, allow_failure: bool) -> Self {
+        self.allow_failure = allow_failure;
+        self
+    }
+
+    /// Declare a package this command installs, for later steps that need it
+    pub fn installs_package(mut self, package: impl Into<String>) -> Self {
+        self.packages.push(package.into());
+        self
+    }
+
+    /// Declare a service this command starts, for later steps that need it
+    pub fn starts_service(mut self, service: impl Into<String>) -> Self {
+        self.services.push(service.into());
+        self
+    }
+
+    /// Run from cloud-init `bootcmd` instead of `runcmd`
+    ///
+    /// bootcmd runs before packages are installed and again on every boot,
+    /// so the command should be cheap or guarded with [`Self::unless`].
+    pub fn at_boot(mut self) -> Self {
+        self.boot = true;
+        self
+    }
+
+    /// Wrap a command with `sudo -u` / `env` when a user or env vars are set
+    fn run_as(&self, cmd: &str) -> String {
+        if self.user.is_none() && self.env.is_empty() {
+            return cmd.to_string();
+        }
+
+        let mut prefix = String::new();
+        if let Some(user) = &self.user {
+            prefix.push_str(&format!("sudo -u {} ", shell_quote(user)));
+        }
+        if !self.env.is_empty() {
+            prefix.push_str("env ");
+            for (key, value) in &self.env {
+                debug_assert!(
+                    is_env_name(key),
+                    "invalid environment variable name {key:?}"
+                );
+                prefix.push_str(&format!("{key}={} ", shell_quote(value)));
+            }
+        }
+
+        format!("{prefix}sh -c {}", shell_quote(cmd))
+    }
+
+    /// The command with user, timeout and retry wrappers applied
+    fn wrapped_command(&self) -> String {
+        let mut cmd = self.run_as(&self.command);
+
+        if let Some(secs) = self.timeout_secs {
+            cmd = format!("timeout {secs} bash -c {}", shell_quote(&cmd));
+        }
+
+        // The loop's own status hides a failed last attempt from errexit, so
+        // an exhausted retry fails on the explicit test after it
+        if let Some(attempts) = self.retries.filter(|n| *n > 1) {
+            cmd = format!(
+                "tengu_ok=0; \
+                 for tengu_attempt in $(seq 1 {attempts}); do \
+                 {cmd} && {{ tengu_ok=1; break; }}; \
+                 [ \"$tengu_attempt\" -lt {attempts} ] && sleep 2; \
+                 done; \
+                 [ \"$tengu_ok\" = 1 ]"
+            );
+        }
+
+        cmd
+    }
+}
+
+/// Whether `key` can be set as `key=value` by `env`
+fn is_env_name(key: &str) -> bool {
+    let mut chars = key.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Quote a string for safe use as a single shell word
+pub(crate) fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
 }
 
 impl Step for RunCommand {
@@ -35,22 +192,59 @@ impl Step for RunCommand {
         &self.description
     }
 
+    fn kind(&self) -> &'static str {
+        "RunCommand"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::RunCommand(self.clone()))
+    }
+
     fn to_cloud_init(&self) -> CloudInitFragment {
+        // bootcmd has no idempotency wrapper, so guard inline
+        if self.boot {
+            return CloudInitFragment {
+                bootcmd: self.to_bash(),
+                ..Default::default()
+            };
+        }
+        // The renderer guards runcmd with check_command()
         CloudInitFragment {
-            runcmd: self.to_bash(),
+            runcmd: vec![self.wrapped_command()],
             ..Default::default()
         }
     }
 
     fn to_bash(&self) -> Vec<String> {
-        if let Some(unless) = &self.unless {
-            vec![format!("{} || {{ {}; }}", unless, self.command)]
+        let cmd = self.wrapped_command();
+        if let Some(unless) = self.check_command() {
+            vec![format!("{unless} || {{ {cmd}; }}")]
         } else {
-            vec![self.command.clone()]
+            vec![cmd]
         }
     }
 
     fn check_command(&self) -> Option<String> {
-        self.unless.clone()
+        self.unless.as_deref().map(|unless| self.run_as(unless))
+    }
+
+    fn parallel_safe(&self) -> bool {
+        self.parallel_safe
+    }
+
+    fn allow_failure(&self) -> bool {
+        self.allow_failure
+    }
+
+    fn provides(&self) -> Vec<Dependency> {
+        self.packages
+            .iter()
+            .map(|package| Dependency::Package(package.clone()))
+            .chain(
+                self.services
+                    .iter()
+                    .map(|service| Dependency::Service(service.clone())),
+            )
+            .collect()
     }
 }
@@ -1,9 +1,11 @@
 //! Directory management steps
 
-use super::{CloudInitFragment, Step};
+use serde::{Deserialize, Serialize};
+
+use super::{CloudInitFragment, InvalidOwner, KnownStep, Owner, Step};
 
 /// Ensure a directory exists
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnsureDirectory {
     /// Directory path
     pub path: String,
@@ -34,11 +36,22 @@ impl EnsureDirectory {
         self
     }
 
-    /// Set directory owner
+    /// Set directory owner (not validated; see [`Self::try_with_owner`])
     pub fn with_owner(mut self, owner: impl Into<String>) -> Self {
         self.owner = Some(owner.into());
         self
     }
+
+    /// Set directory owner, rejecting anything but `name`, `name:group` or `uid:gid`
+    pub fn try_with_owner(self, owner: &str) -> Result<Self, InvalidOwner> {
+        let owner: Owner = owner.parse()?;
+        Ok(self.with_owner(owner.to_string()))
+    }
+
+    /// Set directory owner by numeric user and group ID
+    pub fn with_owner_ids(self, uid: u32, gid: u32) -> Self {
+        self.with_owner(Owner::Id { uid, gid }.to_string())
+    }
 }
 
 impl Step for EnsureDirectory {
@@ -46,6 +59,14 @@ impl Step for EnsureDirectory {
         &self.description
     }
 
+    fn kind(&self) -> &'static str {
+        "EnsureDirectory"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::EnsureDirectory(self.clone()))
+    }
+
     fn to_cloud_init(&self) -> CloudInitFragment {
         CloudInitFragment {
             runcmd: self.to_bash(),
@@ -56,14 +77,15 @@ impl Step for EnsureDirectory {
     fn to_bash(&self) -> Vec<String> {
         let mut cmds = vec![format!("mkdir -p {}", self.path)];
 
-        if let Some(perms) = &self.permissions {
-            cmds.push(format!("chmod {} {}", perms, self.path));
-        }
-
+        // chown before chmod, so the requested mode is what ends up applied
         if let Some(owner) = &self.owner {
             cmds.push(format!("chown {} {}", owner, self.path));
         }
 
+        if let Some(perms) = &self.permissions {
+            cmds.push(format!("chmod {} {}", perms, self.path));
+        }
+
         cmds
     }
 
@@ -0,0 +1,253 @@
+//! Docker network, volume and compose steps
+
+use serde::{Deserialize, Serialize};
+
+use super::{CloudInitFragment, Dependency, KnownStep, Step, shell_quote};
+
+/// Ensure a Docker network exists
+///
+/// Must come after the step that starts the `docker` service.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnsureDockerNetwork {
+    /// Network name
+    pub name: String,
+    /// Network driver (default: Docker's, i.e. `bridge`)
+    pub driver: Option<String>,
+    /// Description
+    description: String,
+}
+
+impl EnsureDockerNetwork {
+    /// Create a new network step
+    pub fn new(name: impl Into<String>) -> Self {
+        let name = name.into();
+        let description = format!("Ensure Docker network {name}");
+        Self {
+            name,
+            driver: None,
+            description,
+        }
+    }
+
+    /// Set the network driver (e.g. `bridge`, `overlay`)
+    pub fn driver(mut self, driver: impl Into<String>) -> Self {
+        self.driver = Some(driver.into());
+        self
+    }
+
+    /// Succeeds if the network exists
+    fn exists(&self) -> String {
+        format!(
+            "docker network inspect {} >/dev/null 2>&1",
+            shell_quote(&self.name)
+        )
+    }
+}
+
+impl Step for EnsureDockerNetwork {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "EnsureDockerNetwork"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::EnsureDockerNetwork(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        CloudInitFragment {
+            runcmd: self.to_bash(),
+            ..Default::default()
+        }
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        let driver = self
+            .driver
+            .as_ref()
+            .map(|d| format!(" --driver {}", shell_quote(d)))
+            .unwrap_or_default();
+        vec![format!(
+            "{} || docker network create{driver} {}",
+            self.exists(),
+            shell_quote(&self.name)
+        )]
+    }
+
+    fn check_command(&self) -> Option<String> {
+        Some(self.exists())
+    }
+
+    fn requires(&self) -> Vec<Dependency> {
+        vec![Dependency::Service("docker".into())]
+    }
+}
+
+/// Ensure a named Docker volume exists
+///
+/// Must come after the step that starts the `docker` service.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnsureDockerVolume {
+    /// Volume name
+    pub name: String,
+    /// Description
+    description: String,
+}
+
+impl EnsureDockerVolume {
+    /// Create a new volume step
+    pub fn new(name: impl Into<String>) -> Self {
+        let name = name.into();
+        let description = format!("Ensure Docker volume {name}");
+        Self { name, description }
+    }
+
+    /// Succeeds if the volume exists
+    fn exists(&self) -> String {
+        format!(
+            "docker volume inspect {} >/dev/null 2>&1",
+            shell_quote(&self.name)
+        )
+    }
+}
+
+impl Step for EnsureDockerVolume {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "EnsureDockerVolume"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::EnsureDockerVolume(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        CloudInitFragment {
+            runcmd: self.to_bash(),
+            ..Default::default()
+        }
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        vec![format!(
+            "{} || docker volume create {}",
+            self.exists(),
+            shell_quote(&self.name)
+        )]
+    }
+
+    fn check_command(&self) -> Option<String> {
+        Some(self.exists())
+    }
+
+    fn requires(&self) -> Vec<Dependency> {
+        vec![Dependency::Service("docker".into())]
+    }
+}
+
+/// Bring up a Compose project with `docker compose up -d`
+///
+/// Pair it with a [`WriteFile`](super::WriteFile) for the compose file.
+/// Skipped when every service in the file is running and the resolved
+/// configuration (`docker compose config`) is the one last brought up, whose
+/// hash is kept in `<path>.sha256`. Must come after the step that starts the
+/// `docker` service.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunComposeFile {
+    /// Path to the compose file
+    pub path: String,
+    /// Compose project name (default: the file's directory name)
+    pub project_name: Option<String>,
+    /// Description
+    description: String,
+}
+
+impl RunComposeFile {
+    /// Create a new compose step
+    pub fn new(path: impl Into<String>) -> Self {
+        let path = path.into();
+        let description = format!("Bring up {path}");
+        Self {
+            path,
+            project_name: None,
+            description,
+        }
+    }
+
+    /// Set the project name, isolating it from other projects on the host
+    pub fn project_name(mut self, name: impl Into<String>) -> Self {
+        self.project_name = Some(name.into());
+        self
+    }
+
+    /// `docker compose -f <path> [-p <project>]`
+    fn compose(&self) -> String {
+        let mut cmd = format!("docker compose -f {}", shell_quote(&self.path));
+        if let Some(project) = &self.project_name {
+            cmd.push_str(&format!(" -p {}", shell_quote(project)));
+        }
+        cmd
+    }
+
+    /// Hash of the resolved configuration, as stored after `up -d`
+    fn config_hash(&self) -> String {
+        format!("{} config 2>/dev/null | sha256sum", self.compose())
+    }
+
+    /// File holding the hash of the configuration last brought up
+    fn stamp(&self) -> String {
+        shell_quote(&format!("{}.sha256", self.path))
+    }
+}
+
+impl Step for RunComposeFile {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "RunComposeFile"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::RunComposeFile(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        CloudInitFragment {
+            runcmd: self.to_bash(),
+            ..Default::default()
+        }
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        vec![
+            format!("{} up -d", self.compose()),
+            format!("{} > {}", self.config_hash(), self.stamp()),
+        ]
+    }
+
+    fn check_command(&self) -> Option<String> {
+        // Running services must match the services declared in the file, and
+        // the file must not have changed since it was brought up
+        let compose = self.compose();
+        Some(format!(
+            "[ -n \"$({compose} config --services 2>/dev/null)\" ] && \
+             [ \"$({compose} ps --status running --services 2>/dev/null | sort)\" = \
+             \"$({compose} config --services 2>/dev/null | sort)\" ] && \
+             [ \"$({})\" = \"$(cat {} 2>/dev/null)\" ]",
+            self.config_hash(),
+            self.stamp(),
+        ))
+    }
+
+    fn requires(&self) -> Vec<Dependency> {
+        vec![Dependency::Service("docker".into())]
+    }
+}
@@ -1,10 +1,12 @@
 //! File management steps
 
-use super::{CloudInitFile, CloudInitFragment, Step};
+use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
+use super::{CloudInitFile, CloudInitFragment, InvalidOwner, KnownStep, Owner, Step};
+
 /// Write a file with specified content
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WriteFile {
     /// File path
     pub path: String,
@@ -14,6 +16,8 @@ pub struct WriteFile {
     pub permissions: Option<String>,
     /// File owner (e.g., "root:root")
     pub owner: Option<String>,
+    /// Write after packages are installed (cloud-init `defer: true`)
+    pub deferred: bool,
     /// Description
     description: String,
 }
@@ -28,6 +32,7 @@ impl WriteFile {
             content: content.into(),
             permissions: None,
             owner: None,
+            deferred: false,
             description,
         }
     }
@@ -38,12 +43,33 @@ impl WriteFile {
         self
     }
 
-    /// Set file owner
+    /// Set file owner (not validated; see [`Self::try_with_owner`])
     pub fn with_owner(mut self, owner: impl Into<String>) -> Self {
         self.owner = Some(owner.into());
         self
     }
 
+    /// Set file owner, rejecting anything but `name`, `name:group` or `uid:gid`
+    pub fn try_with_owner(self, owner: &str) -> Result<Self, InvalidOwner> {
+        let owner: Owner = owner.parse()?;
+        Ok(self.with_owner(owner.to_string()))
+    }
+
+    /// Set file owner by numeric user and group ID
+    pub fn with_owner_ids(self, uid: u32, gid: u32) -> Self {
+        self.with_owner(Owner::Id { uid, gid }.to_string())
+    }
+
+    /// Defer the cloud-init write until after packages are installed
+    ///
+    /// For files that depend on a package, e.g. a unit for a just-installed
+    /// binary or a file owned by a user the package creates. Bash output
+    /// already writes files in step order.
+    pub fn deferred(mut self, deferred: bool) -> Self {
+        self.deferred = deferred;
+        self
+    }
+
     /// Compute SHA256 hash of content (hex-encoded)
     fn content_hash(&self) -> String {
         let mut hasher = Sha256::new();
@@ -58,6 +84,14 @@ impl Step for WriteFile {
         &self.description
     }
 
+    fn kind(&self) -> &'static str {
+        "WriteFile"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::WriteFile(self.clone()))
+    }
+
     fn to_cloud_init(&self) -> CloudInitFragment {
         CloudInitFragment {
             write_files: vec![CloudInitFile {
@@ -65,6 +99,7 @@ impl Step for WriteFile {
                 content: self.content.clone(),
                 permissions: self.permissions.clone(),
                 owner: self.owner.clone(),
+                defer: self.deferred,
             }],
             ..Default::default()
         }
@@ -81,7 +116,8 @@ impl Step for WriteFile {
         // Pre-compute expected hash at generation time
         let expected_hash = self.content_hash();
 
-        // Use base64 encoding to avoid heredoc indentation issues
+        // Use base64 encoding to avoid heredoc indentation issues; no content
+        // line can collide with a delimiter or break out of the quoting
         let encoded = STANDARD.encode(&self.content);
 
         // Compare hash and write only if different
@@ -93,14 +129,15 @@ fi"#,
             self.path, expected_hash, encoded, self.path
         ));
 
-        if let Some(perms) = &self.permissions {
-            cmds.push(format!("chmod {} '{}'", perms, self.path));
-        }
-
+        // chown before chmod: changing the owner clears setuid/setgid bits
         if let Some(owner) = &self.owner {
             cmds.push(format!("chown {} '{}'", owner, self.path));
         }
 
+        if let Some(perms) = &self.permissions {
+            cmds.push(format!("chmod {} '{}'", perms, self.path));
+        }
+
         cmds
     }
 
@@ -1,12 +1,26 @@
 //! Firewall (UFW) management steps
 
-use super::{CloudInitFragment, Step};
+use serde::{Deserialize, Serialize};
+
+use super::{CloudInitFragment, KnownStep, Step, WriteFile, shell_quote};
+
+/// UFW defaults file holding the `IPV6=` setting
+const UFW_DEFAULTS: &str = "/etc/default/ufw";
+
+/// Directory UFW loads application profiles from
+const UFW_APPLICATIONS_DIR: &str = "/etc/ufw/applications.d";
+
+/// Rule specs that keep SSH reachable
+const SSH_RULES: &[&str] = &["22", "22/tcp", "ssh", "OpenSSH"];
 
 /// A UFW allow rule
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UfwRule {
-    /// Port/protocol to allow (e.g., "22/tcp", "80/tcp")
+    /// Port/protocol to allow (e.g., "22/tcp", "80/tcp"), or an application profile name
     pub allow: String,
+    /// `allow` names an application profile (e.g., "Nginx Full")
+    #[serde(default)]
+    pub app: bool,
 }
 
 impl UfwRule {
@@ -14,12 +28,30 @@ impl UfwRule {
     pub fn new(allow: impl Into<String>) -> Self {
         Self {
             allow: allow.into(),
+            app: false,
+        }
+    }
+
+    /// Create a rule allowing an application profile
+    pub fn app(profile: impl Into<String>) -> Self {
+        Self {
+            allow: profile.into(),
+            app: true,
+        }
+    }
+
+    /// Argument to `ufw allow`, quoted the way `ufw show added` prints it
+    fn spec(&self) -> String {
+        if self.app && self.allow.contains(' ') {
+            format!("'{}'", self.allow)
+        } else {
+            self.allow.clone()
         }
     }
 }
 
 /// Ensure UFW firewall is configured and enabled
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnsureFirewall {
     /// Rules to apply
     pub rules: Vec<UfwRule>,
@@ -27,6 +59,12 @@ pub struct EnsureFirewall {
     pub default_incoming: String,
     /// Default outgoing policy
     pub default_outgoing: String,
+    /// Manage IPv6 rules (`IPV6=` in `/etc/default/ufw`); left as-is when unset
+    pub ipv6: Option<bool>,
+    /// Logging level (off, low, medium, high, full); left as-is when unset
+    pub logging: Option<String>,
+    /// Reset all rules before applying, removing any not declared here
+    pub reset_before_apply: bool,
     /// Description
     description: String,
 }
@@ -38,6 +76,9 @@ impl EnsureFirewall {
             rules: vec![],
             default_incoming: "deny".into(),
             default_outgoing: "allow".into(),
+            ipv6: None,
+            logging: None,
+            reset_before_apply: false,
             description: "Configure firewall".into(),
         }
     }
@@ -48,6 +89,26 @@ impl EnsureFirewall {
         self
     }
 
+    /// Add a rule to allow an application profile (see `ufw app list`)
+    pub fn allow_app(mut self, profile: impl Into<String>) -> Self {
+        self.rules.push(UfwRule::app(profile));
+        self
+    }
+
+    /// File registering a custom application profile under `/etc/ufw/applications.d/`
+    ///
+    /// `ports` uses UFW's profile syntax (e.g., `80,443/tcp`). Add it before
+    /// the firewall step that allows `name`.
+    pub fn app_profile(name: &str, title: &str, ports: &str) -> WriteFile {
+        let file = name.to_lowercase().replace(' ', "-");
+        WriteFile::new(
+            format!("{UFW_APPLICATIONS_DIR}/{file}"),
+            format!("[{name}]\ntitle={title}\ndescription={title}\nports={ports}\n"),
+        )
+        .with_permissions("0644")
+        .with_owner("root:root")
+    }
+
     /// Set default incoming policy
     pub fn default_incoming(mut self, policy: impl Into<String>) -> Self {
         self.default_incoming = policy.into();
@@ -59,6 +120,63 @@ impl EnsureFirewall {
         self.default_outgoing = policy.into();
         self
     }
+
+    /// Enable or disable IPv6 support
+    pub fn ipv6(mut self, enabled: bool) -> Self {
+        self.ipv6 = Some(enabled);
+        self
+    }
+
+    /// Set the logging level
+    pub fn logging(mut self, level: impl Into<String>) -> Self {
+        self.logging = Some(level.into());
+        self
+    }
+
+    /// Converge on exactly the declared rules via `ufw --force reset`
+    ///
+    /// By default rules are only ever added, so a port dropped from the config
+    /// stays open. Resetting removes it, but briefly disables the firewall
+    /// and drops every rule; an SSH rule is therefore always applied first
+    /// (`22/tcp` if none is declared) so re-enabling can't lock you out.
+    pub fn reset_before_apply(mut self, reset: bool) -> Self {
+        self.reset_before_apply = reset;
+        self
+    }
+
+    /// Rule specs in the order they are applied
+    ///
+    /// With reset, SSH rules move to the front and one is added if missing.
+    fn applied_rules(&self) -> Vec<String> {
+        let rules = self.rules.iter();
+        if !self.reset_before_apply {
+            return rules.map(UfwRule::spec).collect();
+        }
+
+        let (ssh, other): (Vec<_>, Vec<_>) =
+            rules.partition(|r| SSH_RULES.contains(&r.allow.as_str()));
+        let mut ssh: Vec<_> = ssh.into_iter().map(UfwRule::spec).collect();
+        let other = other.into_iter().map(UfwRule::spec);
+        if ssh.is_empty() {
+            ssh.push("22/tcp".into());
+        }
+        ssh.extend(other);
+        ssh
+    }
+
+    /// `IPV6=` line matching the configured setting
+    fn ipv6_line(&self) -> Option<&'static str> {
+        self.ipv6
+            .map(|enabled| if enabled { "IPV6=yes" } else { "IPV6=no" })
+    }
+
+    /// Expected `Logging:` line in `ufw status verbose`
+    fn logging_status(&self) -> Option<String> {
+        self.logging.as_deref().map(|level| match level {
+            "off" => "Logging: off".to_string(),
+            level => format!("Logging: on ({level})"),
+        })
+    }
 }
 
 impl Default for EnsureFirewall {
@@ -72,6 +190,14 @@ impl Step for EnsureFirewall {
         &self.description
     }
 
+    fn kind(&self) -> &'static str {
+        "EnsureFirewall"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::EnsureFirewall(self.clone()))
+    }
+
     fn to_cloud_init(&self) -> CloudInitFragment {
         CloudInitFragment {
             runcmd: self.to_bash(),
@@ -80,23 +206,61 @@ impl Step for EnsureFirewall {
     }
 
     fn to_bash(&self) -> Vec<String> {
-        let mut cmds = vec![
-            format!("ufw default {} incoming", self.default_incoming),
-            format!("ufw default {} outgoing", self.default_outgoing),
-        ];
+        let mut cmds = vec![];
+
+        // Set first so the rules below are also added for IPv6; an active
+        // firewall only picks up the change on reload
+        if let Some(line) = self.ipv6_line() {
+            cmds.push(format!(
+                "grep -qx '{line}' {UFW_DEFAULTS} || {{ \
+                 if grep -q '^IPV6=' {UFW_DEFAULTS}; then sed -i 's/^IPV6=.*/{line}/' {UFW_DEFAULTS}; \
+                 else echo '{line}' >> {UFW_DEFAULTS}; fi; \
+                 if ufw status | grep -q 'Status: active'; then ufw reload; fi; }}"
+            ));
+        }
+
+        if self.reset_before_apply {
+            cmds.push("ufw --force reset".to_string());
+        }
 
-        for rule in &self.rules {
+        cmds.push(format!("ufw default {} incoming", self.default_incoming));
+        cmds.push(format!("ufw default {} outgoing", self.default_outgoing));
+
+        for rule in self.applied_rules() {
             // ufw allow is already idempotent
-            cmds.push(format!("ufw allow {}", rule.allow));
+            cmds.push(format!("ufw allow {rule}"));
         }
 
         // Enable if not already
         cmds.push("ufw status | grep -q 'Status: active' || ufw --force enable".to_string());
 
+        if let Some(level) = &self.logging {
+            cmds.push(format!("ufw logging {level}"));
+        }
+
         cmds
     }
 
     fn check_command(&self) -> Option<String> {
-        Some("ufw status | grep -q 'Status: active'".to_string())
+        let mut check = "ufw status | grep -q 'Status: active'".to_string();
+        if self.reset_before_apply {
+            // Rules must match exactly, so undeclared ones trigger a reset
+            let expected = self
+                .applied_rules()
+                .iter()
+                .map(|r| shell_quote(&format!("ufw allow {r}")))
+                .collect::<Vec<_>>()
+                .join(" ");
+            check.push_str(&format!(
+                " && [ \"$(ufw show added | grep '^ufw ' | sort)\" = \"$(printf '%s\\n' {expected} | sort)\" ]"
+            ));
+        }
+        if let Some(line) = self.ipv6_line() {
+            check.push_str(&format!(" && grep -qx '{line}' {UFW_DEFAULTS}"));
+        }
+        if let Some(status) = self.logging_status() {
+            check.push_str(&format!(" && ufw status verbose | grep -q '{status}'"));
+        }
+        Some(check)
     }
 }
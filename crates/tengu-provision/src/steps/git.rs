@@ -0,0 +1,152 @@
+//! Git checkout steps
+
+use serde::{Deserialize, Serialize};
+
+use super::{CloudInitFragment, Dependency, KnownStep, Step, shell_quote};
+
+/// Clone a git repository, or update an existing checkout
+///
+/// Without a pinned [`rev`](Self::rev) an existing checkout is updated with
+/// `git pull --ff-only` whenever its HEAD differs from the remote's tip
+/// (`git ls-remote`); with one, the exact commit is fetched and checked out
+/// (detached). Requires the `git` package.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GitClone {
+    /// Repository URL
+    pub url: String,
+    /// Checkout directory
+    pub dest: String,
+    /// Branch to clone (default: the remote's default branch)
+    pub branch: Option<String>,
+    /// Shallow clone with this many commits of history
+    pub depth: Option<u32>,
+    /// Commit to check out (full SHA)
+    pub rev: Option<String>,
+    /// Description
+    description: String,
+}
+
+impl GitClone {
+    /// Create a new clone step
+    pub fn new(url: impl Into<String>, dest: impl Into<String>) -> Self {
+        let url = url.into();
+        let dest = dest.into();
+        let description = format!("Clone {url} to {dest}");
+        Self {
+            url,
+            dest,
+            branch: None,
+            depth: None,
+            rev: None,
+            description,
+        }
+    }
+
+    /// Clone this branch
+    pub fn branch(mut self, branch: impl Into<String>) -> Self {
+        self.branch = Some(branch.into());
+        self
+    }
+
+    /// Shallow clone with `depth` commits of history
+    pub fn depth(mut self, depth: u32) -> Self {
+        self.depth = Some(depth);
+        self
+    }
+
+    /// Pin the checkout to a commit (full SHA) for reproducible deploys
+    pub fn rev(mut self, rev: impl Into<String>) -> Self {
+        self.rev = Some(rev.into());
+        self
+    }
+
+    /// `git -C <dest>`
+    fn git(&self) -> String {
+        format!("git -C {}", shell_quote(&self.dest))
+    }
+
+    /// `--depth N` if set, with a leading space
+    fn depth_arg(&self) -> String {
+        self.depth
+            .map(|d| format!(" --depth {d}"))
+            .unwrap_or_default()
+    }
+}
+
+impl Step for GitClone {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "GitClone"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::GitClone(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        CloudInitFragment {
+            runcmd: self.to_bash(),
+            ..Default::default()
+        }
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        let dest = shell_quote(&self.dest);
+        let branch = self
+            .branch
+            .as_ref()
+            .map(|b| format!(" --branch {}", shell_quote(b)))
+            .unwrap_or_default();
+        let update = if self.rev.is_some() {
+            // A pinned checkout is moved below, never pulled
+            ":".to_string()
+        } else {
+            format!("{} pull --ff-only", self.git())
+        };
+
+        let mut cmds = vec![format!(
+            "if [ -d {dest}/.git ]; then\n    {update}\nelse\n    mkdir -p \"$(dirname {dest})\"\n    git clone{branch}{} {} {dest}\nfi",
+            self.depth_arg(),
+            shell_quote(&self.url),
+        )];
+
+        if let Some(rev) = &self.rev {
+            let rev = shell_quote(rev);
+            cmds.push(format!(
+                "if [ \"$({git} rev-parse HEAD)\" != {rev} ]; then\n    {git} fetch{} origin {rev}\n    {git} checkout -q --detach {rev}\nfi",
+                self.depth_arg(),
+                git = self.git(),
+            ));
+        }
+
+        cmds
+    }
+
+    fn check_command(&self) -> Option<String> {
+        let expected = if let Some(rev) = &self.rev {
+            shell_quote(rev)
+        } else {
+            // Up to date when HEAD is the tip the remote would pull
+            let remote_ref = self.branch.as_ref().map_or_else(
+                || "HEAD".to_string(),
+                |b| shell_quote(&format!("refs/heads/{b}")),
+            );
+            format!(
+                "\"$({} ls-remote origin {remote_ref} 2>/dev/null | cut -f1 | head -n1)\"",
+                self.git()
+            )
+        };
+        Some(format!(
+            "[ -d {}/.git ] && [ \"$({} rev-parse HEAD 2>/dev/null)\" = {expected} ]",
+            shell_quote(&self.dest),
+            self.git(),
+        ))
+    }
+
+    fn requires(&self) -> Vec<Dependency> {
+        vec![Dependency::Package("git".into())]
+    }
+}
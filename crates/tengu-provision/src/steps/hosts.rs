@@ -0,0 +1,103 @@
+//! `/etc/hosts` entry steps
+
+use serde::{Deserialize, Serialize};
+
+use super::{CloudInitFragment, KnownStep, Step, shell_quote};
+
+/// Ensure `/etc/hosts` maps a hostname (and optional aliases) to an IP
+///
+/// Keyed on the hostname: any other line naming it is replaced, so moving a
+/// host to a new IP doesn't leave a stale pin behind.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnsureHostEntry {
+    /// IP address
+    pub ip: String,
+    /// Canonical hostname
+    pub hostname: String,
+    /// Additional names on the same line
+    pub aliases: Vec<String>,
+    /// Description
+    description: String,
+}
+
+impl EnsureHostEntry {
+    /// Create a new hosts entry step
+    pub fn new(ip: impl Into<String>, hostname: impl Into<String>) -> Self {
+        let ip = ip.into();
+        let hostname = hostname.into();
+        let description = format!("Map {hostname} to {ip} in /etc/hosts");
+        Self {
+            ip,
+            hostname,
+            aliases: vec![],
+            description,
+        }
+    }
+
+    /// Add aliases after the hostname
+    pub fn with_aliases<I, S>(mut self, aliases: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.aliases = aliases.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Names on the line, hostname first
+    fn names(&self) -> String {
+        std::iter::once(&self.hostname)
+            .chain(&self.aliases)
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// Escape regex metacharacters that occur in IPs and hostnames
+fn regex_escape(s: &str) -> String {
+    s.replace('.', "\\.")
+}
+
+impl Step for EnsureHostEntry {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "EnsureHostEntry"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::EnsureHostEntry(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        CloudInitFragment {
+            runcmd: self.to_bash(),
+            ..Default::default()
+        }
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        let line = format!("{}\t{}", self.ip, self.names());
+        vec![
+            // Drop any non-comment line naming this host; rewrite in place since
+            // /etc/hosts may be a bind mount that can't be replaced
+            format!(
+                "awk -v h={} '/^[[:space:]]*#/ {{ print; next }} {{ for (i = 2; i <= NF; i++) if ($i == h) next }} 1' /etc/hosts > /etc/hosts.tengu",
+                shell_quote(&self.hostname)
+            ),
+            "cat /etc/hosts.tengu > /etc/hosts && rm -f /etc/hosts.tengu".into(),
+            format!("printf '%s\\n' {} >> /etc/hosts", shell_quote(&line)),
+        ]
+    }
+
+    fn check_command(&self) -> Option<String> {
+        Some(format!(
+            "grep -qE '^{}\\s+{}$' /etc/hosts",
+            regex_escape(&self.ip),
+            regex_escape(&self.names())
+        ))
+    }
+}
@@ -3,23 +3,55 @@
 //! Each step implements the [`Step`] trait and can render to both
 //! cloud-init YAML fragments and idempotent bash commands.
 
+mod block;
+mod caddy;
 mod command;
 mod directory;
+mod docker;
 mod file;
 mod firewall;
+mod git;
+mod hosts;
+mod mount;
+mod owner;
 mod package;
+mod replace;
 mod service;
+mod swap;
+mod symlink;
+mod system;
+mod template;
+mod upgrades;
 mod user;
 
+pub use block::EnsureBlockInFile;
+pub use caddy::{CADDY_SITES_DIR, CaddySite};
 pub use command::RunCommand;
+pub(crate) use command::shell_quote;
 pub use directory::EnsureDirectory;
+pub use docker::{EnsureDockerNetwork, EnsureDockerVolume, RunComposeFile};
 pub use file::WriteFile;
 pub use firewall::{EnsureFirewall, UfwRule};
-pub use package::{InstallDebFromUrl, InstallPackage, Repository};
-pub use service::EnsureService;
+pub use git::GitClone;
+pub use hosts::EnsureHostEntry;
+pub use mount::EnsureMount;
+pub use owner::{InvalidOwner, Owner};
+pub(crate) use package::TRACK_PKG_FN;
+pub use package::{
+    AddAptRepositories, DebSignature, EnsureAptHold, EnsureAptPreference, InstallDebFromUrl,
+    InstallPackage, InstallPackages, RemovePackage, Repository, RepositoryFormat, WaitForAptLock,
+    mirror_urls,
+};
+pub use replace::ReplaceInFile;
+pub use service::{EnsureService, EnsureServiceLimits};
+pub use swap::EnsureSwap;
+pub use symlink::EnsureSymlink;
+pub use system::{SetHostname, SetLocale, SetTimezone};
+pub use template::{TemplateError, TemplateFile};
+pub use upgrades::EnsureUnattendedUpgrades;
 pub use user::EnsureUser;
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 /// Result of running a step
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -32,6 +64,29 @@ pub enum StepResult {
     Failed(String),
 }
 
+/// Something a step makes available to the steps after it
+///
+/// Checked by [`Manifest::validate_ordering`](crate::Manifest::validate_ordering).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Dependency {
+    /// An installed apt/dpkg package
+    Package(String),
+    /// An apt repository, identified by its keyring path
+    Repository(String),
+    /// A running systemd service
+    Service(String),
+}
+
+impl std::fmt::Display for Dependency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Package(name) => write!(f, "package {name}"),
+            Self::Repository(keyring) => write!(f, "repository {keyring}"),
+            Self::Service(name) => write!(f, "service {name}"),
+        }
+    }
+}
+
 /// A single installation step
 ///
 /// All steps must be:
@@ -42,6 +97,9 @@ pub trait Step: Send + Sync {
     /// Human-readable description of what this step does
     fn description(&self) -> &str;
 
+    /// Stable type name of this step (e.g. `"InstallPackage"`)
+    fn kind(&self) -> &'static str;
+
     /// Render as cloud-init YAML fragment
     fn to_cloud_init(&self) -> CloudInitFragment;
 
@@ -53,6 +111,113 @@ pub trait Step: Send + Sync {
     /// If `Some(cmd)` is returned and the command succeeds (exit 0),
     /// the step will be skipped. If `None`, the step always runs.
     fn check_command(&self) -> Option<String>;
+
+    /// Whether this step may run concurrently with neighbouring steps
+    ///
+    /// Only steps that share no state with the steps around them should opt
+    /// in. The bash renderer still serializes apt/dpkg steps among themselves.
+    fn parallel_safe(&self) -> bool {
+        false
+    }
+
+    /// Whether a failure is reported and tolerated instead of failing the run
+    ///
+    /// For best-effort steps; renderers stop the step at its first failing
+    /// command and carry on with the next step.
+    fn allow_failure(&self) -> bool {
+        false
+    }
+
+    /// What this step makes available to later steps
+    fn provides(&self) -> Vec<Dependency> {
+        vec![]
+    }
+
+    /// What an earlier step must have provided
+    fn requires(&self) -> Vec<Dependency> {
+        vec![]
+    }
+
+    /// This step as a [`KnownStep`], for serializing manifests
+    ///
+    /// `None` for step types defined outside this crate.
+    fn to_known(&self) -> Option<KnownStep> {
+        None
+    }
+}
+
+/// One of this crate's step types, (de)serialized tagged by [`Step::kind`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum KnownStep {
+    AddAptRepositories(AddAptRepositories),
+    CaddySite(CaddySite),
+    EnsureAptHold(EnsureAptHold),
+    EnsureAptPreference(EnsureAptPreference),
+    EnsureBlockInFile(EnsureBlockInFile),
+    EnsureDirectory(EnsureDirectory),
+    EnsureDockerNetwork(EnsureDockerNetwork),
+    EnsureDockerVolume(EnsureDockerVolume),
+    EnsureFirewall(EnsureFirewall),
+    EnsureHostEntry(EnsureHostEntry),
+    EnsureMount(EnsureMount),
+    EnsureService(EnsureService),
+    EnsureServiceLimits(EnsureServiceLimits),
+    EnsureSwap(EnsureSwap),
+    EnsureSymlink(EnsureSymlink),
+    EnsureUnattendedUpgrades(Box<EnsureUnattendedUpgrades>),
+    EnsureUser(EnsureUser),
+    GitClone(GitClone),
+    InstallDebFromUrl(InstallDebFromUrl),
+    InstallPackage(InstallPackage),
+    InstallPackages(InstallPackages),
+    RemovePackage(RemovePackage),
+    ReplaceInFile(ReplaceInFile),
+    RunCommand(RunCommand),
+    RunComposeFile(RunComposeFile),
+    SetHostname(SetHostname),
+    SetLocale(SetLocale),
+    SetTimezone(SetTimezone),
+    WaitForAptLock(WaitForAptLock),
+    WriteFile(WriteFile),
+}
+
+impl KnownStep {
+    /// Box the wrapped step
+    pub fn into_step(self) -> Box<dyn Step> {
+        match self {
+            Self::AddAptRepositories(step) => Box::new(step),
+            Self::CaddySite(step) => Box::new(step),
+            Self::EnsureAptHold(step) => Box::new(step),
+            Self::EnsureAptPreference(step) => Box::new(step),
+            Self::EnsureBlockInFile(step) => Box::new(step),
+            Self::EnsureDirectory(step) => Box::new(step),
+            Self::EnsureDockerNetwork(step) => Box::new(step),
+            Self::EnsureDockerVolume(step) => Box::new(step),
+            Self::EnsureFirewall(step) => Box::new(step),
+            Self::EnsureHostEntry(step) => Box::new(step),
+            Self::EnsureMount(step) => Box::new(step),
+            Self::EnsureService(step) => Box::new(step),
+            Self::EnsureServiceLimits(step) => Box::new(step),
+            Self::EnsureSwap(step) => Box::new(step),
+            Self::EnsureSymlink(step) => Box::new(step),
+            Self::EnsureUnattendedUpgrades(step) => step,
+            Self::EnsureUser(step) => Box::new(step),
+            Self::GitClone(step) => Box::new(step),
+            Self::InstallDebFromUrl(step) => Box::new(step),
+            Self::InstallPackage(step) => Box::new(step),
+            Self::InstallPackages(step) => Box::new(step),
+            Self::RemovePackage(step) => Box::new(step),
+            Self::ReplaceInFile(step) => Box::new(step),
+            Self::RunCommand(step) => Box::new(step),
+            Self::RunComposeFile(step) => Box::new(step),
+            Self::SetHostname(step) => Box::new(step),
+            Self::SetLocale(step) => Box::new(step),
+            Self::SetTimezone(step) => Box::new(step),
+            Self::WaitForAptLock(step) => Box::new(step),
+            Self::WriteFile(step) => Box::new(step),
+        }
+    }
 }
 
 /// Fragment that can be merged into a cloud-init config
@@ -62,10 +227,19 @@ pub struct CloudInitFragment {
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub packages: Vec<String>,
 
+    /// Users to declare natively in the `users:` section
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<CloudInitUser>,
+
     /// Files to write
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub write_files: Vec<CloudInitFile>,
 
+    /// Commands to run early, before packages are installed (on every boot,
+    /// so they must be guarded)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bootcmd: Vec<String>,
+
     /// Commands to run
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub runcmd: Vec<String>,
@@ -80,4 +254,23 @@ pub struct CloudInitFile {
     pub permissions: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub owner: Option<String>,
+    /// Write in the final stage, after packages and users exist
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub defer: bool,
+}
+
+/// A user entry in cloud-init format
+#[derive(Debug, Clone, Serialize)]
+pub struct CloudInitUser {
+    pub name: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<String>,
+    pub shell: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sudo: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ssh_authorized_keys: Vec<String>,
+    /// System account without a home directory
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub system: bool,
 }
@@ -0,0 +1,111 @@
+//! Block device mount steps
+
+use serde::{Deserialize, Serialize};
+
+use super::{CloudInitFragment, KnownStep, Step};
+
+/// Ensure a block device has a filesystem and is mounted persistently
+///
+/// The device is only formatted when it has no filesystem yet, so an
+/// existing volume re-attached to a new server keeps its data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnsureMount {
+    /// Block device (e.g., `/dev/disk/by-id/scsi-0HC_Volume_123`)
+    pub device: String,
+    /// Mount point
+    pub mount_path: String,
+    /// Filesystem created on an empty device
+    pub fs_type: String,
+    /// fstab mount options
+    pub options: String,
+    /// Description
+    description: String,
+}
+
+impl EnsureMount {
+    /// Create a new mount step (ext4, `defaults,nofail`)
+    pub fn new(device: impl Into<String>, mount_path: impl Into<String>) -> Self {
+        let device = device.into();
+        let mount_path = mount_path.into();
+        let description = format!("Mount {device} at {mount_path}");
+        Self {
+            device,
+            mount_path,
+            fs_type: "ext4".into(),
+            options: "defaults,nofail".into(),
+            description,
+        }
+    }
+
+    /// Set the filesystem type
+    pub fn fs_type(mut self, fs_type: impl Into<String>) -> Self {
+        self.fs_type = fs_type.into();
+        self
+    }
+
+    /// Set the fstab mount options
+    pub fn options(mut self, options: impl Into<String>) -> Self {
+        self.options = options.into();
+        self
+    }
+
+    /// Matches an fstab entry for the mount point
+    fn fstab_grep(&self) -> String {
+        format!("grep -qE '^\\S+\\s+{}\\s' /etc/fstab", self.mount_path)
+    }
+}
+
+impl Step for EnsureMount {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "EnsureMount"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::EnsureMount(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        // bootcmd so the mount exists before packages write to it; it runs
+        // every boot without an idempotency wrapper, so guard inline
+        let check = self.check_command().unwrap_or_default();
+        CloudInitFragment {
+            bootcmd: vec![format!(
+                "if ! ({check}); then\n{}\nfi",
+                self.to_bash().join("\n")
+            )],
+            ..Default::default()
+        }
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        let Self {
+            device,
+            mount_path,
+            fs_type,
+            options,
+            ..
+        } = self;
+        vec![
+            // blkid prints nothing for a device without a filesystem
+            format!("[ -n \"$(blkid -o value -s TYPE {device})\" ] || mkfs.{fs_type} -q {device}"),
+            format!("mkdir -p {mount_path}"),
+            format!(
+                "{} || echo '{device} {mount_path} {fs_type} {options} 0 2' >> /etc/fstab",
+                self.fstab_grep()
+            ),
+            format!("mountpoint -q {mount_path} || mount {mount_path}"),
+        ]
+    }
+
+    fn check_command(&self) -> Option<String> {
+        Some(format!(
+            "mountpoint -q {} && {}",
+            self.mount_path,
+            self.fstab_grep()
+        ))
+    }
+}
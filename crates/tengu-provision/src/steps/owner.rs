@@ -0,0 +1,78 @@
+//! File ownership specs
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Error parsing an [`Owner`]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("invalid owner '{0}' (expected name, name:group or uid:gid)")]
+pub struct InvalidOwner(pub String);
+
+/// Owner passed to `chown`: `name`, `name:group` or numeric `uid:gid`
+///
+/// Parsing only checks the shape; whether the user and group exist is only
+/// known on the target host.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Owner {
+    /// User name, optionally with a group name
+    Name {
+        /// User name
+        user: String,
+        /// Group name
+        group: Option<String>,
+    },
+    /// Numeric user and group IDs
+    Id {
+        /// User ID
+        uid: u32,
+        /// Group ID
+        gid: u32,
+    },
+}
+
+/// Whether `s` is a valid user or group name (`useradd` rules)
+fn is_name(s: &str) -> bool {
+    let name = s.strip_suffix('$').unwrap_or(s);
+    let mut chars = name.chars();
+    s.len() <= 32
+        && chars
+            .next()
+            .is_some_and(|c| c.is_ascii_lowercase() || c == '_')
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+impl FromStr for Owner {
+    type Err = InvalidOwner;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidOwner(s.to_string());
+        match s.split_once(':') {
+            None if is_name(s) => Ok(Self::Name {
+                user: s.to_string(),
+                group: None,
+            }),
+            Some((user, group)) if is_name(user) && is_name(group) => Ok(Self::Name {
+                user: user.to_string(),
+                group: Some(group.to_string()),
+            }),
+            Some((uid, gid)) => match (uid.parse(), gid.parse()) {
+                (Ok(uid), Ok(gid)) => Ok(Self::Id { uid, gid }),
+                _ => Err(invalid()),
+            },
+            None => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Owner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Name { user, group: None } => write!(f, "{user}"),
+            Self::Name {
+                user,
+                group: Some(group),
+            } => write!(f, "{user}:{group}"),
+            Self::Id { uid, gid } => write!(f, "{uid}:{gid}"),
+        }
+    }
+}
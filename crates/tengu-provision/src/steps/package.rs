@@ -1,9 +1,45 @@
 //! Package installation steps
 
-use super::{CloudInitFragment, Step};
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::{CloudInitFragment, Dependency, KnownStep, Step, WriteFile};
+
+/// Shell function recording the packages tengu installed, for a clean uninstall
+///
+/// Package steps call `track_pkg NAME` after installing, so any script
+/// running their commands must define it first.
+pub(crate) const TRACK_PKG_FN: &str = r#"# Track installed packages for clean uninstall
+TENGU_INSTALLED_FILE="/etc/tengu/installed-by-tengu.txt"
+track_pkg() {
+    local pkg="$1"
+    mkdir -p /etc/tengu
+    if ! grep -qxF "$pkg" "$TENGU_INSTALLED_FILE" 2>/dev/null; then
+        echo "$pkg" >> "$TENGU_INSTALLED_FILE"
+    fi
+}
+"#;
+
+/// On-disk format of an apt source
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RepositoryFormat {
+    /// One-line `deb [...]` entry in a `.list` file
+    #[default]
+    OneLine,
+    /// deb822 stanza in a `.sources` file
+    Deb822 {
+        /// Repository URIs
+        uris: String,
+        /// Suites (e.g., `$(lsb_release -cs)`)
+        suites: String,
+        /// Components (e.g., "main")
+        components: String,
+    },
+}
 
 /// Repository configuration for adding external apt sources
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Repository {
     /// URL to the GPG key
     pub key_url: String,
@@ -11,6 +47,10 @@ pub struct Repository {
     pub repo_line: String,
     /// Path to store the keyring (e.g., "/usr/share/keyrings/docker.gpg")
     pub keyring_path: String,
+    /// Source file format
+    pub format: RepositoryFormat,
+    /// Whether the key is ASCII-armored and must be dearmored (default true)
+    pub key_is_armored: bool,
 }
 
 impl Repository {
@@ -20,6 +60,8 @@ impl Repository {
             key_url: "https://download.docker.com/linux/ubuntu/gpg".into(),
             repo_line: "deb [arch=$(dpkg --print-architecture) signed-by=/usr/share/keyrings/docker-archive-keyring.gpg] https://download.docker.com/linux/ubuntu $(lsb_release -cs) stable".into(),
             keyring_path: "/usr/share/keyrings/docker-archive-keyring.gpg".into(),
+            format: RepositoryFormat::OneLine,
+            key_is_armored: true,
         }
     }
 
@@ -29,17 +71,112 @@ impl Repository {
             key_url: "https://www.postgresql.org/media/keys/ACCC4CF8.asc".into(),
             repo_line: "deb [signed-by=/usr/share/keyrings/postgresql-archive-keyring.gpg] https://apt.postgresql.org/pub/repos/apt $(lsb_release -cs)-pgdg main".into(),
             keyring_path: "/usr/share/keyrings/postgresql-archive-keyring.gpg".into(),
+            format: RepositoryFormat::OneLine,
+            key_is_armored: true,
+        }
+    }
+
+    /// Repository written as a deb822 `.sources` file with `Signed-By`
+    pub fn deb822(
+        key_url: impl Into<String>,
+        uris: impl Into<String>,
+        suites: impl Into<String>,
+        components: impl Into<String>,
+        keyring_path: impl Into<String>,
+    ) -> Self {
+        let (uris, suites, components) = (uris.into(), suites.into(), components.into());
+        let keyring_path = keyring_path.into();
+        Self {
+            key_url: key_url.into(),
+            repo_line: format!("deb [signed-by={keyring_path}] {uris} {suites} {components}"),
+            keyring_path,
+            format: RepositoryFormat::Deb822 {
+                uris,
+                suites,
+                components,
+            },
+            key_is_armored: true,
+        }
+    }
+
+    /// The same repository served from `mirror` (see [`mirror_urls`])
+    #[must_use]
+    pub fn mirrored(&self, mirror: &str) -> Self {
+        let format = match &self.format {
+            RepositoryFormat::OneLine => RepositoryFormat::OneLine,
+            RepositoryFormat::Deb822 {
+                uris,
+                suites,
+                components,
+            } => RepositoryFormat::Deb822 {
+                uris: mirror_urls(uris, mirror),
+                suites: suites.clone(),
+                components: components.clone(),
+            },
+        };
+        Self {
+            key_url: mirror_urls(&self.key_url, mirror),
+            repo_line: mirror_urls(&self.repo_line, mirror),
+            keyring_path: self.keyring_path.clone(),
+            format,
+            key_is_armored: self.key_is_armored,
+        }
+    }
+
+    /// Mark the key as armored (dearmored on download) or a binary keyring
+    pub fn key_is_armored(mut self, armored: bool) -> Self {
+        self.key_is_armored = armored;
+        self
+    }
+
+    /// Shell command downloading the key to the keyring path
+    ///
+    /// Binary keyrings are saved as-is; dearmoring them again corrupts them.
+    fn fetch_key(&self) -> String {
+        if self.key_is_armored {
+            format!(
+                "curl -fsSL {} | gpg --dearmor -o {}",
+                self.key_url, self.keyring_path
+            )
+        } else {
+            format!("curl -fsSL -o {} {}", self.keyring_path, self.key_url)
+        }
+    }
+
+    /// Path of the apt source file for `name`
+    fn source_path(&self, name: &str) -> String {
+        match self.format {
+            RepositoryFormat::OneLine => format!("/etc/apt/sources.list.d/{name}.list"),
+            RepositoryFormat::Deb822 { .. } => format!("/etc/apt/sources.list.d/{name}.sources"),
+        }
+    }
+
+    /// Shell command writing the apt source to `path` (`$(...)` is expanded)
+    fn write_source(&self, path: &str) -> String {
+        match &self.format {
+            RepositoryFormat::OneLine => format!("echo \"{}\" > {path}", self.repo_line),
+            RepositoryFormat::Deb822 {
+                uris,
+                suites,
+                components,
+            } => format!(
+                "printf '%s\\n' \"Types: deb\" \"URIs: {uris}\" \"Suites: {suites}\" \
+                 \"Components: {components}\" \"Signed-By: {}\" > {path}",
+                self.keyring_path
+            ),
         }
     }
 }
 
 /// Install an apt package, optionally from an external repository
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InstallPackage {
     /// Package name
     pub name: String,
     /// External repository to add (if any)
     pub repository: Option<Repository>,
+    /// Keyring path of a repository an earlier step adds
+    pub requires_repository: Option<String>,
     /// Description override
     description: String,
 }
@@ -52,6 +189,7 @@ impl InstallPackage {
         Self {
             name,
             repository: None,
+            requires_repository: None,
             description,
         }
     }
@@ -61,6 +199,90 @@ impl InstallPackage {
         self.repository = Some(repo);
         self
     }
+
+    /// Install from a repository added by an earlier step
+    pub fn from_repository(mut self, repo: &Repository) -> Self {
+        self.requires_repository = Some(repo.keyring_path.clone());
+        self
+    }
+
+    /// Hand the repository setup over to an earlier step
+    ///
+    /// Returns the repository, if any; the package then only requires it.
+    pub(crate) fn take_repository(&mut self) -> Option<Repository> {
+        let repo = self.repository.take()?;
+        self.requires_repository = Some(repo.keyring_path.clone());
+        Some(repo)
+    }
+}
+
+/// Point every `http(s)://host/path` URL in `text` at `mirror`
+///
+/// The mirror serves each upstream under its host name, so
+/// `https://download.docker.com/linux/ubuntu` becomes
+/// `{mirror}/download.docker.com/linux/ubuntu`.
+pub fn mirror_urls(text: &str, mirror: &str) -> String {
+    let mirror = mirror.trim_end_matches('/');
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("http") {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let scheme = ["https://", "http://"]
+            .into_iter()
+            .find(|scheme| rest.starts_with(scheme));
+        match scheme {
+            // Already mirrored (e.g. applied twice)
+            Some(_) if rest.starts_with(&format!("{mirror}/")) => {
+                out.push_str(mirror);
+                rest = &rest[mirror.len()..];
+            }
+            Some(scheme) => {
+                out.push_str(mirror);
+                out.push('/');
+                rest = &rest[scheme.len()..];
+            }
+            None => {
+                out.push_str("http");
+                rest = &rest["http".len()..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Idempotent commands fetching `repo`'s key and writing its source file for `name`
+///
+/// The source file write is followed by `then_update` (e.g. `apt-get update`).
+fn repository_setup(repo: &Repository, name: &str, then_update: &str) -> [String; 2] {
+    let source = repo.source_path(name);
+    [
+        format!(
+            "if [ ! -f {} ]; then \
+                {}; \
+            fi",
+            repo.keyring_path,
+            repo.fetch_key()
+        ),
+        // Key on the source file itself: the repo line contains `$(...)` that is
+        // expanded when written, so grepping for the template never matches
+        format!(
+            "if [ ! -f {source} ]; then \
+                {};{then_update} \
+            fi",
+            repo.write_source(&source)
+        ),
+    ]
+}
+
+/// `bootcmd` entries adding `repo` for cloud-init's packages module
+fn repository_bootcmd(repo: &Repository, name: &str) -> [String; 2] {
+    let source = repo.source_path(name);
+    [
+        format!("[ -f {} ] || {}", repo.keyring_path, repo.fetch_key()),
+        format!("[ -f {source} ] || {}", repo.write_source(&source)),
+    ]
 }
 
 impl Step for InstallPackage {
@@ -68,20 +290,23 @@ impl Step for InstallPackage {
         &self.description
     }
 
+    fn kind(&self) -> &'static str {
+        "InstallPackage"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::InstallPackage(self.clone()))
+    }
+
     fn to_cloud_init(&self) -> CloudInitFragment {
         let mut fragment = CloudInitFragment::default();
 
-        // Add repository setup commands if needed
+        // Repository setup goes in bootcmd so the packages module (which runs
+        // apt-get update itself) can already see it; bootcmd runs every boot
         if let Some(repo) = &self.repository {
-            fragment.runcmd.push(format!(
-                "curl -fsSL {} | gpg --dearmor -o {}",
-                repo.key_url, repo.keyring_path
-            ));
-            fragment.runcmd.push(format!(
-                "echo '{}' > /etc/apt/sources.list.d/{}.list",
-                repo.repo_line, self.name
-            ));
-            fragment.runcmd.push("apt-get update".into());
+            fragment
+                .bootcmd
+                .extend(repository_bootcmd(repo, &self.name));
         }
 
         fragment.packages.push(self.name.clone());
@@ -93,19 +318,7 @@ impl Step for InstallPackage {
 
         // Add repo if specified
         if let Some(repo) = &self.repository {
-            cmds.push(format!(
-                "if [ ! -f {} ]; then \
-                    curl -fsSL {} | gpg --dearmor -o {}; \
-                fi",
-                repo.keyring_path, repo.key_url, repo.keyring_path
-            ));
-            cmds.push(format!(
-                "if ! grep -q '{}' /etc/apt/sources.list.d/*.list 2>/dev/null; then \
-                    echo \"{}\" > /etc/apt/sources.list.d/{}.list; \
-                    apt-get update; \
-                fi",
-                repo.repo_line, repo.repo_line, self.name
-            ));
+            cmds.extend(repository_setup(repo, &self.name, " apt-get update;"));
         }
 
         // Idempotent install + track (wait for apt lock first)
@@ -129,17 +342,537 @@ impl Step for InstallPackage {
             self.name
         ))
     }
+
+    fn parallel_safe(&self) -> bool {
+        true
+    }
+
+    fn provides(&self) -> Vec<Dependency> {
+        let mut provides = vec![Dependency::Package(self.name.clone())];
+        if let Some(repo) = &self.repository {
+            provides.push(Dependency::Repository(repo.keyring_path.clone()));
+        }
+        provides
+    }
+
+    fn requires(&self) -> Vec<Dependency> {
+        self.requires_repository
+            .iter()
+            .map(|keyring| Dependency::Repository(keyring.clone()))
+            .collect()
+    }
+}
+
+/// Add several external apt repositories, then run `apt-get update` once
+///
+/// Built by [`Manifest::coalesce_apt_updates`](crate::Manifest::coalesce_apt_updates)
+/// from the repositories of [`InstallPackage`] steps. Each source keeps the
+/// file name its package step would have used.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddAptRepositories {
+    /// Source file name (the package that needed it) and repository
+    pub repositories: Vec<(String, Repository)>,
+    /// Description
+    description: String,
+}
+
+impl AddAptRepositories {
+    /// Create a step adding `repositories`
+    pub fn new(repositories: Vec<(String, Repository)>) -> Self {
+        let description = format!("Add {} apt repositories", repositories.len());
+        Self {
+            repositories,
+            description,
+        }
+    }
+}
+
+impl Step for AddAptRepositories {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "AddAptRepositories"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::AddAptRepositories(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        CloudInitFragment {
+            bootcmd: self
+                .repositories
+                .iter()
+                .flat_map(|(name, repo)| repository_bootcmd(repo, name))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        // The step only runs when a key or source is missing, so one of them is new
+        let mut cmds: Vec<String> = self
+            .repositories
+            .iter()
+            .flat_map(|(name, repo)| repository_setup(repo, name, ""))
+            .collect();
+        cmds.push(
+            "while fuser /var/lib/dpkg/lock-frontend >/dev/null 2>&1; do sleep 3; done".into(),
+        );
+        cmds.push("apt-get update".into());
+        cmds
+    }
+
+    fn check_command(&self) -> Option<String> {
+        Some(
+            self.repositories
+                .iter()
+                .flat_map(|(name, repo)| {
+                    [
+                        format!("[ -f {} ]", repo.keyring_path),
+                        format!("[ -f {} ]", repo.source_path(name)),
+                    ]
+                })
+                .collect::<Vec<_>>()
+                .join(" && "),
+        )
+    }
+
+    fn provides(&self) -> Vec<Dependency> {
+        self.repositories
+            .iter()
+            .map(|(_, repo)| Dependency::Repository(repo.keyring_path.clone()))
+            .collect()
+    }
+}
+
+/// Install several apt packages in a single `apt-get` transaction
+///
+/// Runs the repository setup commands, one `apt-get update`, then one
+/// `apt-get install` for every package. Built by
+/// [`Manifest::packages_only`](crate::Manifest::packages_only).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstallPackages {
+    /// Package names
+    pub names: Vec<String>,
+    /// Idempotent commands adding external repositories (run before the update)
+    pub repository_setup: Vec<String>,
+    /// Description
+    description: String,
+}
+
+impl InstallPackages {
+    /// Create a new batched package installation step
+    pub fn new(names: Vec<String>) -> Self {
+        let description = format!("Install {} packages", names.len());
+        Self {
+            names,
+            repository_setup: vec![],
+            description,
+        }
+    }
+
+    /// Add repository setup commands to run before `apt-get update`
+    pub fn with_repository_setup(mut self, commands: Vec<String>) -> Self {
+        self.repository_setup = commands;
+        self
+    }
+}
+
+impl Step for InstallPackages {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "InstallPackages"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::InstallPackages(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        CloudInitFragment {
+            bootcmd: self.repository_setup.clone(),
+            packages: self.names.clone(),
+            ..Default::default()
+        }
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        let names = self.names.join(" ");
+        let mut cmds = self.repository_setup.clone();
+        cmds.push(
+            "while fuser /var/lib/dpkg/lock-frontend >/dev/null 2>&1; do sleep 3; done".into(),
+        );
+        cmds.push("apt-get update".into());
+        cmds.push(format!(
+            "apt-get install -y {names} && for pkg in {names}; do track_pkg \"$pkg\"; done"
+        ));
+        cmds
+    }
+
+    fn check_command(&self) -> Option<String> {
+        Some(format!(
+            "for pkg in {}; do \
+                 dpkg-query -W -f='${{Status}}' \"$pkg\" 2>/dev/null | grep -q 'ok installed' || exit 1; \
+             done",
+            self.names.join(" ")
+        ))
+    }
+
+    fn provides(&self) -> Vec<Dependency> {
+        self.names
+            .iter()
+            .map(|name| Dependency::Package(name.clone()))
+            .collect()
+    }
+}
+
+/// Remove (or purge) an apt package if it is installed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemovePackage {
+    /// Package name
+    pub name: String,
+    /// Purge configuration files as well
+    pub purge: bool,
+    /// Description
+    description: String,
+}
+
+impl RemovePackage {
+    /// Create a new package removal step
+    pub fn new(name: impl Into<String>) -> Self {
+        let name = name.into();
+        let description = format!("Remove {name}");
+        Self {
+            name,
+            purge: false,
+            description,
+        }
+    }
+
+    /// Purge configuration files instead of a plain remove
+    pub fn purge(mut self, purge: bool) -> Self {
+        self.purge = purge;
+        self
+    }
+}
+
+impl Step for RemovePackage {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "RemovePackage"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::RemovePackage(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        CloudInitFragment {
+            runcmd: self.to_bash(),
+            ..Default::default()
+        }
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        let action = if self.purge { "purge" } else { "remove" };
+        // `if` rather than `&&` so an absent package exits 0 under `set -e`
+        vec![format!(
+            "if dpkg -s {name} >/dev/null 2>&1; then \
+                 while fuser /var/lib/dpkg/lock-frontend >/dev/null 2>&1; do sleep 3; done; \
+                 apt-get {action} -y {name}; \
+             fi",
+            name = self.name
+        )]
+    }
+
+    fn check_command(&self) -> Option<String> {
+        Some(format!("! dpkg -s {} >/dev/null 2>&1", self.name))
+    }
+}
+
+/// Locks held by apt/dpkg while they run
+const APT_LOCKS: &str = "/var/lib/dpkg/lock-frontend /var/lib/dpkg/lock /var/lib/apt/lists/lock";
+
+/// Wait until no process holds the apt/dpkg locks
+///
+/// On cloud images unattended-upgrades often holds the dpkg lock at first
+/// boot, failing the first `apt-get install` with "Could not get lock". Fails
+/// (without killing anything) once `timeout_secs` have passed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WaitForAptLock {
+    /// Give up after this many seconds
+    pub timeout_secs: u32,
+    /// Description
+    description: String,
+}
+
+impl Default for WaitForAptLock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WaitForAptLock {
+    /// Create a new lock wait step (10 minute timeout)
+    pub fn new() -> Self {
+        Self {
+            timeout_secs: 600,
+            description: "Wait for apt locks".into(),
+        }
+    }
+
+    /// Set the timeout
+    pub fn timeout_secs(mut self, secs: u32) -> Self {
+        self.timeout_secs = secs;
+        self
+    }
+}
+
+impl Step for WaitForAptLock {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "WaitForAptLock"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::WaitForAptLock(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        CloudInitFragment {
+            runcmd: self.to_bash(),
+            ..Default::default()
+        }
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        // Subshell so giving up fails the step rather than exiting the script
+        vec![format!(
+            "(\n\
+             waited=0\n\
+             while fuser {APT_LOCKS} >/dev/null 2>&1; do\n\
+             \x20   if [ \"$waited\" -ge {timeout} ]; then\n\
+             \x20       echo \"apt locks still held after {timeout}s\" >&2\n\
+             \x20       exit 1\n\
+             \x20   fi\n\
+             \x20   sleep 5\n\
+             \x20   waited=$((waited + 5))\n\
+             done\n\
+             )",
+            timeout = self.timeout_secs
+        )]
+    }
+
+    fn check_command(&self) -> Option<String> {
+        Some(format!("! fuser {APT_LOCKS} >/dev/null 2>&1"))
+    }
+}
+
+/// Hold an apt package at its installed version (`apt-mark hold`)
+///
+/// Keeps `apt upgrade` (and unattended-upgrades) from moving a pinned package.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnsureAptHold {
+    /// Package name
+    pub name: String,
+    /// Description
+    description: String,
+}
+
+impl EnsureAptHold {
+    /// Create a new package hold step
+    pub fn new(name: impl Into<String>) -> Self {
+        let name = name.into();
+        let description = format!("Hold {name}");
+        Self { name, description }
+    }
+}
+
+impl Step for EnsureAptHold {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "EnsureAptHold"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::EnsureAptHold(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        // The renderer guards runcmd with check_command()
+        CloudInitFragment {
+            runcmd: vec![format!("apt-mark hold {}", self.name)],
+            ..Default::default()
+        }
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        vec![format!(
+            "apt-mark showhold | grep -qx {name} || apt-mark hold {name}",
+            name = self.name
+        )]
+    }
+
+    fn check_command(&self) -> Option<String> {
+        Some(format!("apt-mark showhold | grep -qx {}", self.name))
+    }
+
+    fn requires(&self) -> Vec<Dependency> {
+        vec![Dependency::Package(self.name.clone())]
+    }
+}
+
+/// Directory apt reads pinning preferences from
+const APT_PREFERENCES_DIR: &str = "/etc/apt/preferences.d";
+
+/// Pin packages to an origin with an `/etc/apt/preferences.d` stanza
+///
+/// E.g. prefer the pgdg repository over the distro Postgres packages:
+/// `EnsureAptPreference::new("postgresql*", "origin apt.postgresql.org", 700)`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnsureAptPreference {
+    /// Package name or pattern (`Package:`)
+    pub package: String,
+    /// Pin (`Pin:`, e.g. `origin apt.postgresql.org` or `release n=noble`)
+    pub pin: String,
+    /// Pin priority (`Pin-Priority:`)
+    pub priority: i32,
+    /// File name under `/etc/apt/preferences.d`
+    pub name: String,
+    /// Description
+    description: String,
+}
+
+impl EnsureAptPreference {
+    /// Create a preference named after `package`
+    pub fn new(package: impl Into<String>, pin: impl Into<String>, priority: i32) -> Self {
+        let package = package.into();
+        // apt ignores files with anything but alphanumerics, `-`, `_` and `.`
+        let name: String = package
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+        let name = match name.trim_matches('-') {
+            "" => "tengu".to_string(),
+            name => name.to_string(),
+        };
+        let description = format!("Pin {package}");
+        Self {
+            package,
+            pin: pin.into(),
+            priority,
+            name,
+            description,
+        }
+    }
+
+    /// Write the preference to `/etc/apt/preferences.d/{name}`
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = name.into();
+        self
+    }
+
+    /// Path of the preferences file
+    pub fn path(&self) -> String {
+        format!("{APT_PREFERENCES_DIR}/{}", self.name)
+    }
+
+    /// Preferences file stanza
+    pub fn content(&self) -> String {
+        format!(
+            "Package: {}\nPin: {}\nPin-Priority: {}\n",
+            self.package, self.pin, self.priority
+        )
+    }
+
+    /// The preferences file write
+    fn file(&self) -> WriteFile {
+        WriteFile::new(self.path(), self.content())
+            .with_permissions("0644")
+            .with_owner("root:root")
+    }
+}
+
+impl Step for EnsureAptPreference {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "EnsureAptPreference"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::EnsureAptPreference(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        // Not deferred: write_files runs before packages are installed
+        self.file().to_cloud_init()
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        self.file().to_bash()
+    }
+
+    fn check_command(&self) -> Option<String> {
+        self.file().check_command()
+    }
+}
+
+/// Detached GPG signature for an [`InstallDebFromUrl`] package
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DebSignature {
+    /// Signing key (armored or binary)
+    pub key_url: String,
+    /// Signature URL template (can contain `{arch}` placeholder)
+    pub sig_url: String,
 }
 
 /// Install a .deb package from a URL
-#[derive(Debug, Clone)]
+///
+/// With [`InstallDebFromUrl::with_sha256_url`] or
+/// [`InstallDebFromUrl::with_signature`] the download is verified first and
+/// left uninstalled if verification fails.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct InstallDebFromUrl {
     /// Package name (for dpkg -s check)
     pub name: String,
-    /// URL template (can contain `{arch}` placeholder)
+    /// URL template (can contain `{arch}` placeholder; `{{arch}}` also works)
     pub url_template: String,
     /// Custom check command (optional, defaults to dpkg -s)
     pub custom_check: Option<String>,
+    /// Checksum file URL template (`sha256sum` output, can contain `{arch}`)
+    #[serde(default)]
+    pub sha256_url: Option<String>,
+    /// Detached GPG signature to verify against
+    #[serde(default)]
+    pub signature: Option<DebSignature>,
+    /// dpkg architecture to asset name (e.g. `amd64` -> `x86_64`); unmapped
+    /// architectures pass through
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub arch_map: BTreeMap<String, String>,
     /// Description
     description: String,
 }
@@ -153,6 +886,9 @@ impl InstallDebFromUrl {
             name,
             url_template: url_template.into(),
             custom_check: None,
+            sha256_url: None,
+            signature: None,
+            arch_map: BTreeMap::new(),
             description,
         }
     }
@@ -163,6 +899,54 @@ impl InstallDebFromUrl {
         self
     }
 
+    /// Verify the download against a checksum file (e.g. `{url}.sha256`)
+    pub fn with_sha256_url(mut self, url_template: impl Into<String>) -> Self {
+        self.sha256_url = Some(url_template.into());
+        self
+    }
+
+    /// Verify the download against a detached signature made by the key at `key_url`
+    ///
+    /// The key is imported into a throwaway keyring, not root's.
+    pub fn with_signature(
+        mut self,
+        key_url: impl Into<String>,
+        sig_url_template: impl Into<String>,
+    ) -> Self {
+        self.signature = Some(DebSignature {
+            key_url: key_url.into(),
+            sig_url: sig_url_template.into(),
+        });
+        self
+    }
+
+    /// Translate `dpkg --print-architecture` names before substituting `{arch}`
+    ///
+    /// E.g. `[("amd64", "x86_64"), ("arm64", "aarch64")]` for assets named
+    /// after `uname -m`.
+    pub fn with_arch_map<K: Into<String>, V: Into<String>>(
+        mut self,
+        map: impl IntoIterator<Item = (K, V)>,
+    ) -> Self {
+        self.arch_map = map
+            .into_iter()
+            .map(|(dpkg, asset)| (dpkg.into(), asset.into()))
+            .collect();
+        self
+    }
+
+    /// The same package downloaded from `mirror` (see [`mirror_urls`])
+    #[must_use]
+    pub fn mirrored(mut self, mirror: &str) -> Self {
+        self.url_template = mirror_urls(&self.url_template, mirror);
+        self.sha256_url = self.sha256_url.map(|url| mirror_urls(&url, mirror));
+        if let Some(signature) = &mut self.signature {
+            signature.key_url = mirror_urls(&signature.key_url, mirror);
+            signature.sig_url = mirror_urls(&signature.sig_url, mirror);
+        }
+        self
+    }
+
     /// Ollama from the official installer
     pub fn ollama() -> Self {
         // Ollama provides a .deb in their releases
@@ -173,13 +957,88 @@ impl InstallDebFromUrl {
         .with_check("command -v ollama >/dev/null 2>&1")
     }
 
-    /// Tengu Caddy (custom Caddy build with Cloudflare DNS)
-    pub fn tengu_caddy() -> Self {
+    /// Tengu Caddy (custom Caddy build with Cloudflare DNS) from the
+    /// releases at `releases_url` (see [`TenguConfig::releases_url`])
+    ///
+    /// [`TenguConfig::releases_url`]: crate::TenguConfig::releases_url
+    pub fn tengu_caddy(releases_url: &str) -> Self {
         Self::new(
             "tengu-caddy",
-            "https://github.com/tengu-apps/tengu-caddy/releases/latest/download/tengu-caddy_2.11.2-3_{arch}.deb",
+            format!("{releases_url}/latest/download/tengu-caddy_2.11.2-3_{{arch}}.deb"),
         )
     }
+
+    /// Download the .deb, run `install` if it verifies, then remove it
+    fn script(&self, install: &str) -> String {
+        let name = &self.name;
+        let deb = format!("/tmp/{name}.deb");
+        let mut script = String::from("ARCH=$(dpkg --print-architecture)\n");
+        if !self.arch_map.is_empty() {
+            script.push_str("case \"$ARCH\" in\n");
+            for (dpkg, asset) in &self.arch_map {
+                script.push_str(&format!("    {dpkg}) ARCH={asset} ;;\n"));
+            }
+            script.push_str("esac\n");
+        }
+        script.push_str(&format!(
+            "URL={}\nwget -q \"$URL\" -O {deb}\n",
+            url_for_arch(&self.url_template)
+        ));
+
+        let mut checks = Vec::new();
+        if let Some(sha256_url) = &self.sha256_url {
+            script.push_str(&format!("SHA256_URL={}\n", url_for_arch(sha256_url)));
+            checks.push(format!(
+                "[ \"$(wget -qO- \"$SHA256_URL\" | awk '{{print $1}}')\" = \"$(sha256sum {deb} | cut -d' ' -f1)\" ]"
+            ));
+        }
+        if let Some(signature) = &self.signature {
+            script.push_str(&format!(
+                "SIG_URL={sig_url}\n\
+                 wget -q \"$SIG_URL\" -O {deb}.sig\n\
+                 GNUPG_DIR=$(mktemp -d)\n\
+                 wget -qO- '{key_url}' | gpg --homedir \"$GNUPG_DIR\" --batch --import 2>/dev/null\n",
+                sig_url = url_for_arch(&signature.sig_url),
+                key_url = signature.key_url
+            ));
+            checks.push(format!(
+                "gpg --homedir \"$GNUPG_DIR\" --batch --verify {deb}.sig {deb} 2>/dev/null"
+            ));
+        }
+
+        if checks.is_empty() {
+            script.push_str(install);
+            script.push('\n');
+        } else {
+            let indented: Vec<String> = install.lines().map(|line| format!("    {line}")).collect();
+            script.push_str(&format!(
+                "tengu_verified=1\n\
+                 if {}; then\n{}\nelse\n    \
+                 echo 'ERROR: {name}.deb failed verification, not installing' >&2\n    \
+                 tengu_verified=0\n\
+                 fi\n",
+                checks.join(" && "),
+                indented.join("\n")
+            ));
+        }
+        if self.signature.is_some() {
+            script.push_str(&format!("rm -rf \"$GNUPG_DIR\" {deb}.sig\n"));
+        }
+        script.push_str(&format!("rm -f {deb}"));
+        // Clean up first, then fail the step on a rejected package
+        if !checks.is_empty() {
+            script.push_str("\n[ \"$tengu_verified\" = 1 ]");
+        }
+        script
+    }
+}
+
+/// Shell expression expanding `{arch}` (or `{{arch}}`) in `template` to `$ARCH`
+fn url_for_arch(template: &str) -> String {
+    format!(
+        "$(echo '{}' | sed \"s/{{arch}}/$ARCH/g\")",
+        template.replace("{{arch}}", "{arch}")
+    )
 }
 
 impl Step for InstallDebFromUrl {
@@ -187,47 +1046,34 @@ impl Step for InstallDebFromUrl {
         &self.description
     }
 
-    fn to_cloud_init(&self) -> CloudInitFragment {
-        let mut fragment = CloudInitFragment::default();
+    fn kind(&self) -> &'static str {
+        "InstallDebFromUrl"
+    }
 
-        // Cloud-init doesn't have built-in idempotency for runcmd,
-        // so we include the check inline
-        let check = self
-            .custom_check
-            .clone()
-            .unwrap_or_else(|| format!("dpkg -s {} >/dev/null 2>&1", self.name));
-
-        let cmd = format!(
-            r#"if ! {check}; then
-    ARCH=$(dpkg --print-architecture)
-    URL=$(echo '{url}' | sed "s/{{arch}}/$ARCH/g")
-    wget -q "$URL" -O /tmp/{name}.deb
-    dpkg -i --force-confold /tmp/{name}.deb || apt-get install -f -y
-    rm -f /tmp/{name}.deb
-fi"#,
-            check = check,
-            url = self.url_template,
-            name = self.name
-        );
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::InstallDebFromUrl(self.clone()))
+    }
 
-        fragment.runcmd.push(cmd);
-        fragment
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        // The renderer guards runcmd with check_command()
+        CloudInitFragment {
+            runcmd: vec![self.script(&format!(
+                "dpkg -i --force-confold /tmp/{name}.deb || apt-get install -f -y",
+                name = self.name
+            ))],
+            ..Default::default()
+        }
     }
 
     fn to_bash(&self) -> Vec<String> {
         // The idempotency check will be wrapped by the renderer using check_command()
         // So to_bash() just returns the actual installation commands
-        vec![format!(
-            r#"ARCH=$(dpkg --print-architecture)
-URL=$(echo '{url}' | sed "s/{{arch}}/$ARCH/g")
-wget -q "$URL" -O /tmp/{name}.deb
-while fuser /var/lib/dpkg/lock-frontend >/dev/null 2>&1; do sleep 3; done
+        vec![self.script(&format!(
+            r"while fuser /var/lib/dpkg/lock-frontend >/dev/null 2>&1; do sleep 3; done
 dpkg -i --force-confold /tmp/{name}.deb || {{ while fuser /var/lib/dpkg/lock-frontend >/dev/null 2>&1; do sleep 3; done; apt-get install -f -y; }}
-rm -f /tmp/{name}.deb
-track_pkg {name}"#,
-            url = self.url_template,
+track_pkg {name}",
             name = self.name
-        )]
+        ))]
     }
 
     fn check_command(&self) -> Option<String> {
@@ -238,4 +1084,12 @@ track_pkg {name}"#,
             ))
         })
     }
+
+    fn parallel_safe(&self) -> bool {
+        true
+    }
+
+    fn provides(&self) -> Vec<Dependency> {
+        vec![Dependency::Package(self.name.clone())]
+    }
 }
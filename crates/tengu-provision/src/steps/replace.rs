@@ -0,0 +1,112 @@
+//! In-place text substitution steps
+
+use serde::{Deserialize, Serialize};
+
+use super::{CloudInitFragment, KnownStep, Step, shell_quote};
+
+/// Substitute a regex in an existing file with `sed -i -E`
+///
+/// The replacement may use `\1`-style capture groups and `&`. Both sides use
+/// `|` as the sed delimiter; any `|` in them is escaped automatically (in the
+/// regex it still means alternation).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplaceInFile {
+    /// File to edit
+    pub path: String,
+    /// Extended regex to match
+    pub regex: String,
+    /// Replacement text
+    pub replacement: String,
+    /// Only replace in the first matching line of the file
+    pub first_only: bool,
+    /// Description
+    description: String,
+}
+
+impl ReplaceInFile {
+    /// Create a new substitution step
+    pub fn new(
+        path: impl Into<String>,
+        regex: impl Into<String>,
+        replacement: impl Into<String>,
+    ) -> Self {
+        let path = path.into();
+        let description = format!("Update {path}");
+        Self {
+            path,
+            regex: regex.into(),
+            replacement: replacement.into(),
+            first_only: false,
+            description,
+        }
+    }
+
+    /// Only replace in the first matching line instead of every line
+    pub fn first_only(mut self) -> Self {
+        self.first_only = true;
+        self
+    }
+
+    /// Sed script with the delimiter escaped on both sides
+    fn sed_script(&self) -> String {
+        let regex = self.regex.replace('|', "\\|");
+        let replacement = self.replacement.replace('|', "\\|");
+        let substitute = format!("s|{regex}|{replacement}|");
+        if self.first_only {
+            // GNU sed: the 0, address lets the range end on the first line
+            format!("0,\\|{regex}|{substitute}")
+        } else {
+            substitute
+        }
+    }
+}
+
+impl Step for ReplaceInFile {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "ReplaceInFile"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::ReplaceInFile(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        CloudInitFragment {
+            runcmd: self.to_bash(),
+            ..Default::default()
+        }
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        vec![format!(
+            "sed -i -E {} {}",
+            shell_quote(&self.sed_script()),
+            shell_quote(&self.path)
+        )]
+    }
+
+    fn check_command(&self) -> Option<String> {
+        let path = shell_quote(&self.path);
+        // Done means substituting again would change nothing, which also holds
+        // for a replacement the regex itself matches
+        let unchanged = format!(
+            "sed -E {} {path} | cmp -s - {path}",
+            shell_quote(&self.sed_script())
+        );
+        if self.replacement.contains(['\\', '&']) {
+            return Some(unchanged);
+        }
+
+        let present = format!("grep -qF -- {} {path}", shell_quote(&self.replacement));
+        if self.first_only {
+            // Later matches are left alone, so the replacement being there is enough
+            Some(present)
+        } else {
+            Some(format!("{present} && {unchanged}"))
+        }
+    }
+}
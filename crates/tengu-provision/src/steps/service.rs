@@ -1,9 +1,11 @@
 //! Systemd service management steps
 
-use super::{CloudInitFragment, Step};
+use serde::{Deserialize, Serialize};
+
+use super::{CloudInitFragment, Dependency, KnownStep, Step, WriteFile};
 
 /// Ensure a systemd service is enabled and/or started
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnsureService {
     /// Service name
     pub name: String,
@@ -16,6 +18,8 @@ pub struct EnsureService {
     readiness_check: Option<String>,
     /// Max seconds to wait for readiness (default: 30)
     readiness_timeout: u32,
+    /// Package that ships the unit, installed by an earlier step
+    package: Option<String>,
     /// Description
     description: String,
 }
@@ -31,6 +35,7 @@ impl EnsureService {
             started: true,
             readiness_check: None,
             readiness_timeout: 30,
+            package: None,
             description,
         }
     }
@@ -60,6 +65,12 @@ impl EnsureService {
         self.readiness_timeout = seconds;
         self
     }
+
+    /// Declare the package that ships this service's unit
+    pub fn from_package(mut self, package: impl Into<String>) -> Self {
+        self.package = Some(package.into());
+        self
+    }
 }
 
 impl Step for EnsureService {
@@ -67,6 +78,14 @@ impl Step for EnsureService {
         &self.description
     }
 
+    fn kind(&self) -> &'static str {
+        "EnsureService"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::EnsureService(self.clone()))
+    }
+
     fn to_cloud_init(&self) -> CloudInitFragment {
         CloudInitFragment {
             runcmd: self.to_bash(),
@@ -135,4 +154,116 @@ impl Step for EnsureService {
             None
         }
     }
+
+    fn provides(&self) -> Vec<Dependency> {
+        if self.started {
+            vec![Dependency::Service(self.name.clone())]
+        } else {
+            vec![]
+        }
+    }
+
+    fn requires(&self) -> Vec<Dependency> {
+        self.package
+            .iter()
+            .map(|package| Dependency::Package(package.clone()))
+            .collect()
+    }
+}
+
+/// Cap a service's resources with a systemd drop-in
+///
+/// Writes `/etc/systemd/system/{name}.service.d/limits.conf`, reloads
+/// systemd and restarts the service if it is running. The step only runs
+/// when the drop-in differs, so an unchanged service is left alone.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnsureServiceLimits {
+    /// Service name
+    pub name: String,
+    /// `MemoryMax=` (e.g. `8G`)
+    pub memory_max: Option<String>,
+    /// `CPUQuota=` (e.g. `200%` for two cores)
+    pub cpu_quota: Option<String>,
+    /// Description
+    description: String,
+}
+
+impl EnsureServiceLimits {
+    /// Create a limits step for `name` (no limits until set)
+    pub fn new(name: impl Into<String>) -> Self {
+        let name = name.into();
+        let description = format!("Limit resources of {name}");
+        Self {
+            name,
+            memory_max: None,
+            cpu_quota: None,
+            description,
+        }
+    }
+
+    /// Set `MemoryMax=`
+    pub fn memory_max(mut self, limit: impl Into<String>) -> Self {
+        self.memory_max = Some(limit.into());
+        self
+    }
+
+    /// Set `CPUQuota=`
+    pub fn cpu_quota(mut self, quota: impl Into<String>) -> Self {
+        self.cpu_quota = Some(quota.into());
+        self
+    }
+
+    /// Path of the drop-in
+    pub fn path(&self) -> String {
+        format!("/etc/systemd/system/{}.service.d/limits.conf", self.name)
+    }
+
+    /// Drop-in contents
+    pub fn content(&self) -> String {
+        let mut content = String::from("[Service]\n");
+        if let Some(limit) = &self.memory_max {
+            content.push_str(&format!("MemoryMax={limit}\n"));
+        }
+        if let Some(quota) = &self.cpu_quota {
+            content.push_str(&format!("CPUQuota={quota}\n"));
+        }
+        content
+    }
+
+    /// The drop-in write
+    fn file(&self) -> WriteFile {
+        WriteFile::new(self.path(), self.content())
+            .with_permissions("0644")
+            .with_owner("root:root")
+    }
+}
+
+impl Step for EnsureServiceLimits {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "EnsureServiceLimits"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::EnsureServiceLimits(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        // Written before runcmd installs the service, so it starts with the limits
+        self.file().to_cloud_init()
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        let mut cmds = self.file().to_bash();
+        cmds.push("systemctl daemon-reload".into());
+        cmds.push(format!("systemctl try-restart {}", self.name));
+        cmds
+    }
+
+    fn check_command(&self) -> Option<String> {
+        self.file().check_command()
+    }
 }
@@ -0,0 +1,128 @@
+//! Swap file management steps
+
+use serde::{Deserialize, Serialize};
+
+use super::{CloudInitFragment, KnownStep, Step};
+
+/// Path of the managed swap file
+const SWAPFILE: &str = "/swapfile";
+
+/// Sysctl drop-in holding the swappiness setting
+const SWAPPINESS_CONF: &str = "/etc/sysctl.d/99-tengu-swap.conf";
+
+/// [`EnsureSwap::auto`] adds no swap on hosts with more RAM than this (MiB)
+const AUTO_SWAP_MAX_RAM_MB: u32 = 16 * 1024;
+
+/// Largest swap file [`EnsureSwap::auto`] creates (MiB)
+const AUTO_SWAP_MAX_MB: u32 = 4 * 1024;
+
+/// Ensure a swap file exists, is active, and persists across reboots
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnsureSwap {
+    /// Swap size as accepted by `fallocate -l` (e.g., "2G")
+    pub size: String,
+    /// `vm.swappiness` value applied via a sysctl drop-in
+    pub swappiness: Option<u8>,
+    /// Size from the host's RAM at run time instead of `size`
+    auto: bool,
+    /// Description
+    description: String,
+}
+
+impl EnsureSwap {
+    /// Create a new swap step
+    pub fn new(size: impl Into<String>) -> Self {
+        let size = size.into();
+        let description = format!("Ensure {size} swap at {SWAPFILE}");
+        Self {
+            size,
+            swappiness: None,
+            auto: false,
+            description,
+        }
+    }
+
+    /// Size the swap file from `/proc/meminfo` when the step runs
+    ///
+    /// The RAM isn't known when the manifest is built, so the script works it
+    /// out: as much swap as RAM up to 4G, and none above 16G of RAM.
+    pub fn auto() -> Self {
+        Self {
+            size: "${TENGU_SWAP_MB}M".into(),
+            swappiness: None,
+            auto: true,
+            description: format!("Ensure RAM-sized swap at {SWAPFILE}"),
+        }
+    }
+
+    /// Set `vm.swappiness` (0-100)
+    pub fn swappiness(mut self, swappiness: u8) -> Self {
+        self.swappiness = Some(swappiness);
+        self
+    }
+}
+
+impl Step for EnsureSwap {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "EnsureSwap"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::EnsureSwap(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        // Early in bootcmd so package installs already have swap; bootcmd
+        // has no idempotency wrapper and runs every boot, so guard inline
+        let check = self.check_command().unwrap_or_default();
+        CloudInitFragment {
+            bootcmd: vec![format!(
+                "if ! ({check}); then\n{}\nfi",
+                self.to_bash().join("\n")
+            )],
+            ..Default::default()
+        }
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        let mut cmds = vec![];
+        if self.auto {
+            cmds.push(format!(
+                "TENGU_SWAP_MB=$(awk '/^MemTotal:/ {{ mb = int($2 / 1024); print (mb < {AUTO_SWAP_MAX_MB} ? mb : {AUTO_SWAP_MAX_MB}) }}' /proc/meminfo)"
+            ));
+        }
+        cmds.extend([
+            format!("[ -f {SWAPFILE} ] || fallocate -l {} {SWAPFILE}", self.size),
+            format!("chmod 600 {SWAPFILE}"),
+            format!(
+                "swapon --show | grep -q {SWAPFILE} || {{ mkswap {SWAPFILE} && swapon {SWAPFILE}; }}"
+            ),
+            format!(
+                "grep -q '^{SWAPFILE} ' /etc/fstab || echo '{SWAPFILE} none swap sw 0 0' >> /etc/fstab"
+            ),
+        ]);
+
+        if let Some(swappiness) = self.swappiness {
+            cmds.push(format!(
+                "echo 'vm.swappiness = {swappiness}' > {SWAPPINESS_CONF} && sysctl -p {SWAPPINESS_CONF}"
+            ));
+        }
+
+        cmds
+    }
+
+    fn check_command(&self) -> Option<String> {
+        let mut check = format!("swapon --show | grep -q {SWAPFILE}");
+        if self.auto {
+            // Large hosts are satisfied without swap
+            check.push_str(&format!(
+                " || awk '/^MemTotal:/ {{ exit !($2 / 1024 > {AUTO_SWAP_MAX_RAM_MB}) }}' /proc/meminfo"
+            ));
+        }
+        Some(check)
+    }
+}
@@ -0,0 +1,70 @@
+//! Symbolic link steps
+
+use serde::{Deserialize, Serialize};
+
+use super::{CloudInitFragment, KnownStep, Step, shell_quote};
+
+/// Ensure `link` is a symbolic link pointing at `target`
+///
+/// A link pointing elsewhere is replaced; the target itself need not exist.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnsureSymlink {
+    /// Path of the link
+    pub link: String,
+    /// Path the link points at
+    pub target: String,
+    /// Description
+    description: String,
+}
+
+impl EnsureSymlink {
+    /// Create a new symlink step
+    pub fn new(link: impl Into<String>, target: impl Into<String>) -> Self {
+        let link = link.into();
+        let target = target.into();
+        let description = format!("Link {link} to {target}");
+        Self {
+            link,
+            target,
+            description,
+        }
+    }
+}
+
+impl Step for EnsureSymlink {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "EnsureSymlink"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::EnsureSymlink(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        CloudInitFragment {
+            runcmd: self.to_bash(),
+            ..Default::default()
+        }
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        let link = shell_quote(&self.link);
+        vec![
+            format!("mkdir -p \"$(dirname {link})\""),
+            // -n replaces a link to a directory instead of linking inside it
+            format!("ln -sfn {} {link}", shell_quote(&self.target)),
+        ]
+    }
+
+    fn check_command(&self) -> Option<String> {
+        Some(format!(
+            "[ -L {link} ] && [ \"$(readlink {link})\" = {} ]",
+            shell_quote(&self.target),
+            link = shell_quote(&self.link)
+        ))
+    }
+}
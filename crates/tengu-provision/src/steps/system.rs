@@ -0,0 +1,159 @@
+//! System basics: hostname, timezone, locale
+//!
+//! Cloud-init applies these natively from the manifest's `hostname:`,
+//! `timezone:` and `locale:` keys, so these steps contribute nothing to
+//! cloud-init output and only matter for bash rendering.
+
+use serde::{Deserialize, Serialize};
+
+use super::{CloudInitFragment, KnownStep, Step};
+
+/// Set the system hostname
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetHostname {
+    /// Hostname
+    pub hostname: String,
+    /// Description
+    description: String,
+}
+
+impl SetHostname {
+    /// Create a new hostname step
+    pub fn new(hostname: impl Into<String>) -> Self {
+        let hostname = hostname.into();
+        let description = format!("Set hostname to {hostname}");
+        Self {
+            hostname,
+            description,
+        }
+    }
+}
+
+impl Step for SetHostname {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "SetHostname"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::SetHostname(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        CloudInitFragment::default()
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        vec![format!("hostnamectl set-hostname {}", self.hostname)]
+    }
+
+    fn check_command(&self) -> Option<String> {
+        Some(format!("[ \"$(hostname)\" = \"{}\" ]", self.hostname))
+    }
+}
+
+/// Set the system timezone
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetTimezone {
+    /// Timezone (e.g., "UTC", "Europe/Berlin")
+    pub timezone: String,
+    /// Description
+    description: String,
+}
+
+impl SetTimezone {
+    /// Create a new timezone step
+    pub fn new(timezone: impl Into<String>) -> Self {
+        let timezone = timezone.into();
+        let description = format!("Set timezone to {timezone}");
+        Self {
+            timezone,
+            description,
+        }
+    }
+}
+
+impl Step for SetTimezone {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "SetTimezone"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::SetTimezone(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        CloudInitFragment::default()
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        vec![format!("timedatectl set-timezone {}", self.timezone)]
+    }
+
+    fn check_command(&self) -> Option<String> {
+        Some(format!(
+            "[ \"$(timedatectl show -p Timezone --value 2>/dev/null)\" = \"{}\" ]",
+            self.timezone
+        ))
+    }
+}
+
+/// Generate and set the default system locale
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SetLocale {
+    /// Locale (e.g., "en_US.UTF-8")
+    pub locale: String,
+    /// Description
+    description: String,
+}
+
+impl SetLocale {
+    /// Create a new locale step
+    pub fn new(locale: impl Into<String>) -> Self {
+        let locale = locale.into();
+        let description = format!("Set locale to {locale}");
+        Self {
+            locale,
+            description,
+        }
+    }
+}
+
+impl Step for SetLocale {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "SetLocale"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::SetLocale(self.clone()))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        CloudInitFragment::default()
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        vec![
+            format!("locale-gen {}", self.locale),
+            format!("update-locale LANG={}", self.locale),
+        ]
+    }
+
+    fn check_command(&self) -> Option<String> {
+        Some(format!(
+            "grep -qx 'LANG={}' /etc/default/locale 2>/dev/null",
+            self.locale
+        ))
+    }
+}
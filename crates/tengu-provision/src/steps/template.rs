@@ -0,0 +1,81 @@
+//! Templated file steps (rendered with Tera at manifest-build time)
+
+use tera::{Context, Tera};
+
+use super::WriteFile;
+
+/// Error rendering a [`TemplateFile`]
+#[derive(Debug, thiserror::Error)]
+#[error("failed to render template for {path}: {source:?}")]
+pub struct TemplateError {
+    /// Destination path of the template that failed
+    pub path: String,
+    /// Underlying Tera error
+    #[source]
+    pub source: tera::Error,
+}
+
+/// A file whose content is rendered from a Tera template
+///
+/// Rendering happens when the manifest is built, producing a plain
+/// [`WriteFile`] so the rendered content is what gets hashed and shipped.
+#[derive(Debug, Clone)]
+pub struct TemplateFile {
+    /// File path
+    pub path: String,
+    /// Tera template source
+    pub template: String,
+    /// Template context (a JSON object)
+    pub context: serde_json::Value,
+    /// File permissions (e.g., "0644")
+    pub permissions: Option<String>,
+    /// File owner (e.g., "root:root")
+    pub owner: Option<String>,
+}
+
+impl TemplateFile {
+    /// Create a new templated file
+    pub fn new(path: impl Into<String>, template: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            template: template.into(),
+            context: serde_json::Value::Object(serde_json::Map::new()),
+            permissions: None,
+            owner: None,
+        }
+    }
+
+    /// Set the template context
+    pub fn context(mut self, context: serde_json::Value) -> Self {
+        self.context = context;
+        self
+    }
+
+    /// Set file permissions
+    pub fn with_permissions(mut self, perms: impl Into<String>) -> Self {
+        self.permissions = Some(perms.into());
+        self
+    }
+
+    /// Set file owner
+    pub fn with_owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = Some(owner.into());
+        self
+    }
+
+    /// Render the template into a [`WriteFile`] step
+    pub fn render(&self) -> Result<WriteFile, TemplateError> {
+        let err = |source| TemplateError {
+            path: self.path.clone(),
+            source,
+        };
+
+        let ctx = Context::from_value(self.context.clone()).map_err(err)?;
+        let content = Tera::one_off(&self.template, &ctx, false).map_err(err)?;
+
+        let mut file = WriteFile::new(&self.path, content);
+        file.permissions.clone_from(&self.permissions);
+        file.owner.clone_from(&self.owner);
+        Ok(file)
+    }
+}
@@ -0,0 +1,112 @@
+//! Automatic security update steps
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    CloudInitFragment, Dependency, EnsureService, InstallPackage, KnownStep, Step, WriteFile,
+};
+
+/// Periodic apt settings that turn unattended upgrades on
+const AUTO_UPGRADES_CONF: &str = "\
+APT::Periodic::Update-Package-Lists \"1\";
+APT::Periodic::Unattended-Upgrade \"1\";
+";
+
+/// Unattended-upgrades origins, limited to the security pocket
+const UNATTENDED_UPGRADES_CONF: &str = "\
+// Managed by tengu-init: security updates only
+Unattended-Upgrade::Allowed-Origins {
+    \"${distro_id}:${distro_codename}-security\";
+    \"${distro_id}ESMApps:${distro_codename}-apps-security\";
+    \"${distro_id}ESM:${distro_codename}-infra-security\";
+};
+Unattended-Upgrade::Remove-Unused-Kernel-Packages \"true\";
+Unattended-Upgrade::Automatic-Reboot \"false\";
+";
+
+/// Install and enable `unattended-upgrades` for security patches only
+///
+/// Composes [`InstallPackage`], two [`WriteFile`]s and [`EnsureService`], so
+/// each part keeps its own idempotency in the bash output.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnsureUnattendedUpgrades {
+    package: InstallPackage,
+    files: [WriteFile; 2],
+    service: EnsureService,
+    /// Description
+    description: String,
+}
+
+impl EnsureUnattendedUpgrades {
+    /// Create a new unattended-upgrades step
+    pub fn new() -> Self {
+        Self {
+            package: InstallPackage::new("unattended-upgrades"),
+            files: [
+                WriteFile::new("/etc/apt/apt.conf.d/20auto-upgrades", AUTO_UPGRADES_CONF)
+                    .with_permissions("0644"),
+                WriteFile::new(
+                    "/etc/apt/apt.conf.d/50unattended-upgrades",
+                    UNATTENDED_UPGRADES_CONF,
+                )
+                .with_permissions("0644"),
+            ],
+            service: EnsureService::new("unattended-upgrades"),
+            description: "Enable unattended security upgrades".into(),
+        }
+    }
+}
+
+impl Default for EnsureUnattendedUpgrades {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Step for EnsureUnattendedUpgrades {
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn kind(&self) -> &'static str {
+        "EnsureUnattendedUpgrades"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::EnsureUnattendedUpgrades(Box::new(self.clone())))
+    }
+
+    fn to_cloud_init(&self) -> CloudInitFragment {
+        let mut fragment = self.package.to_cloud_init();
+        for file in &self.files {
+            fragment
+                .write_files
+                .extend(file.to_cloud_init().write_files);
+        }
+        fragment.runcmd = self.service.to_cloud_init().runcmd;
+        fragment
+    }
+
+    fn to_bash(&self) -> Vec<String> {
+        let mut cmds = self.package.to_bash();
+        for file in &self.files {
+            cmds.extend(file.to_bash());
+        }
+        cmds.extend(self.service.to_bash());
+        cmds
+    }
+
+    fn check_command(&self) -> Option<String> {
+        // Enabled, and with both config files still as written
+        let mut checks =
+            vec!["systemctl is-enabled unattended-upgrades >/dev/null 2>&1".to_string()];
+        checks.extend(self.files.iter().filter_map(Step::check_command));
+        Some(checks.join(" && "))
+    }
+
+    fn provides(&self) -> Vec<Dependency> {
+        let mut provides = self.package.provides();
+        provides.extend(self.service.provides());
+        provides
+    }
+}
@@ -1,9 +1,11 @@
 //! User management steps
 
-use super::{CloudInitFragment, Step};
+use serde::{Deserialize, Serialize};
+
+use super::{CloudInitFragment, CloudInitUser, KnownStep, Step};
 
 /// Ensure a system user exists with specified configuration
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EnsureUser {
     /// Username
     pub name: String,
@@ -15,6 +17,9 @@ pub struct EnsureUser {
     pub sudo: Option<String>,
     /// SSH authorized keys
     pub ssh_keys: Vec<String>,
+    /// System account: no home directory, no login shell, no SSH keys
+    #[serde(default)]
+    pub system: bool,
     /// Description
     description: String,
 }
@@ -30,6 +35,7 @@ impl EnsureUser {
             shell: "/bin/bash".into(),
             sudo: None,
             ssh_keys: vec![],
+            system: false,
             description,
         }
     }
@@ -57,6 +63,27 @@ impl EnsureUser {
         self.ssh_keys = keys.into_iter().map(Into::into).collect();
         self
     }
+
+    /// Make this a system account for running a service
+    ///
+    /// The account gets `/usr/sbin/nologin` and no home directory, so any
+    /// SSH keys are ignored: nobody can log in as it.
+    pub fn system(mut self, system: bool) -> Self {
+        self.system = system;
+        if system {
+            self.shell = "/usr/sbin/nologin".into();
+            self.description = format!("Ensure system user {} exists", self.name);
+        } else {
+            self.shell = "/bin/bash".into();
+            self.description = format!("Ensure user {} exists", self.name);
+        }
+        self
+    }
+
+    /// SSH keys to install, none for system accounts
+    fn authorized_keys(&self) -> &[String] {
+        if self.system { &[] } else { &self.ssh_keys }
+    }
 }
 
 impl Step for EnsureUser {
@@ -64,11 +91,25 @@ impl Step for EnsureUser {
         &self.description
     }
 
+    fn kind(&self) -> &'static str {
+        "EnsureUser"
+    }
+
+    fn to_known(&self) -> Option<KnownStep> {
+        Some(KnownStep::EnsureUser(self.clone()))
+    }
+
     fn to_cloud_init(&self) -> CloudInitFragment {
-        // Cloud-init handles users differently - this would be in the users: section
-        // For now, we emit runcmd equivalents
+        // Users are declared natively in the users: section, not as shell commands
         CloudInitFragment {
-            runcmd: self.to_bash(),
+            users: vec![CloudInitUser {
+                name: self.name.clone(),
+                groups: self.groups.clone(),
+                shell: self.shell.clone(),
+                sudo: self.sudo.clone(),
+                ssh_authorized_keys: self.authorized_keys().to_vec(),
+                system: self.system,
+            }],
             ..Default::default()
         }
     }
@@ -77,13 +118,17 @@ impl Step for EnsureUser {
         let mut cmds = vec![];
 
         // Create user if not exists (handle leftover group from prior install)
+        let opts = if self.system {
+            format!("-r -s {} -M", self.shell)
+        } else {
+            format!("-m -s {}", self.shell)
+        };
         cmds.push(format!(
             "id {name} >/dev/null 2>&1 || {{ \
-                 getent group {name} >/dev/null 2>&1 && useradd -m -s {shell} -g {name} {name} || \
-                 useradd -m -s {shell} {name}; \
+                 getent group {name} >/dev/null 2>&1 && useradd {opts} -g {name} {name} || \
+                 useradd {opts} {name}; \
              }}",
             name = self.name,
-            shell = self.shell
         ));
 
         // Add to groups
@@ -106,13 +151,13 @@ impl Step for EnsureUser {
         }
 
         // SSH keys
-        if !self.ssh_keys.is_empty() {
+        if !self.authorized_keys().is_empty() {
             cmds.push(format!(
                 "mkdir -p /home/{}/.ssh && chmod 700 /home/{}/.ssh",
                 self.name, self.name
             ));
 
-            for key in &self.ssh_keys {
+            for key in self.authorized_keys() {
                 // Escape single quotes in key
                 let key_escaped = key.replace('\'', "'\\''");
                 cmds.push(format!(
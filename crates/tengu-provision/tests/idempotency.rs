@@ -0,0 +1,212 @@
+//! Steps are idempotent: applied twice, the second run is skipped and
+//! changes nothing
+//!
+//! Each fixture targets paths inside a scratch directory, so its bash runs
+//! against a real `/bin/sh` without root or a chroot.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use tengu_provision::Step;
+use tengu_provision::steps::{EnsureDirectory, EnsureSymlink, ReplaceInFile, WriteFile};
+
+/// A scratch directory steps are pointed at, removed on drop
+struct Sandbox {
+    root: PathBuf,
+}
+
+impl Sandbox {
+    fn new(name: &str) -> Self {
+        let root =
+            std::env::temp_dir().join(format!("tengu-idempotency-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        Self { root }
+    }
+
+    /// Absolute path of `relative` inside the sandbox
+    fn path(&self, relative: &str) -> String {
+        self.root.join(relative).display().to_string()
+    }
+
+    /// Run `script` with `/bin/sh`, returning whether it succeeded
+    fn sh(&self, script: &str) -> bool {
+        Command::new("/bin/sh")
+            .args(["-c", script])
+            .current_dir(&self.root)
+            .status()
+            .unwrap()
+            .success()
+    }
+
+    /// Whether the step's check passes, i.e. the renderers would skip it
+    fn skipped(&self, step: &dyn Step) -> bool {
+        let check = step
+            .check_command()
+            .unwrap_or_else(|| panic!("{} has no check command", step.description()));
+        self.sh(&check)
+    }
+
+    /// Run the step's commands like the bash renderer does
+    fn apply(&self, step: &dyn Step) {
+        let script = step.to_bash().join("\n");
+        assert!(self.sh(&script), "{} failed:\n{script}", step.description());
+    }
+
+    /// Every entry under the root: type, mode, contents or link target
+    fn snapshot(&self) -> BTreeMap<PathBuf, String> {
+        fn walk(dir: &Path, out: &mut BTreeMap<PathBuf, String>) {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                let meta = fs::symlink_metadata(&path).unwrap();
+                let mode = meta.permissions().mode() & 0o7777;
+                let state = if meta.file_type().is_symlink() {
+                    format!("link -> {}", fs::read_link(&path).unwrap().display())
+                } else if meta.is_dir() {
+                    walk(&path, out);
+                    format!("dir {mode:o}")
+                } else {
+                    format!("file {mode:o} {:?}", fs::read(&path).unwrap())
+                };
+                out.insert(path, state);
+            }
+        }
+
+        let mut out = BTreeMap::new();
+        walk(&self.root, &mut out);
+        out
+    }
+
+    /// Apply `step` twice: it must run the first time and be skipped after,
+    /// and a forced second run must leave the sandbox unchanged
+    fn assert_idempotent(&self, step: &dyn Step) {
+        assert!(
+            !self.skipped(step),
+            "{} is skipped before it ran",
+            step.description()
+        );
+        self.apply(step);
+        assert!(
+            self.skipped(step),
+            "{} would run again after applying",
+            step.description()
+        );
+
+        let applied = self.snapshot();
+        self.apply(step);
+        assert_eq!(
+            self.snapshot(),
+            applied,
+            "{} changed state on its second run",
+            step.description()
+        );
+        assert!(self.skipped(step));
+    }
+}
+
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+#[test]
+fn test_write_file_is_idempotent() {
+    let sandbox = Sandbox::new("write-file");
+    for (name, content) in [
+        ("trailing-newline.conf", "key = value\n"),
+        ("no-trailing-newline.conf", "key = value"),
+        ("empty.conf", ""),
+        ("quotes.sh", "echo 'single' \"double\" $HOME `date`\n\n"),
+    ] {
+        let step =
+            WriteFile::new(sandbox.path(&format!("etc/{name}")), content).with_permissions("0640");
+        sandbox.assert_idempotent(&step);
+        assert_eq!(
+            fs::read_to_string(sandbox.path(&format!("etc/{name}"))).unwrap(),
+            content
+        );
+    }
+}
+
+#[test]
+fn test_write_file_rewrites_changed_content() {
+    let sandbox = Sandbox::new("write-file-changed");
+    let path = sandbox.path("app.conf");
+    sandbox.assert_idempotent(&WriteFile::new(&path, "v1\n"));
+
+    let step = WriteFile::new(&path, "v2\n");
+    assert!(!sandbox.skipped(&step));
+    sandbox.apply(&step);
+    assert!(sandbox.skipped(&step));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "v2\n");
+}
+
+#[test]
+fn test_ensure_directory_is_idempotent() {
+    let sandbox = Sandbox::new("directory");
+    sandbox.assert_idempotent(
+        &EnsureDirectory::new(sandbox.path("var/lib/app")).with_permissions("0750"),
+    );
+    sandbox.assert_idempotent(&EnsureDirectory::new(sandbox.path("var/log/app")));
+}
+
+#[test]
+fn test_ensure_symlink_is_idempotent() {
+    let sandbox = Sandbox::new("symlink");
+    sandbox.assert_idempotent(&EnsureDirectory::new(sandbox.path("releases/v2")));
+    sandbox.assert_idempotent(&EnsureSymlink::new(
+        sandbox.path("current"),
+        sandbox.path("releases/v2"),
+    ));
+    // Dangling targets are fine
+    sandbox.assert_idempotent(&EnsureSymlink::new(
+        sandbox.path("etc/missing"),
+        "/nonexistent/target",
+    ));
+}
+
+#[test]
+fn test_ensure_symlink_repoints_existing_link() {
+    let sandbox = Sandbox::new("symlink-repoint");
+    fs::create_dir_all(sandbox.path("v1")).unwrap();
+    fs::create_dir_all(sandbox.path("v2")).unwrap();
+    sandbox.assert_idempotent(&EnsureSymlink::new(
+        sandbox.path("current"),
+        sandbox.path("v1"),
+    ));
+
+    let step = EnsureSymlink::new(sandbox.path("current"), sandbox.path("v2"));
+    assert!(!sandbox.skipped(&step));
+    sandbox.apply(&step);
+    assert!(sandbox.skipped(&step));
+    // Replaced, not created inside the directory the old link pointed at
+    assert!(fs::read_dir(sandbox.path("v1")).unwrap().next().is_none());
+}
+
+#[test]
+fn test_replace_in_file_is_idempotent() {
+    let sandbox = Sandbox::new("replace");
+    let path = sandbox.path("postgresql.conf");
+    fs::write(&path, "#max_connections = 100\nshared_buffers = 128MB\n").unwrap();
+
+    // The replacement matches the regex too
+    sandbox.assert_idempotent(&ReplaceInFile::new(
+        &path,
+        "^#?max_connections = .*",
+        "max_connections = 200",
+    ));
+    // Capture groups can't be grepped for, but re-substituting is a no-op
+    sandbox.assert_idempotent(&ReplaceInFile::new(
+        &path,
+        "^(shared_buffers) = [0-9]+MB",
+        r"\1 = 1GB",
+    ));
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "max_connections = 200\nshared_buffers = 1GB\n"
+    );
+}